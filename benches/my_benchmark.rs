@@ -1,9 +1,16 @@
-use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
 use folds::{
     self,
+    channel::{ShardedFold, SharedFold},
     common::*,
-    fold::{run_fold1_iter, run_fold_iter, Fold1},
+    fold::{
+        merge_tree, run_fold1_iter, run_fold_iter, run_fold_par_iter, run_fold_par_iter_by_key,
+        Fold, Fold1, FoldPar,
+    },
+    sketch::{HdrHistogram, HyperLogLog, TDigest, TopK},
+    stats::CM4,
 };
+use rayon::prelude::*;
 
 fn bench_sum(c: &mut Criterion) {
     let mut group = c.benchmark_group("Sum");
@@ -69,6 +76,22 @@ fn bench_par(c: &mut Criterion) {
                 })
             },
         );
+
+        group.bench_with_input(BenchmarkId::new("HandRolled", n), &xs.clone(), |b, xs| {
+            b.iter(move || {
+                let mut xs = xs.clone();
+                xs.next().map(|x| {
+                    let (mut min, mut max, mut sum, mut last) = (x, x, x, x);
+                    for x in xs {
+                        min = min.min(x);
+                        max = max.max(x);
+                        sum += x;
+                        last = x;
+                    }
+                    (((min, max), sum), last)
+                })
+            })
+        });
     }
     group.finish();
 }
@@ -84,15 +107,254 @@ fn bench_group(c: &mut Criterion) {
         });
 
         group.bench_with_input(BenchmarkId::new("2 groups", n), &xs.clone(), |b, xs| {
-            b.iter(move || run_fold_iter(&Sum::SUM.group_by(|i| i % 2), xs.clone()))
+            b.iter(move || run_fold_iter(&Sum::SUM.group_by(|i: &i32| i % 2), xs.clone()))
         });
 
         group.bench_with_input(BenchmarkId::new("4 groups", n), &xs.clone(), |b, xs| {
-            b.iter(move || run_fold_iter(&Sum::SUM.group_by(|i| i % 4), xs.clone()))
+            b.iter(move || run_fold_iter(&Sum::SUM.group_by(|i: &i32| i % 4), xs.clone()))
+        });
+    }
+    group.finish();
+}
+
+fn bench_group_high_cardinality(c: &mut Criterion) {
+    let mut group = c.benchmark_group("GroupHighCardinality");
+
+    for keys in [10_000, 100_000, 1_000_000] {
+        let xs = (0..1_000_000)
+            .map(|i| (i * 7919) % keys)
+            .collect::<Vec<i64>>();
+
+        group.bench_with_input(BenchmarkId::new("Sum", keys), &xs, |b, xs| {
+            b.iter(|| run_fold_iter(&Sum::SUM.group_by(|i: &i64| *i), xs.iter().copied()))
+        });
+
+        // large states, where keeping them out of the map helps
+        let moments = CM4::CM4.par(CM4::CM4).pre_map(|i: i64| i as f64);
+        group.bench_with_input(BenchmarkId::new("CM4 x2", keys), &xs, |b, xs| {
+            b.iter(|| run_fold_iter(&moments.group_by(|i: &i64| *i), xs.iter().copied()))
+        });
+        group.bench_with_input(BenchmarkId::new("CM4 x2 arena", keys), &xs, |b, xs| {
+            b.iter(|| run_fold_iter(&moments.group_by_arena(|i: &i64| *i), xs.iter().copied()))
+        });
+    }
+    group.finish();
+}
+
+fn bench_group_par(c: &mut Criterion) {
+    let mut group = c.benchmark_group("GroupPar");
+    group.sample_size(10);
+
+    for keys in [10_000, 100_000, 1_000_000] {
+        let xs = (0..4_000_000).map(|i| i % keys).collect::<Vec<i64>>();
+
+        group.bench_with_input(BenchmarkId::new("Count", keys), &xs, |b, xs| {
+            b.iter(|| run_fold_par_iter(xs.par_iter().copied(), &Count::COUNT.group_by(|i| *i)))
+        });
+        group.bench_with_input(BenchmarkId::new("Count by key", keys), &xs, |b, xs| {
+            let fld = Count::COUNT.group_by(|i: &i64| *i);
+            let partitions = rayon::current_num_threads();
+            b.iter(|| run_fold_par_iter_by_key(xs.par_iter().copied(), &fld, partitions))
+        });
+    }
+    group.finish();
+}
+
+fn bench_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Merge");
+    group.sample_size(10);
+
+    // partial states of 100k keys each, as from 32 workers
+    let fld = Count::COUNT.group_by(|i: &i64| *i);
+    let states: Vec<_> = (0..32)
+        .map(|w| {
+            let mut acc = fld.empty();
+            fld.step_chunk(
+                (0..100_000).map(|i| (i * 31 + w) % 200_000).collect(),
+                &mut acc,
+            );
+            acc
+        })
+        .collect();
+
+    group.bench_function("left to right", |b| {
+        b.iter_batched(
+            || states.clone(),
+            |states| {
+                states.into_iter().fold(fld.empty(), |mut m1, m2| {
+                    fld.merge(&mut m1, m2);
+                    m1
+                })
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("tree", |b| {
+        b.iter_batched(
+            || states.clone(),
+            |states| merge_tree(&fld, states),
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+/// Run a fold one value at a time, or in chunks of 1024 with `step_chunk`
+fn step_each<F: Fold>(fold: &F, xs: &[F::A]) -> F::B
+where
+    F::A: Copy,
+{
+    let mut acc = fold.empty();
+    xs.iter().for_each(|x| fold.step(*x, &mut acc));
+    fold.output(acc)
+}
+
+fn step_chunks<F: Fold>(fold: &F, xs: &[F::A]) -> F::B
+where
+    F::A: Copy,
+{
+    let mut acc = fold.empty();
+    xs.chunks(1024)
+        .for_each(|ch| fold.step_chunk(ch.to_vec(), &mut acc));
+    fold.output(acc)
+}
+
+fn bench_chunked(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Chunked");
+
+    let xs = (0..1_000_000).map(|i| i as f64).collect::<Vec<f64>>();
+    group.bench_function("Sum step", |b| b.iter(|| step_each(&Sum::SUM, &xs)));
+    group.bench_function("Sum step_chunk", |b| b.iter(|| step_chunks(&Sum::SUM, &xs)));
+    group.bench_function("CM4 step", |b| b.iter(|| step_each(&CM4::CM4, &xs)));
+    group.bench_function("CM4 step_chunk", |b| b.iter(|| step_chunks(&CM4::CM4, &xs)));
+
+    for keys in [16, 1024, 65_536] {
+        let xs = (0..1_000_000)
+            .map(|i| (i * 7919) % keys)
+            .collect::<Vec<i64>>();
+        let fld = Count::COUNT.group_by(|i: &i64| *i);
+        group.bench_with_input(BenchmarkId::new("Group step", keys), &xs, |b, xs| {
+            b.iter(|| step_each(&fld, xs))
+        });
+        group.bench_with_input(BenchmarkId::new("Group step_chunk", keys), &xs, |b, xs| {
+            b.iter(|| step_chunks(&fld, xs))
         });
     }
     group.finish();
 }
 
-criterion_group!(benches, bench_sum, bench_minmax, bench_par, bench_group);
+fn bench_par_threads(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ParThreads");
+    group.sample_size(10);
+
+    let xs = (0..4_000_000).map(|i| i as f64).collect::<Vec<f64>>();
+    let moments = CM4::CM4;
+    let grouped = Count::COUNT.group_by(|x: &f64| (*x as i64) % 100_000);
+    for threads in [1, 2, 4, 8] {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        group.bench_with_input(BenchmarkId::new("CM4", threads), &xs, |b, xs| {
+            b.iter(|| pool.install(|| run_fold_par_iter(xs.par_iter().copied(), &moments)))
+        });
+        group.bench_with_input(BenchmarkId::new("Group", threads), &xs, |b, xs| {
+            b.iter(|| pool.install(|| run_fold_par_iter(xs.par_iter().copied(), &grouped)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_sketch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Sketch");
+
+    // a skewed distribution, where sketches do the most work
+    let xs = (0..1_000_000u64)
+        .map(|i| (i * 7919 % 1_000_003).pow(2) / 1_000_003)
+        .collect::<Vec<u64>>();
+    let fs = xs.iter().map(|x| *x as f64).collect::<Vec<f64>>();
+
+    group.bench_function("TDigest", |b| {
+        b.iter(|| step_each(&TDigest::default(), &fs))
+    });
+    group.bench_function("TDigest step_chunk", |b| {
+        b.iter(|| step_chunks(&TDigest::default(), &fs))
+    });
+    group.bench_function("HyperLogLog", |b| {
+        b.iter(|| step_each(&HyperLogLog::default(), &xs))
+    });
+    group.bench_function("HdrHistogram", |b| {
+        b.iter(|| step_each(&HdrHistogram::default(), &xs))
+    });
+    group.bench_function("TopK 10", |b| b.iter(|| step_each(&TopK::new(10), &xs)));
+    group.finish();
+}
+
+fn bench_shared(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Shared");
+    group.sample_size(10);
+
+    // every thread steps the same counter, as request handlers would
+    let per_thread = 250_000;
+    let fld = Sum::<u64>::SUM.par(Count::COUNT);
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("Mutex", threads), &threads, |b, n| {
+            b.iter(|| {
+                let shared = SharedFold::with_shards(fld, 1);
+                std::thread::scope(|s| {
+                    for _ in 0..*n {
+                        s.spawn(|| (0..per_thread).for_each(|x| shared.step(x)));
+                    }
+                });
+                shared.into_output()
+            })
+        });
+        group.bench_with_input(
+            BenchmarkId::new("Sharded mutex", threads),
+            &threads,
+            |b, n| {
+                b.iter(|| {
+                    let shared = SharedFold::new(fld);
+                    std::thread::scope(|s| {
+                        for _ in 0..*n {
+                            s.spawn(|| (0..per_thread).for_each(|x| shared.step(x)));
+                        }
+                    });
+                    shared.into_output()
+                })
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("Sharded atomic", threads),
+            &threads,
+            |b, n| {
+                b.iter(|| {
+                    let sharded = ShardedFold::new(fld);
+                    std::thread::scope(|s| {
+                        for _ in 0..*n {
+                            s.spawn(|| (0..per_thread).for_each(|x| sharded.step(x)));
+                        }
+                    });
+                    sharded.snapshot()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_sum,
+    bench_minmax,
+    bench_par,
+    bench_group,
+    bench_group_high_cardinality,
+    bench_group_par,
+    bench_merge,
+    bench_chunked,
+    bench_par_threads,
+    bench_sketch,
+    bench_shared
+);
 criterion_main!(benches);
@@ -0,0 +1,32 @@
+// Profile the bytes of a file picked in the browser, as it streams in.
+//
+// Build with
+//   cargo build --example browser --target wasm32-unknown-unknown --features wasm
+//   wasm-bindgen --target web --out-dir pkg \
+//       target/wasm32-unknown-unknown/debug/examples/browser.wasm
+// and call it from a page with
+//   import init, { profile } from "./pkg/browser.js";
+//   await init();
+//   input.onchange = async () => console.log(await profile(input.files[0].stream()));
+use folds::fold::*;
+use folds::profile::numeric;
+use folds::sketch::HyperLogLog;
+use folds::wasm::{bytes, run_fold_readable_stream};
+
+use wasm_bindgen::prelude::*;
+use web_sys::ReadableStream;
+
+#[wasm_bindgen]
+pub async fn profile(stream: ReadableStream) -> Result<String, JsValue> {
+    let fld = numeric()
+        .pre_map(|b: u8| Some(b as f64))
+        .par(HyperLogLog::default())
+        .batched()
+        .pre_map(bytes);
+    let (profile, distinct) = run_fold_readable_stream(&stream, &fld).await?;
+    Ok(format!(
+        "{:.0} distinct bytes\n{}",
+        distinct.estimate(),
+        profile
+    ))
+}
@@ -4,9 +4,9 @@ use rayon::iter::IntoParallelIterator;
 
 fn main() {
     let xs: Vec<i64> = vec![1, 2, 3, 4, 5];
-    let fld = Sum::SUM
+    let fld = Sum::<i64>::SUM
         .filter(|x| x % 2 == 0)
-        .par(Sum::SUM.group_by(|x| x % 2));
+        .par(Sum::SUM.group_by(|x: &i64| x % 2));
 
     let fld1 = Min::MIN.par(Max::MAX);
 
@@ -31,9 +31,7 @@ fn main() {
     let avg = run_fold_iter(&avger, xs.clone().into_iter());
     println!("Avg : {avg}");
 
-    let avger = Count::COUNT
-        .par(Sum::<f64>::SUM)
-        .post_map(|(n, sum)| sum / (n as f64));
+    let avger = Count::COUNT.zip_with(Sum::<f64>::SUM, |n, sum| sum / (n as f64));
 
     let avg = run_fold_par_iter(xs.into_par_iter(), &avger);
     println!("Par Avg : {avg}")
@@ -0,0 +1,45 @@
+use folds::common::*;
+use folds::fold::*;
+use folds::sqlx::{run_fold_rows, run_fold_rows_as};
+
+use sqlx::sqlite::SqlitePool;
+use sqlx::FromRow;
+
+#[derive(FromRow)]
+struct Order {
+    region: String,
+    amount: f64,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), sqlx::Error> {
+    let pool = SqlitePool::connect("sqlite::memory:").await?;
+    sqlx::query("create table orders (region text, amount real)")
+        .execute(&pool)
+        .await?;
+    sqlx::query(
+        "insert into orders values \
+         ('north', 12.0), ('south', 3.5), ('north', 7.25), ('east', 20.0), ('south', 1.0)",
+    )
+    .execute(&pool)
+    .await?;
+
+    // count and total per region, folded as the rows stream in
+    let fld = Count::COUNT
+        .par(Sum::<f64>::SUM)
+        .pre_map(|o: Order| o.amount)
+        .group_by(|o: &Order| o.region.clone());
+
+    let rows = sqlx::query_as("select region, amount from orders").fetch(&pool);
+    let mut by_region: Vec<_> = run_fold_rows(rows, &fld).await?.into_iter().collect();
+    by_region.sort_by(|a, b| a.0.cmp(&b.0));
+    for (region, (n, total)) in by_region {
+        println!("{region}: {n} orders, {total:.2} total");
+    }
+
+    // or decode rows with `FromRow` while folding
+    let rows = sqlx::query("select region, amount from orders").fetch(&pool);
+    let total = run_fold_rows_as(rows, &Sum::<f64>::SUM.pre_map(|o: Order| o.amount)).await?;
+    println!("total: {total:.2}");
+    Ok(())
+}
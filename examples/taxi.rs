@@ -1,6 +1,6 @@
 use arrow::array::Array;
 use folds::fold::Fold1 as _;
-use folds::{self, fold::run_fold_par_stream, stats::SampleN};
+use folds::{self, fold::run_fold_par_stream};
 use parquet::arrow::async_reader;
 use parquet::arrow::ProjectionMask;
 
@@ -33,12 +33,10 @@ async fn main() {
                 .column(0)
                 .as_any()
                 .downcast_ref::<arrow::array::Float64Array>()?;
-            Some(Vec::from(&prim_arr.values()[..]))
+            Some(prim_arr.iter().collect::<Vec<Option<f64>>>())
         });
 
-    let fld = folds::stats::CM4::CM4
-        .par(SampleN::<20, f64>::SAMPLE)
-        .batched();
+    let fld = folds::profile::numeric().batched();
 
     println!("Summary");
 
@@ -49,15 +47,7 @@ async fn main() {
     }
 
     println!("passenger_count");
-    if let Some(ans) = run_fold_par_stream(&fld, threads, stream).await {
-        let ((mu, var, skw, krt), sample) = ans;
-        println!(" >>     mean: {:.3}", mu);
-        println!(" >>      var: {:.3}", var);
-        println!(" >>     skew: {:.3}", skw);
-        println!(" >> kurtosis: {:.3}", krt);
-
-        if let Ok(sample) = sample {
-            println!(" >> {:?}", sample)
-        }
+    if let Ok(profile) = run_fold_par_stream(&fld, threads, stream).await {
+        println!("{}", profile);
     }
 }
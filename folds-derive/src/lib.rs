@@ -0,0 +1,205 @@
+//! Derive macros for the folds crate, re-exported from it behind the
+//! `derive` feature
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, Path, Type};
+
+/// Derive `Fold1`, `Fold` and `FoldPar` for a struct whose fields are
+/// folds over parts of a record, along with a `<Name>Output` struct
+/// holding the output of each field.
+///
+/// The record type is given by `#[fold(input = Record)]`, and
+/// `#[fold(derive(...))]` adds derives to the output struct. Each field
+/// takes `#[fold(get = ...)]`, a function from `&Record` to its input,
+/// and fields without one are given a clone of the record.
+/// `Fold`, `FoldPar` and `FoldDescribe` are implemented when every
+/// field implements them.
+#[proc_macro_derive(FoldCompose, attributes(fold))]
+pub fn derive_fold_compose(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+struct Part {
+    name: syn::Ident,
+    vis: syn::Visibility,
+    ty: Type,
+    get: Option<Expr>,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "FoldCompose does not support generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "FoldCompose needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "FoldCompose can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut record: Option<Type> = None;
+    let mut derives: Vec<Path> = Vec::new();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("fold")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("input") {
+                record = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("derive") {
+                meta.parse_nested_meta(|d| {
+                    derives.push(d.path);
+                    Ok(())
+                })
+            } else {
+                Err(meta.error("expected `input` or `derive`"))
+            }
+        })?;
+    }
+    let record = record
+        .ok_or_else(|| syn::Error::new_spanned(&input.ident, "missing `#[fold(input = ...)]`"))?;
+
+    let mut parts = Vec::new();
+    for field in fields {
+        let mut get = None;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("fold")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("get") {
+                    get = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `get`"))
+                }
+            })?;
+        }
+        parts.push(Part {
+            name: field.ident.clone().expect("named fields have names"),
+            vis: field.vis.clone(),
+            ty: field.ty.clone(),
+            get,
+        });
+    }
+
+    let ident = &input.ident;
+    let vis = &input.vis;
+    let output = format_ident!("{}Output", ident);
+    let state = format_ident!("{}State", ident);
+    let fold = quote!(::folds::fold);
+
+    let names: Vec<_> = parts.iter().map(|p| &p.name).collect();
+    let vises: Vec<_> = parts.iter().map(|p| &p.vis).collect();
+    let tys: Vec<_> = parts.iter().map(|p| &p.ty).collect();
+    let gets: Vec<_> = parts
+        .iter()
+        .map(|p| match &p.get {
+            Some(get) => quote!((#get)(&x)),
+            None => quote!(::core::clone::Clone::clone(&x)),
+        })
+        .collect();
+    let output_doc = format!("Output of `{}`", ident);
+    let ident_name = ident.to_string();
+    let field_names: Vec<_> = names.iter().map(|n| n.to_string()).collect();
+    let derives = (!derives.is_empty()).then(|| quote!(#[derive(#(#derives),*)]));
+
+    // the bounds are higher ranked so that they are checked where the
+    // impls are used, rather than being rejected as trivially false
+    Ok(quote! {
+        #[doc = #output_doc]
+        #derives
+        #vis struct #output {
+            #( #vises #names: <#tys as #fold::Fold1>::B, )*
+        }
+
+        #[doc(hidden)]
+        #vis struct #state {
+            #( #names: <#tys as #fold::Fold1>::M, )*
+        }
+
+        impl ::core::clone::Clone for #state
+        where
+            #( for<'__f> <#tys as #fold::Fold1>::M: ::core::clone::Clone, )*
+        {
+            fn clone(&self) -> Self {
+                #state {
+                    #( #names: ::core::clone::Clone::clone(&self.#names), )*
+                }
+            }
+        }
+
+        impl #fold::Fold1 for #ident {
+            type A = #record;
+            type B = #output;
+            type M = #state;
+
+            fn init(&self, x: Self::A) -> Self::M {
+                #state {
+                    #( #names: #fold::Fold1::init(&self.#names, #gets), )*
+                }
+            }
+
+            fn step(&self, x: Self::A, acc: &mut Self::M) {
+                #( #fold::Fold1::step(&self.#names, #gets, &mut acc.#names); )*
+            }
+
+            fn output(&self, acc: Self::M) -> Self::B {
+                #output {
+                    #( #names: #fold::Fold1::output(&self.#names, acc.#names), )*
+                }
+            }
+        }
+
+        impl #fold::Fold for #ident
+        where
+            #( for<'__f> #tys: #fold::Fold, )*
+        {
+            fn empty(&self) -> Self::M {
+                #state {
+                    #( #names: #fold::Fold::empty(&self.#names), )*
+                }
+            }
+        }
+
+        impl #fold::FoldPar for #ident
+        where
+            #( for<'__f> #tys: #fold::FoldPar, )*
+        {
+            fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+                #( #fold::FoldPar::merge(&self.#names, &mut m1.#names, m2.#names); )*
+            }
+        }
+
+        impl #fold::FoldDescribe for #ident
+        where
+            #( for<'__f> #tys: #fold::FoldDescribe, )*
+        {
+            fn describe_structure(&self) -> #fold::Structure {
+                let children = ::std::vec![
+                    #({
+                        let s = #fold::FoldDescribe::describe_structure(&self.#names);
+                        #fold::Structure {
+                            name: ::std::format!("{} = {}", #field_names, s.name),
+                            ..s
+                        }
+                    },)*
+                ];
+                #fold::Structure::new::<Self>(#ident_name, children)
+            }
+        }
+    })
+}
@@ -0,0 +1,708 @@
+//! Folds over arrow arrays, which handle nulls and use arrow's
+//! compute kernels rather than stepping one value at a time.
+use crate::columns::Columns;
+use crate::fold::*;
+
+use std::marker::PhantomData;
+
+use ::arrow::array::{
+    Array, ArrayRef, ArrowNativeTypeOp, ArrowNumericType, ArrowPrimitiveType, AsArray,
+    PrimitiveArray,
+};
+use ::arrow::buffer::NullBuffer;
+use ::arrow::compute;
+use ::arrow::datatypes::DataType;
+
+// only needed by the record batch runner
+#[cfg(feature = "async")]
+use {
+    ::arrow::error::ArrowError,
+    ::arrow::record_batch::RecordBatch,
+    futures::{Stream, StreamExt},
+    rustc_hash::FxHashMap,
+};
+
+/// Sum of the non-null values of each array
+pub struct Sum<T> {
+    ghost: PhantomData<T>,
+}
+
+// derive would require `T: Clone`, which arrow's type markers are not
+impl<T> Clone for Sum<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Sum<T> {}
+
+impl<T: ArrowNumericType> Sum<T> {
+    pub const SUM: Self = Sum { ghost: PhantomData };
+}
+
+impl<T: ArrowNumericType> Fold1 for Sum<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    type A = PrimitiveArray<T>;
+    type B = T::Native;
+    type M = T::Native;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        compute::sum(&x).unwrap_or(T::Native::ZERO)
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        if let Some(s) = compute::sum(&x) {
+            *acc = acc.add_wrapping(s)
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+impl<T: ArrowNumericType> Fold for Sum<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    fn empty(&self) -> Self::M {
+        T::Native::ZERO
+    }
+}
+
+impl<T: ArrowNumericType> FoldPar for Sum<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        *m1 = m1.add_wrapping(m2)
+    }
+}
+
+/// Minimum of the non-null values of each array,
+/// `None` if every value was null
+pub struct Min<T> {
+    ghost: PhantomData<T>,
+}
+
+impl<T> Clone for Min<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Min<T> {}
+
+impl<T: ArrowNumericType> Min<T> {
+    pub const MIN: Self = Min { ghost: PhantomData };
+}
+
+impl<T: ArrowNumericType> Fold1 for Min<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    type A = PrimitiveArray<T>;
+    type B = Option<T::Native>;
+    type M = Option<T::Native>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        compute::min(&x)
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.merge(acc, compute::min(&x))
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+impl<T: ArrowNumericType> Fold for Min<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    fn empty(&self) -> Self::M {
+        None
+    }
+}
+
+impl<T: ArrowNumericType> FoldPar for Min<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        match (*m1, m2) {
+            (Some(x1), Some(x2)) if x2.is_lt(x1) => *m1 = m2,
+            (None, _) => *m1 = m2,
+            _ => {}
+        }
+    }
+}
+
+/// Maximum of the non-null values of each array,
+/// `None` if every value was null
+pub struct Max<T> {
+    ghost: PhantomData<T>,
+}
+
+impl<T> Clone for Max<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Max<T> {}
+
+impl<T: ArrowNumericType> Max<T> {
+    pub const MAX: Self = Max { ghost: PhantomData };
+}
+
+impl<T: ArrowNumericType> Fold1 for Max<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    type A = PrimitiveArray<T>;
+    type B = Option<T::Native>;
+    type M = Option<T::Native>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        compute::max(&x)
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.merge(acc, compute::max(&x))
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+impl<T: ArrowNumericType> Fold for Max<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    fn empty(&self) -> Self::M {
+        None
+    }
+}
+
+impl<T: ArrowNumericType> FoldPar for Max<T>
+where
+    T::Native: ArrowNativeTypeOp,
+{
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        match (*m1, m2) {
+            (Some(x1), Some(x2)) if x2.is_gt(x1) => *m1 = m2,
+            (None, _) => *m1 = m2,
+            _ => {}
+        }
+    }
+}
+
+/// Number of non-null and null values of any kind of array
+#[derive(Copy, Clone)]
+pub struct CountNulls<A> {
+    ghost: PhantomData<A>,
+}
+
+impl<A: Array> CountNulls<A> {
+    pub const COUNT_NULLS: Self = CountNulls { ghost: PhantomData };
+}
+
+impl<A: Array> Fold1 for CountNulls<A> {
+    type A = A;
+    /// (non-null, null)
+    type B = (usize, usize);
+    type M = (usize, usize);
+
+    fn init(&self, x: Self::A) -> Self::M {
+        (x.len() - x.null_count(), x.null_count())
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.merge(acc, self.init(x))
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+impl<A: Array> Fold for CountNulls<A> {
+    fn empty(&self) -> Self::M {
+        (0, 0)
+    }
+}
+
+impl<A: Array> FoldPar for CountNulls<A> {
+    fn merge(&self, (n1, z1): &mut Self::M, (n2, z2): Self::M) {
+        *n1 += n2;
+        *z1 += z2;
+    }
+}
+
+/// Lift a fold over values to a fold over arrays of those values,
+/// skipping nulls. Each array is handed to the inner fold as one chunk.
+pub fn values<T, F>(inner: F) -> Values<T, F>
+where
+    T: ArrowPrimitiveType,
+    F: Fold<A = T::Native>,
+{
+    Values {
+        inner,
+        ghost: PhantomData,
+    }
+}
+
+/// Fold over the non-null values of arrays, see `values`
+pub struct Values<T, F> {
+    inner: F,
+    ghost: PhantomData<T>,
+}
+
+impl<T, F: Clone> Clone for Values<T, F> {
+    fn clone(&self) -> Self {
+        Values {
+            inner: self.inner.clone(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<T, F: Copy> Copy for Values<T, F> {}
+
+impl<T: ArrowPrimitiveType, F: Fold<A = T::Native>> Fold1 for Values<T, F> {
+    type A = PrimitiveArray<T>;
+    type B = F::B;
+    type M = F::M;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.inner.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let xs = if x.null_count() == 0 {
+            x.values().to_vec()
+        } else {
+            x.iter().flatten().collect()
+        };
+        self.inner.step_chunk(xs, acc)
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<T: ArrowPrimitiveType, F: Fold<A = T::Native>> Fold for Values<T, F> {
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<T: ArrowPrimitiveType, F: Fold<A = T::Native> + FoldPar> FoldPar for Values<T, F> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Folds which can step over a borrowed slice of values in place, such
+/// as the values buffer of an arrow array, see `buffers`
+pub trait StepSlice: Fold1 {
+    /// Step over every value of `values`.
+    /// By default they are copied into a chunk.
+    fn step_slice(&self, values: &[Self::A], acc: &mut Self::M)
+    where
+        Self::A: Clone,
+    {
+        self.step_chunk(values.to_vec(), acc)
+    }
+
+    /// Step over the values of `values` which `validity` marks as
+    /// valid, a run of valid values at a time
+    fn step_masked(&self, values: &[Self::A], validity: Option<&NullBuffer>, acc: &mut Self::M)
+    where
+        Self::A: Clone,
+    {
+        match validity {
+            Some(nulls) if nulls.null_count() > 0 => {
+                for (start, end) in nulls.valid_slices() {
+                    self.step_slice(&values[start..end], acc)
+                }
+            }
+            _ => self.step_slice(values, acc),
+        }
+    }
+}
+
+impl<A: core::ops::AddAssign + Copy> StepSlice for crate::common::Sum<A>
+where
+    A: for<'a> core::iter::Sum<&'a A>,
+{
+    fn step_slice(&self, values: &[A], acc: &mut A) {
+        *acc += values.iter().sum()
+    }
+}
+
+impl<A: Ord + Copy> StepSlice for crate::common::Min<A> {
+    fn step_slice(&self, values: &[A], acc: &mut A) {
+        if let Some(&x) = values.iter().min() {
+            self.step(x, acc)
+        }
+    }
+}
+
+impl<A: Ord + Copy> StepSlice for crate::common::Max<A> {
+    fn step_slice(&self, values: &[A], acc: &mut A) {
+        if let Some(&x) = values.iter().max() {
+            self.step(x, acc)
+        }
+    }
+}
+
+impl<A> StepSlice for crate::common::Count<A> {
+    fn step_slice(&self, values: &[A], acc: &mut usize) {
+        *acc += values.len()
+    }
+}
+
+impl StepSlice for crate::stats::CM4<f64> {
+    fn step_slice(&self, values: &[f64], acc: &mut Self::M) {
+        for &x in values {
+            self.step(x, acc)
+        }
+    }
+}
+
+/// Lift a fold over values to a fold over arrays of those values,
+/// skipping nulls. Unlike `values`, the inner fold steps over the
+/// values buffer of each array in place rather than a copy of it.
+pub fn buffers<T, F>(inner: F) -> Buffers<T, F>
+where
+    T: ArrowPrimitiveType,
+    F: Fold<A = T::Native> + StepSlice,
+{
+    Buffers {
+        inner,
+        ghost: PhantomData,
+    }
+}
+
+/// Fold over the non-null values of arrays in place, see `buffers`
+pub struct Buffers<T, F> {
+    inner: F,
+    ghost: PhantomData<T>,
+}
+
+impl<T, F: Clone> Clone for Buffers<T, F> {
+    fn clone(&self) -> Self {
+        Buffers {
+            inner: self.inner.clone(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<T, F: Copy> Copy for Buffers<T, F> {}
+
+impl<T: ArrowPrimitiveType, F: Fold<A = T::Native> + StepSlice> Fold1 for Buffers<T, F> {
+    type A = PrimitiveArray<T>;
+    type B = F::B;
+    type M = F::M;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.inner.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step_masked(x.values(), x.nulls(), acc)
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<T: ArrowPrimitiveType, F: Fold<A = T::Native> + StepSlice> Fold for Buffers<T, F> {
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<T, F> FoldPar for Buffers<T, F>
+where
+    T: ArrowPrimitiveType,
+    F: Fold<A = T::Native> + StepSlice + FoldPar,
+{
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Binds column names to folds, see `run_fold_record_batches`
+#[derive(Clone)]
+pub struct BatchSpec<B> {
+    types: Vec<DataType>,
+    columns: Columns<ArrayRef, B>,
+}
+
+impl<B> BatchSpec<B> {
+    pub fn new() -> Self {
+        BatchSpec {
+            types: Vec::new(),
+            columns: Columns::new(),
+        }
+    }
+
+    /// Fold over the named column as a `PrimitiveArray<T>`,
+    /// casting it to `T` if needed
+    pub fn primitive<T, F>(self, name: &str, fold: F) -> Self
+    where
+        T: ArrowPrimitiveType,
+        F: Fold<A = PrimitiveArray<T>, B = B> + FoldPar + Send + Sync + 'static,
+        F::M: Send + 'static,
+    {
+        let fold = fold.pre_map(|arr: ArrayRef| arr.as_primitive::<T>().clone());
+        self.array(name, T::DATA_TYPE, fold.boxed())
+    }
+
+    /// Fold over the named column, casting it to `data_type` if needed
+    pub fn array(mut self, name: &str, data_type: DataType, fold: BoxedFold<ArrayRef, B>) -> Self {
+        self.types.push(data_type);
+        self.columns = self.columns.column(name, fold);
+        self
+    }
+
+    #[cfg(feature = "async")]
+    fn project(&self, batch: &RecordBatch) -> Result<Vec<ArrayRef>, ArrowError> {
+        self.columns
+            .names()
+            .zip(self.types.iter())
+            .map(|(name, data_type)| {
+                let col = batch
+                    .column_by_name(name)
+                    .ok_or_else(|| ArrowError::SchemaError(format!("no column named {}", name)))?;
+                if col.data_type() == data_type {
+                    Ok(col.clone())
+                } else {
+                    compute::cast(col, data_type)
+                }
+            })
+            .collect()
+    }
+}
+
+impl<B> Default for BatchSpec<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run the folds of a `BatchSpec` over a stream of record batches,
+/// returning the output of each fold keyed by column name.
+/// Fails if a column is missing or cannot be cast to the expected type.
+#[cfg(feature = "async")]
+pub async fn run_fold_record_batches<B>(
+    spec: &BatchSpec<B>,
+    batches: impl Stream<Item = RecordBatch>,
+) -> Result<FxHashMap<String, B>, ArrowError> {
+    let mut acc = spec.columns.empty();
+    futures::pin_mut!(batches);
+    while let Some(batch) = batches.next().await {
+        spec.columns.step(spec.project(&batch)?, &mut acc);
+    }
+    Ok(spec.columns.output(acc))
+}
+
+impl<T> StateSize for Sum<T>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<T> StateSize for Min<T>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<T> StateSize for Max<T>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A: Array> StateSize for CountNulls<A> {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<T: ArrowPrimitiveType, F: Fold<A = T::Native> + StateSize> StateSize for Values<T, F> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<T, F> StateSize for Buffers<T, F>
+where
+    T: ArrowPrimitiveType,
+    F: Fold<A = T::Native> + StepSlice + StateSize,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<T> FoldDescribe for Sum<T>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Sum")
+    }
+}
+
+impl<T> FoldDescribe for Min<T>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Min")
+    }
+}
+
+impl<T> FoldDescribe for Max<T>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Max")
+    }
+}
+
+impl<A: Array> FoldDescribe for CountNulls<A> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("CountNulls")
+    }
+}
+
+impl<T: ArrowPrimitiveType, F: Fold<A = T::Native> + FoldDescribe> FoldDescribe for Values<T, F> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("Values", vec![self.inner.describe_structure()])
+    }
+}
+
+impl<T, F> FoldDescribe for Buffers<T, F>
+where
+    T: ArrowPrimitiveType,
+    F: Fold<A = T::Native> + StepSlice + FoldDescribe,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("Buffers", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::arrow::array::{Float64Array, Int64Array};
+    use ::arrow::datatypes::Int64Type;
+
+    #[test]
+    fn nulls_are_skipped() {
+        let arrs = vec![
+            Int64Array::from(vec![Some(1), None, Some(3)]),
+            Int64Array::from(vec![None, None]),
+            Int64Array::from(vec![Some(-2), Some(10)]),
+        ];
+        assert_eq!(run_fold_iter(&Sum::SUM, arrs.iter().cloned()), 12);
+        assert_eq!(run_fold_iter(&Min::MIN, arrs.iter().cloned()), Some(-2));
+        assert_eq!(run_fold_iter(&Max::MAX, arrs.iter().cloned()), Some(10));
+        assert_eq!(
+            run_fold_iter(&CountNulls::COUNT_NULLS, arrs.iter().cloned()),
+            (4, 3)
+        );
+
+        let cnt = values::<Int64Type, _>(crate::common::Count::COUNT);
+        assert_eq!(run_fold_iter(&cnt, arrs.into_iter()), 4);
+    }
+
+    #[test]
+    fn buffers_in_place() {
+        use crate::common;
+        use ::arrow::datatypes::Float64Type;
+
+        let arr = Int64Array::from(vec![Some(1), None, Some(3), Some(4), None, Some(6)]);
+        // a slice has offsets into both buffers
+        let arrs = [arr.clone(), arr.slice(2, 3), Int64Array::from(vec![5, 5])];
+        let sum = buffers::<Int64Type, _>(common::Sum::<i64>::SUM);
+        assert_eq!(run_fold_iter(&sum, arrs.iter().cloned()), 14 + 7 + 10);
+        let cnt = buffers::<Int64Type, _>(common::Count::COUNT);
+        assert_eq!(run_fold_iter(&cnt, arrs.iter().cloned()), 4 + 2 + 2);
+        // the value under a null is never seen
+        let arr = Int64Array::new(vec![1, 9, 3].into(), Some(vec![true, false, true].into()));
+        let mut max = 0;
+        common::Max::MAX.step_masked(arr.values(), arr.nulls(), &mut max);
+        assert_eq!(max, 3);
+
+        let xs = Float64Array::from(vec![Some(1.0), None, Some(2.0), Some(3.0)]);
+        let cm4 = buffers::<Float64Type, _>(crate::stats::CM4::CM4);
+        let copied = values::<Float64Type, _>(crate::stats::CM4::CM4);
+        let moments = run_fold_iter(&cm4, std::iter::once(xs.clone()));
+        assert_eq!(moments, run_fold_iter(&copied, std::iter::once(xs)));
+        assert_eq!(moments.mean(), 2.0);
+    }
+
+    #[test]
+    fn float_min_max() {
+        let arrs = vec![Float64Array::from(vec![Some(1.5), None, Some(-0.5)])];
+        assert_eq!(run_fold_iter(&Min::MIN, arrs.iter().cloned()), Some(-0.5));
+        assert_eq!(run_fold_iter(&Max::MAX, arrs.into_iter()), Some(1.5));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn record_batches() {
+        use ::arrow::array::Int32Array;
+        use ::arrow::datatypes::Float64Type;
+        use std::sync::Arc;
+
+        let batch = RecordBatch::try_from_iter([
+            ("a", Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef),
+            (
+                "b",
+                Arc::new(Float64Array::from(vec![Some(0.5), None, Some(2.0)])),
+            ),
+        ])
+        .unwrap();
+        let spec = BatchSpec::new()
+            .primitive("a", Sum::<Float64Type>::SUM)
+            .primitive("b", Sum::<Float64Type>::SUM);
+        let batches = futures::stream::iter(vec![batch.clone(), batch.clone()]);
+        let out = run_fold_record_batches(&spec, batches).await.unwrap();
+        assert_eq!(out["a"], 12.0);
+        assert_eq!(out["b"], 5.0);
+
+        let spec = BatchSpec::new().primitive("c", Sum::<Float64Type>::SUM);
+        let batches = futures::stream::iter(vec![batch]);
+        assert!(run_fold_record_batches(&spec, batches).await.is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Folds whose steps can await, for aggregations which need async
+//! work for each value, such as enriching it with a remote lookup
+use crate::fold::*;
+
+use std::cell::RefCell;
+use std::future::{self, Future};
+use std::marker::PhantomData;
+
+use futures::{Stream, StreamExt};
+
+/// Like `Fold1`, but `init` and `step` return futures
+pub trait AsyncFold1 {
+    /// Input type
+    type A;
+    /// Output type
+    type B;
+    /// Intermediate state type
+    type M;
+
+    fn init(&self, x: Self::A) -> impl Future<Output = Self::M>;
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) -> impl Future<Output = ()>;
+
+    fn output(&self, acc: Self::M) -> Self::B;
+
+    /// Await `func` on each input before passing it to this fold
+    fn pre_map_async<A2, Func, Fut>(self, func: Func) -> PreMapAsync<Self, A2, Func>
+    where
+        Self: Sized,
+        Func: Fn(A2) -> Fut,
+        Fut: Future<Output = Self::A>,
+    {
+        PreMapAsync {
+            inner: self,
+            func,
+            ghost: PhantomData,
+        }
+    }
+}
+
+/// Like `Fold`, an async fold with an initial state
+pub trait AsyncFold: AsyncFold1 {
+    fn empty(&self) -> Self::M;
+}
+
+/// Like `FoldPar`, an async fold whose states can be merged
+pub trait AsyncFoldPar: AsyncFold1 {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M);
+}
+
+/// A fold lifted into an async fold, see `lift`
+#[derive(Copy, Clone)]
+pub struct Lift<F> {
+    inner: F,
+}
+
+/// Lift a fold into an async fold whose steps complete immediately,
+/// so that it can be used with `pre_map_async`
+pub fn lift<F: Fold1>(fold: F) -> Lift<F> {
+    Lift { inner: fold }
+}
+
+impl<F: Fold1> AsyncFold1 for Lift<F> {
+    type A = F::A;
+    type B = F::B;
+    type M = F::M;
+
+    fn init(&self, x: Self::A) -> impl Future<Output = Self::M> {
+        future::ready(self.inner.init(x))
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) -> impl Future<Output = ()> {
+        self.inner.step(x, acc);
+        future::ready(())
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<F: Fold> AsyncFold for Lift<F> {
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<F: FoldPar> AsyncFoldPar for Lift<F> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// See `AsyncFold1::pre_map_async`
+#[derive(Copy, Clone)]
+pub struct PreMapAsync<F, A2, Func> {
+    inner: F,
+    func: Func,
+    ghost: PhantomData<A2>,
+}
+
+impl<F, A2, Func, Fut> AsyncFold1 for PreMapAsync<F, A2, Func>
+where
+    F: AsyncFold1,
+    Func: Fn(A2) -> Fut,
+    Fut: Future<Output = F::A>,
+{
+    type A = A2;
+    type B = F::B;
+    type M = F::M;
+
+    async fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init((self.func)(x).await).await
+    }
+
+    async fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step((self.func)(x).await, acc).await
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<F, A2, Func, Fut> AsyncFold for PreMapAsync<F, A2, Func>
+where
+    F: AsyncFold,
+    Func: Fn(A2) -> Fut,
+    Fut: Future<Output = F::A>,
+{
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<F, A2, Func, Fut> AsyncFoldPar for PreMapAsync<F, A2, Func>
+where
+    F: AsyncFoldPar,
+    Func: Fn(A2) -> Fut,
+    Fut: Future<Output = F::A>,
+{
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Run an async fold over a stream, with up to `concurrency` steps
+/// in flight at once. Each step in flight has its own partial state,
+/// and these are merged at the end, so as with the parallel runners
+/// values are not folded in the order they arrive.
+pub async fn run_async_fold_stream<F>(
+    fold: &F,
+    xs: impl Stream<Item = F::A>,
+    concurrency: usize,
+) -> F::B
+where
+    F: AsyncFold + AsyncFoldPar,
+{
+    assert!(
+        concurrency > 0,
+        "run_async_fold_stream: concurrency must be positive"
+    );
+    // partial states not currently being stepped
+    let idle = RefCell::new(Vec::new());
+    xs.map(|x| {
+        let mut acc = idle.borrow_mut().pop().unwrap_or_else(|| fold.empty());
+        async move {
+            fold.step(x, &mut acc).await;
+            acc
+        }
+    })
+    .buffer_unordered(concurrency)
+    .for_each(|acc| {
+        idle.borrow_mut().push(acc);
+        future::ready(())
+    })
+    .await;
+
+    let mut acc = fold.empty();
+    for m in idle.into_inner() {
+        fold.merge(&mut acc, m);
+    }
+    fold.output(acc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn bounded_lookups() {
+        let in_flight = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        let lookup = |x: u64| {
+            let (in_flight, most) = (&in_flight, &most);
+            async move {
+                let n = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(n, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(x % 3)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                x * 10
+            }
+        };
+        let fld = lift(Sum::<u64>::SUM).pre_map_async(lookup);
+        let out = run_async_fold_stream(&fld, futures::stream::iter(0..100), 4).await;
+        assert_eq!(out, 49500);
+        assert!(most.load(Ordering::SeqCst) <= 4);
+
+        let mut acc = fld.init(1).await;
+        fld.step(2, &mut acc).await;
+        assert_eq!(fld.output(acc), 30);
+    }
+}
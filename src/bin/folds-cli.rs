@@ -0,0 +1,99 @@
+//! Aggregate a csv or parquet file from the command line, e.g.
+//! `folds-cli trips.parquet --agg "mean(fare), p99(fare) group by passenger_count"`
+use folds::cli::Query;
+use folds::csv::CsvOptions;
+use folds::parquet::ParOptions;
+
+use std::process::exit;
+
+const USAGE: &str = "usage: folds-cli FILE --agg QUERY [--threads N] [--delimiter C]
+
+QUERY is a comma separated list of aggregations of columns,
+optionally followed by `group by COLUMN`, e.g.
+    mean(fare), p99(fare) group by passenger_count
+The aggregations are count, sum, mean, std, min, max, median,
+and percentiles such as p50, p99 or p999.
+Files ending in .parquet are read as parquet, others as csv with a header.";
+
+struct Args {
+    path: String,
+    query: Query,
+    threads: usize,
+    delimiter: u8,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut path = None;
+    let mut query = None;
+    let mut threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut delimiter = b',';
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--agg" => query = Some(value()?.parse::<Query>().map_err(|e| e.to_string())?),
+            "--threads" => {
+                threads = value()?
+                    .parse()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or("--threads must be a positive number")?
+            }
+            "--delimiter" => match value()?.as_bytes() {
+                [c] => delimiter = *c,
+                _ => return Err("--delimiter must be a single character".to_owned()),
+            },
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                exit(0)
+            }
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+    Ok(Args {
+        path: path.ok_or("no file given")?,
+        query: query.ok_or("no --agg given")?,
+        threads,
+        delimiter,
+    })
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("{e}\n\n{USAGE}");
+        exit(2)
+    });
+
+    let table = if args.path.ends_with(".parquet") {
+        let options = ParOptions {
+            threads: args.threads,
+            ..ParOptions::default()
+        };
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(args.threads)
+            .build()
+            .expect("failed to start the runtime")
+            .block_on(args.query.run_parquet(&args.path, options))
+    } else {
+        let options = CsvOptions {
+            delimiter: args.delimiter,
+            ..CsvOptions::default()
+        };
+        std::fs::File::open(&args.path)
+            .map_err(|e| ::csv::Error::from(e).into())
+            .and_then(|file| {
+                args.query
+                    .run_csv(std::io::BufReader::new(file), options, args.threads)
+            })
+    };
+
+    match table {
+        Ok(table) => print!("{table}"),
+        Err(e) => {
+            eprintln!("{}: {e}", args.path);
+            exit(1)
+        }
+    }
+}
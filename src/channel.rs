@@ -0,0 +1,358 @@
+//! Runners for producer/consumer architectures
+use crate::fold::*;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex, MutexGuard};
+use std::thread;
+
+/// Fold over everything sent on a channel, until every sender is dropped.
+/// For crossbeam channels use `run_fold_iter` with `rx.iter()`.
+pub fn run_fold_channel<F: Fold>(rx: mpsc::Receiver<F::A>, fold: &F) -> F::B {
+    run_fold_iter(fold, rx.into_iter())
+}
+
+/// Fold over everything sent on a tokio channel, until every sender is dropped
+#[cfg(feature = "async")]
+pub async fn run_fold_tokio_channel<F: Fold>(
+    mut rx: tokio::sync::mpsc::Receiver<F::A>,
+    fold: &F,
+) -> F::B {
+    let mut acc = fold.empty();
+    while let Some(x) = rx.recv().await {
+        fold.step(x, &mut acc);
+    }
+    fold.output(acc)
+}
+
+/// Handle given to each producer by `run_fold_producers`
+pub struct Producer<'f, F: Fold> {
+    fold: &'f F,
+    acc: F::M,
+}
+
+impl<F: Fold> Producer<'_, F> {
+    /// Fold a value into this producer's partial state
+    pub fn send(&mut self, x: F::A) {
+        self.fold.step(x, &mut self.acc)
+    }
+}
+
+/// Run each producer on its own thread, where it folds values into
+/// its own partial state rather than sending them over a channel.
+/// The partial states are merged as a tree once every producer has
+/// finished.
+/// Panics in a producer are propagated.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn run_fold_producers<F, P>(fold: &F, producers: impl IntoIterator<Item = P>) -> F::B
+where
+    F: Fold + FoldPar + Sync,
+    F::M: Send,
+    P: FnOnce(&mut Producer<'_, F>) + Send,
+{
+    thread::scope(|s| {
+        let handles: Vec<_> = producers
+            .into_iter()
+            .map(|producer| {
+                s.spawn(move || {
+                    let mut handle = Producer {
+                        fold,
+                        acc: fold.empty(),
+                    };
+                    producer(&mut handle);
+                    handle.acc
+                })
+            })
+            .collect();
+
+        let states: Vec<_> = handles
+            .into_iter()
+            .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect();
+        fold.output(merge_tree(fold, states).unwrap_or_else(|| fold.empty()))
+    })
+}
+
+/// Number of values sent to a worker at a time by `run_fold_par_threads`
+#[cfg(feature = "threads")]
+const THREAD_CHUNK: usize = 1024;
+
+/// Fold over an iterator on `n_threads` scoped worker threads.
+/// The iterator is read on the calling thread and sent in chunks over
+/// a crossbeam channel, which whichever worker is free picks up, so
+/// values are not folded in order. Panics in a worker are propagated.
+#[cfg(feature = "threads")]
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+pub fn run_fold_par_threads<F>(
+    xs: impl IntoIterator<Item = F::A>,
+    fold: &F,
+    n_threads: usize,
+) -> F::B
+where
+    F: Fold + FoldPar + Sync,
+    F::A: Send,
+    F::M: Send,
+{
+    assert!(
+        n_threads > 0,
+        "run_fold_par_threads: n_threads must be positive"
+    );
+    // a couple of chunks per worker keeps them busy without buffering much
+    let (tx, rx) = crossbeam_channel::bounded::<Vec<F::A>>(2 * n_threads);
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..n_threads)
+            .map(|_| {
+                let rx = rx.clone();
+                s.spawn(move || {
+                    let mut acc = fold.empty();
+                    for chunk in rx {
+                        fold.step_chunk(chunk, &mut acc);
+                    }
+                    acc
+                })
+            })
+            .collect();
+        drop(rx);
+
+        let mut xs = xs.into_iter();
+        loop {
+            let chunk: Vec<_> = xs.by_ref().take(THREAD_CHUNK).collect();
+            // the workers only hang up early when one has panicked,
+            // which is propagated when it is joined below
+            if chunk.is_empty() || tx.send(chunk).is_err() {
+                break;
+            }
+        }
+        drop(tx);
+
+        let states: Vec<_> = handles
+            .into_iter()
+            .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect();
+        fold.output(merge_tree(fold, states).unwrap_or_else(|| fold.empty()))
+    })
+}
+
+/// A fold which many threads can step at once, such as the metrics of
+/// a server fed by each of its request handlers.
+/// The state is split into shards, each behind a lock, and each thread
+/// steps its own shard so that threads rarely wait on each other.
+/// The shards are merged whenever the output is read.
+pub struct SharedFold<F: Fold> {
+    fold: F,
+    shards: Box<[Mutex<F::M>]>,
+}
+
+/// Threads are given shards in turn, the first time they step any
+/// `SharedFold`
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+impl<F: Fold + FoldPar> SharedFold<F> {
+    /// Share `fold` with a shard for each core
+    pub fn new(fold: F) -> Self {
+        let shards = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_shards(fold, shards)
+    }
+
+    /// Share `fold` with `shards` shards, fewer than the number of
+    /// threads stepping it means they will sometimes wait
+    pub fn with_shards(fold: F, shards: usize) -> Self {
+        assert!(shards > 0, "SharedFold: shards must be positive");
+        let shards = (0..shards).map(|_| Mutex::new(fold.empty())).collect();
+        SharedFold { fold, shards }
+    }
+
+    fn lock(shard: &Mutex<F::M>) -> MutexGuard<'_, F::M> {
+        shard
+            .lock()
+            .expect("a thread panicked while stepping a SharedFold")
+    }
+
+    /// Fold a value into the shard of this thread
+    pub fn step(&self, x: F::A) {
+        let i = SHARD.with(|i| *i) % self.shards.len();
+        self.fold.step(x, &mut Self::lock(&self.shards[i]))
+    }
+
+    /// Fold a chunk of values into the shard of this thread, taking its
+    /// lock once
+    pub fn step_chunk(&self, xs: Vec<F::A>) {
+        let i = SHARD.with(|i| *i) % self.shards.len();
+        self.fold.step_chunk(xs, &mut Self::lock(&self.shards[i]))
+    }
+
+    /// Output of everything stepped so far. Every shard is locked at
+    /// once while their states are copied, so a value stepped on
+    /// another thread is either wholly in the output or not at all.
+    pub fn snapshot(&self) -> F::B
+    where
+        F::M: Clone,
+    {
+        let guards: Vec<_> = self.shards.iter().map(Self::lock).collect();
+        let states: Vec<_> = guards.iter().map(|m| F::M::clone(m)).collect();
+        drop(guards);
+        Self::merge_output(&self.fold, states)
+    }
+
+    /// Output of everything stepped so far, starting over from empty
+    /// shards, such as to report the metrics of each interval
+    pub fn take(&self) -> F::B {
+        let mut guards: Vec<_> = self.shards.iter().map(Self::lock).collect();
+        let states: Vec<_> = guards
+            .iter_mut()
+            .map(|m| std::mem::replace(&mut **m, self.fold.empty()))
+            .collect();
+        drop(guards);
+        Self::merge_output(&self.fold, states)
+    }
+
+    /// Output of everything stepped, once no thread is stepping
+    pub fn into_output(self) -> F::B {
+        let states: Vec<_> = self
+            .shards
+            .into_vec()
+            .into_iter()
+            .map(|m| {
+                m.into_inner()
+                    .expect("a thread panicked while stepping a SharedFold")
+            })
+            .collect();
+        Self::merge_output(&self.fold, states)
+    }
+
+    fn merge_output(fold: &F, states: Vec<F::M>) -> F::B {
+        let mut states = states.into_iter();
+        let mut acc = states.next().unwrap_or_else(|| fold.empty());
+        for m in states {
+            fold.merge(&mut acc, m);
+        }
+        fold.output(acc)
+    }
+}
+
+/// A state on a cache line of its own, so that threads stepping
+/// neighbouring shards do not contend for the line
+#[repr(align(128))]
+struct Padded<T>(T);
+
+/// A fold which many threads can step at once without locks, for hot
+/// counters and sums. Like `SharedFold` the state is split into
+/// shards which each thread steps its own of, but the shards are
+/// atomics rather than behind locks.
+/// Unlike `SharedFold`, reading the output does not stop the other
+/// threads, so a value stepped while reading may be left out.
+pub struct ShardedFold<F: AtomicFold> {
+    fold: F,
+    shards: Box<[Padded<F::Atomic>]>,
+}
+
+impl<F: AtomicFold> ShardedFold<F> {
+    /// Share `fold` with a shard for each core
+    pub fn new(fold: F) -> Self {
+        let shards = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_shards(fold, shards)
+    }
+
+    /// Share `fold` with `shards` shards
+    pub fn with_shards(fold: F, shards: usize) -> Self {
+        assert!(shards > 0, "ShardedFold: shards must be positive");
+        let shards = (0..shards).map(|_| Padded(fold.empty_atomic())).collect();
+        ShardedFold { fold, shards }
+    }
+
+    /// Fold a value into the shard of this thread
+    #[inline]
+    pub fn step(&self, x: F::A) {
+        let i = SHARD.with(|i| *i) % self.shards.len();
+        self.fold.step_atomic(x, &self.shards[i].0)
+    }
+
+    /// Output of everything stepped so far
+    pub fn snapshot(&self) -> F::B {
+        let states = self.shards.iter().map(|m| self.fold.load(&m.0)).collect();
+        SharedFold::merge_output(&self.fold, states)
+    }
+
+    /// Output of everything stepped so far, starting over from empty
+    /// shards
+    pub fn take(&self) -> F::B {
+        let states = self.shards.iter().map(|m| self.fold.take(&m.0)).collect();
+        SharedFold::merge_output(&self.fold, states)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    #[test]
+    fn std_channel() {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || (0..100).for_each(|i| tx.send(i).unwrap()));
+        assert_eq!(run_fold_channel(rx, &Sum::<i64>::SUM), 4950);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn tokio_channel() {
+        let (tx, rx) = tokio::sync::mpsc::channel(8);
+        tokio::spawn(async move {
+            for i in 0..100 {
+                tx.send(i).await.unwrap();
+            }
+        });
+        assert_eq!(run_fold_tokio_channel(rx, &Count::COUNT).await, 100);
+    }
+
+    #[test]
+    fn producers() {
+        let producers = (0..4)
+            .map(|p| move |tx: &mut Producer<'_, _>| (0..100).for_each(|i| tx.send(p * 100 + i)));
+        assert_eq!(run_fold_producers(&Sum::<i64>::SUM, producers), 79800);
+    }
+
+    #[test]
+    fn shared_fold() {
+        let shared = SharedFold::with_shards(Sum::<i64>::SUM.par(Count::COUNT), 2);
+        thread::scope(|s| {
+            for p in 0..4 {
+                let shared = &shared;
+                s.spawn(move || (0..100).for_each(|i| shared.step(p * 100 + i)));
+            }
+            let (sum, count) = shared.snapshot();
+            assert!(count <= 400 && sum <= 79800);
+        });
+        assert_eq!(shared.snapshot(), (79800, 400));
+
+        assert_eq!(shared.take(), (79800, 400));
+        shared.step_chunk(vec![1, 2, 3]);
+        assert_eq!(shared.into_output(), (6, 3));
+    }
+
+    #[test]
+    fn sharded_fold() {
+        let sharded = ShardedFold::with_shards(Sum::<i64>::SUM.par(Count::COUNT), 3);
+        thread::scope(|s| {
+            for p in 0..4 {
+                let sharded = &sharded;
+                s.spawn(move || (0..100).for_each(|i| sharded.step(p * 100 + i)));
+            }
+        });
+        assert_eq!(sharded.snapshot(), (79800, 400));
+        assert_eq!(sharded.take(), (79800, 400));
+        assert_eq!(sharded.snapshot(), (0, 0));
+    }
+
+    #[cfg(feature = "threads")]
+    #[test]
+    fn par_threads() {
+        let fld = Sum::<i64>::SUM.par(Count::COUNT);
+        assert_eq!(run_fold_par_threads(0..10_000, &fld, 4), (49995000, 10_000));
+        assert_eq!(run_fold_par_threads(0..0, &fld, 3), (0, 0));
+        assert_eq!(run_fold_par_threads(0..10, &fld, 1), (45, 10));
+    }
+}
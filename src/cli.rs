@@ -0,0 +1,371 @@
+//! Aggregations of csv and parquet files described by a small query
+//! language, as run by the `folds-cli` binary, e.g.
+//! `mean(fare), p99(fare) group by passenger_count`
+use crate::fold::*;
+use crate::profile::{numeric, Numeric, NumericProfile};
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use ::arrow::array::{Array, AsArray, RecordBatch};
+use ::arrow::compute::cast;
+use ::arrow::datatypes::{DataType, Float64Type};
+use ::arrow::util::display::array_value_to_string;
+use ::parquet::errors::ParquetError;
+use rustc_hash::FxHashMap;
+
+use crate::csv::CsvOptions;
+use crate::parquet::{aggregate_file, ParOptions};
+
+/// Why an aggregation failed
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("invalid query: {0}")]
+    Query(String),
+    #[error("no column named {0}")]
+    MissingColumn(String),
+    #[error(transparent)]
+    Csv(#[from] ::csv::Error),
+    #[error(transparent)]
+    Parquet(#[from] ParquetError),
+}
+
+/// Aggregation of a column
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Func {
+    Count,
+    Sum,
+    Mean,
+    StdDev,
+    Min,
+    Max,
+    /// Approximate quantile, from a t-digest
+    Quantile(f64),
+}
+
+impl FromStr for Func {
+    type Err = Error;
+
+    /// Quantiles are written as percentiles, `p99`, `p100`, or `p999`
+    /// for the 99.9th, or as `median`
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let func = match s {
+            "count" => Func::Count,
+            "sum" => Func::Sum,
+            "mean" | "avg" => Func::Mean,
+            "std" | "stddev" => Func::StdDev,
+            "min" => Func::Min,
+            "max" => Func::Max,
+            "median" => Func::Quantile(0.5),
+            _ => {
+                let digits = s
+                    .strip_prefix('p')
+                    .filter(|d| !d.is_empty() && d.bytes().all(|b| b.is_ascii_digit()))
+                    .ok_or_else(|| Error::Query(format!("unknown aggregation {s}")))?;
+                // digits after the whole percent are decimals
+                let whole = if digits.starts_with("100") { 3 } else { 2 };
+                let decimals = digits.len().saturating_sub(whole);
+                let value: f64 = digits
+                    .parse()
+                    .map_err(|_| Error::Query(format!("invalid percentile {s}")))?;
+                let q = value / 10f64.powi(decimals as i32 + 2);
+                if q > 1.0 {
+                    return Err(Error::Query(format!("percentile over 100 in {s}")));
+                }
+                Func::Quantile(q)
+            }
+        };
+        Ok(func)
+    }
+}
+
+/// An aggregation of one column, such as `p99(fare)`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Agg {
+    pub func: Func,
+    pub column: String,
+    /// As written in the query, for the header of the output
+    pub name: String,
+}
+
+impl Agg {
+    fn of(&self, profile: &NumericProfile) -> f64 {
+        if profile.count == 0 {
+            return match self.func {
+                Func::Count | Func::Sum => 0.0,
+                _ => f64::NAN,
+            };
+        }
+        match self.func {
+            Func::Count => profile.count as f64,
+            Func::Sum => profile.sum,
+            Func::Mean => profile.mean,
+            Func::StdDev => profile.std_dev,
+            Func::Min => profile.min,
+            Func::Max => profile.max,
+            Func::Quantile(q) => profile.quantiles.quantile(q),
+        }
+    }
+}
+
+impl FromStr for Agg {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let s = s.trim();
+        let (func, rest) = s
+            .split_once('(')
+            .ok_or_else(|| Error::Query(format!("expected func(column), found {s}")))?;
+        let column = rest
+            .strip_suffix(')')
+            .ok_or_else(|| Error::Query(format!("unclosed parenthesis in {s}")))?
+            .trim();
+        if column.is_empty() {
+            return Err(Error::Query(format!("no column in {s}")));
+        }
+        Ok(Agg {
+            func: func.trim().to_lowercase().parse()?,
+            column: column.to_owned(),
+            name: s.to_owned(),
+        })
+    }
+}
+
+/// Aggregations of numeric columns, optionally grouped by a column
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    pub aggs: Vec<Agg>,
+    pub group_by: Option<String>,
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let lower = s.to_ascii_lowercase();
+        let (aggs, group_by) = match lower.find(" group by ") {
+            Some(i) => (&s[..i], Some(s[i + " group by ".len()..].trim())),
+            None => (s, None),
+        };
+        if group_by == Some("") {
+            return Err(Error::Query("no column to group by".to_owned()));
+        }
+        let aggs = aggs
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<Agg>, _>>()?;
+        Ok(Query {
+            aggs,
+            group_by: group_by.map(str::to_owned),
+        })
+    }
+}
+
+/// A row to aggregate: its group, or the empty string when the query
+/// is not grouped, and the values of the aggregated columns
+type Row = (String, Vec<Option<f64>>);
+
+type RowsFold =
+    GroupedFold<PreMap<Many<Numeric>, Row, fn(Row) -> Vec<Option<f64>>>, fn(&Row) -> String>;
+
+impl Query {
+    /// Columns which are aggregated, each once, in order of first use
+    pub fn columns(&self) -> Vec<&str> {
+        let mut columns: Vec<&str> = Vec::new();
+        for agg in &self.aggs {
+            if !columns.contains(&agg.column.as_str()) {
+                columns.push(&agg.column)
+            }
+        }
+        columns
+    }
+
+    /// Profile every aggregated column at once, computing all the
+    /// aggregations of a column from its profile
+    fn fold(&self) -> RowsFold {
+        numeric()
+            .many(self.columns().len())
+            .pre_map((|(_, values)| values) as fn(Row) -> Vec<Option<f64>>)
+            .group_by((|(key, _)| key.clone()) as fn(&Row) -> String)
+    }
+
+    fn table(&self, groups: FxHashMap<String, Vec<NumericProfile>>) -> Table {
+        let columns = self.columns();
+        let mut header: Vec<String> = self.group_by.iter().cloned().collect();
+        header.extend(self.aggs.iter().map(|agg| agg.name.clone()));
+
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|(k1, _), (k2, _)| k1.cmp(k2));
+        let rows = groups
+            .into_iter()
+            .map(|(key, profiles)| {
+                let mut row: Vec<String> = self.group_by.iter().map(|_| key.clone()).collect();
+                row.extend(self.aggs.iter().map(|agg| {
+                    let i = columns.iter().position(|c| *c == agg.column).unwrap();
+                    agg.of(&profiles[i]).to_string()
+                }));
+                row
+            })
+            .collect();
+        Table { header, rows }
+    }
+
+    /// Aggregate the records of a csv file with a header, on
+    /// `threads` worker threads. Values which are not numbers are
+    /// counted as missing.
+    pub fn run_csv(
+        &self,
+        reader: impl io::Read,
+        options: CsvOptions,
+        threads: usize,
+    ) -> Result<Table, Error> {
+        let mut reader = ::csv::ReaderBuilder::new()
+            .has_headers(true)
+            .delimiter(options.delimiter)
+            .from_reader(reader);
+        let headers = reader.headers()?.clone();
+        let index = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| Error::MissingColumn(name.to_owned()))
+        };
+        let key = self.group_by.as_deref().map(index).transpose()?;
+        let values = self
+            .columns()
+            .into_iter()
+            .map(index)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // the records are read on this thread, so the first error can
+        // be kept aside and returned once the run has stopped
+        let mut error = None;
+        let rows = reader.into_records().map_while(|record| {
+            let record = record.map_err(|e| error = Some(e)).ok()?;
+            let key = key.map_or_else(String::new, |i| record[i].to_owned());
+            let values = values
+                .iter()
+                .map(|i| record[*i].trim().parse().ok())
+                .collect();
+            Some((key, values))
+        });
+        let groups = crate::channel::run_fold_par_threads(rows, &self.fold(), threads);
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(self.table(groups)),
+        }
+    }
+
+    /// Aggregate a parquet file, folding its record batches in parallel.
+    /// Aggregated columns are cast to floats.
+    pub async fn run_parquet(
+        &self,
+        path: impl AsRef<Path>,
+        options: ParOptions,
+    ) -> Result<Table, Error> {
+        let columns: Vec<String> = self.columns().into_iter().map(str::to_owned).collect();
+        let mut projection: Vec<&str> = self.group_by.as_deref().into_iter().collect();
+        projection.extend(self.columns());
+
+        let group_by = self.group_by.clone();
+        let fld = self
+            .fold()
+            .batched()
+            .pre_map(move |batch: RecordBatch| batch_rows(&batch, group_by.as_deref(), &columns));
+        let groups = aggregate_file(path, &projection, &fld, options).await?;
+        Ok(self.table(groups))
+    }
+}
+
+/// The rows of a batch, which has every column as `aggregate_file`
+/// checked them against the schema of the file
+fn batch_rows(batch: &RecordBatch, group_by: Option<&str>, columns: &[String]) -> Vec<Row> {
+    let column = |name: &str| batch.column_by_name(name).expect("projected column");
+    let keys = group_by.map(column);
+    let values: Vec<_> = columns
+        .iter()
+        .map(|name| cast(column(name), &DataType::Float64).ok())
+        .collect();
+    (0..batch.num_rows())
+        .map(|i| {
+            let key = keys.map_or_else(String::new, |keys| match keys.is_null(i) {
+                true => String::new(),
+                false => array_value_to_string(keys, i).unwrap_or_default(),
+            });
+            let values = values
+                .iter()
+                .map(|col| {
+                    let col = col.as_ref()?.as_primitive::<Float64Type>();
+                    col.is_valid(i).then(|| col.value(i))
+                })
+                .collect();
+            (key, values)
+        })
+        .collect()
+}
+
+/// Output of a query, with a row for each group in order of their keys
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+/// Tab separated, with a header
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.header.join("\t"))?;
+        for row in &self.rows {
+            writeln!(f, "{}", row.join("\t"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_query() {
+        let query: Query = "mean(fare), p99(fare), P999(tip) group by passenger_count"
+            .parse()
+            .unwrap();
+        assert_eq!(query.group_by.as_deref(), Some("passenger_count"));
+        let funcs: Vec<_> = query.aggs.iter().map(|agg| agg.func).collect();
+        assert_eq!(
+            funcs,
+            vec![Func::Mean, Func::Quantile(0.99), Func::Quantile(0.999)]
+        );
+        assert_eq!(query.columns(), vec!["fare", "tip"]);
+
+        assert!("mean fare".parse::<Query>().is_err());
+        assert!("mode(fare)".parse::<Query>().is_err());
+        assert_eq!("p100".parse::<Func>().unwrap(), Func::Quantile(1.0));
+        assert_eq!("p5".parse::<Func>().unwrap(), Func::Quantile(0.05));
+        assert!("p1001".parse::<Func>().is_err());
+        assert!("sum(fare) group by ".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn aggregate_csv() {
+        let data = "passengers,fare\n1,10\n2,20\n1,30\n2,\n1,x\n";
+        let query: Query = "count(fare), sum(fare), max(fare) group by passengers"
+            .parse()
+            .unwrap();
+        let table = query
+            .run_csv(data.as_bytes(), CsvOptions::default(), 2)
+            .unwrap();
+        assert_eq!(
+            table.to_string(),
+            "passengers\tcount(fare)\tsum(fare)\tmax(fare)\n1\t2\t40\t30\n2\t1\t20\t20\n"
+        );
+
+        let query: Query = "mean(tip)".parse().unwrap();
+        let missing = query.run_csv(data.as_bytes(), CsvOptions::default(), 2);
+        assert!(matches!(missing, Err(Error::MissingColumn(_))));
+    }
+}
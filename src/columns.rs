@@ -0,0 +1,136 @@
+use crate::fold::*;
+
+use std::any::Any;
+
+use rustc_hash::FxHashMap;
+
+/// A different fold for each named column of a wide stream.
+/// This is like `Many`, except each column gets its own (boxed) fold,
+/// and the output is keyed by column name.
+#[derive(Clone)]
+pub struct Columns<A, B> {
+    cols: Vec<(String, BoxedFold<A, B>)>,
+}
+
+impl<A, B> Columns<A, B> {
+    pub fn new() -> Self {
+        Columns { cols: Vec::new() }
+    }
+
+    /// Add a column, inputs must list columns in the order they were added
+    pub fn column(mut self, name: impl Into<String>, fold: BoxedFold<A, B>) -> Self {
+        self.cols.push((name.into(), fold));
+        self
+    }
+
+    /// Names of the columns, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.cols.iter().map(|(name, _)| name.as_str())
+    }
+
+    fn check_width(&self, width: usize) {
+        if width != self.cols.len() {
+            panic!("Columns: expected width {}, got {}", self.cols.len(), width)
+        }
+    }
+}
+
+impl<A, B> Default for Columns<A, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A, B> Fold1 for Columns<A, B> {
+    type A = Vec<A>;
+    type B = FxHashMap<String, B>;
+    type M = Vec<Box<dyn Any + Send>>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        self.check_width(x.len());
+        self.cols
+            .iter()
+            .zip(x)
+            .map(|((_, fold), x)| fold.init(x))
+            .collect()
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.check_width(x.len());
+        for (((_, fold), m), x) in self.cols.iter().zip(acc.iter_mut()).zip(x) {
+            fold.step(x, m)
+        }
+    }
+
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        // transpose rows into one chunk per column
+        let mut cols: Vec<Vec<A>> = self
+            .cols
+            .iter()
+            .map(|_| Vec::with_capacity(xs.len()))
+            .collect();
+        for row in xs {
+            self.check_width(row.len());
+            for (col, x) in cols.iter_mut().zip(row) {
+                col.push(x)
+            }
+        }
+        for (((_, fold), m), col) in self.cols.iter().zip(acc.iter_mut()).zip(cols) {
+            fold.step_chunk(col, m)
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.cols
+            .iter()
+            .zip(acc)
+            .map(|((name, fold), m)| (name.clone(), fold.output(m)))
+            .collect()
+    }
+}
+
+impl<A, B> Fold for Columns<A, B> {
+    fn empty(&self) -> Self::M {
+        self.cols.iter().map(|(_, fold)| fold.empty()).collect()
+    }
+}
+
+impl<A, B> FoldPar for Columns<A, B> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (((_, fold), m1), m2) in self.cols.iter().zip(m1.iter_mut()).zip(m2) {
+            fold.merge(m1, m2)
+        }
+    }
+}
+
+/// Each column is a child, named after it
+impl<A, B> FoldDescribe for Columns<A, B> {
+    fn describe_structure(&self) -> Structure {
+        let children = self
+            .cols
+            .iter()
+            .map(|(name, fold)| Structure {
+                name: format!("column {}", name),
+                ..fold.describe_structure()
+            })
+            .collect();
+        Structure::new::<Self>("Columns", children)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    #[test]
+    fn named_columns() {
+        let fld = Columns::new()
+            .column("total", Sum::<i64>::SUM.boxed())
+            .column("biggest", Max::MAX.or_default().boxed());
+        let rows = vec![vec![1, 5], vec![2, 7], vec![3, 4]];
+        let out = run_fold_iter(&fld, rows.into_iter());
+        assert_eq!(out["total"], 6);
+        assert_eq!(out["biggest"], 7);
+    }
+}
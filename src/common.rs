@@ -1,79 +1,102 @@
 use crate::fold::*;
 
-use std::marker::PhantomData;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::sync::atomic::Ordering::Relaxed;
 
 #[derive(Copy, Clone)]
 pub struct Sum<A> {
     ghost: PhantomData<A>,
 }
 
-impl<A: std::ops::AddAssign<A> + From<u8>> Sum<A> {
+impl<A: core::ops::AddAssign<A> + From<u8>> Sum<A> {
     pub const SUM: Self = Sum { ghost: PhantomData };
 }
 
-impl<A: std::ops::AddAssign> Fold1 for Sum<A>
+impl<A: core::ops::AddAssign> Fold1 for Sum<A>
 where
-    A: for<'a> std::iter::Sum<&'a A>,
+    A: for<'a> core::iter::Sum<&'a A>,
 {
     type A = A;
     type B = A;
     type M = A;
 
+    #[inline]
     fn init(&self, x: Self::A) -> Self::M {
         x
     }
 
+    #[inline]
     fn step(&self, x: Self::A, acc: &mut Self::M) {
         *acc += x
     }
 
+    #[inline]
     fn output(&self, acc: Self::M) -> Self::B {
         acc
     }
 
+    #[inline]
     fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
         *acc += xs.iter().sum();
     }
 }
 
-impl<A: std::ops::AddAssign + From<u8>> Fold for Sum<A>
+impl<A: core::ops::AddAssign + From<u8>> Fold for Sum<A>
 where
-    A: for<'a> std::iter::Sum<&'a A>,
+    A: for<'a> core::iter::Sum<&'a A>,
 {
+    #[inline]
     fn empty(&self) -> Self::M {
         From::from(0)
     }
 }
 
-impl<A: std::ops::AddAssign + From<u8>> FoldPar for Sum<A>
+impl<A: core::ops::AddAssign + From<u8>> FoldPar for Sum<A>
 where
-    A: for<'a> std::iter::Sum<&'a A>,
+    A: for<'a> core::iter::Sum<&'a A>,
 {
+    #[inline]
     fn merge(&self, m1: &mut Self::M, m2: Self::M) {
         *m1 += m2
     }
 }
 
+/// The delta is the new sum
+impl<A: core::ops::AddAssign + Clone> Incremental for Sum<A>
+where
+    A: for<'a> core::iter::Sum<&'a A>,
+{
+    type Delta = A;
+
+    fn step_delta(&self, x: Self::A, acc: &mut Self::M) -> Option<Self::Delta> {
+        self.step(x, acc);
+        Some(acc.clone())
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Max<A> {
     ghost: PhantomData<A>,
 }
 
-impl<A: std::cmp::Ord> Max<A> {
+impl<A: core::cmp::Ord> Max<A> {
     pub const MAX: Self = Max { ghost: PhantomData };
 }
 
-impl<A: std::cmp::Ord> Fold1 for Max<A> {
+impl<A: core::cmp::Ord> Fold1 for Max<A> {
     type A = A;
 
     type B = A;
 
     type M = A;
 
+    #[inline]
     fn init(&self, x: A) -> Self::M {
         x
     }
 
+    #[inline]
     fn step(&self, x: A, acc: &mut A) {
         if x < *acc {
         } else {
@@ -81,12 +104,14 @@ impl<A: std::cmp::Ord> Fold1 for Max<A> {
         }
     }
 
+    #[inline]
     fn output(&self, acc: Self::M) -> Self::B {
         acc
     }
 }
 
-impl<A: std::cmp::Ord> FoldPar for Max<A> {
+impl<A: core::cmp::Ord> FoldPar for Max<A> {
+    #[inline]
     fn merge(&self, m1: &mut Self::M, m2: Self::M) {
         if *m1 > m2 {
         } else {
@@ -95,26 +120,42 @@ impl<A: std::cmp::Ord> FoldPar for Max<A> {
     }
 }
 
+/// The delta is the new maximum, only when it changed
+impl<A: core::cmp::Ord + Clone> Incremental for Max<A> {
+    type Delta = A;
+
+    fn step_delta(&self, x: Self::A, acc: &mut Self::M) -> Option<Self::Delta> {
+        if x > *acc {
+            *acc = x;
+            Some(acc.clone())
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Min<A> {
     ghost: PhantomData<A>,
 }
 
-impl<A: std::cmp::Ord> Min<A> {
+impl<A: core::cmp::Ord> Min<A> {
     pub const MIN: Self = Min { ghost: PhantomData };
 }
 
-impl<A: std::cmp::Ord> Fold1 for Min<A> {
+impl<A: core::cmp::Ord> Fold1 for Min<A> {
     type A = A;
 
     type B = A;
 
     type M = A;
 
+    #[inline]
     fn init(&self, x: A) -> Self::M {
         x
     }
 
+    #[inline]
     fn step(&self, x: A, acc: &mut A) {
         if x > *acc {
         } else {
@@ -122,12 +163,14 @@ impl<A: std::cmp::Ord> Fold1 for Min<A> {
         }
     }
 
+    #[inline]
     fn output(&self, acc: Self::M) -> Self::B {
         acc
     }
 }
 
-impl<A: std::cmp::Ord> FoldPar for Min<A> {
+impl<A: core::cmp::Ord> FoldPar for Min<A> {
+    #[inline]
     fn merge(&self, m1: &mut Self::M, m2: Self::M) {
         if *m1 < m2 {
         } else {
@@ -136,6 +179,20 @@ impl<A: std::cmp::Ord> FoldPar for Min<A> {
     }
 }
 
+/// The delta is the new minimum, only when it changed
+impl<A: core::cmp::Ord + Clone> Incremental for Min<A> {
+    type Delta = A;
+
+    fn step_delta(&self, x: Self::A, acc: &mut Self::M) -> Option<Self::Delta> {
+        if x < *acc {
+            *acc = x;
+            Some(acc.clone())
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct First<A> {
     ghost: PhantomData<A>,
@@ -150,17 +207,26 @@ impl<A> Fold1 for First<A> {
     type B = A;
     type M = A;
 
+    #[inline]
     fn init(&self, x: A) -> Self::M {
         x
     }
 
+    #[inline]
     fn step(&self, _x: A, _acc: &mut A) {}
 
+    #[inline]
     fn output(&self, acc: Self::M) -> Self::B {
         acc
     }
 }
 
+/// The first state holds the earlier values, so it is kept
+impl<A> FoldParOrdered for First<A> {
+    #[inline]
+    fn merge_at(&self, _m1: &mut Self::M, _m2: Self::M, _offset: usize) {}
+}
+
 #[derive(Copy, Clone)]
 pub struct Last<A> {
     ghost: PhantomData<A>,
@@ -175,19 +241,30 @@ impl<A> Fold1 for Last<A> {
     type B = A;
     type M = A;
 
+    #[inline]
     fn init(&self, x: A) -> Self::M {
         x
     }
 
+    #[inline]
     fn step(&self, x: A, acc: &mut A) {
         *acc = x;
     }
 
+    #[inline]
     fn output(&self, acc: Self::M) -> Self::B {
         acc
     }
 }
 
+/// The second state holds the later values, so it is kept
+impl<A> FoldParOrdered for Last<A> {
+    #[inline]
+    fn merge_at(&self, m1: &mut Self::M, m2: Self::M, _offset: usize) {
+        *m1 = m2
+    }
+}
+
 #[derive(Copy, Clone)]
 pub struct Count<A> {
     ghost: PhantomData<A>,
@@ -202,33 +279,814 @@ impl<A> Fold1 for Count<A> {
     type B = usize;
     type M = usize;
 
+    #[inline]
     fn init(&self, _x: Self::A) -> Self::M {
         1
     }
+    #[inline]
     fn step(&self, _x: Self::A, acc: &mut Self::M) {
         *acc += 1;
     }
+    #[inline]
     fn output(&self, acc: Self::M) -> Self::B {
         acc
     }
 
+    #[inline]
     fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
         *acc += xs.len();
     }
 }
 
+/// The delta is the new count
+impl<A> Incremental for Count<A> {
+    type Delta = usize;
+
+    fn step_delta(&self, _x: Self::A, acc: &mut Self::M) -> Option<Self::Delta> {
+        *acc += 1;
+        Some(*acc)
+    }
+}
+
 impl<A> Fold for Count<A> {
+    #[inline]
     fn empty(&self) -> Self::M {
         0
     }
 }
 
 impl<A> FoldPar for Count<A> {
+    #[inline]
     fn merge(&self, m1: &mut Self::M, m2: Self::M) {
         *m1 += m2
     }
 }
 
+/// Position and value of the first minimum, counting the values
+/// to know their positions
+#[derive(Copy, Clone)]
+pub struct ArgMin<A> {
+    ghost: PhantomData<A>,
+}
+
+impl<A: core::cmp::Ord> ArgMin<A> {
+    pub const ARG_MIN: Self = ArgMin { ghost: PhantomData };
+}
+
+impl<A: core::cmp::Ord> Fold1 for ArgMin<A> {
+    type A = A;
+    type B = Option<(usize, A)>;
+    type M = (usize, Option<(usize, A)>);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        (1, Some((0, x)))
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, (n, best): &mut Self::M) {
+        if best.as_ref().is_none_or(|(_, y)| x < *y) {
+            *best = Some((*n, x));
+        }
+        *n += 1;
+    }
+
+    #[inline]
+    fn output(&self, (_, best): Self::M) -> Self::B {
+        best
+    }
+}
+
+impl<A: core::cmp::Ord> Fold for ArgMin<A> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (0, None)
+    }
+}
+
+impl<A: core::cmp::Ord> FoldParOrdered for ArgMin<A> {
+    #[inline]
+    fn merge_at(&self, (n1, best1): &mut Self::M, (n2, best2): Self::M, offset: usize) {
+        if let Some((i, x)) = best2 {
+            if best1.as_ref().is_none_or(|(_, y)| x < *y) {
+                *best1 = Some((i + offset, x));
+            }
+        }
+        *n1 += n2;
+    }
+}
+
+/// Position and value of the first maximum, counting the values
+/// to know their positions
+#[derive(Copy, Clone)]
+pub struct ArgMax<A> {
+    ghost: PhantomData<A>,
+}
+
+impl<A: core::cmp::Ord> ArgMax<A> {
+    pub const ARG_MAX: Self = ArgMax { ghost: PhantomData };
+}
+
+impl<A: core::cmp::Ord> Fold1 for ArgMax<A> {
+    type A = A;
+    type B = Option<(usize, A)>;
+    type M = (usize, Option<(usize, A)>);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        (1, Some((0, x)))
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, (n, best): &mut Self::M) {
+        if best.as_ref().is_none_or(|(_, y)| x > *y) {
+            *best = Some((*n, x));
+        }
+        *n += 1;
+    }
+
+    #[inline]
+    fn output(&self, (_, best): Self::M) -> Self::B {
+        best
+    }
+}
+
+impl<A: core::cmp::Ord> Fold for ArgMax<A> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (0, None)
+    }
+}
+
+impl<A: core::cmp::Ord> FoldParOrdered for ArgMax<A> {
+    #[inline]
+    fn merge_at(&self, (n1, best1): &mut Self::M, (n2, best2): Self::M, offset: usize) {
+        if let Some((i, x)) = best2 {
+            if best1.as_ref().is_none_or(|(_, y)| x > *y) {
+                *best1 = Some((i + offset, x));
+            }
+        }
+        *n1 += n2;
+    }
+}
+
+/// Whether the values are in ascending order. No values are sorted.
+#[derive(Copy, Clone)]
+pub struct IsSorted<A> {
+    ghost: PhantomData<A>,
+}
+
+impl<A: core::cmp::PartialOrd + Clone> IsSorted<A> {
+    pub const IS_SORTED: Self = IsSorted { ghost: PhantomData };
+}
+
+impl<A: core::cmp::PartialOrd + Clone> Fold1 for IsSorted<A> {
+    type A = A;
+    type B = bool;
+    /// The first and last values, and whether they are sorted
+    type M = Option<(A, A, bool)>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        Some((x.clone(), x, true))
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        match acc {
+            Some((_, last, sorted)) => {
+                *sorted &= *last <= x;
+                *last = x;
+            }
+            None => *acc = self.init(x),
+        }
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.is_none_or(|(_, _, sorted)| sorted)
+    }
+}
+
+impl<A: core::cmp::PartialOrd + Clone> Fold for IsSorted<A> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        None
+    }
+}
+
+impl<A: core::cmp::PartialOrd + Clone> FoldPar for IsSorted<A> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        match (m1.as_mut(), m2) {
+            (Some((_, last1, sorted1)), Some((first2, last2, sorted2))) => {
+                *sorted1 &= sorted2 && *last1 <= first2;
+                *last1 = last2;
+            }
+            (None, m2) => *m1 = m2,
+            (_, None) => {}
+        }
+    }
+}
+
+impl<A: core::cmp::PartialOrd + Clone> FoldParOrdered for IsSorted<A> {
+    #[inline]
+    fn merge_at(&self, m1: &mut Self::M, m2: Self::M, _offset: usize) {
+        self.merge(m1, m2)
+    }
+}
+
+impl Sum<()> {
+    /// Sum of any type implementing `core::iter::Sum`, such as
+    /// `Wrapping` or `Duration`
+    pub fn of<T: core::iter::Sum<T>>() -> SumOf<T> {
+        SumOf { ghost: PhantomData }
+    }
+}
+
+/// Sum by `core::iter::Sum`, see `Sum::of`
+#[derive(Copy, Clone)]
+pub struct SumOf<T> {
+    ghost: PhantomData<T>,
+}
+
+impl<T: core::iter::Sum<T>> Fold1 for SumOf<T> {
+    type A = T;
+    type B = T;
+    type M = T;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        x
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let sum = core::mem::replace(acc, self.empty());
+        *acc = [sum, x].into_iter().sum();
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        let sum = core::mem::replace(acc, self.empty());
+        *acc = core::iter::once(sum).chain(xs).sum();
+    }
+}
+
+impl<T: core::iter::Sum<T>> Fold for SumOf<T> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        core::iter::empty().sum()
+    }
+}
+
+impl<T: core::iter::Sum<T>> FoldPar for SumOf<T> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.step(m2, m1)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Product<A> {
+    ghost: PhantomData<A>,
+}
+
+impl Product<()> {
+    /// Product of any type implementing `core::iter::Product`
+    pub fn of<T: core::iter::Product<T>>() -> Product<T> {
+        Product { ghost: PhantomData }
+    }
+}
+
+impl<A: core::iter::Product<A>> Fold1 for Product<A> {
+    type A = A;
+    type B = A;
+    type M = A;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        x
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let product = core::mem::replace(acc, self.empty());
+        *acc = [product, x].into_iter().product();
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        let product = core::mem::replace(acc, self.empty());
+        *acc = core::iter::once(product).chain(xs).product();
+    }
+}
+
+impl<A: core::iter::Product<A>> Fold for Product<A> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        core::iter::empty().product()
+    }
+}
+
+impl<A: core::iter::Product<A>> FoldPar for Product<A> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.step(m2, m1)
+    }
+}
+
+/// Collect values into any `Default + Extend` collection, such as a
+/// `Vec`, `String` or `BTreeSet`
+pub struct Collect<C, A> {
+    ghost: PhantomData<fn(A) -> C>,
+}
+
+impl<C, A> Clone for Collect<C, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C, A> Copy for Collect<C, A> {}
+
+impl<C: Default + Extend<A>, A> Collect<C, A> {
+    pub const COLLECT: Self = Collect { ghost: PhantomData };
+}
+
+impl<C: Default + Extend<A>, A> Fold1 for Collect<C, A> {
+    type A = A;
+    type B = C;
+    type M = C;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = C::default();
+        acc.extend(core::iter::once(x));
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        acc.extend(core::iter::once(x))
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        acc.extend(xs)
+    }
+}
+
+impl<C: Default + Extend<A>, A> Fold for Collect<C, A> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        C::default()
+    }
+}
+
+/// Collections are merged by extending the first with the second
+impl<C: Default + Extend<A> + IntoIterator<Item = A>, A> FoldPar for Collect<C, A> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.extend(m2)
+    }
+}
+
+/// Histogram of `BINS` equal width bins over `lo..hi`, whose state is
+/// an array of counts, so it never allocates. Values outside the range
+/// are counted in the first or last bin, and NaNs are not counted.
+#[derive(Copy, Clone, Debug)]
+pub struct HistogramN<const BINS: usize> {
+    lo: f64,
+    hi: f64,
+    /// Bins per unit
+    scale: f64,
+}
+
+impl<const BINS: usize> HistogramN<BINS> {
+    pub fn new(lo: f64, hi: f64) -> Self {
+        assert!(BINS > 0, "HistogramN: BINS must be positive");
+        assert!(lo < hi, "HistogramN: lo must be less than hi");
+        HistogramN {
+            lo,
+            hi,
+            scale: BINS as f64 / (hi - lo),
+        }
+    }
+
+    /// Index of the bin of `x`, casting saturates so values below the
+    /// range land in the first bin without a branch
+    #[inline]
+    fn bin(&self, x: f64) -> usize {
+        (((x - self.lo) * self.scale) as usize).min(BINS - 1)
+    }
+
+    /// Lower and upper edges of bin `i`
+    pub fn bin_range(&self, i: usize) -> (f64, f64) {
+        let width = (self.hi - self.lo) / BINS as f64;
+        (self.lo + i as f64 * width, self.lo + (i + 1) as f64 * width)
+    }
+}
+
+impl<const BINS: usize> Fold1 for HistogramN<BINS> {
+    type A = f64;
+    type B = [u64; BINS];
+    type M = [u64; BINS];
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        if !x.is_nan() {
+            acc[self.bin(x)] += 1;
+        }
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        for x in xs.into_iter().filter(|x| !x.is_nan()) {
+            acc[self.bin(x)] += 1;
+        }
+    }
+}
+
+impl<const BINS: usize> Fold for HistogramN<BINS> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        [0; BINS]
+    }
+}
+
+impl<const BINS: usize> FoldPar for HistogramN<BINS> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (n1, n2) in m1.iter_mut().zip(m2) {
+            *n1 += n2;
+        }
+    }
+}
+
+// Atomic sums wrap on overflow, where the sums they merge into panic
+// in debug builds
+#[cfg(target_has_atomic = "64")]
+impl AtomicFold for Sum<i64> {
+    type Atomic = core::sync::atomic::AtomicI64;
+
+    fn empty_atomic(&self) -> Self::Atomic {
+        Default::default()
+    }
+
+    #[inline]
+    fn step_atomic(&self, x: Self::A, acc: &Self::Atomic) {
+        acc.fetch_add(x, Relaxed);
+    }
+
+    fn load(&self, acc: &Self::Atomic) -> Self::M {
+        acc.load(Relaxed)
+    }
+
+    fn take(&self, acc: &Self::Atomic) -> Self::M {
+        acc.swap(0, Relaxed)
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl AtomicFold for Sum<u64> {
+    type Atomic = core::sync::atomic::AtomicU64;
+
+    fn empty_atomic(&self) -> Self::Atomic {
+        Default::default()
+    }
+
+    #[inline]
+    fn step_atomic(&self, x: Self::A, acc: &Self::Atomic) {
+        acc.fetch_add(x, Relaxed);
+    }
+
+    fn load(&self, acc: &Self::Atomic) -> Self::M {
+        acc.load(Relaxed)
+    }
+
+    fn take(&self, acc: &Self::Atomic) -> Self::M {
+        acc.swap(0, Relaxed)
+    }
+}
+
+impl AtomicFold for Sum<usize> {
+    type Atomic = core::sync::atomic::AtomicUsize;
+
+    fn empty_atomic(&self) -> Self::Atomic {
+        Default::default()
+    }
+
+    #[inline]
+    fn step_atomic(&self, x: Self::A, acc: &Self::Atomic) {
+        acc.fetch_add(x, Relaxed);
+    }
+
+    fn load(&self, acc: &Self::Atomic) -> Self::M {
+        acc.load(Relaxed)
+    }
+
+    fn take(&self, acc: &Self::Atomic) -> Self::M {
+        acc.swap(0, Relaxed)
+    }
+}
+
+impl<A> AtomicFold for Count<A> {
+    type Atomic = core::sync::atomic::AtomicUsize;
+
+    fn empty_atomic(&self) -> Self::Atomic {
+        Default::default()
+    }
+
+    #[inline]
+    fn step_atomic(&self, _x: Self::A, acc: &Self::Atomic) {
+        acc.fetch_add(1, Relaxed);
+    }
+
+    fn load(&self, acc: &Self::Atomic) -> Self::M {
+        acc.load(Relaxed)
+    }
+
+    fn take(&self, acc: &Self::Atomic) -> Self::M {
+        acc.swap(0, Relaxed)
+    }
+}
+
+impl<A> StepWeighted for Sum<A>
+where
+    Self: Fold1<A = A, M = A>,
+    A: core::ops::Mul<Output = A> + core::ops::AddAssign + Copy,
+{
+    type Weight = A;
+
+    #[inline]
+    fn step_weighted(&self, x: Self::A, weight: A, acc: &mut Self::M) {
+        *acc += x * weight
+    }
+}
+
+/// Weights are frequencies
+impl<A> StepWeighted for Count<A> {
+    type Weight = usize;
+
+    #[inline]
+    fn step_weighted(&self, _x: Self::A, weight: usize, acc: &mut Self::M) {
+        *acc += weight
+    }
+}
+
+// The state of these folds is a single value. Heap memory owned by
+// the value, e.g. by a `String`, is not counted.
+impl<A> StateSize for Sum<A>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A> StateSize for Max<A>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A> StateSize for Min<A>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A> StateSize for First<A> {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A> StateSize for Last<A> {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A> StateSize for Count<A> {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A> StateSize for ArgMin<A>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A> StateSize for ArgMax<A>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A> StateSize for IsSorted<A>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<T> StateSize for SumOf<T>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<A> StateSize for Product<A>
+where
+    Self: Fold1,
+{
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+impl<const BINS: usize> StateSize for HistogramN<BINS> {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        core::mem::size_of::<Self::M>()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for Sum<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Sum")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for Max<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Max")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for Min<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Min")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for First<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("First")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for Last<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Last")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for Count<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Count")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for ArgMin<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("ArgMin")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for ArgMax<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("ArgMax")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for IsSorted<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("IsSorted")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> FoldDescribe for SumOf<T>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("SumOf")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A> FoldDescribe for Product<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Product")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<C, A> FoldDescribe for Collect<C, A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Collect")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<const BINS: usize> FoldDescribe for HistogramN<BINS> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("HistogramN")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -263,4 +1121,89 @@ mod tests {
             go(n)
         }
     }
+    #[test]
+    fn std_accumulators() {
+        use core::num::Wrapping;
+        use std::collections::BTreeSet;
+        use std::time::Duration;
+
+        let secs = (1..=3).map(Duration::from_secs);
+        assert_eq!(run_fold_iter(&Sum::of(), secs), Duration::from_secs(6));
+        let xs = [Wrapping(u8::MAX), Wrapping(2)];
+        assert_eq!(run_fold_iter(&Sum::of(), xs.into_iter()), Wrapping(1));
+        assert_eq!(run_fold_iter(&Product::of::<u64>(), 1..=5), 120);
+        assert_eq!(
+            run_fold_iter(&Product::of::<f64>(), core::iter::empty()),
+            1.0
+        );
+
+        let letters = Collect::<String, char>::COLLECT.par(Collect::<BTreeSet<_>, _>::COLLECT);
+        let (s, set) = run_fold1_iter(&letters, "abca".chars()).unwrap();
+        assert_eq!(s, "abca");
+        assert_eq!(set.into_iter().collect::<String>(), "abc");
+    }
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn first_last_par() {
+        use rayon::prelude::*;
+
+        let fld = First::FIRST.par(Last::LAST).optional();
+        let xs: Vec<usize> = iota(100_000);
+        assert_eq!(
+            run_fold_par_iter_ordered(xs.into_par_iter(), &fld),
+            Some((0, 99_999))
+        );
+        assert_eq!(
+            run_fold_par_iter_ordered(Vec::new().into_par_iter(), &fld),
+            None
+        );
+    }
+    #[test]
+    fn positions() {
+        let xs = [3, 1, 4, 1, 5, 9, 2, 6, 5];
+        let fld = ArgMin::ARG_MIN
+            .par(ArgMax::ARG_MAX)
+            .par(IsSorted::IS_SORTED);
+        assert_eq!(
+            run_fold_iter(&fld, xs.into_iter()),
+            ((Some((1, 1)), Some((5, 9))), false)
+        );
+        assert!(run_fold_iter(&IsSorted::IS_SORTED, 0..10));
+        assert_eq!(run_fold_iter(&ArgMin::<i32>::ARG_MIN, 0..0), None);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn positions_par() {
+        use rayon::prelude::*;
+
+        let mut xs: Vec<usize> = iota(100_000);
+        xs[0] = 5;
+        xs[70_000] = 0;
+        let fld = ArgMin::ARG_MIN
+            .par(ArgMax::ARG_MAX)
+            .par(IsSorted::IS_SORTED);
+        assert_eq!(
+            run_fold_par_iter_ordered(xs.into_par_iter(), &fld),
+            ((Some((70_000, 0)), Some((99_999, 99_999))), false)
+        );
+        let ys: Vec<usize> = iota(100_000);
+        assert!(run_fold_par_iter_ordered(
+            ys.into_par_iter(),
+            &IsSorted::IS_SORTED
+        ));
+    }
+
+    #[test]
+    fn fixed_histogram() {
+        let fld = HistogramN::<4>::new(0.0, 8.0);
+        let xs = [-1.0, 0.5, 2.0, 3.9, 4.0, 7.5, 8.0, 100.0, f64::NAN];
+        assert_eq!(run_fold_iter(&fld, xs.into_iter()), [2, 2, 1, 3]);
+
+        let mut acc = fld.empty();
+        fld.step_chunk(xs[..4].to_vec(), &mut acc);
+        fld.merge(&mut acc, run_fold_iter(&fld, xs[4..].iter().copied()));
+        assert_eq!(acc, [2, 2, 1, 3]);
+        assert_eq!(fld.bin_range(1), (2.0, 4.0));
+    }
 }
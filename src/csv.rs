@@ -0,0 +1,100 @@
+//! Folding over csv files, deserializing each record with serde
+use crate::fold::*;
+
+use std::io;
+
+use ::csv::{Error, ReaderBuilder};
+use serde::de::DeserializeOwned;
+
+/// Options for the csv runners
+#[derive(Clone, Copy, Debug)]
+pub struct CsvOptions {
+    /// Whether the first row is a header, in which case records
+    /// can be deserialized into structs by field name
+    pub has_headers: bool,
+    pub delimiter: u8,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            has_headers: true,
+            delimiter: b',',
+        }
+    }
+}
+
+fn reader<R: io::Read>(reader: R, options: CsvOptions) -> ::csv::Reader<R> {
+    ReaderBuilder::new()
+        .has_headers(options.has_headers)
+        .delimiter(options.delimiter)
+        .from_reader(reader)
+}
+
+/// Fold over the records of a csv file, stopping at the first
+/// malformed row
+pub fn run_fold_csv<R, F>(
+    reader: impl io::Read,
+    fold: &F,
+    options: CsvOptions,
+) -> Result<F::B, Error>
+where
+    R: DeserializeOwned,
+    F: Fold<A = R>,
+{
+    let mut acc = fold.empty();
+    for record in self::reader(reader, options).into_deserialize() {
+        fold.step(record?, &mut acc);
+    }
+    Ok(fold.output(acc))
+}
+
+/// Fold over the records of a csv file, feeding rows which fail to
+/// parse or deserialize to a second fold instead of stopping.
+/// I/O errors still stop the run.
+pub fn run_fold_csv_lossy<R, F, E>(
+    reader: impl io::Read,
+    fold: &F,
+    errors: &E,
+    options: CsvOptions,
+) -> Result<(F::B, E::B), Error>
+where
+    R: DeserializeOwned,
+    F: Fold<A = R>,
+    E: Fold<A = Error>,
+{
+    let mut acc = fold.empty();
+    let mut err_acc = errors.empty();
+    for record in self::reader(reader, options).into_deserialize() {
+        match record {
+            Ok(r) => fold.step(r, &mut acc),
+            Err(e) if e.is_io_error() => return Err(e),
+            Err(e) => errors.step(e, &mut err_acc),
+        }
+    }
+    Ok((fold.output(acc), errors.output(err_acc)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    const DATA: &str = "name;score\na;1.5\nb;oops\nc;2.5\n";
+
+    #[test]
+    fn lossy_rows() {
+        let options = CsvOptions {
+            delimiter: b';',
+            ..Default::default()
+        };
+        let fld = Sum::<f64>::SUM.pre_map(|(_, x): (String, f64)| x);
+
+        assert!(run_fold_csv(DATA.as_bytes(), &fld, options).is_err());
+
+        let (total, bad) =
+            run_fold_csv_lossy(DATA.as_bytes(), &fld, &Count::COUNT, options).unwrap();
+        assert_eq!(total, 4.0);
+        assert_eq!(bad, 1);
+    }
+}
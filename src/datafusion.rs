@@ -0,0 +1,117 @@
+//! Exposing folds to DataFusion as aggregate functions
+use crate::fold::*;
+
+use std::fmt;
+use std::sync::Arc;
+
+use ::arrow::array::{ArrayRef, AsArray};
+use ::arrow::datatypes::DataType;
+use ::datafusion::error::{DataFusionError, Result};
+use ::datafusion::logical_expr::{create_udaf, Accumulator, AggregateUDF, Volatility};
+use ::datafusion::scalar::ScalarValue;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Expose a fold as a DataFusion aggregate function called `name`.
+/// The fold is given the argument column of each batch, and its
+/// partial states are serialized as json to be merged across partitions.
+/// The memory of the state is reported to DataFusion by `StateSize`.
+pub fn udaf<F>(name: &str, input_type: DataType, return_type: DataType, fold: F) -> AggregateUDF
+where
+    F: Fold<A = ArrayRef> + FoldPar + StateSize + Clone + Send + Sync + 'static,
+    F::M: Serialize + DeserializeOwned + Clone + Send + Sync,
+    F::B: Into<ScalarValue>,
+{
+    create_udaf(
+        name,
+        vec![input_type],
+        Arc::new(return_type),
+        Volatility::Immutable,
+        Arc::new(move |_| {
+            Ok(Box::new(FoldAccumulator {
+                acc: fold.empty(),
+                fold: fold.clone(),
+            }))
+        }),
+        Arc::new(vec![DataType::Binary]),
+    )
+}
+
+/// Accumulator running a fold, see `udaf`
+pub struct FoldAccumulator<F: Fold> {
+    fold: F,
+    acc: F::M,
+}
+
+impl<F: Fold> fmt::Debug for FoldAccumulator<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FoldAccumulator").finish_non_exhaustive()
+    }
+}
+
+fn external(e: serde_json::Error) -> DataFusionError {
+    DataFusionError::External(Box::new(e))
+}
+
+impl<F> Accumulator for FoldAccumulator<F>
+where
+    F: Fold<A = ArrayRef> + FoldPar + StateSize + Send + Sync,
+    F::M: Serialize + DeserializeOwned + Clone + Send + Sync,
+    F::B: Into<ScalarValue>,
+{
+    fn update_batch(&mut self, values: &[ArrayRef]) -> Result<()> {
+        self.fold.step(values[0].clone(), &mut self.acc);
+        Ok(())
+    }
+
+    fn evaluate(&self) -> Result<ScalarValue> {
+        Ok(self.fold.output(self.acc.clone()).into())
+    }
+
+    fn size(&self) -> usize {
+        std::mem::size_of_val(self) + self.fold.state_bytes(&self.acc)
+    }
+
+    fn state(&self) -> Result<Vec<ScalarValue>> {
+        let bytes = serde_json::to_vec(&self.acc).map_err(external)?;
+        Ok(vec![ScalarValue::Binary(Some(bytes))])
+    }
+
+    fn merge_batch(&mut self, states: &[ArrayRef]) -> Result<()> {
+        for bytes in states[0].as_binary::<i32>().iter().flatten() {
+            let m = serde_json::from_slice(bytes).map_err(external)?;
+            self.fold.merge(&mut self.acc, m);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrow::Sum;
+    use ::arrow::array::{Float64Array, RecordBatch};
+    use ::arrow::datatypes::Float64Type;
+    use ::datafusion::prelude::SessionContext;
+
+    #[tokio::test]
+    async fn sql_sum() {
+        let fold = Sum::<Float64Type>::SUM.pre_map(|arr: ArrayRef| arr.as_primitive().clone());
+        let ctx = SessionContext::new();
+        ctx.register_udaf(udaf("fold_sum", DataType::Float64, DataType::Float64, fold));
+
+        let xs = Arc::new(Float64Array::from(vec![Some(1.0), None, Some(2.5)])) as ArrayRef;
+        let batch = RecordBatch::try_from_iter([("x", xs)]).unwrap();
+        ctx.register_batch("t", batch).unwrap();
+
+        let out = ctx
+            .sql("select fold_sum(x) from t")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let col = out[0].column(0).as_primitive::<Float64Type>();
+        assert_eq!(col.value(0), 3.5);
+    }
+}
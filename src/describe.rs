@@ -0,0 +1,149 @@
+//! Describing the structure of composed folds, so that large folds
+//! built from config can be inspected and rendered with graphviz
+use crate::fold::*;
+
+use std::any::type_name;
+use std::fmt::{self, Write};
+
+/// Folds which can describe how they are composed, see `Structure`
+pub trait FoldDescribe: Fold1 {
+    fn describe_structure(&self) -> Structure;
+}
+
+/// A node in the tree of a composed fold, with the folds it is built
+/// from as children
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Structure {
+    pub name: String,
+    /// Input type, without module paths
+    pub input: String,
+    /// Output type, without module paths
+    pub output: String,
+    pub children: Vec<Structure>,
+}
+
+impl Structure {
+    /// A node for the fold `F`, named `name`
+    pub fn new<F: Fold1 + ?Sized>(name: impl Into<String>, children: Vec<Structure>) -> Self {
+        Structure {
+            name: name.into(),
+            input: short_type_name(type_name::<F::A>()),
+            output: short_type_name(type_name::<F::B>()),
+            children,
+        }
+    }
+
+    /// A node for the fold `F` with no children
+    pub fn leaf<F: Fold1 + ?Sized>(name: impl Into<String>) -> Self {
+        Self::new::<F>(name, Vec::new())
+    }
+
+    /// Render as a graphviz DOT digraph, with an edge from each fold
+    /// to the folds it is built from
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph fold {\n    node [shape=box];\n");
+        self.write_dot(&mut out, &mut 0);
+        out.push_str("}\n");
+        out
+    }
+
+    /// Writes the nodes and edges of this subtree, returning its id
+    fn write_dot(&self, out: &mut String, next: &mut usize) -> usize {
+        let id = *next;
+        *next += 1;
+        let label = format!("{}\n{} -> {}", self.name, self.input, self.output);
+        writeln!(out, "    n{} [label=\"{}\"];", id, escape(&label)).unwrap();
+        for child in &self.children {
+            let child_id = child.write_dot(out, next);
+            writeln!(out, "    n{} -> n{};", id, child_id).unwrap();
+        }
+        id
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(
+            f,
+            "{:indent$}{}: {} -> {}",
+            "",
+            self.name,
+            self.input,
+            self.output,
+            indent = 2 * depth
+        )?;
+        for child in &self.children {
+            writeln!(f)?;
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// An indented tree, one fold per line
+impl fmt::Display for Structure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Drop module paths from a type name, so that
+/// `alloc::vec::Vec<core::option::Option<f64>>` becomes `Vec<Option<f64>>`
+pub(crate) fn short_type_name(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut segment = 0;
+    for (i, c) in name.char_indices() {
+        if c == ':' {
+            segment = i + 1;
+        } else if !(c.is_alphanumeric() || c == '_') {
+            out.push_str(&name[segment..i]);
+            out.push(c);
+            segment = i + 1;
+        }
+    }
+    out.push_str(&name[segment..]);
+    out
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    #[test]
+    fn describe_and_render() {
+        let fld = Sum::<i64>::SUM
+            .par(Count::COUNT)
+            .pre_map(|x: i32| x as i64)
+            .filter(|x: &i32| *x > 0);
+        let s = fld.describe_structure();
+        assert_eq!(
+            s.to_string(),
+            "FilteredFold: i32 -> (i64, usize)\n\
+             \x20 PreMap: i32 -> (i64, usize)\n\
+             \x20   Par2: i64 -> (i64, usize)\n\
+             \x20     Sum: i64 -> i64\n\
+             \x20     Count: i64 -> usize"
+        );
+
+        let dot = s.to_dot();
+        assert!(dot.starts_with("digraph fold {"));
+        assert!(dot.contains("n0 [label=\"FilteredFold\\ni32 -> (i64, usize)\"];"));
+        assert!(dot.contains("n2 -> n4;"));
+        assert_eq!(dot.matches("->").count(), 4 + 5);
+    }
+
+    #[test]
+    fn short_names() {
+        assert_eq!(
+            short_type_name("alloc::vec::Vec<core::option::Option<f64>>"),
+            "Vec<Option<f64>>"
+        );
+        assert_eq!(short_type_name("(i64, usize)"), "(i64, usize)");
+    }
+}
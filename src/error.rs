@@ -0,0 +1,23 @@
+//! The error type of the fallible runners
+use std::io;
+
+/// Why a run failed
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// A worker panicked, or was cancelled by its runtime
+    #[error("worker failed")]
+    Join(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// Reading the input failed
+    #[error("reading input failed")]
+    Io(#[from] io::Error),
+    /// A fallible input yielded an error, when failing fast
+    #[error("input failed")]
+    Source(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// The run was stopped before all of its input was consumed
+    #[error("run was cancelled")]
+    Cancelled,
+    /// The state of the run outgrew its budget, see `RunOptions`
+    #[error("state grew to {bytes} bytes, over the budget of {max}")]
+    StateTooLarge { bytes: usize, max: usize },
+}
@@ -1,12 +1,60 @@
-use std::hash::Hash;
-use std::marker::PhantomData;
+#[cfg(feature = "std")]
+use crate::describe::short_type_name;
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeMap;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cell::RefCell;
+#[cfg(feature = "alloc")]
+use core::hash::{BuildHasher, Hash};
+use core::marker::PhantomData;
+use core::mem::size_of;
+use core::ops::ControlFlow;
+#[cfg(feature = "std")]
+use std::any::type_name;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "async")]
+use std::{future::Future, pin::pin};
 
+#[cfg(feature = "std")]
 use rustc_hash::FxHashMap;
+#[cfg(feature = "std")]
+use std::collections::hash_map::Entry;
 
-use rayon;
+/// Without std, grouped folds use hashbrown's map with the same hasher
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+pub type FxHashMap<K, V> =
+    hashbrown::HashMap<K, V, core::hash::BuildHasherDefault<rustc_hash::FxHasher>>;
+#[cfg(all(feature = "alloc", not(feature = "std")))]
+use hashbrown::hash_map::Entry;
+
+#[cfg(feature = "rayon")]
 use rayon::iter::{IndexedParallelIterator, ParallelIterator};
 
-use futures::{self, Stream, StreamExt};
+#[cfg(feature = "async")]
+use crate::spawn::{Spawner, Tokio};
+#[cfg(feature = "std")]
+use crate::Error;
+#[cfg(feature = "async")]
+use futures::{self, Stream, StreamExt, TryStreamExt};
+
+#[cfg(feature = "std")]
+pub use crate::describe::{FoldDescribe, Structure};
+#[cfg(feature = "async")]
+pub use crate::group::run_fold_stream_ttl;
+#[cfg(feature = "std")]
+pub use crate::group::{
+    DenseColumns, Examples, GroupedArena, GroupedByRef, GroupedSample, GroupedTtl, OwnedKey,
+    SampledGroups, ToOwnedKey, WithExamples,
+};
+#[cfg(feature = "std")]
+pub use crate::sketch::{GroupedSketch, Winsorized};
+pub use either::Either;
 
 /// Trait representing that something can be seen as a "fold1", i.e.
 /// a fold that will always be given at least one input.
@@ -38,6 +86,7 @@ pub trait Fold1 {
 
     /// Perform fold grouped by a key.
     /// Resulting output type is a HashMap
+    #[cfg(feature = "alloc")]
     fn group_by<GetKey, Key>(self, get_key: GetKey) -> GroupedFold<Self, GetKey>
     where
         Self: Sized,
@@ -50,6 +99,149 @@ pub trait Fold1 {
         }
     }
 
+    /// Perform fold grouped by a key, like `group_by`, and also output
+    /// the fold over all values. Each value is only stepped into its
+    /// group, and the total is the merge of the groups' states.
+    #[cfg(feature = "alloc")]
+    fn group_by_with_total<GetKey, Key>(self, get_key: GetKey) -> GroupedWithTotal<Self, GetKey>
+    where
+        Self: FoldPar + Fold + Sized,
+        Self::M: Clone,
+        Key: Hash + Eq,
+        GetKey: Fn(&Self::A) -> Key,
+    {
+        GroupedWithTotal {
+            inner: self.group_by(get_key),
+        }
+    }
+
+    /// Perform a sketch grouped by a key, like `group_by`, sharing a
+    /// budget of `budget` bytes between the sketches of the keys. As
+    /// keys are added the sketches are made smaller, such as lower
+    /// precision HyperLogLogs, so memory does not grow with the number
+    /// of keys until the sketches are as small as they go.
+    #[cfg(feature = "std")]
+    fn group_by_sketch<GetKey, Key>(
+        self,
+        get_key: GetKey,
+        budget: usize,
+    ) -> GroupedSketch<Self, GetKey>
+    where
+        Self: crate::sketch::SketchBudget,
+        Key: Hash + Eq,
+        GetKey: Fn(&Self::A) -> Key,
+    {
+        GroupedSketch::new(self, get_key, budget)
+    }
+
+    /// Cross-tabulate, folding the values of each pair of row and
+    /// column keys. The output is a map from row keys to maps from
+    /// column keys to outputs, with only the pairs which were seen.
+    #[cfg(feature = "alloc")]
+    fn pivot<RowKey, ColKey, Row, Col>(
+        self,
+        row_key: RowKey,
+        col_key: ColKey,
+    ) -> GroupedFold<GroupedFold<Self, ColKey>, RowKey>
+    where
+        Self: Sized,
+        Row: Hash + Eq,
+        Col: Hash + Eq,
+        RowKey: Fn(&Self::A) -> Row,
+        ColKey: Fn(&Self::A) -> Col,
+    {
+        self.group_by(col_key).group_by(row_key)
+    }
+
+    /// Cross-tabulate like `pivot`, when there are `n_cols` columns
+    /// known ahead of time. Each row is output as a `Vec` with a cell
+    /// for every column, and `col_index` must be less than `n_cols`.
+    #[cfg(feature = "std")]
+    fn pivot_dense<RowKey, ColIndex, Row>(
+        self,
+        row_key: RowKey,
+        col_index: ColIndex,
+        n_cols: usize,
+    ) -> GroupedFold<DenseColumns<Self, ColIndex>, RowKey>
+    where
+        Self: Fold + Sized,
+        Row: Hash + Eq,
+        RowKey: Fn(&Self::A) -> Row,
+        ColIndex: Fn(&Self::A) -> usize,
+    {
+        DenseColumns::new(self, col_index, n_cols).group_by(row_key)
+    }
+
+    /// Perform fold grouped by a key, like `group_by`, but only for a
+    /// sample of about `rate` of the keys, chosen by hashing them with
+    /// `seed`. Sampled keys see all of their values, and the output can
+    /// scale up totals over keys, bounding memory for keys of extreme
+    /// cardinality.
+    #[cfg(feature = "std")]
+    fn group_by_sampled<GetKey, Key>(
+        self,
+        get_key: GetKey,
+        rate: f64,
+        seed: u64,
+    ) -> GroupedSample<Self, GetKey>
+    where
+        Self: Sized,
+        Key: Hash + Eq,
+        GetKey: Fn(&Self::A) -> Key,
+    {
+        GroupedSample::new(self, get_key, rate, seed)
+    }
+
+    /// Perform fold grouped by a key, like `group_by`, where the key is
+    /// given in a borrowed form such as `(&str, u32)` for an owned key
+    /// `(String, u32)`. Groups are looked up by the borrowed key, which
+    /// is only made owned for new groups.
+    #[cfg(feature = "std")]
+    fn group_by_ref<Key, GetKey>(self, get_key: GetKey) -> GroupedByRef<Self, GetKey, Key>
+    where
+        Self: Sized,
+        Key: OwnedKey,
+        GetKey: for<'a> Fn(&'a Self::A) -> Key::Ref<'a>,
+    {
+        GroupedByRef::new(self, get_key)
+    }
+
+    /// Perform fold grouped by a key, like `group_by`, but keeping the
+    /// states of the groups together in one slab rather than in the
+    /// map itself. This is faster when the states are large.
+    #[cfg(feature = "std")]
+    fn group_by_arena<GetKey, Key>(self, get_key: GetKey) -> GroupedArena<Self, GetKey>
+    where
+        Self: Sized,
+        Key: Hash + Eq,
+        GetKey: Fn(&Self::A) -> Key,
+    {
+        GroupedArena::new(self, get_key)
+    }
+
+    /// Perform fold grouped by a key, closing a group once no value
+    /// for it has been seen for longer than `ttl`, measured in the
+    /// event time given by `get_time`. A key seen again after its
+    /// group was closed starts a new group. See `run_fold_stream_ttl`
+    /// to receive closed groups as they happen.
+    #[cfg(feature = "std")]
+    fn group_by_with_ttl<GetKey, Key, GetTime, T, D>(
+        self,
+        get_key: GetKey,
+        get_time: GetTime,
+        ttl: D,
+    ) -> GroupedTtl<Self, GetKey, GetTime, D>
+    where
+        Self: Sized,
+        Key: Hash + Eq + Clone,
+        GetKey: Fn(&Self::A) -> Key,
+        T: Copy + Ord + core::ops::Add<D, Output = T>,
+        GetTime: Fn(&Self::A) -> T,
+        D: Copy,
+    {
+        GroupedTtl::new(self, get_key, get_time, ttl)
+    }
+
     /// Only fold over input values satiisfying the given predicate.
     fn filter<Pred>(self, pred: Pred) -> FilteredFold<Self, Pred>
     where
@@ -59,6 +251,40 @@ pub trait Fold1 {
         FilteredFold { inner: self, pred }
     }
 
+    /// Bound input values to `[lo, hi]` before folding them, so that
+    /// outliers count as `lo` or `hi`. Incomparable values such as NaN
+    /// are passed through.
+    fn clamp(self, lo: Self::A, hi: Self::A) -> Clamped<Self>
+    where
+        Self: Sized,
+        Self::A: PartialOrd,
+    {
+        assert!(lo <= hi, "clamp requires lo <= hi");
+        Clamped {
+            inner: self,
+            lo,
+            hi,
+        }
+    }
+
+    /// Bound input values to the `lo` and `hi` quantiles, such as 0.05
+    /// and 0.95, as estimated by `sketch` from the first `warm_up`
+    /// values. The warm up values are kept until the bounds are known,
+    /// then folded clamped like the rest.
+    #[cfg(feature = "std")]
+    fn winsorize_by_quantile(
+        self,
+        sketch: crate::sketch::TDigest,
+        lo: f64,
+        hi: f64,
+        warm_up: usize,
+    ) -> Winsorized<Self>
+    where
+        Self: Sized + Fold<A = f64>,
+    {
+        Winsorized::new(self, sketch, lo, hi, warm_up)
+    }
+
     /// Perform this fold in parallel with another.
     /// The second fold must have the same (copyable) input type.
     /// The resulting output type will be a pair.
@@ -71,6 +297,22 @@ pub trait Fold1 {
         Par2 { f1: self, f2 }
     }
 
+    /// Perform this fold in parallel with another and combine
+    /// their outputs with the given function.
+    /// This is `par` followed by a `post_map` over the pair.
+    fn zip_with<F2, B2, Func>(self, f2: F2, func: Func) -> ZipWith<Self, F2, Func>
+    where
+        F2: Fold1<A = Self::A> + Sized,
+        Self::A: Copy,
+        Self: Sized,
+        Func: Fn(Self::B, F2::B) -> B2,
+    {
+        ZipWith {
+            inner: self.par(f2),
+            func,
+        }
+    }
+
     /// Apply a function to all inputs.
     /// Note that this changes the input type of the fold.
     /// This is a contravariant functor fmap
@@ -86,6 +328,34 @@ pub trait Fold1 {
         }
     }
 
+    /// Fold over the differences between consecutive inputs, such as
+    /// returns or deltas, rather than the inputs themselves.
+    /// The first input has no difference, so only sets the previous value.
+    fn differences<A>(self) -> Differences<Self, A>
+    where
+        Self: Sized + Fold,
+        A: Copy + core::ops::Sub<Output = Self::A>,
+    {
+        Differences {
+            inner: self,
+            ghost: PhantomData,
+        }
+    }
+
+    /// Fold over pairs of consecutive inputs, `(previous, current)`,
+    /// for counting transitions or checking monotonicity.
+    /// The first input has no previous value, so only sets it.
+    fn pairwise<T>(self) -> Pairwise<Self, T>
+    where
+        Self: Sized + Fold<A = (T, T)>,
+        T: Clone,
+    {
+        Pairwise {
+            inner: self,
+            ghost: PhantomData,
+        }
+    }
+
     /// Apply a function to the output.
     /// Note that this changes the output type of the fold.
     /// This is a covariant functor fmap
@@ -100,6 +370,22 @@ pub trait Fold1 {
         }
     }
 
+    /// Compose two folds, where the second only sees what changed in
+    /// the output of the first after each value, as reported by
+    /// `Incremental::step_delta`. Unlike `then`, this does not
+    /// recompute the whole output of the first fold for each value,
+    /// e.g. a grouped fold only reports the group that was updated.
+    fn then_delta<F2>(self, next: F2) -> DeltaComposed<Self, F2>
+    where
+        Self: Incremental + Sized,
+        F2: Fold1<A = Self::Delta>,
+    {
+        DeltaComposed {
+            first: self,
+            second: next,
+        }
+    }
+
     /// Compose two folds
     /// This folds the second over the scan of the first
     fn then<F2: Fold<A = Self::B>>(self, next: F2) -> ComposedFold<Self, F2>
@@ -123,12 +409,174 @@ pub trait Fold1 {
         Batched { inner: self }
     }
 
-    /// Paralellizes a fold with itself over a wide stream
+    /// Paralellizes a fold with itself over a wide stream.
+    /// Inputs must have width `n`, see `Many::resizing` to relax this.
     fn many(self, n: usize) -> Many<Self>
     where
         Self: Sized,
     {
-        Many { inner: self, n }
+        Many {
+            inner: self,
+            n,
+            width: Width::Strict,
+        }
+    }
+
+    /// Like `many`, but for a width known at compile time, so the
+    /// inputs, state and outputs are arrays rather than `Vec`s and
+    /// stay on the stack.
+    fn many_n<const N: usize>(self) -> ManyN<Self, N>
+    where
+        Self: Sized,
+    {
+        ManyN { inner: self }
+    }
+
+    /// Route each input to this fold if the predicate holds and
+    /// to the second fold otherwise.
+    /// Unlike `par` of two `filter`s, each element is only looked
+    /// at once and no `Copy` bound is required.
+    /// The resulting output type will be a pair.
+    fn partition<Pred, F2>(self, pred: Pred, f2: F2) -> Partition<Self, F2, Pred>
+    where
+        Self: Sized,
+        F2: Fold1<A = Self::A>,
+        Pred: Fn(&Self::A) -> bool,
+    {
+        Partition { f1: self, f2, pred }
+    }
+
+    /// Perform this fold alongside another over pairs of inputs,
+    /// feeding the first component to this fold and the second to
+    /// the other.
+    /// The resulting output type will be a pair.
+    fn zip<F2>(self, f2: F2) -> Zip<Self, F2>
+    where
+        Self: Sized,
+        F2: Fold1,
+    {
+        Zip { f1: self, f2 }
+    }
+
+    /// Perform this fold alongside another over a merged stream of
+    /// `Either`s, feeding `Left` values to this fold and `Right`
+    /// values to the other.
+    /// The resulting output type will be a pair.
+    fn par_either<F2>(self, f2: F2) -> ParEither<Self, F2>
+    where
+        Self: Fold + Sized,
+        F2: Fold,
+    {
+        ParEither { f1: self, f2 }
+    }
+
+    /// Turn this fold into a `Fold` whose output is `None`
+    /// when run over no input.
+    fn optional(self) -> Maybe<Self>
+    where
+        Self: Sized,
+    {
+        Maybe { inner: self }
+    }
+
+    /// Turn this fold into a `Fold` which outputs the given
+    /// default value when run over no input.
+    fn with_default(self, default: Self::B) -> WithDefault<Self, Self::B>
+    where
+        Self: Sized,
+        Self::B: Clone,
+    {
+        WithDefault {
+            inner: self.optional(),
+            default,
+        }
+    }
+
+    /// Turn this fold into a `Fold` which outputs `Default::default()`
+    /// when run over no input.
+    fn or_default(self) -> WithDefault<Self, Self::B>
+    where
+        Self: Sized,
+        Self::B: Clone + Default,
+    {
+        self.with_default(Default::default())
+    }
+
+    /// Erase the type of this fold, keeping only its input and
+    /// output types. This allows folds with different internal
+    /// state to be stored side by side, e.g. in `Columns`.
+    fn boxed(self) -> BoxedFold<Self::A, Self::B>
+    where
+        Self: Fold + FoldPar + Send + Sync + Sized + 'static,
+        Self::M: Send + 'static,
+    {
+        BoxedFold {
+            inner: Arc::new(self),
+        }
+    }
+
+    /// Weight each input by `get_weight`, so that it counts as many
+    /// times as its weight, such as for a weighted count, histogram or
+    /// value counts with `group_by`.
+    /// The weights are part of the state, so merges carry them over.
+    fn weighted<GetWeight>(self, get_weight: GetWeight) -> Weighted<Self, GetWeight>
+    where
+        Self: Sized + StepWeighted + Fold,
+        GetWeight: Fn(&Self::A) -> Self::Weight,
+    {
+        Weighted {
+            inner: self,
+            get_weight,
+        }
+    }
+
+    /// Fold over `Result`s, aggregating the `Ok` values and
+    /// collecting the errors on the side.
+    /// The resulting output type is a pair of the inner output, `None`
+    /// if there were no `Ok` values, and an `ErrorSummary`.
+    fn partition_errors<E>(self) -> PartitionErrors<Self, E>
+    where
+        Self: Sized,
+    {
+        PartitionErrors {
+            inner: self.optional(),
+            ghost: PhantomData,
+        }
+    }
+
+    /// Call `callback` with the number of elements a state has
+    /// processed each time it has processed another `every` elements.
+    /// Each state counts for itself, so parallel runners report the
+    /// progress of each partial state.
+    fn with_progress<C>(self, every: usize, callback: C) -> WithProgress<Self, C>
+    where
+        Self: Sized,
+        C: Fn(usize),
+    {
+        assert!(every > 0, "with_progress: every must be positive");
+        WithProgress {
+            inner: self,
+            every,
+            #[cfg(feature = "std")]
+            period: None,
+            callback,
+        }
+    }
+
+    /// Like `with_progress`, but calling `callback` once at least
+    /// `period` has passed since a state last reported
+    #[cfg(feature = "std")]
+    fn with_progress_timed<C>(self, period: Duration, callback: C) -> WithProgress<Self, C>
+    where
+        Self: Sized,
+        C: Fn(usize),
+    {
+        WithProgress {
+            inner: self,
+            every: usize::MAX,
+            period: Some(period),
+            callback,
+        }
     }
 }
 
@@ -142,494 +590,4722 @@ pub trait FoldPar: Fold1 {
     fn merge(&self, m1: &mut Self::M, m2: Self::M);
 }
 
-pub fn run_fold_iter<I, O>(fold: &impl Fold<A = I, B = O>, xs: impl Iterator<Item = I>) -> O {
-    let mut acc = fold.empty();
-    xs.for_each(|i| fold.step(i, &mut acc));
-    fold.output(acc)
+/// Folds whose merges need the position of the values of the second
+/// state, such as the index of the minimum, so that they are correct
+/// when run in parallel, see `run_fold_par_iter_ordered`
+pub trait FoldParOrdered: Fold1 {
+    /// Merge `m2` into `m1`, where the values of `m2` directly follow
+    /// those of `m1`, of which there were `offset`
+    fn merge_at(&self, m1: &mut Self::M, m2: Self::M, offset: usize);
 }
 
-pub fn run_fold1_iter<I, O>(
-    fold: &impl Fold1<A = I, B = O>,
-    mut xs: impl Iterator<Item = I>,
-) -> Option<O> {
-    if let Some(first) = xs.next() {
-        let mut acc = fold.init(first);
-        xs.for_each(|i| fold.step(i, &mut acc));
-        Some(fold.output(acc))
-    } else {
-        None
-    }
-}
+/// The parts of a fold which has an empty state, for implementing
+/// `Fold1` and `Fold` without a separate `init`, see `Fold1From`
+pub trait FoldImpl {
+    type A;
+    type B;
+    type M;
 
-/// Run a fold over a stream of values
-pub async fn run_fold_stream<O, I>(fold: &impl Fold<A = I, B = O>, xs: impl Stream<Item = I>) -> O {
-    fold.output(
-        xs.fold(fold.empty(), |mut acc, x| async move {
-            fold.step(x, &mut acc);
-            acc
-        })
-        .await,
-    )
+    fn empty(&self) -> Self::M;
+    fn step(&self, x: Self::A, acc: &mut Self::M);
+    fn output(&self, acc: Self::M) -> Self::B;
 }
 
-/// Run a fold over a stream of values in parallel
-pub async fn run_fold_par_stream<O, I, F>(
-    fold: &F,
-    j: usize,
-    xs: impl StreamExt<Item = I>,
-) -> Option<O>
-where
-    F: Fold<A = I, B = O> + FoldPar + Send + Sync + Clone + 'static,
-    F::M: Send + Sync,
-    I: Send + 'static,
-{
-    Some(
-        fold.output(
-            xs.map(move |x| {
-                let f = fold.clone();
-                tokio::task::spawn_blocking(move || f.init(x))
-            })
-            .buffered(j)
-            .fold(fold.empty(), |mut m1, m2| async move {
-                if let Ok(m2) = m2 {
-                    fold.merge(&mut m1, m2);
-                }
-                m1
-            })
-            .await,
-        ),
-    )
+/// A `FoldImpl` whose states can be merged
+pub trait FoldImplPar: FoldImpl {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M);
 }
 
-/// Run a fold over a parallel iterator of values
-pub fn run_fold_par_iter<I, O, F>(iter: impl IndexedParallelIterator<Item = I>, fold: &F) -> O
-where
-    F: FoldPar + Fold<A = I, B = O> + Sync,
-    F::M: Send,
-{
-    fold.output(
-        iter.chunks(1024)
-            .map(|ch| {
-                let mut acc = fold.empty();
-                ch.into_iter().for_each(|i| fold.step(i, &mut acc));
-                acc
-            })
-            .reduce(
-                || fold.empty(),
-                |mut m1, m2| {
-                    fold.merge(&mut m1, m2);
-                    m1
-                },
-            ),
-    )
+/// Fold built from a `FoldImpl`, where `init` is derived as
+/// `empty` followed by `step` so that the two cannot disagree
+#[derive(Copy, Clone)]
+pub struct Fold1From<F> {
+    inner: F,
 }
 
-pub fn run_fold1_par_iter<I, O, F>(
-    iter: impl IndexedParallelIterator<Item = I>,
-    fold: &F,
-) -> Option<O>
-where
-    F: FoldPar + Fold<A = I, B = O> + Sync,
-    F::M: Send + Copy,
-    I: Copy,
-{
-    let mut accs: Vec<_> = iter
-        .chunks(1024)
-        .map(|mut ch| {
-            let rest = ch.drain(1..).collect();
-            let x0 = ch.get(0)?;
-            let mut acc = fold.init(*x0);
-            fold.step_chunk(rest, &mut acc);
-            Some(acc)
-        })
-        .filter_map(|x| x)
-        .collect();
-
-    // let mut a0 = accs.get(0)?;
-    // let mut a0 = accs[0];
-    let (a0, rest) = accs.split_first_mut()?;
-    for a in rest {
-        fold.merge(a0, *a);
+impl<F: FoldImpl> Fold1From<F> {
+    pub fn new(inner: F) -> Self {
+        Fold1From { inner }
     }
-    Some(fold.output(*a0))
-}
-
-#[derive(Copy, Clone)]
-pub struct Par2<F1, F2> {
-    f1: F1,
-    f2: F2,
 }
 
-impl<I: Copy, F1: Fold1<A = I>, F2: Fold1<A = I>> Fold1 for Par2<F1, F2> {
-    type A = I;
-    type B = (F1::B, F2::B);
-    type M = (F1::M, F2::M);
+impl<F: FoldImpl> Fold1 for Fold1From<F> {
+    type A = F::A;
+    type B = F::B;
+    type M = F::M;
 
+    #[inline]
     fn init(&self, x: Self::A) -> Self::M {
-        (self.f1.init(x), self.f2.init(x))
+        let mut acc = self.inner.empty();
+        self.inner.step(x, &mut acc);
+        acc
     }
 
-    fn step(&self, x: Self::A, (acc1, acc2): &mut (<F1 as Fold1>::M, <F2 as Fold1>::M)) {
-        self.f1.step(x, acc1);
-        self.f2.step(x, acc2);
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, acc)
     }
 
-    fn step_chunk(&self, xs: Vec<Self::A>, (acc1, acc2): &mut Self::M)
-    where
-        Self::A: Copy,
-    {
-        self.f1.step_chunk(xs.clone(), acc1);
-        self.f2.step_chunk(xs, acc2);
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
     }
+}
 
-    fn output(&self, (acc1, acc2): Self::M) -> Self::B {
-        (self.f1.output(acc1), self.f2.output(acc2))
+impl<F: FoldImpl> Fold for Fold1From<F> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
     }
 }
 
-impl<I: Copy, F1: Fold<A = I>, F2: Fold<A = I>> Fold for Par2<F1, F2> {
+impl<F: FoldImplPar> FoldPar for Fold1From<F> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Fold from an initial state and a closure stepping it, like the
+/// arguments to `Iterator::fold`, see `fold_fn_mut`
+pub struct FoldFnMut<M, A, Func> {
+    init: M,
+    func: RefCell<Func>,
+    ghost: PhantomData<fn(A)>,
+}
+
+impl<M: Clone, A, Func: Clone> Clone for FoldFnMut<M, A, Func> {
+    fn clone(&self) -> Self {
+        FoldFnMut {
+            init: self.init.clone(),
+            func: self.func.clone(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+/// Fold with the state `init` and the step `func`, whose output is the
+/// final state. The closure may mutate its captures, so the fold cannot
+/// be stepped from more than one place at once and has no `FoldPar`.
+pub fn fold_fn_mut<M: Clone, A, Func: FnMut(&mut M, A)>(
+    init: M,
+    func: Func,
+) -> FoldFnMut<M, A, Func> {
+    FoldFnMut {
+        init,
+        func: RefCell::new(func),
+        ghost: PhantomData,
+    }
+}
+
+impl<M: Clone, A, Func: FnMut(&mut M, A)> Fold1 for FoldFnMut<M, A, Func> {
+    type A = A;
+    type B = M;
+    type M = M;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.init.clone();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        (self.func.borrow_mut())(acc, x)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+impl<M: Clone, A, Func: FnMut(&mut M, A)> Fold for FoldFnMut<M, A, Func> {
+    #[inline]
     fn empty(&self) -> Self::M {
-        (self.f1.empty(), self.f2.empty())
+        self.init.clone()
     }
 }
 
-impl<F1: FoldPar, F2: FoldPar> FoldPar for Par2<F1, F2>
+/// Folds which can report how their output changed with each value
+pub trait Incremental: Fold1 {
+    /// Description of a change to the output
+    type Delta;
+
+    /// Step the state, returning how the output changed, if at all
+    fn step_delta(&self, x: Self::A, acc: &mut Self::M) -> Option<Self::Delta>;
+}
+
+/// Folds which can step a value as though it were seen many times,
+/// see `Fold1::weighted`
+pub trait StepWeighted: Fold1 {
+    type Weight: Copy;
+
+    fn step_weighted(&self, x: Self::A, weight: Self::Weight, acc: &mut Self::M);
+}
+
+/// Folds which can estimate the memory held by their state
+pub trait StateSize: Fold1 {
+    /// Approximate size of `m` in bytes, including the heap memory
+    /// it owns
+    fn state_bytes(&self, m: &Self::M) -> usize;
+}
+
+/// Folds whose state can be stepped through a shared reference with
+/// atomic operations, so that many threads can step it without a lock,
+/// see `channel::ShardedFold`
+pub trait AtomicFold: Fold + FoldPar {
+    /// The state as atomics
+    type Atomic: Send + Sync;
+
+    fn empty_atomic(&self) -> Self::Atomic;
+
+    fn step_atomic(&self, x: Self::A, acc: &Self::Atomic);
+
+    /// The current state, leaving `acc` as it is
+    fn load(&self, acc: &Self::Atomic) -> Self::M;
+
+    /// The current state, leaving `acc` empty
+    fn take(&self, acc: &Self::Atomic) -> Self::M;
+}
+
+/// Folds behind references and smart pointers are folds, so that one
+/// fold can be shared between threads or tasks by `Arc` rather than
+/// cloned for each
+macro_rules! impl_fold_for_pointer {
+    ($($ptr:ty),*) => {$(
+        impl<F: Fold1 + ?Sized> Fold1 for $ptr {
+            type A = F::A;
+            type B = F::B;
+            type M = F::M;
+
+            #[inline]
+            fn init(&self, x: Self::A) -> Self::M {
+                (**self).init(x)
+            }
+
+            #[inline]
+            fn step(&self, x: Self::A, acc: &mut Self::M) {
+                (**self).step(x, acc)
+            }
+
+            #[inline]
+            fn output(&self, acc: Self::M) -> Self::B {
+                (**self).output(acc)
+            }
+
+            #[inline]
+            fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+                (**self).step_chunk(xs, acc)
+            }
+        }
+
+        impl<F: Fold + ?Sized> Fold for $ptr {
+            #[inline]
+            fn empty(&self) -> Self::M {
+                (**self).empty()
+            }
+        }
+
+        impl<F: FoldPar + ?Sized> FoldPar for $ptr {
+            #[inline]
+            fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+                (**self).merge(m1, m2)
+            }
+        }
+
+        impl<F: FoldParOrdered + ?Sized> FoldParOrdered for $ptr {
+            #[inline]
+            fn merge_at(&self, m1: &mut Self::M, m2: Self::M, offset: usize) {
+                (**self).merge_at(m1, m2, offset)
+            }
+        }
+
+        impl<F: Incremental + ?Sized> Incremental for $ptr {
+            type Delta = F::Delta;
+
+            #[inline]
+            fn step_delta(&self, x: Self::A, acc: &mut Self::M) -> Option<Self::Delta> {
+                (**self).step_delta(x, acc)
+            }
+        }
+
+        impl<F: StateSize + ?Sized> StateSize for $ptr {
+            #[inline]
+            fn state_bytes(&self, m: &Self::M) -> usize {
+                (**self).state_bytes(m)
+            }
+        }
+
+        #[cfg(feature = "std")]
+        impl<F: FoldDescribe + ?Sized> FoldDescribe for $ptr {
+            fn describe_structure(&self) -> Structure {
+                (**self).describe_structure()
+            }
+        }
+    )*};
+}
+
+impl_fold_for_pointer!(&F, Box<F>, Rc<F>, Arc<F>);
+
+/// Heap memory owned by a state, i.e. its size less its inline size
+pub(crate) fn heap_bytes<F: StateSize>(fold: &F, m: &F::M) -> usize {
+    fold.state_bytes(m).saturating_sub(size_of::<F::M>())
+}
+
+pub fn run_fold_iter<I, O>(fold: &impl Fold<A = I, B = O>, xs: impl Iterator<Item = I>) -> O {
+    let mut acc = fold.empty();
+    xs.for_each(|i| fold.step(i, &mut acc));
+    fold.output(acc)
+}
+
+/// Merge partial states into the final output without stepping any
+/// values, such as the states produced by the map side of a map-reduce
+/// job. Serialized states should be deserialized into `F::M` first,
+/// see `try_merge_outputs`.
+pub fn merge_outputs<F: Fold + FoldPar>(fold: &F, states: impl IntoIterator<Item = F::M>) -> F::B {
+    let mut acc = fold.empty();
+    for m in states {
+        fold.merge(&mut acc, m);
+    }
+    fold.output(acc)
+}
+
+/// Merge partial states like `merge_outputs`, stopping at the first
+/// error, such as one from deserializing a state
+pub fn try_merge_outputs<F: Fold + FoldPar, E>(
+    fold: &F,
+    states: impl IntoIterator<Item = Result<F::M, E>>,
+) -> Result<F::B, E> {
+    let mut acc = fold.empty();
+    for m in states {
+        fold.merge(&mut acc, m?);
+    }
+    Ok(fold.output(acc))
+}
+
+/// Merge states pairwise as a balanced tree, keeping their order.
+/// The merges at each level of the tree run in parallel on scoped
+/// threads, so heavy states such as large grouped maps are not all
+/// merged into one on a single thread. Once a level no longer splits
+/// across threads its states are merged in order, as a tree does more
+/// work than merging into one state when it is not parallel.
+#[cfg(feature = "std")]
+pub fn merge_tree<F>(fold: &F, states: impl IntoIterator<Item = F::M>) -> Option<F::M>
 where
-    Par2<F1, F2>: Fold1<M = (F1::M, F2::M)>,
+    F: FoldPar + Sync,
+    F::M: Send,
 {
-    fn merge(&self, (m11, m12): &mut Self::M, (m21, m22): Self::M) {
-        self.f1.merge(m11, m21);
-        self.f2.merge(m12, m22);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    merge_tree_on(fold, states.into_iter().collect(), threads)
+}
+
+#[cfg(feature = "std")]
+fn merge_tree_on<F>(fold: &F, mut level: Vec<F::M>, threads: usize) -> Option<F::M>
+where
+    F: FoldPar + Sync,
+    F::M: Send,
+{
+    loop {
+        // an even number of states for each thread
+        let per_thread = 2 * level.len().div_ceil(2).div_ceil(threads);
+        if per_thread >= level.len() {
+            break;
+        }
+        let mut batches = Vec::new();
+        let mut states = level.into_iter();
+        loop {
+            let batch: Vec<_> = states.by_ref().take(per_thread).collect();
+            if batch.is_empty() {
+                break;
+            }
+            batches.push(batch);
+        }
+        level = std::thread::scope(|s| {
+            let handles: Vec<_> = batches
+                .into_iter()
+                .map(|batch| s.spawn(move || merge_pairs(fold, batch)))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+                .collect()
+        });
+    }
+    level.into_iter().reduce(|mut m1, m2| {
+        #[cfg(feature = "tracing")]
+        tracing::trace!("merge");
+        fold.merge(&mut m1, m2);
+        m1
+    })
+}
+
+/// Merge each pair of adjacent states, halving their number
+#[cfg(feature = "std")]
+fn merge_pairs<F: FoldPar>(fold: &F, states: Vec<F::M>) -> Vec<F::M> {
+    let mut merged = Vec::with_capacity(states.len().div_ceil(2));
+    let mut states = states.into_iter();
+    while let Some(mut m1) = states.next() {
+        if let Some(m2) = states.next() {
+            #[cfg(feature = "tracing")]
+            tracing::trace!("merge");
+            fold.merge(&mut m1, m2);
+        }
+        merged.push(m1);
     }
+    merged
 }
 
-#[derive(Copy, Clone)]
-pub struct FilteredFold<F, P> {
-    inner: F,
-    pred: P,
+/// States merged pairwise as they arrive, like the carries of a binary
+/// counter, so that a stream of states is merged as a balanced tree
+/// while holding a logarithmic number of them
+#[cfg(feature = "async")]
+struct PairwiseMerge<M> {
+    /// States with the height of the tree they were merged from,
+    /// earliest first
+    stack: Vec<(u32, M)>,
 }
 
-impl<F: Fold1, P: Fn(&F::A) -> bool> Fold1 for FilteredFold<F, P> {
-    type A = F::A;
-    type B = F::B;
-    type M = F::M;
+#[cfg(feature = "async")]
+impl<M> PairwiseMerge<M> {
+    fn new() -> Self {
+        PairwiseMerge { stack: Vec::new() }
+    }
+
+    fn push<F: FoldPar<M = M>>(&mut self, fold: &F, mut m: M) {
+        let mut height = 0;
+        while self.stack.last().is_some_and(|(h, _)| *h == height) {
+            let (_, mut earlier) = self.stack.pop().unwrap();
+            #[cfg(feature = "tracing")]
+            tracing::trace!("merge");
+            fold.merge(&mut earlier, m);
+            m = earlier;
+            height += 1;
+        }
+        self.stack.push((height, m));
+    }
+
+    fn finish<F: FoldPar<M = M>>(self, fold: &F) -> Option<M> {
+        self.stack
+            .into_iter()
+            .rev()
+            .map(|(_, m)| m)
+            .reduce(|m2, mut m1| {
+                fold.merge(&mut m1, m2);
+                m1
+            })
+    }
+}
+
+pub fn run_fold1_iter<I, O>(
+    fold: &impl Fold1<A = I, B = O>,
+    mut xs: impl Iterator<Item = I>,
+) -> Option<O> {
+    if let Some(first) = xs.next() {
+        let mut acc = fold.init(first);
+        xs.for_each(|i| fold.step(i, &mut acc));
+        Some(fold.output(acc))
+    } else {
+        None
+    }
+}
+
+/// Run a fold over a stream of values
+#[cfg(feature = "async")]
+pub async fn run_fold_stream<O, I>(fold: &impl Fold<A = I, B = O>, xs: impl Stream<Item = I>) -> O {
+    fold.output(
+        xs.fold(fold.empty(), |mut acc, x| async move {
+            fold.step(x, &mut acc);
+            acc
+        })
+        .await,
+    )
+}
+
+/// Run a fold over a stream of values in parallel, on tokio's
+/// blocking thread pool. The fold is cloned once and shared by the
+/// workers, and folds which are not `Clone` can be run as an `Arc`.
+#[cfg(feature = "async")]
+pub async fn run_fold_par_stream<O, I, F>(
+    fold: &F,
+    j: usize,
+    xs: impl StreamExt<Item = I>,
+) -> Result<O, Error>
+where
+    F: Fold<A = I, B = O> + FoldPar + Send + Sync + Clone + 'static,
+    F::M: Send + Sync,
+    I: Send + 'static,
+{
+    run_fold_par_stream_with(&Tokio, fold, j, xs).await
+}
+
+/// Run a fold over a stream of values in parallel, with up to `j`
+/// values being folded at once by `spawner`. The states are merged
+/// pairwise as a tree as they arrive. Stops at the first worker which
+/// fails.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(j)))]
+#[cfg(feature = "async")]
+pub async fn run_fold_par_stream_with<O, I, F, S>(
+    spawner: &S,
+    fold: &F,
+    j: usize,
+    xs: impl StreamExt<Item = I>,
+) -> Result<O, Error>
+where
+    F: Fold<A = I, B = O> + FoldPar + Send + Sync + Clone + 'static,
+    F::M: Send + Sync,
+    I: Send + 'static,
+    S: Spawner,
+{
+    let shared = Arc::new(fold.clone());
+    let acc = xs
+        .map(move |x| {
+            let f = Arc::clone(&shared);
+            spawner.spawn_blocking(move || f.init(x))
+        })
+        .buffered(j)
+        .try_fold(PairwiseMerge::new(), |mut merged, m| async move {
+            merged.push(fold, m);
+            Ok(merged)
+        })
+        .await?;
+    Ok(fold.output(acc.finish(fold).unwrap_or_else(|| fold.empty())))
+}
+
+/// What a runner over fallible values does with the errors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop at the first error, failing the run with `Error::Source`
+    #[default]
+    FailFast,
+    /// Skip the values which failed, only counting them
+    SkipAndCount,
+    /// Skip the values which failed, keeping their errors
+    CollectErrors,
+}
+
+/// Run a fold over a stream of fallible values in parallel, on tokio's
+/// blocking thread pool. See `run_fold_par_try_stream_with`.
+#[cfg(feature = "async")]
+pub async fn run_fold_par_try_stream<O, I, E, F>(
+    fold: &F,
+    j: usize,
+    policy: ErrorPolicy,
+    xs: impl StreamExt<Item = Result<I, E>>,
+) -> Result<(O, ErrorSummary<E>), Error>
+where
+    F: Fold<A = I, B = O> + FoldPar + Send + Sync + Clone + 'static,
+    F::M: Send + Sync,
+    I: Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+{
+    run_fold_par_try_stream_with(&Tokio, fold, j, policy, xs).await
+}
+
+/// Run a fold over a stream of fallible values in parallel, like
+/// `run_fold_par_stream_with`, handling errors by `policy`.
+/// The output comes with a summary of the errors which were skipped,
+/// which is empty when failing fast.
+#[cfg(feature = "async")]
+pub async fn run_fold_par_try_stream_with<O, I, E, F, S>(
+    spawner: &S,
+    fold: &F,
+    j: usize,
+    policy: ErrorPolicy,
+    xs: impl StreamExt<Item = Result<I, E>>,
+) -> Result<(O, ErrorSummary<E>), Error>
+where
+    F: Fold<A = I, B = O> + FoldPar + Send + Sync + Clone + 'static,
+    F::M: Send + Sync,
+    I: Send + 'static,
+    E: std::error::Error + Send + Sync + 'static,
+    S: Spawner,
+{
+    let mut errors = ErrorSummary::new();
+    let errors_mut = &mut errors;
+    let shared = Arc::new(fold.clone());
+    let acc = xs
+        .filter_map(move |x| {
+            let x = match (x, policy) {
+                (Ok(x), _) => Some(Ok(x)),
+                (Err(e), ErrorPolicy::FailFast) => Some(Err(Error::Source(Box::new(e)))),
+                (Err(_), ErrorPolicy::SkipAndCount) => {
+                    errors_mut.count += 1;
+                    None
+                }
+                (Err(e), ErrorPolicy::CollectErrors) => {
+                    errors_mut.push(e);
+                    None
+                }
+            };
+            futures::future::ready(x)
+        })
+        .map(move |x| {
+            let f = Arc::clone(&shared);
+            async move {
+                let x = x?;
+                spawner.spawn_blocking(move || f.init(x)).await
+            }
+        })
+        .buffered(j)
+        .try_fold(PairwiseMerge::new(), |mut merged, m| async move {
+            merged.push(fold, m);
+            Ok(merged)
+        })
+        .await?;
+    let acc = acc.finish(fold).unwrap_or_else(|| fold.empty());
+    Ok((fold.output(acc), errors))
+}
+
+/// Run a fresh fold over each window of `size` consecutive values,
+/// starting every `step` values, yielding the output of each window.
+/// Windows overlap when `step < size`, and windows which would run
+/// past the end of the input are not folded.
+pub fn run_fold_windows_iter<'f, F>(
+    fold: &'f F,
+    xs: impl Iterator<Item = F::A> + 'f,
+    size: usize,
+    step: usize,
+) -> impl Iterator<Item = F::B> + 'f
+where
+    F: Fold,
+    F::A: Clone,
+{
+    assert!(
+        size > 0 && step > 0,
+        "run_fold_windows_iter: size and step must be positive"
+    );
+    let mut xs = xs.fuse();
+    let mut window = VecDeque::with_capacity(size);
+    // values between windows, when they do not overlap
+    let mut gap = 0;
+    core::iter::from_fn(move || {
+        while gap > 0 {
+            xs.next()?;
+            gap -= 1;
+        }
+        while window.len() < size {
+            window.push_back(xs.next()?);
+        }
+        let mut acc = fold.empty();
+        fold.step_chunk(window.iter().cloned().collect(), &mut acc);
+        window.drain(..step.min(size));
+        gap = step.saturating_sub(size);
+        Some(fold.output(acc))
+    })
+}
+
+/// Run a fold over the segments of an iterator, restarting it at each
+/// value for which `is_boundary` fires and yielding the output of each
+/// segment. The boundary value begins the next segment, so delimiters
+/// such as blank lines should be filtered out by the fold.
+/// `is_boundary` sees every value, so it may track state such as the
+/// current day, to split on day rollovers.
+pub fn run_fold_segments<'f, F>(
+    fold: &'f F,
+    xs: impl Iterator<Item = F::A> + 'f,
+    mut is_boundary: impl FnMut(&F::A) -> bool + 'f,
+) -> impl Iterator<Item = F::B> + 'f
+where
+    F: Fold,
+{
+    let mut xs = xs.fuse();
+    // segments start from `empty` rather than `init`, which filtered
+    // folds do not filter
+    let mut acc: Option<F::M> = None;
+    core::iter::from_fn(move || {
+        for x in xs.by_ref() {
+            let boundary = is_boundary(&x);
+            let done = match acc {
+                Some(_) if boundary => acc.replace(fold.empty()),
+                _ => None,
+            };
+            fold.step(x, acc.get_or_insert_with(|| fold.empty()));
+            if let Some(m) = done {
+                return Some(fold.output(m));
+            }
+        }
+        acc.take().map(|m| fold.output(m))
+    })
+}
+
+/// Run a fold over an iterator, calling `on_size` with the size of
+/// the state after every `every` values, and once more at the end for
+/// any values since, so that memory use can be reported or capped.
+/// Returning `ControlFlow::Break` from `on_size` stops the run with the
+/// output so far.
+pub fn run_fold_iter_sized<F>(
+    fold: &F,
+    xs: impl Iterator<Item = F::A>,
+    every: usize,
+    mut on_size: impl FnMut(usize) -> ControlFlow<()>,
+) -> Outcome<F::B>
+where
+    F: Fold + StateSize,
+{
+    assert!(every > 0, "run_fold_iter_sized: every must be positive");
+    let mut acc = fold.empty();
+    let mut n = 0;
+    for x in xs {
+        fold.step(x, &mut acc);
+        n += 1;
+        if n % every == 0 && on_size(fold.state_bytes(&acc)).is_break() {
+            return Outcome::Cancelled(fold.output(acc));
+        }
+    }
+    if n % every != 0 {
+        // the whole input was consumed, so the run is complete either way
+        let _ = on_size(fold.state_bytes(&acc));
+    }
+    Outcome::Complete(fold.output(acc))
+}
+
+/// Limits on a run, see `run_fold_iter_bounded`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RunOptions {
+    /// Fail the run once its state is estimated to hold more bytes,
+    /// as reported by `StateSize`
+    pub max_state_bytes: Option<usize>,
+    /// Number of values between estimates of the size of the state,
+    /// as estimating it may walk the whole state
+    pub check_every: usize,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            max_state_bytes: None,
+            check_every: 1024,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl RunOptions {
+    fn check(&self, bytes: usize) -> Result<(), Error> {
+        match self.max_state_bytes {
+            Some(max) if bytes > max => Err(Error::StateTooLarge { bytes, max }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Run a fold over an iterator within the limits of `options`, failing
+/// with `Error::StateTooLarge` once the state outgrows its budget, so
+/// that a service is not brought down by an unexpectedly large input.
+/// The state is checked by `run_fold_iter_sized` every
+/// `options.check_every` values and at the end.
+#[cfg(feature = "std")]
+pub fn run_fold_iter_bounded<F>(
+    fold: &F,
+    xs: impl Iterator<Item = F::A>,
+    options: RunOptions,
+) -> Result<F::B, Error>
+where
+    F: Fold + StateSize,
+{
+    assert!(
+        options.check_every > 0,
+        "run_fold_iter_bounded: check_every must be positive"
+    );
+    if options.max_state_bytes.is_none() {
+        return Ok(run_fold_iter(fold, xs));
+    }
+    let mut err = None;
+    let out = run_fold_iter_sized(fold, xs, options.check_every, |bytes| {
+        match options.check(bytes) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(e) => {
+                err = Some(e);
+                ControlFlow::Break(())
+            }
+        }
+    });
+    match err {
+        Some(e) => Err(e),
+        None => Ok(out.into_inner()),
+    }
+}
+
+/// Run a fold over a stream of values within the limits of `options`,
+/// like `run_fold_iter_bounded`
+#[cfg(feature = "async")]
+pub async fn run_fold_stream_bounded<F>(
+    fold: &F,
+    xs: impl Stream<Item = F::A>,
+    options: RunOptions,
+) -> Result<F::B, Error>
+where
+    F: Fold + StateSize,
+{
+    assert!(
+        options.check_every > 0,
+        "run_fold_stream_bounded: check_every must be positive"
+    );
+    if options.max_state_bytes.is_none() {
+        return Ok(run_fold_stream(fold, xs).await);
+    }
+    let mut acc = fold.empty();
+    let mut xs = pin!(xs.chunks(options.check_every));
+    while let Some(chunk) = xs.next().await {
+        fold.step_chunk(chunk, &mut acc);
+        options.check(fold.state_bytes(&acc))?;
+    }
+    Ok(fold.output(acc))
+}
+
+/// Run a fold over an iterator in chunks of `chunk_size`, also
+/// returning how much was consumed and how long it took, so that
+/// throughput can be logged
+#[cfg(feature = "std")]
+pub fn run_fold_with_stats<F: Fold>(
+    fold: &F,
+    xs: impl IntoIterator<Item = F::A>,
+    chunk_size: usize,
+) -> (F::B, RunStats) {
+    assert!(
+        chunk_size > 0,
+        "run_fold_with_stats: chunk_size must be positive"
+    );
+    let start = Instant::now();
+    let mut stats = RunStats::default();
+    let mut acc = fold.empty();
+    let mut xs = xs.into_iter();
+    loop {
+        let chunk: Vec<_> = xs.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        stats.items += chunk.len();
+        stats.chunks += 1;
+        fold.step_chunk(chunk, &mut acc);
+    }
+    let out = fold.output(acc);
+    stats.duration = start.elapsed();
+    (out, stats)
+}
+
+/// What a run consumed, see `run_fold_with_stats`
+#[cfg(feature = "std")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunStats {
+    /// Number of values folded
+    pub items: usize,
+    /// Number of chunks the values were folded in
+    pub chunks: usize,
+    /// Time taken, including computing the output
+    pub duration: Duration,
+}
+
+#[cfg(feature = "std")]
+impl RunStats {
+    /// Values folded per second, `None` if the run took no measurable
+    /// time
+    pub fn throughput(&self) -> Option<f64> {
+        let secs = self.duration.as_secs_f64();
+        (secs > 0.0).then(|| self.items as f64 / secs)
+    }
+}
+
+/// Output of a runner which can be stopped early
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome<B> {
+    /// The whole input was consumed
+    Complete(B),
+    /// The runner was stopped, and this is the output for the
+    /// input consumed up to that point
+    Cancelled(B),
+}
+
+impl<B> Outcome<B> {
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Outcome::Complete(_))
+    }
+
+    /// The output, whether or not it is complete
+    pub fn into_inner(self) -> B {
+        match self {
+            Outcome::Complete(b) | Outcome::Cancelled(b) => b,
+        }
+    }
+
+    /// The output if it is complete, and `Error::Cancelled` otherwise
+    #[cfg(feature = "std")]
+    pub fn into_result(self) -> Result<B, Error> {
+        match self {
+            Outcome::Complete(b) => Ok(b),
+            Outcome::Cancelled(_) => Err(Error::Cancelled),
+        }
+    }
+}
+
+/// Run a fold over a stream of values until the stream ends or
+/// `cancel` resolves, whichever comes first. `cancel` can be any
+/// future, such as a timer or a cancellation token's `cancelled()`.
+#[cfg(feature = "async")]
+pub async fn run_fold_stream_cancellable<O, I>(
+    fold: &impl Fold<A = I, B = O>,
+    xs: impl Stream<Item = I>,
+    cancel: impl Future<Output = ()>,
+) -> Outcome<O> {
+    let (xs, cancel) = (pin!(xs), pin!(cancel));
+    let mut xs = xs.take_until(cancel);
+    let mut acc = fold.empty();
+    while let Some(x) = xs.next().await {
+        fold.step(x, &mut acc);
+    }
+    if xs.take_result().is_some() {
+        Outcome::Cancelled(fold.output(acc))
+    } else {
+        Outcome::Complete(fold.output(acc))
+    }
+}
+
+/// Run a fold over a stream of values, stopping at `timeout` if the
+/// stream has not ended by then. The outcome is `Cancelled` with the
+/// partial output when the deadline was hit.
+#[cfg(feature = "async")]
+pub async fn run_fold_stream_timeout<O, I>(
+    fold: &impl Fold<A = I, B = O>,
+    xs: impl Stream<Item = I>,
+    timeout: Duration,
+) -> Outcome<O> {
+    run_fold_stream_cancellable(fold, xs, tokio::time::sleep(timeout)).await
+}
+
+/// Run a fold over a stream of values, yielding the output so far
+/// after every `n` values and once more at the end of the stream
+#[cfg(feature = "async")]
+pub fn emit_every<'f, F>(
+    fold: &'f F,
+    xs: impl Stream<Item = F::A> + 'f,
+    n: usize,
+) -> impl Stream<Item = F::B> + 'f
+where
+    F: Fold,
+    F::M: Clone,
+{
+    assert!(n > 0, "emit_every: n must be positive");
+    let state = Some((Box::pin(xs.chunks(n)), fold.empty()));
+    futures::stream::unfold(state, move |state| async move {
+        let (mut xs, mut acc) = state?;
+        match xs.next().await {
+            Some(xs_n) if xs_n.len() == n => {
+                fold.step_chunk(xs_n, &mut acc);
+                let b = fold.output(acc.clone());
+                Some((b, Some((xs, acc))))
+            }
+            // a short chunk is the end of the stream
+            Some(xs_n) => {
+                fold.step_chunk(xs_n, &mut acc);
+                Some((fold.output(acc), None))
+            }
+            None => Some((fold.output(acc), None)),
+        }
+    })
+}
+
+/// Run a fold over a stream of values, yielding the output so far
+/// every `period` and once more at the end of the stream
+#[cfg(feature = "async")]
+pub fn emit_every_duration<'f, F>(
+    fold: &'f F,
+    xs: impl Stream<Item = F::A> + 'f,
+    period: Duration,
+) -> impl Stream<Item = F::B> + 'f
+where
+    F: Fold,
+    F::M: Clone,
+{
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let state = Some((Box::pin(xs), ticks, fold.empty()));
+    futures::stream::unfold(state, move |state| async move {
+        let (mut xs, mut ticks, mut acc) = state?;
+        loop {
+            let next = match futures::future::select(xs.next(), pin!(ticks.tick())).await {
+                futures::future::Either::Left((x, _)) => Some(x),
+                futures::future::Either::Right(_) => None,
+            };
+            match next {
+                Some(Some(x)) => fold.step(x, &mut acc),
+                Some(None) => return Some((fold.output(acc), None)),
+                None => {
+                    let b = fold.output(acc.clone());
+                    return Some((b, Some((xs, ticks, acc))));
+                }
+            }
+        }
+    })
+}
+
+/// Run a fold over a stream of values in parallel until the stream
+/// ends or `cancel` resolves. Values already handed to a worker when
+/// `cancel` resolves are still folded. Stops at the first worker
+/// which fails.
+#[cfg(feature = "async")]
+pub async fn run_fold_par_stream_cancellable<O, I, F>(
+    fold: &F,
+    j: usize,
+    xs: impl Stream<Item = I>,
+    cancel: impl Future<Output = ()>,
+) -> Result<Outcome<O>, Error>
+where
+    F: Fold<A = I, B = O> + FoldPar + Send + Sync + Clone + 'static,
+    F::M: Send + Sync,
+    I: Send + 'static,
+{
+    let (xs, cancel) = (pin!(xs), pin!(cancel));
+    let mut xs = xs.take_until(cancel);
+    let shared = Arc::new(fold.clone());
+    let mut states = xs
+        .by_ref()
+        .map(|x| {
+            let f = Arc::clone(&shared);
+            Tokio.spawn_blocking(move || f.init(x))
+        })
+        .buffered(j);
+    let mut merged = PairwiseMerge::new();
+    while let Some(m) = states.try_next().await? {
+        merged.push(fold, m);
+    }
+    drop(states);
+    let acc = merged.finish(fold).unwrap_or_else(|| fold.empty());
+    if xs.take_result().is_some() {
+        Ok(Outcome::Cancelled(fold.output(acc)))
+    } else {
+        Ok(Outcome::Complete(fold.output(acc)))
+    }
+}
+
+/// Run a fold over a parallel iterator of values
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+#[cfg(feature = "rayon")]
+pub fn run_fold_par_iter<I, O, F>(iter: impl IndexedParallelIterator<Item = I>, fold: &F) -> O
+where
+    F: FoldPar + Fold<A = I, B = O> + Sync,
+    F::M: Send,
+{
+    fold.output(
+        iter.chunks(1024)
+            .map(|ch| {
+                #[cfg(feature = "tracing")]
+                tracing::trace!(len = ch.len(), "chunk");
+                let mut acc = fold.empty();
+                ch.into_iter().for_each(|i| fold.step(i, &mut acc));
+                acc
+            })
+            .reduce(
+                || fold.empty(),
+                |mut m1, m2| {
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!("merge");
+                    fold.merge(&mut m1, m2);
+                    m1
+                },
+            ),
+    )
+}
+
+/// Run an order-sensitive fold over a parallel iterator of values,
+/// giving each merge the number of values before the second state
+#[cfg(feature = "rayon")]
+pub fn run_fold_par_iter_ordered<I, O, F>(
+    iter: impl IndexedParallelIterator<Item = I>,
+    fold: &F,
+) -> O
+where
+    F: FoldParOrdered + Fold<A = I, B = O> + Sync,
+    F::M: Send,
+{
+    let merged = iter
+        .chunks(1024)
+        .map(|ch| {
+            let len = ch.len();
+            let mut acc = fold.empty();
+            fold.step_chunk(ch, &mut acc);
+            (len, acc)
+        })
+        .reduce_with(|(n1, mut m1), (n2, m2)| {
+            fold.merge_at(&mut m1, m2, n1);
+            (n1 + n2, m1)
+        });
+    fold.output(merged.map_or_else(|| fold.empty(), |(_, m)| m))
+}
+
+/// Run a grouped fold over a parallel iterator of values, partitioning
+/// the values by a hash of their key into `partitions` parts which are
+/// folded in parallel. Each key is in one part, so the groups of the
+/// parts are concatenated rather than merged, which is faster when
+/// there are many keys. The inner fold need not be mergeable.
+/// Each part is folded on a thread of its own as values arrive, sent to
+/// it in chunks over a bounded channel, so memory is bounded by the
+/// groups and the chunks in flight rather than by the input.
+/// Keys are found twice, once to partition and once to group.
+#[cfg(feature = "rayon")]
+pub fn run_fold_par_iter_by_key<F, Key, GetKey>(
+    iter: impl ParallelIterator<Item = F::A>,
+    fold: &GroupedFold<F, GetKey>,
+    partitions: usize,
+) -> FxHashMap<Key, F::B>
+where
+    F: Fold1 + Sync,
+    F::A: Send,
+    F::B: Send,
+    Key: Hash + Eq + Send,
+    GetKey: Fn(&F::A) -> Key + Sync,
+{
+    const CHUNK: usize = 1024;
+    assert!(
+        partitions > 0,
+        "run_fold_par_iter_by_key: partitions must be positive"
+    );
+    std::thread::scope(|s| {
+        let (senders, workers): (Vec<_>, Vec<_>) = (0..partitions)
+            .map(|_| {
+                let (tx, rx) = std::sync::mpsc::sync_channel::<Vec<F::A>>(4);
+                let worker = s.spawn(move || {
+                    let mut acc = FxHashMap::default();
+                    for chunk in rx {
+                        fold.step_chunk(chunk, &mut acc);
+                    }
+                    fold.output(acc)
+                });
+                (tx, worker)
+            })
+            .unzip();
+
+        let empty = || (0..partitions).map(|_| Vec::new()).collect::<Vec<_>>();
+        iter.fold(empty, |mut buckets, x| {
+            let part = (crate::sketch::hash64(&(fold.get_key)(&x)) % partitions as u64) as usize;
+            buckets[part].push(x);
+            if buckets[part].len() == CHUNK {
+                let chunk = core::mem::replace(&mut buckets[part], Vec::with_capacity(CHUNK));
+                senders[part].send(chunk).expect("worker stopped");
+            }
+            buckets
+        })
+        .for_each(|buckets| {
+            for (tx, chunk) in senders.iter().zip(buckets) {
+                if !chunk.is_empty() {
+                    tx.send(chunk).expect("worker stopped");
+                }
+            }
+        });
+        drop(senders);
+
+        let mut out = FxHashMap::default();
+        for worker in workers {
+            match worker.join() {
+                Ok(groups) => out.extend(groups),
+                Err(e) => std::panic::resume_unwind(e),
+            }
+        }
+        out
+    })
+}
+
+/// Run a fold over a parallel iterator of values, with no output for
+/// an empty iterator. The chunk states are merged as a tree, in parallel.
+#[cfg(feature = "rayon")]
+pub fn run_fold1_par_iter<I, O, F>(
+    iter: impl IndexedParallelIterator<Item = I>,
+    fold: &F,
+) -> Option<O>
+where
+    F: FoldPar + Fold<A = I, B = O> + Sync,
+    F::M: Send,
+    I: Send,
+{
+    iter.chunks(1024)
+        .filter_map(|mut ch| {
+            let rest = ch.split_off(1);
+            let mut acc = fold.init(ch.pop()?);
+            fold.step_chunk(rest, &mut acc);
+            Some(acc)
+        })
+        .reduce_with(|mut m1, m2| {
+            #[cfg(feature = "tracing")]
+            tracing::trace!("merge");
+            fold.merge(&mut m1, m2);
+            m1
+        })
+        .map(|acc| fold.output(acc))
+}
+
+#[derive(Copy, Clone)]
+pub struct Par2<F1, F2> {
+    f1: F1,
+    f2: F2,
+}
+
+impl<I: Copy, F1: Fold1<A = I>, F2: Fold1<A = I>> Fold1 for Par2<F1, F2> {
+    type A = I;
+    type B = (F1::B, F2::B);
+    type M = (F1::M, F2::M);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        (self.f1.init(x), self.f2.init(x))
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, (acc1, acc2): &mut (<F1 as Fold1>::M, <F2 as Fold1>::M)) {
+        self.f1.step(x, acc1);
+        self.f2.step(x, acc2);
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, (acc1, acc2): &mut Self::M)
+    where
+        Self::A: Copy,
+    {
+        self.f1.step_chunk(xs.clone(), acc1);
+        self.f2.step_chunk(xs, acc2);
+    }
+
+    #[inline]
+    fn output(&self, (acc1, acc2): Self::M) -> Self::B {
+        (self.f1.output(acc1), self.f2.output(acc2))
+    }
+}
+
+impl<I: Copy, F1: Fold<A = I>, F2: Fold<A = I>> Fold for Par2<F1, F2> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (self.f1.empty(), self.f2.empty())
+    }
+}
+
+impl<F1: FoldPar, F2: FoldPar> FoldPar for Par2<F1, F2>
+where
+    Par2<F1, F2>: Fold1<M = (F1::M, F2::M)>,
+{
+    #[inline]
+    fn merge(&self, (m11, m12): &mut Self::M, (m21, m22): Self::M) {
+        self.f1.merge(m11, m21);
+        self.f2.merge(m12, m22);
+    }
+}
+
+impl<I: Copy, F1, F2> FoldParOrdered for Par2<F1, F2>
+where
+    F1: FoldParOrdered + Fold1<A = I>,
+    F2: FoldParOrdered + Fold1<A = I>,
+{
+    #[inline]
+    fn merge_at(&self, (m11, m12): &mut Self::M, (m21, m22): Self::M, offset: usize) {
+        self.f1.merge_at(m11, m21, offset);
+        self.f2.merge_at(m12, m22, offset);
+    }
+}
+
+/// Each fold is stepped atomically, but not the pair, so a load may see
+/// a value stepped into the first state and not yet into the second
+impl<I: Copy, F1, F2> AtomicFold for Par2<F1, F2>
+where
+    F1: AtomicFold + Fold<A = I>,
+    F2: AtomicFold + Fold<A = I>,
+{
+    type Atomic = (F1::Atomic, F2::Atomic);
+
+    fn empty_atomic(&self) -> Self::Atomic {
+        (self.f1.empty_atomic(), self.f2.empty_atomic())
+    }
+
+    #[inline]
+    fn step_atomic(&self, x: Self::A, (acc1, acc2): &Self::Atomic) {
+        self.f1.step_atomic(x, acc1);
+        self.f2.step_atomic(x, acc2);
+    }
+
+    fn load(&self, (acc1, acc2): &Self::Atomic) -> Self::M {
+        (self.f1.load(acc1), self.f2.load(acc2))
+    }
+
+    fn take(&self, (acc1, acc2): &Self::Atomic) -> Self::M {
+        (self.f1.take(acc1), self.f2.take(acc2))
+    }
+}
+
+impl<I: Copy, W: Copy, F1, F2> StepWeighted for Par2<F1, F2>
+where
+    F1: StepWeighted<A = I, Weight = W>,
+    F2: StepWeighted<A = I, Weight = W>,
+{
+    type Weight = W;
+
+    #[inline]
+    fn step_weighted(&self, x: Self::A, weight: W, (acc1, acc2): &mut Self::M) {
+        self.f1.step_weighted(x, weight, acc1);
+        self.f2.step_weighted(x, weight, acc2);
+    }
+}
+
+/// Two folds in parallel with combined output, see `Fold1::zip_with`
+#[derive(Copy, Clone)]
+pub struct ZipWith<F1, F2, Func> {
+    inner: Par2<F1, F2>,
+    func: Func,
+}
+
+impl<I: Copy, F1: Fold1<A = I>, F2: Fold1<A = I>, B2, Func: Fn(F1::B, F2::B) -> B2> Fold1
+    for ZipWith<F1, F2, Func>
+{
+    type A = I;
+    type B = B2;
+    type M = (F1::M, F2::M);
+
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x)
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, acc)
+    }
+
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        self.inner.step_chunk(xs, acc)
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let (b1, b2) = self.inner.output(acc);
+        (self.func)(b1, b2)
+    }
+}
+
+impl<I: Copy, F1: Fold<A = I>, F2: Fold<A = I>, B2, Func: Fn(F1::B, F2::B) -> B2> Fold
+    for ZipWith<F1, F2, Func>
+{
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<I: Copy, F1, F2, B2, Func> FoldPar for ZipWith<F1, F2, Func>
+where
+    F1: FoldPar<A = I>,
+    F2: FoldPar<A = I>,
+    Func: Fn(F1::B, F2::B) -> B2,
+{
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct FilteredFold<F, P> {
+    inner: F,
+    pred: P,
+}
+
+impl<F: Fold1, P: Fn(&F::A) -> bool> Fold1 for FilteredFold<F, P> {
+    type A = F::A;
+    type B = F::B;
+    type M = F::M;
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        if (self.pred)(&x) {
+            self.inner.step(x, acc)
+        }
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        // This cannot be close to optimal
+        // I should not pay this allocation each time
+        // Consider passing mask to step_chunk
+        let mut xs_in = Vec::with_capacity(xs.len() / 2);
+
+        for x in xs {
+            if (self.pred)(&x) {
+                xs_in.push(x)
+            }
+        }
+        self.inner.step_chunk(xs_in, acc);
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x)
+    }
+}
+
+impl<F: Incremental, P: Fn(&F::A) -> bool> Incremental for FilteredFold<F, P> {
+    type Delta = F::Delta;
+
+    fn step_delta(&self, x: Self::A, acc: &mut Self::M) -> Option<Self::Delta> {
+        if (self.pred)(&x) {
+            self.inner.step_delta(x, acc)
+        } else {
+            None
+        }
+    }
+}
+
+impl<F: Fold, P: Fn(&F::A) -> bool> Fold for FilteredFold<F, P> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<F: FoldPar, P: Fn(&F::A) -> bool> FoldPar for FilteredFold<F, P> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Fold over inputs bounded to a range, see `Fold1::clamp`
+#[derive(Copy, Clone)]
+pub struct Clamped<F: Fold1> {
+    inner: F,
+    lo: F::A,
+    hi: F::A,
+}
+
+impl<F: Fold1> Clamped<F>
+where
+    F::A: PartialOrd + Clone,
+{
+    #[inline]
+    fn bound(&self, x: F::A) -> F::A {
+        if x < self.lo {
+            self.lo.clone()
+        } else if x > self.hi {
+            self.hi.clone()
+        } else {
+            x
+        }
+    }
+}
+
+impl<F: Fold1> Fold1 for Clamped<F>
+where
+    F::A: PartialOrd + Clone,
+{
+    type A = F::A;
+    type B = F::B;
+    type M = F::M;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(self.bound(x))
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(self.bound(x), acc)
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        let xs = xs.into_iter().map(|x| self.bound(x)).collect();
+        self.inner.step_chunk(xs, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<F: Incremental> Incremental for Clamped<F>
+where
+    F::A: PartialOrd + Clone,
+{
+    type Delta = F::Delta;
+
+    fn step_delta(&self, x: Self::A, acc: &mut Self::M) -> Option<Self::Delta> {
+        self.inner.step_delta(self.bound(x), acc)
+    }
+}
+
+impl<F: Fold> Fold for Clamped<F>
+where
+    F::A: PartialOrd + Clone,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<F: FoldPar> FoldPar for Clamped<F>
+where
+    F::A: PartialOrd + Clone,
+{
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Copy, Clone)]
+pub struct GroupedFold<F, GetKey> {
+    inner: F,
+    get_key: GetKey,
+}
+
+#[cfg(feature = "alloc")]
+impl<F: Fold1, Key: Hash + Eq, GetKey: Fn(&F::A) -> Key> Fold1 for GroupedFold<F, GetKey> {
+    type A = F::A;
+    type B = FxHashMap<Key, F::B>;
+    type M = FxHashMap<Key, F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = FxHashMap::default();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        // a single lookup, which either steps or inserts
+        match acc.entry((self.get_key)(&x)) {
+            Entry::Occupied(mut e) => self.inner.step(x, e.get_mut()),
+            Entry::Vacant(e) => {
+                e.insert(self.inner.init(x));
+                #[cfg(feature = "tracing")]
+                if acc.len().is_power_of_two() && acc.len() >= 1024 {
+                    tracing::debug!(
+                        keys = acc.len(),
+                        state_bytes = acc.capacity() * size_of::<(Key, F::M)>(),
+                        "group map grew"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Sorts the chunk by the hash of its keys, so each distinct key
+    /// is looked up once and its values are passed on as one chunk
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        let hasher = acc.hasher().clone();
+        let mut keyed: Vec<_> = xs
+            .into_iter()
+            .map(|x| {
+                let key = (self.get_key)(&x);
+                (hasher.hash_one(&key), key, x)
+            })
+            .collect();
+        // stable, so the values of each key keep their order
+        keyed.sort_by_key(|(h, _, _)| *h);
+
+        let mut keyed = keyed.into_iter().peekable();
+        while let Some((h, key, x)) = keyed.next() {
+            let mut run = Vec::new();
+            while let Some((_, _, x)) = keyed.next_if(|(h2, k2, _)| *h2 == h && *k2 == key) {
+                run.push(x);
+            }
+            let m = match acc.entry(key) {
+                Entry::Occupied(e) => {
+                    let m = e.into_mut();
+                    self.inner.step(x, m);
+                    m
+                }
+                Entry::Vacant(e) => e.insert(self.inner.init(x)),
+            };
+            self.inner.step_chunk(run, m);
+        }
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.into_iter()
+            .map(|(k, m)| (k, self.inner.output(m)))
+            .collect()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey> StepWeighted for GroupedFold<F, GetKey>
+where
+    F: StepWeighted + Fold,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    type Weight = F::Weight;
+
+    #[inline]
+    fn step_weighted(&self, x: Self::A, weight: Self::Weight, acc: &mut Self::M) {
+        let m = acc
+            .entry((self.get_key)(&x))
+            .or_insert_with(|| self.inner.empty());
+        self.inner.step_weighted(x, weight, m)
+    }
+}
+
+/// The delta is the key of the group that was updated, with the delta of
+/// its fold
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey> Incremental for GroupedFold<F, GetKey>
+where
+    F: Incremental + Fold,
+    Key: Hash + Eq + Clone,
+    GetKey: Fn(&F::A) -> Key,
+{
+    type Delta = (Key, F::Delta);
+
+    fn step_delta(&self, x: Self::A, acc: &mut Self::M) -> Option<Self::Delta> {
+        let key = (self.get_key)(&x);
+        let m = acc.entry(key.clone()).or_insert_with(|| self.inner.empty());
+        let delta = self.inner.step_delta(x, m)?;
+        Some((key, delta))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F: Fold, Key: Hash + Eq, GetKey: Fn(&F::A) -> Key> Fold for GroupedFold<F, GetKey> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        FxHashMap::default()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F: FoldPar, Key: Hash + Eq, GetKey: Fn(&F::A) -> Key> FoldPar for GroupedFold<F, GetKey> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        // merging into the identity is common in parallel reductions
+        if m1.is_empty() {
+            *m1 = m2;
+            return;
+        }
+        // partitions tend to share most of their keys, so only reserve
+        // for half of them, as `HashMap::extend` does
+        m1.reserve(m2.len().div_ceil(2));
+        for (k, v) in m2 {
+            match m1.entry(k) {
+                Entry::Occupied(mut e) => self.inner.merge(e.get_mut(), v),
+                Entry::Vacant(e) => {
+                    e.insert(v);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct PreMap<F: Fold1, A2, PreFunc: Fn(A2) -> F::A> {
+    inner: F,
+    pre_func: PreFunc,
+    ghost: PhantomData<A2>,
+}
+
+#[cfg(feature = "alloc")]
+impl<F, GetKey> GroupedFold<F, GetKey> {
+    /// Output the groups as a `BTreeMap` sorted by key, rather than a
+    /// `HashMap` whose order changes from run to run
+    pub fn sorted(self) -> SortedGroups<F, GetKey> {
+        SortedGroups { inner: self }
+    }
+
+    /// Output the groups as a `Vec` sorted by `proj` of their outputs,
+    /// with ties broken by key. Use `core::cmp::Reverse` to sort
+    /// the largest first.
+    pub fn sorted_by<Proj>(self, proj: Proj) -> SortedGroupsBy<F, GetKey, Proj> {
+        SortedGroupsBy { inner: self, proj }
+    }
+
+    /// Output each group with its share of the total of `proj` over
+    /// all groups, such as the proportion of a count or sum.
+    /// Shares are NaN when the total is zero.
+    pub fn normalize_by_total<Proj>(self, proj: Proj) -> NormalizedGroups<F, GetKey, Proj> {
+        NormalizedGroups { inner: self, proj }
+    }
+
+    /// Output each group with its rank by `proj` of its output, from
+    /// 1 for the smallest, with ties broken by key as in `sorted_by`.
+    /// Use `core::cmp::Reverse` to rank the largest first.
+    pub fn rank_keys<Proj>(self, proj: Proj) -> RankedGroups<F, GetKey, Proj> {
+        RankedGroups { inner: self, proj }
+    }
+
+    /// Also keep up to `n` of the inputs of each group, chosen by
+    /// `keep`, so that reports can show example rows for each key.
+    /// Each group outputs its inner output with its examples.
+    #[cfg(feature = "std")]
+    pub fn with_examples(self, n: usize, keep: Examples) -> GroupedFold<WithExamples<F>, GetKey> {
+        GroupedFold {
+            inner: WithExamples::new(self.inner, n, keep),
+            get_key: self.get_key,
+        }
+    }
+}
+
+/// Grouped fold which also outputs the total over all groups,
+/// see `Fold1::group_by_with_total`
+#[cfg(feature = "alloc")]
+#[derive(Copy, Clone)]
+pub struct GroupedWithTotal<F, GetKey> {
+    inner: GroupedFold<F, GetKey>,
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey> Fold1 for GroupedWithTotal<F, GetKey>
+where
+    F: FoldPar + Fold,
+    F::M: Clone,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    type A = F::A;
+    type B = (FxHashMap<Key, F::B>, F::B);
+    type M = FxHashMap<Key, F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x)
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, acc)
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        self.inner.step_chunk(xs, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        let fold = &self.inner.inner;
+        let mut total = fold.empty();
+        for m in acc.values() {
+            fold.merge(&mut total, m.clone());
+        }
+        (self.inner.output(acc), fold.output(total))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey> Fold for GroupedWithTotal<F, GetKey>
+where
+    F: FoldPar + Fold,
+    F::M: Clone,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey> FoldPar for GroupedWithTotal<F, GetKey>
+where
+    F: FoldPar + Fold,
+    F::M: Clone,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Grouped fold with its output sorted by key, see `GroupedFold::sorted`
+#[cfg(feature = "alloc")]
+#[derive(Copy, Clone)]
+pub struct SortedGroups<F, GetKey> {
+    inner: GroupedFold<F, GetKey>,
+}
+
+#[cfg(feature = "alloc")]
+impl<F: Fold1, Key: Hash + Ord, GetKey: Fn(&F::A) -> Key> Fold1 for SortedGroups<F, GetKey> {
+    type A = F::A;
+    type B = BTreeMap<Key, F::B>;
+    type M = FxHashMap<Key, F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x)
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, acc)
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        self.inner.step_chunk(xs, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc).into_iter().collect()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F: Fold, Key: Hash + Ord, GetKey: Fn(&F::A) -> Key> Fold for SortedGroups<F, GetKey> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F: FoldPar, Key: Hash + Ord, GetKey: Fn(&F::A) -> Key> FoldPar for SortedGroups<F, GetKey> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Grouped fold with its output sorted by a projection of the
+/// outputs of the groups, see `GroupedFold::sorted_by`
+#[cfg(feature = "alloc")]
+#[derive(Copy, Clone)]
+pub struct SortedGroupsBy<F, GetKey, Proj> {
+    inner: GroupedFold<F, GetKey>,
+    proj: Proj,
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj, O> Fold1 for SortedGroupsBy<F, GetKey, Proj>
+where
+    F: Fold1,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    type A = F::A;
+    type B = Vec<(Key, F::B)>;
+    type M = FxHashMap<Key, F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x)
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, acc)
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        self.inner.step_chunk(xs, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        let mut out: Vec<_> = self
+            .inner
+            .output(acc)
+            .into_iter()
+            .map(|(k, b)| ((self.proj)(&b), k, b))
+            .collect();
+        out.sort_unstable_by(|(o1, k1, _), (o2, k2, _)| o1.cmp(o2).then_with(|| k1.cmp(k2)));
+        out.into_iter().map(|(_, k, b)| (k, b)).collect()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj, O> Fold for SortedGroupsBy<F, GetKey, Proj>
+where
+    F: Fold,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj, O> FoldPar for SortedGroupsBy<F, GetKey, Proj>
+where
+    F: FoldPar,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Grouped fold whose groups are output with their share of the
+/// total, see `GroupedFold::normalize_by_total`
+#[cfg(feature = "alloc")]
+#[derive(Copy, Clone)]
+pub struct NormalizedGroups<F, GetKey, Proj> {
+    inner: GroupedFold<F, GetKey>,
+    proj: Proj,
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj> Fold1 for NormalizedGroups<F, GetKey, Proj>
+where
+    F: Fold1,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> f64,
+{
+    type A = F::A;
+    type B = FxHashMap<Key, (F::B, f64)>;
+    type M = FxHashMap<Key, F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x)
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, acc)
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        self.inner.step_chunk(xs, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        let groups = self.inner.output(acc);
+        let total: f64 = groups.values().map(&self.proj).sum();
+        groups
+            .into_iter()
+            .map(|(k, b)| {
+                let share = (self.proj)(&b) / total;
+                (k, (b, share))
+            })
+            .collect()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj> Fold for NormalizedGroups<F, GetKey, Proj>
+where
+    F: Fold,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> f64,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj> FoldPar for NormalizedGroups<F, GetKey, Proj>
+where
+    F: FoldPar,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> f64,
+{
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Grouped fold whose groups are output with their rank, see
+/// `GroupedFold::rank_keys`
+#[cfg(feature = "alloc")]
+#[derive(Copy, Clone)]
+pub struct RankedGroups<F, GetKey, Proj> {
+    inner: GroupedFold<F, GetKey>,
+    proj: Proj,
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj, O> Fold1 for RankedGroups<F, GetKey, Proj>
+where
+    F: Fold1,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    type A = F::A;
+    type B = FxHashMap<Key, (F::B, usize)>;
+    type M = FxHashMap<Key, F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x)
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, acc)
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        self.inner.step_chunk(xs, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        let mut out: Vec<_> = self
+            .inner
+            .output(acc)
+            .into_iter()
+            .map(|(k, b)| ((self.proj)(&b), k, b))
+            .collect();
+        out.sort_unstable_by(|(o1, k1, _), (o2, k2, _)| o1.cmp(o2).then_with(|| k1.cmp(k2)));
+        out.into_iter()
+            .enumerate()
+            .map(|(i, (_, k, b))| (k, (b, i + 1)))
+            .collect()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj, O> Fold for RankedGroups<F, GetKey, Proj>
+where
+    F: Fold,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj, O> FoldPar for RankedGroups<F, GetKey, Proj>
+where
+    F: FoldPar,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+impl<F: Fold1, A2, PreFunc: Fn(A2) -> F::A> Fold1 for PreMap<F, A2, PreFunc> {
+    type A = A2;
+    type B = F::B;
+    type M = F::M;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init((self.pre_func)(x))
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step((self.pre_func)(x), acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<F: Incremental, A2, PreFunc: Fn(A2) -> F::A> Incremental for PreMap<F, A2, PreFunc> {
+    type Delta = F::Delta;
+
+    fn step_delta(&self, x: Self::A, acc: &mut Self::M) -> Option<Self::Delta> {
+        self.inner.step_delta((self.pre_func)(x), acc)
+    }
+}
+
+impl<F: StepWeighted, A2, PreFunc: Fn(A2) -> F::A> StepWeighted for PreMap<F, A2, PreFunc> {
+    type Weight = F::Weight;
+
+    #[inline]
+    fn step_weighted(&self, x: Self::A, weight: Self::Weight, acc: &mut Self::M) {
+        self.inner.step_weighted((self.pre_func)(x), weight, acc)
+    }
+}
+
+impl<F: Fold, A2, PreFunc: Fn(A2) -> F::A> Fold for PreMap<F, A2, PreFunc> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<F: FoldPar, A2, PreFunc: Fn(A2) -> F::A> FoldPar for PreMap<F, A2, PreFunc> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Fold over weighted inputs, see `Fold1::weighted`
+#[derive(Copy, Clone)]
+pub struct Weighted<F, GetWeight> {
+    inner: F,
+    get_weight: GetWeight,
+}
+
+impl<F, GetWeight> Fold1 for Weighted<F, GetWeight>
+where
+    F: StepWeighted + Fold,
+    GetWeight: Fn(&F::A) -> F::Weight,
+{
+    type A = F::A;
+    type B = F::B;
+    type M = F::M;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.inner.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let weight = (self.get_weight)(&x);
+        self.inner.step_weighted(x, weight, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<F, GetWeight> Fold for Weighted<F, GetWeight>
+where
+    F: StepWeighted + Fold,
+    GetWeight: Fn(&F::A) -> F::Weight,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<F, GetWeight> FoldPar for Weighted<F, GetWeight>
+where
+    F: StepWeighted + Fold + FoldPar,
+    GetWeight: Fn(&F::A) -> F::Weight,
+{
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Fold over the differences of consecutive inputs, see
+/// `Fold1::differences`
+pub struct Differences<F, A> {
+    inner: F,
+    ghost: PhantomData<fn(A)>,
+}
+
+impl<F: Clone, A> Clone for Differences<F, A> {
+    fn clone(&self) -> Self {
+        Differences {
+            inner: self.inner.clone(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<F: Copy, A> Copy for Differences<F, A> {}
+
+impl<F, A> Fold1 for Differences<F, A>
+where
+    F: Fold,
+    A: Copy + core::ops::Sub<Output = F::A>,
+{
+    type A = A;
+    type B = F::B;
+    /// The first and last inputs, which merges take the difference
+    /// across, and the state of the inner fold
+    type M = (Option<(A, A)>, F::M);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        (Some((x, x)), self.inner.empty())
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, (ends, acc): &mut Self::M) {
+        match ends {
+            Some((_, last)) => {
+                self.inner.step(x - *last, acc);
+                *last = x;
+            }
+            None => *ends = Some((x, x)),
+        }
+    }
+
+    #[inline]
+    fn output(&self, (_, acc): Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<F, A> Fold for Differences<F, A>
+where
+    F: Fold,
+    A: Copy + core::ops::Sub<Output = F::A>,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (None, self.inner.empty())
+    }
+}
+
+/// The difference across the two states is stepped before merging,
+/// as it comes between their differences
+impl<F, A> FoldParOrdered for Differences<F, A>
+where
+    F: Fold + FoldPar,
+    A: Copy + core::ops::Sub<Output = F::A>,
+{
+    #[inline]
+    fn merge_at(&self, (ends1, acc1): &mut Self::M, (ends2, acc2): Self::M, _offset: usize) {
+        match (ends1.as_mut(), ends2) {
+            (Some((_, last1)), Some((first2, last2))) => {
+                self.inner.step(first2 - *last1, acc1);
+                *last1 = last2;
+            }
+            (None, ends2) => *ends1 = ends2,
+            (_, None) => {}
+        }
+        self.inner.merge(acc1, acc2)
+    }
+}
+
+/// Fold over pairs of consecutive inputs, see `Fold1::pairwise`
+pub struct Pairwise<F, T> {
+    inner: F,
+    ghost: PhantomData<fn(T)>,
+}
+
+impl<F: Clone, T> Clone for Pairwise<F, T> {
+    fn clone(&self) -> Self {
+        Pairwise {
+            inner: self.inner.clone(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<F: Copy, T> Copy for Pairwise<F, T> {}
+
+impl<F, T> Fold1 for Pairwise<F, T>
+where
+    F: Fold<A = (T, T)>,
+    T: Clone,
+{
+    type A = T;
+    type B = F::B;
+    /// The first and last inputs, which merges pair across, and the
+    /// state of the inner fold
+    type M = (Option<(T, T)>, F::M);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        (Some((x.clone(), x)), self.inner.empty())
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, (ends, acc): &mut Self::M) {
+        match ends {
+            Some((_, last)) => {
+                let prev = core::mem::replace(last, x.clone());
+                self.inner.step((prev, x), acc);
+            }
+            None => *ends = Some((x.clone(), x)),
+        }
+    }
+
+    #[inline]
+    fn output(&self, (_, acc): Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<F, T> Fold for Pairwise<F, T>
+where
+    F: Fold<A = (T, T)>,
+    T: Clone,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (None, self.inner.empty())
+    }
+}
+
+/// The pair across the two states is stepped before merging,
+/// as it comes between their pairs
+impl<F, T> FoldParOrdered for Pairwise<F, T>
+where
+    F: Fold<A = (T, T)> + FoldPar,
+    T: Clone,
+{
+    #[inline]
+    fn merge_at(&self, (ends1, acc1): &mut Self::M, (ends2, acc2): Self::M, _offset: usize) {
+        match (ends1.as_mut(), ends2) {
+            (Some((_, last1)), Some((first2, last2))) => {
+                let prev = core::mem::replace(last1, last2);
+                self.inner.step((prev, first2), acc1);
+            }
+            (None, ends2) => *ends1 = ends2,
+            (_, None) => {}
+        }
+        self.inner.merge(acc1, acc2)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct PostMap<F: Fold1, B2, PostFunc: Fn(F::B) -> B2> {
+    inner: F,
+    post_func: PostFunc,
+}
+
+impl<F: Fold1, B2, PostFunc: Fn(F::B) -> B2> Fold1 for PostMap<F, B2, PostFunc> {
+    type A = F::A;
+    type B = B2;
+    type M = F::M;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x)
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        (self.post_func)(self.inner.output(acc))
+    }
+}
+
+impl<F: Fold, B2, PostFunc: Fn(F::B) -> B2> Fold for PostMap<F, B2, PostFunc> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<F: FoldPar, B2, PostFunc: Fn(F::B) -> B2> FoldPar for PostMap<F, B2, PostFunc> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct ComposedFold<F1: Fold1, F2: Fold1> {
+    first: F1,
+    second: F2,
+}
+
+impl<F1: Fold1, F2: Fold1<A = F1::B>> Fold1 for ComposedFold<F1, F2>
+where
+    F1::M: Copy,
+{
+    type A = F1::A;
+
+    type B = F2::B;
+
+    type M = (F1::M, F2::M);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let m1 = self.first.init(x);
+        let m2 = self.second.init(self.first.output(m1));
+        (m1, m2)
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let (m1, m2) = acc;
+        self.first.step(x, m1);
+        let y = self.first.output(*m1);
+        self.second.step(y, m2);
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        let (_m1, m2) = acc;
+        self.second.output(m2)
+    }
+}
+
+impl<F1: Fold, F2: Fold1<A = F1::B>> Fold for ComposedFold<F1, F2>
+where
+    F1::M: Copy,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        let m1 = self.first.empty();
+        let m2 = self.second.init(self.first.output(m1));
+        (m1, m2)
+    }
+}
+
+/// Inclusive scan: yields the output after each value, so the i-th
+/// output covers the first i + 1 values. See `ScanIter` for access to
+/// the state, to checkpoint and later resume the scan.
+pub fn scan<F: Fold, I: Iterator<Item = F::A>>(fld: F, iter: I) -> ScanIter<F, I>
+where
+    F::M: Clone,
+{
+    ScanIter::new(fld, iter)
+}
+
+/// Inclusive scan over an iterator, see `scan`. Between values its
+/// state can be inspected or taken, and a scan resumed from it.
+pub struct ScanIter<F: Fold, I> {
+    fold: F,
+    iter: I,
+    acc: F::M,
+    index: usize,
+}
+
+impl<F: Fold, I> ScanIter<F, I> {
+    pub fn new(fold: F, iter: I) -> Self {
+        let acc = fold.empty();
+        ScanIter::resume(fold, iter, acc, 0)
+    }
+
+    /// Continue a scan from a state taken from an earlier one, which
+    /// had folded `index` values
+    pub fn resume(fold: F, iter: I, state: F::M, index: usize) -> Self {
+        ScanIter {
+            fold,
+            iter,
+            acc: state,
+            index,
+        }
+    }
+
+    /// State after the values folded so far
+    pub fn state(&self) -> &F::M {
+        &self.acc
+    }
+
+    /// Number of values folded so far, which is the index of the next
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The rest of the input, the state and the number of values
+    /// folded, as taken by `resume`
+    pub fn into_parts(self) -> (I, F::M, usize) {
+        (self.iter, self.acc, self.index)
+    }
+
+    /// Yield the index of each value along with the output
+    pub fn indexed(self) -> Indexed<Self> {
+        Indexed { inner: self }
+    }
+}
+
+impl<F: Fold, I: Iterator<Item = F::A>> Iterator for ScanIter<F, I>
+where
+    F::M: Clone,
+{
+    type Item = F::B;
+
+    fn next(&mut self) -> Option<F::B> {
+        let x = self.iter.next()?;
+        self.fold.step(x, &mut self.acc);
+        self.index += 1;
+        Some(self.fold.output(self.acc.clone()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Inclusive scan over a stream, like `ScanIter`
+#[cfg(feature = "async")]
+pub struct ScanStream<F: Fold, S> {
+    fold: F,
+    stream: S,
+    acc: F::M,
+    index: usize,
+}
+
+#[cfg(feature = "async")]
+impl<F: Fold, S> ScanStream<F, S> {
+    pub fn new(fold: F, stream: S) -> Self {
+        let acc = fold.empty();
+        ScanStream::resume(fold, stream, acc, 0)
+    }
+
+    /// Continue a scan from a state taken from an earlier one, which
+    /// had folded `index` values
+    pub fn resume(fold: F, stream: S, state: F::M, index: usize) -> Self {
+        ScanStream {
+            fold,
+            stream,
+            acc: state,
+            index,
+        }
+    }
+
+    /// State after the values folded so far
+    pub fn state(&self) -> &F::M {
+        &self.acc
+    }
+
+    /// Number of values folded so far, which is the index of the next
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The rest of the input, the state and the number of values
+    /// folded, as taken by `resume`
+    pub fn into_parts(self) -> (S, F::M, usize) {
+        (self.stream, self.acc, self.index)
+    }
+
+    /// Yield the index of each value along with the output
+    pub fn indexed(self) -> Indexed<Self> {
+        Indexed { inner: self }
+    }
+}
+
+/// Polling needs the fold, its state and the stream to be `Unpin`,
+/// which streams which are not can be made by `Box::pin`
+#[cfg(feature = "async")]
+impl<F, S> Stream for ScanStream<F, S>
+where
+    F: Fold + Unpin,
+    F::M: Clone + Unpin,
+    S: Stream<Item = F::A> + Unpin,
+{
+    type Item = F::B;
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<F::B>> {
+        let this = self.get_mut();
+        this.stream.poll_next_unpin(cx).map(|x| {
+            this.fold.step(x?, &mut this.acc);
+            this.index += 1;
+            Some(this.fold.output(this.acc.clone()))
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+/// Scan yielding the index of each value with its output, see
+/// `ScanIter::indexed`
+pub struct Indexed<S> {
+    inner: S,
+}
+
+impl<S> Indexed<S> {
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<F: Fold, I: Iterator<Item = F::A>> Iterator for Indexed<ScanIter<F, I>>
+where
+    F::M: Clone,
+{
+    type Item = (usize, F::B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let i = self.inner.index;
+        Some((i, self.inner.next()?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<F, S> Stream for Indexed<ScanStream<F, S>>
+where
+    F: Fold + Unpin,
+    F::M: Clone + Unpin,
+    S: Stream<Item = F::A> + Unpin,
+{
+    type Item = (usize, F::B);
+
+    fn poll_next(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        let inner = &mut self.get_mut().inner;
+        let i = inner.index;
+        inner.poll_next_unpin(cx).map(|b| Some((i, b?)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Exclusive scan: yields the output for the values before each
+/// value, starting with the output of the empty state, so the i-th
+/// output covers the first i values
+pub fn prescan<F: Fold>(fld: F, iter: impl Iterator<Item = F::A>) -> impl Iterator<Item = F::B>
+where
+    F::M: Clone,
+{
+    let mut acc = fld.empty();
+    iter.map(move |x| {
+        let b = fld.output(acc.clone());
+        fld.step(x, &mut acc);
+        b
+    })
+}
+
+/// Inclusive scan yielding the state after each value rather than
+/// its output
+pub fn scan_state<F: Fold>(fld: F, iter: impl Iterator<Item = F::A>) -> impl Iterator<Item = F::M>
+where
+    F::M: Clone,
+{
+    let mut acc = fld.empty();
+    iter.map(move |x| {
+        fld.step(x, &mut acc);
+        acc.clone()
+    })
+}
+
+/// Scan from the back: the i-th output covers the values from the
+/// i-th onwards, e.g. suffix sums. Outputs are in input order.
+pub fn scan_rev<F: Fold>(fld: F, iter: impl DoubleEndedIterator<Item = F::A>) -> Vec<F::B>
+where
+    F::M: Clone,
+{
+    let mut acc = fld.empty();
+    let mut out: Vec<_> = iter
+        .rev()
+        .map(|x| {
+            fld.step(x, &mut acc);
+            fld.output(acc.clone())
+        })
+        .collect();
+    out.reverse();
+    out
+}
+
+/// Composition of folds passing on changes, see `Fold1::then_delta`
+#[derive(Copy, Clone)]
+pub struct DeltaComposed<F1, F2> {
+    first: F1,
+    second: F2,
+}
+
+impl<F1: Incremental + Fold, F2: Fold<A = F1::Delta>> Fold1 for DeltaComposed<F1, F2> {
+    type A = F1::A;
+    type B = F2::B;
+    type M = (F1::M, F2::M);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, (m1, m2): &mut Self::M) {
+        if let Some(delta) = self.first.step_delta(x, m1) {
+            self.second.step(delta, m2);
+        }
+    }
+
+    #[inline]
+    fn output(&self, (_m1, m2): Self::M) -> Self::B {
+        self.second.output(m2)
+    }
+}
+
+impl<F1: Incremental + Fold, F2: Fold<A = F1::Delta>> Fold for DeltaComposed<F1, F2> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (self.first.empty(), self.second.empty())
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Batched<F: Fold1> {
+    inner: F,
+}
+impl<A: Clone, F: Fold<A = A>> Fold1 for Batched<F> {
+    type A = Vec<F::A>;
+
+    type B = F::B;
+
+    type M = F::M;
+
+    // this will panic on empty chunk
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.inner.empty();
+        self.inner.step_chunk(x, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step_chunk(x, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+impl<A: Clone, F: Fold<A = A>> Fold for Batched<F> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+impl<A: Clone, F: FoldPar<A = A> + Fold> FoldPar for Batched<F> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// How `Many` handles inputs whose width differs from its state
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Width {
+    /// Panic on any input or state of the wrong width
+    Strict,
+    /// Grow the state to fit wider inputs.
+    /// Narrower inputs only step the leading folds.
+    Resize,
+}
+
+/// Perform a fold in parallel with itself over a wide stream
+#[derive(Copy, Clone)]
+pub struct Many<F: Fold1> {
+    inner: F,
+    n: usize,
+    width: Width,
+}
+
+impl<F: Fold1> Many<F> {
+    /// Allow inputs of any width, growing the state as needed
+    pub fn resizing(self) -> Self {
+        Many {
+            width: Width::Resize,
+            ..self
+        }
+    }
+
+    fn check_width(&self, width: usize) {
+        if self.width == Width::Strict && width != self.n {
+            panic!("Many: expected width {}, got {}", self.n, width)
+        }
+    }
+}
+
+impl<F: Fold1> Fold1 for Many<F> {
+    type A = Vec<F::A>;
+
+    type B = Vec<F::B>;
+
+    type M = Vec<F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.check_width(x.len());
+        x.into_iter().map(|x| self.inner.init(x)).collect()
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.check_width(x.len());
+        let mut xs = x.into_iter();
+        for (m, x) in acc.iter_mut().zip(xs.by_ref()) {
+            self.inner.step(x, m)
+        }
+        // only non-empty when resizing
+        acc.extend(xs.map(|x| self.inner.init(x)));
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        // transpose rows into one chunk per column
+        let mut cols: Vec<Vec<F::A>> = Vec::with_capacity(acc.len());
+        for row in xs {
+            self.check_width(row.len());
+            if cols.len() < row.len() {
+                cols.resize_with(row.len(), Vec::new);
+            }
+            for (col, x) in cols.iter_mut().zip(row) {
+                col.push(x)
+            }
+        }
+
+        let mut cols = cols.into_iter();
+        for (m, col) in acc.iter_mut().zip(cols.by_ref()) {
+            self.inner.step_chunk(col, m)
+        }
+        for mut col in cols {
+            let rest = col.split_off(1);
+            let mut m = self.inner.init(col.pop().unwrap());
+            self.inner.step_chunk(rest, &mut m);
+            acc.push(m);
+        }
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.into_iter().map(|a| self.inner.output(a)).collect()
+    }
+}
+
+impl<F: Fold> Fold for Many<F> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        let mut accs = Vec::with_capacity(self.n);
+        for _ in 0..self.n {
+            accs.push(self.inner.empty());
+        }
+        accs
+    }
+}
+
+impl<F: FoldPar> FoldPar for Many<F> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.check_width(m2.len());
+        let mut m2 = m2.into_iter();
+        for (m1, m2) in m1.iter_mut().zip(m2.by_ref()) {
+            self.inner.merge(m1, m2)
+        }
+        m1.extend(m2);
+    }
+}
+
+/// Perform a fold in parallel with itself over arrays of width `N`
+#[derive(Copy, Clone)]
+pub struct ManyN<F, const N: usize> {
+    inner: F,
+}
+
+impl<F: Fold1, const N: usize> Fold1 for ManyN<F, N> {
+    type A = [F::A; N];
+    type B = [F::B; N];
+    type M = [F::M; N];
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        x.map(|x| self.inner.init(x))
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        for (m, x) in acc.iter_mut().zip(x) {
+            self.inner.step(x, m)
+        }
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        let mut cols: [Vec<F::A>; N] = core::array::from_fn(|_| Vec::with_capacity(xs.len()));
+        for row in xs {
+            for (col, x) in cols.iter_mut().zip(row) {
+                col.push(x)
+            }
+        }
+        for (m, col) in acc.iter_mut().zip(cols) {
+            self.inner.step_chunk(col, m)
+        }
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.map(|m| self.inner.output(m))
+    }
+}
+
+impl<F: Fold, const N: usize> Fold for ManyN<F, N> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        core::array::from_fn(|_| self.inner.empty())
+    }
+}
+
+impl<F: FoldPar, const N: usize> FoldPar for ManyN<F, N> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (m1, m2) in m1.iter_mut().zip(m2) {
+            self.inner.merge(m1, m2)
+        }
+    }
+}
+
+/// A `Fold1` made total by outputting an `Option`, see `Fold1::optional`
+#[derive(Copy, Clone)]
+pub struct Maybe<F> {
+    inner: F,
+}
+
+impl<F: Fold1> Fold1 for Maybe<F> {
+    type A = F::A;
+    type B = Option<F::B>;
+    type M = Option<F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        Some(self.inner.init(x))
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        match acc {
+            Some(m) => self.inner.step(x, m),
+            None => *acc = Some(self.inner.init(x)),
+        }
+    }
+
+    #[inline]
+    fn step_chunk(&self, mut xs: Vec<Self::A>, acc: &mut Self::M) {
+        match acc {
+            Some(m) => self.inner.step_chunk(xs, m),
+            None if xs.is_empty() => {}
+            None => {
+                let rest = xs.split_off(1);
+                let mut m = self.inner.init(xs.pop().unwrap());
+                self.inner.step_chunk(rest, &mut m);
+                *acc = Some(m);
+            }
+        }
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.map(|m| self.inner.output(m))
+    }
+}
+
+impl<F: Fold1> Fold for Maybe<F> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        None
+    }
+}
+
+impl<F: FoldPar> FoldPar for Maybe<F> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        match (m1.as_mut(), m2) {
+            (Some(m1), Some(m2)) => self.inner.merge(m1, m2),
+            (None, m2) => *m1 = m2,
+            (Some(_), None) => {}
+        }
+    }
+}
+
+impl<F: FoldParOrdered> FoldParOrdered for Maybe<F> {
+    #[inline]
+    fn merge_at(&self, m1: &mut Self::M, m2: Self::M, offset: usize) {
+        match (m1.as_mut(), m2) {
+            (Some(m1), Some(m2)) => self.inner.merge_at(m1, m2, offset),
+            (None, m2) => *m1 = m2,
+            (Some(_), None) => {}
+        }
+    }
+}
+
+/// A `Fold1` with a fallback output, see `Fold1::with_default`
+#[derive(Copy, Clone)]
+pub struct WithDefault<F, B> {
+    inner: Maybe<F>,
+    default: B,
+}
+
+impl<F: Fold1> Fold1 for WithDefault<F, F::B>
+where
+    F::B: Clone,
+{
+    type A = F::A;
+    type B = F::B;
+    type M = Option<F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x)
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, acc)
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        self.inner.step_chunk(xs, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner
+            .output(acc)
+            .unwrap_or_else(|| self.default.clone())
+    }
+}
+
+impl<F: Fold1> Fold for WithDefault<F, F::B>
+where
+    F::B: Clone,
+{
+    #[inline]
+    fn empty(&self) -> Self::M {
+        None
+    }
+}
+
+impl<F: FoldPar> FoldPar for WithDefault<F, F::B>
+where
+    F::B: Clone,
+{
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+/// Route inputs to one of two folds, see `Fold1::partition`
+#[derive(Copy, Clone)]
+pub struct Partition<F1, F2, P> {
+    f1: F1,
+    f2: F2,
+    pred: P,
+}
+
+impl<F1: Fold, F2: Fold<A = F1::A>, P: Fn(&F1::A) -> bool> Fold1 for Partition<F1, F2, P> {
+    type A = F1::A;
+    type B = (F1::B, F2::B);
+    type M = (F1::M, F2::M);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, (acc1, acc2): &mut Self::M) {
+        if (self.pred)(&x) {
+            self.f1.step(x, acc1)
+        } else {
+            self.f2.step(x, acc2)
+        }
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, (acc1, acc2): &mut Self::M) {
+        let mut xs1 = Vec::with_capacity(xs.len());
+        let mut xs2 = Vec::with_capacity(xs.len());
+        for x in xs {
+            if (self.pred)(&x) {
+                xs1.push(x)
+            } else {
+                xs2.push(x)
+            }
+        }
+        self.f1.step_chunk(xs1, acc1);
+        self.f2.step_chunk(xs2, acc2);
+    }
+
+    #[inline]
+    fn output(&self, (acc1, acc2): Self::M) -> Self::B {
+        (self.f1.output(acc1), self.f2.output(acc2))
+    }
+}
+
+impl<F1: Fold, F2: Fold<A = F1::A>, P: Fn(&F1::A) -> bool> Fold for Partition<F1, F2, P> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (self.f1.empty(), self.f2.empty())
+    }
+}
+
+impl<F1, F2, P> FoldPar for Partition<F1, F2, P>
+where
+    F1: Fold + FoldPar,
+    F2: Fold<A = F1::A> + FoldPar,
+    P: Fn(&F1::A) -> bool,
+{
+    #[inline]
+    fn merge(&self, (m11, m12): &mut Self::M, (m21, m22): Self::M) {
+        self.f1.merge(m11, m21);
+        self.f2.merge(m12, m22);
+    }
+}
+
+/// Fold over pairs component-wise, see `Fold1::zip`
+#[derive(Copy, Clone)]
+pub struct Zip<F1, F2> {
+    f1: F1,
+    f2: F2,
+}
+
+impl<F1: Fold1, F2: Fold1> Fold1 for Zip<F1, F2> {
+    type A = (F1::A, F2::A);
+    type B = (F1::B, F2::B);
+    type M = (F1::M, F2::M);
+
+    #[inline]
+    fn init(&self, (x1, x2): Self::A) -> Self::M {
+        (self.f1.init(x1), self.f2.init(x2))
+    }
+
+    #[inline]
+    fn step(&self, (x1, x2): Self::A, (acc1, acc2): &mut Self::M) {
+        self.f1.step(x1, acc1);
+        self.f2.step(x2, acc2);
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, (acc1, acc2): &mut Self::M) {
+        let (xs1, xs2) = xs.into_iter().unzip();
+        self.f1.step_chunk(xs1, acc1);
+        self.f2.step_chunk(xs2, acc2);
+    }
+
+    #[inline]
+    fn output(&self, (acc1, acc2): Self::M) -> Self::B {
+        (self.f1.output(acc1), self.f2.output(acc2))
+    }
+}
+
+impl<F1: Fold, F2: Fold> Fold for Zip<F1, F2> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (self.f1.empty(), self.f2.empty())
+    }
+}
+
+impl<F1: FoldPar, F2: FoldPar> FoldPar for Zip<F1, F2> {
+    #[inline]
+    fn merge(&self, (m11, m12): &mut Self::M, (m21, m22): Self::M) {
+        self.f1.merge(m11, m21);
+        self.f2.merge(m12, m22);
+    }
+}
+
+/// Fold over `Either`s, see `Fold1::par_either`
+#[derive(Copy, Clone)]
+pub struct ParEither<F1, F2> {
+    f1: F1,
+    f2: F2,
+}
+
+impl<F1: Fold, F2: Fold> Fold1 for ParEither<F1, F2> {
+    type A = Either<F1::A, F2::A>;
+    type B = (F1::B, F2::B);
+    type M = (F1::M, F2::M);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, (acc1, acc2): &mut Self::M) {
+        match x {
+            Either::Left(x) => self.f1.step(x, acc1),
+            Either::Right(x) => self.f2.step(x, acc2),
+        }
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, (acc1, acc2): &mut Self::M) {
+        let mut xs1 = Vec::with_capacity(xs.len());
+        let mut xs2 = Vec::with_capacity(xs.len());
+        for x in xs {
+            match x {
+                Either::Left(x) => xs1.push(x),
+                Either::Right(x) => xs2.push(x),
+            }
+        }
+        self.f1.step_chunk(xs1, acc1);
+        self.f2.step_chunk(xs2, acc2);
+    }
+
+    #[inline]
+    fn output(&self, (acc1, acc2): Self::M) -> Self::B {
+        (self.f1.output(acc1), self.f2.output(acc2))
+    }
+}
+
+impl<F1: Fold, F2: Fold> Fold for ParEither<F1, F2> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (self.f1.empty(), self.f2.empty())
+    }
+}
+
+impl<F1: Fold + FoldPar, F2: Fold + FoldPar> FoldPar for ParEither<F1, F2> {
+    #[inline]
+    fn merge(&self, (m11, m12): &mut Self::M, (m21, m22): Self::M) {
+        self.f1.merge(m11, m21);
+        self.f2.merge(m12, m22);
+    }
+}
+
+/// Object safe version of `Fold + FoldPar` used by `BoxedFold`
+trait DynFold<A, B>: Send + Sync {
+    fn dyn_empty(&self) -> Box<dyn Any + Send>;
+    fn dyn_init(&self, x: A) -> Box<dyn Any + Send>;
+    fn dyn_step(&self, x: A, acc: &mut (dyn Any + Send));
+    fn dyn_step_chunk(&self, xs: Vec<A>, acc: &mut (dyn Any + Send));
+    fn dyn_output(&self, acc: Box<dyn Any + Send>) -> B;
+    fn dyn_merge(&self, m1: &mut (dyn Any + Send), m2: Box<dyn Any + Send>);
+}
+
+impl<F> DynFold<F::A, F::B> for F
+where
+    F: Fold + FoldPar + Send + Sync,
+    F::M: Send + 'static,
+{
+    fn dyn_empty(&self) -> Box<dyn Any + Send> {
+        Box::new(Fold::empty(self))
+    }
+
+    fn dyn_init(&self, x: F::A) -> Box<dyn Any + Send> {
+        Box::new(Fold1::init(self, x))
+    }
+
+    fn dyn_step(&self, x: F::A, acc: &mut (dyn Any + Send)) {
+        Fold1::step(self, x, acc.downcast_mut().unwrap())
+    }
+
+    fn dyn_step_chunk(&self, xs: Vec<F::A>, acc: &mut (dyn Any + Send)) {
+        Fold1::step_chunk(self, xs, acc.downcast_mut().unwrap())
+    }
+
+    fn dyn_output(&self, acc: Box<dyn Any + Send>) -> F::B {
+        Fold1::output(self, *acc.downcast().unwrap())
+    }
+
+    fn dyn_merge(&self, m1: &mut (dyn Any + Send), m2: Box<dyn Any + Send>) {
+        FoldPar::merge(self, m1.downcast_mut().unwrap(), *m2.downcast().unwrap())
+    }
+}
+
+/// A fold with its type erased, see `Fold1::boxed`
+pub struct BoxedFold<A, B> {
+    inner: Arc<dyn DynFold<A, B>>,
+}
+
+impl<A, B> Clone for BoxedFold<A, B> {
+    fn clone(&self) -> Self {
+        BoxedFold {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<A, B> Fold1 for BoxedFold<A, B> {
+    type A = A;
+    type B = B;
+    type M = Box<dyn Any + Send>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.dyn_init(x)
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.dyn_step(x, acc.as_mut())
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        self.inner.dyn_step_chunk(xs, acc.as_mut())
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.dyn_output(acc)
+    }
+}
+
+impl<A, B> Fold for BoxedFold<A, B> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.inner.dyn_empty()
+    }
+}
+
+impl<A, B> FoldPar for BoxedFold<A, B> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.dyn_merge(m1.as_mut(), m2)
+    }
+}
+
+/// Errors seen by a `PartitionErrors` fold
+#[derive(Clone, Debug, PartialEq)]
+pub struct ErrorSummary<E> {
+    /// Number of errors seen
+    pub count: usize,
+    /// The errors, in the order they were seen. This is empty when
+    /// errors are only counted, see `ErrorPolicy::SkipAndCount`.
+    pub errors: Vec<E>,
+}
+
+impl<E> ErrorSummary<E> {
+    fn new() -> Self {
+        ErrorSummary {
+            count: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, e: E) {
+        self.count += 1;
+        self.errors.push(e);
+    }
+}
+
+/// Fold over `Result`s, see `Fold1::partition_errors`
+#[derive(Copy, Clone)]
+pub struct PartitionErrors<F, E> {
+    inner: Maybe<F>,
+    ghost: PhantomData<E>,
+}
+
+impl<F: Fold1, E> Fold1 for PartitionErrors<F, E> {
+    type A = Result<F::A, E>;
+    type B = (Option<F::B>, ErrorSummary<E>);
+    type M = (Option<F::M>, ErrorSummary<E>);
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, (acc, errs): &mut Self::M) {
+        match x {
+            Ok(x) => self.inner.step(x, acc),
+            Err(e) => errs.push(e),
+        }
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, (acc, errs): &mut Self::M) {
+        let mut oks = Vec::with_capacity(xs.len());
+        for x in xs {
+            match x {
+                Ok(x) => oks.push(x),
+                Err(e) => errs.push(e),
+            }
+        }
+        self.inner.step_chunk(oks, acc);
+    }
+
+    #[inline]
+    fn output(&self, (acc, errs): Self::M) -> Self::B {
+        (self.inner.output(acc), errs)
+    }
+}
+
+impl<F: Fold1, E> Fold for PartitionErrors<F, E> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        (self.inner.empty(), ErrorSummary::new())
+    }
+}
+
+impl<F: FoldPar, E> FoldPar for PartitionErrors<F, E> {
+    #[inline]
+    fn merge(&self, (m1, errs1): &mut Self::M, (m2, errs2): Self::M) {
+        self.inner.merge(m1, m2);
+        errs1.count += errs2.count;
+        errs1.errors.extend(errs2.errors);
+    }
+}
+
+/// Fold reporting its progress, see `Fold1::with_progress`
+#[derive(Clone)]
+pub struct WithProgress<F, C> {
+    inner: F,
+    every: usize,
+    #[cfg(feature = "std")]
+    period: Option<Duration>,
+    callback: C,
+}
+
+/// State of `WithProgress`
+#[derive(Clone, Debug)]
+pub struct ProgressState<M> {
+    inner: M,
+    seen: usize,
+    /// When this state last reported, if reporting by time
+    #[cfg(feature = "std")]
+    reported: Option<Instant>,
+}
+
+impl<M> ProgressState<M> {
+    /// Number of elements processed by this state
+    pub fn seen(&self) -> usize {
+        self.seen
+    }
+}
+
+impl<F, C: Fn(usize)> WithProgress<F, C> {
+    fn wrap<M>(&self, inner: M) -> ProgressState<M> {
+        ProgressState {
+            inner,
+            seen: 0,
+            #[cfg(feature = "std")]
+            reported: self.period.map(|_| Instant::now()),
+        }
+    }
+
+    fn tick<M>(&self, n: usize, acc: &mut ProgressState<M>) {
+        let before = acc.seen;
+        acc.seen += n;
+        #[allow(unused_mut)]
+        let mut due = acc.seen / self.every > before / self.every;
+        #[cfg(feature = "std")]
+        if let (Some(period), Some(reported)) = (self.period, &mut acc.reported) {
+            let now = Instant::now();
+            if now.duration_since(*reported) >= period {
+                *reported = now;
+                due = true;
+            }
+        }
+        if due {
+            (self.callback)(acc.seen);
+        }
+    }
+}
+
+impl<F: Fold1, C: Fn(usize)> Fold1 for WithProgress<F, C> {
+    type A = F::A;
+    type B = F::B;
+    type M = ProgressState<F::M>;
+
+    #[inline]
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.wrap(self.inner.init(x));
+        self.tick(1, &mut acc);
+        acc
+    }
+
+    #[inline]
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x, &mut acc.inner);
+        self.tick(1, acc)
+    }
+
+    #[inline]
+    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
+        let n = xs.len();
+        self.inner.step_chunk(xs, &mut acc.inner);
+        self.tick(n, acc)
+    }
+
+    #[inline]
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc.inner)
+    }
+}
+
+impl<F: Fold, C: Fn(usize)> Fold for WithProgress<F, C> {
+    #[inline]
+    fn empty(&self) -> Self::M {
+        self.wrap(self.inner.empty())
+    }
+}
+
+impl<F: FoldPar, C: Fn(usize)> FoldPar for WithProgress<F, C> {
+    #[inline]
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(&mut m1.inner, m2.inner);
+        m1.seen += m2.seen;
+    }
+}
+
+impl<F1: StateSize, F2: StateSize> StateSize for Par2<F1, F2>
+where
+    Self: Fold1<M = (F1::M, F2::M)>,
+{
+    fn state_bytes(&self, (m1, m2): &Self::M) -> usize {
+        self.f1.state_bytes(m1) + self.f2.state_bytes(m2)
+    }
+}
+
+impl<F1, F2, Func> StateSize for ZipWith<F1, F2, Func>
+where
+    Self: Fold1<M = (F1::M, F2::M)>,
+    Par2<F1, F2>: StateSize<M = (F1::M, F2::M)>,
+    F1: Fold1,
+    F2: Fold1,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<F: StateSize, P: Fn(&F::A) -> bool> StateSize for FilteredFold<F, P> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<F: StateSize> StateSize for Clamped<F>
+where
+    F::A: PartialOrd + Clone,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+/// Heap memory owned by the keys is not counted
+#[cfg(feature = "alloc")]
+impl<F: StateSize, Key: Hash + Eq, GetKey: Fn(&F::A) -> Key> StateSize for GroupedFold<F, GetKey> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>()
+            + m.capacity() * size_of::<(Key, F::M)>()
+            + m.values()
+                .map(|m| heap_bytes(&self.inner, m))
+                .sum::<usize>()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F: StateSize, Key: Hash + Ord, GetKey: Fn(&F::A) -> Key> StateSize
+    for SortedGroups<F, GetKey>
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj, O> StateSize for SortedGroupsBy<F, GetKey, Proj>
+where
+    F: StateSize,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj> StateSize for NormalizedGroups<F, GetKey, Proj>
+where
+    F: StateSize,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> f64,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<F, Key, GetKey, Proj, O> StateSize for RankedGroups<F, GetKey, Proj>
+where
+    F: StateSize,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<F, GetWeight> StateSize for Weighted<F, GetWeight>
+where
+    F: StepWeighted + Fold + StateSize,
+    GetWeight: Fn(&F::A) -> F::Weight,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<F: StateSize, A2, PreFunc: Fn(A2) -> F::A> StateSize for PreMap<F, A2, PreFunc> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<F, A> StateSize for Differences<F, A>
+where
+    F: Fold + StateSize,
+    A: Copy + core::ops::Sub<Output = F::A>,
+{
+    fn state_bytes(&self, (_, acc): &Self::M) -> usize {
+        size_of::<Self::M>() - size_of::<F::M>() + self.inner.state_bytes(acc)
+    }
+}
+
+impl<F, T> StateSize for Pairwise<F, T>
+where
+    F: Fold<A = (T, T)> + StateSize,
+    T: Clone,
+{
+    fn state_bytes(&self, (_, acc): &Self::M) -> usize {
+        size_of::<Self::M>() - size_of::<F::M>() + self.inner.state_bytes(acc)
+    }
+}
+
+impl<F: StateSize, B2, PostFunc: Fn(F::B) -> B2> StateSize for PostMap<F, B2, PostFunc> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<F1: StateSize, F2: StateSize<A = F1::B>> StateSize for ComposedFold<F1, F2>
+where
+    F1::M: Copy,
+{
+    fn state_bytes(&self, (m1, m2): &Self::M) -> usize {
+        self.first.state_bytes(m1) + self.second.state_bytes(m2)
+    }
+}
+
+impl<A: Clone, F: Fold<A = A> + StateSize> StateSize for Batched<F> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<F: StateSize> StateSize for Many<F> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>()
+            + m.capacity() * size_of::<F::M>()
+            + m.iter().map(|m| heap_bytes(&self.inner, m)).sum::<usize>()
+    }
+}
+
+impl<F: StateSize, const N: usize> StateSize for ManyN<F, N> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() + m.iter().map(|m| heap_bytes(&self.inner, m)).sum::<usize>()
+    }
+}
+
+impl<F: StateSize> StateSize for Maybe<F> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() + m.as_ref().map_or(0, |m| heap_bytes(&self.inner, m))
+    }
+}
+
+impl<F: StateSize> StateSize for WithDefault<F, F::B>
+where
+    F::B: Clone,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        self.inner.state_bytes(m)
+    }
+}
+
+impl<F1, F2, P> StateSize for Partition<F1, F2, P>
+where
+    F1: Fold + StateSize,
+    F2: Fold<A = F1::A> + StateSize,
+    P: Fn(&F1::A) -> bool,
+{
+    fn state_bytes(&self, (m1, m2): &Self::M) -> usize {
+        self.f1.state_bytes(m1) + self.f2.state_bytes(m2)
+    }
+}
+
+impl<F1: StateSize, F2: StateSize> StateSize for Zip<F1, F2> {
+    fn state_bytes(&self, (m1, m2): &Self::M) -> usize {
+        self.f1.state_bytes(m1) + self.f2.state_bytes(m2)
+    }
+}
+
+impl<F1: Fold + StateSize, F2: Fold + StateSize> StateSize for ParEither<F1, F2> {
+    fn state_bytes(&self, (m1, m2): &Self::M) -> usize {
+        self.f1.state_bytes(m1) + self.f2.state_bytes(m2)
+    }
+}
+
+impl<F: StateSize, E> StateSize for PartitionErrors<F, E> {
+    fn state_bytes(&self, (m, errs): &Self::M) -> usize {
+        self.inner.state_bytes(m)
+            + size_of::<ErrorSummary<E>>()
+            + errs.errors.capacity() * size_of::<E>()
+    }
+}
+
+impl<F: StateSize, C: Fn(usize)> StateSize for WithProgress<F, C> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() - size_of::<F::M>() + self.inner.state_bytes(&m.inner)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldImpl> FoldDescribe for Fold1From<F> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>(short_type_name(type_name::<F>()))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<M: Clone, A, Func: FnMut(&mut M, A)> FoldDescribe for FoldFnMut<M, A, Func> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("FoldFnMut")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F1: FoldDescribe, F2: FoldDescribe> FoldDescribe for Par2<F1, F2>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        let children = vec![self.f1.describe_structure(), self.f2.describe_structure()];
+        Structure::new::<Self>("Par2", children)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F1: FoldDescribe, F2: FoldDescribe, Func> FoldDescribe for ZipWith<F1, F2, Func>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        let (f1, f2) = (&self.inner.f1, &self.inner.f2);
+        let children = vec![f1.describe_structure(), f2.describe_structure()];
+        Structure::new::<Self>("ZipWith", children)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe, P: Fn(&F::A) -> bool> FoldDescribe for FilteredFold<F, P> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("FilteredFold", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe> FoldDescribe for Clamped<F>
+where
+    F::A: PartialOrd + Clone,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("Clamped", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, Key, GetKey> FoldDescribe for GroupedFold<F, GetKey>
+where
+    F: FoldDescribe,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn describe_structure(&self) -> Structure {
+        let name = format!("GroupedFold by {}", short_type_name(type_name::<Key>()));
+        Structure::new::<Self>(name, vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, Key, GetKey> FoldDescribe for GroupedWithTotal<F, GetKey>
+where
+    F: FoldDescribe + FoldPar + Fold,
+    F::M: Clone,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("GroupedWithTotal", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, Key, GetKey> FoldDescribe for SortedGroups<F, GetKey>
+where
+    F: FoldDescribe,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("SortedGroups", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, Key, GetKey, Proj, O> FoldDescribe for SortedGroupsBy<F, GetKey, Proj>
+where
+    F: FoldDescribe,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("SortedGroupsBy", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, Key, GetKey, Proj> FoldDescribe for NormalizedGroups<F, GetKey, Proj>
+where
+    F: FoldDescribe,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> f64,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("NormalizedGroups", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, Key, GetKey, Proj, O> FoldDescribe for RankedGroups<F, GetKey, Proj>
+where
+    F: FoldDescribe,
+    Key: Hash + Ord,
+    GetKey: Fn(&F::A) -> Key,
+    Proj: Fn(&F::B) -> O,
+    O: Ord,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("RankedGroups", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F, GetWeight> FoldDescribe for Weighted<F, GetWeight>
+where
+    F: StepWeighted + Fold + FoldDescribe,
+    GetWeight: Fn(&F::A) -> F::Weight,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("Weighted", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe, A2, PreFunc: Fn(A2) -> F::A> FoldDescribe for PreMap<F, A2, PreFunc> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("PreMap", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe, A> FoldDescribe for Differences<F, A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("Differences", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe, T> FoldDescribe for Pairwise<F, T>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("Pairwise", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe, B2, PostFunc: Fn(F::B) -> B2> FoldDescribe for PostMap<F, B2, PostFunc> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("PostMap", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F1: FoldDescribe, F2: FoldDescribe<A = F1::B>> FoldDescribe for ComposedFold<F1, F2>
+where
+    F1::M: Copy,
+{
+    fn describe_structure(&self) -> Structure {
+        let children = vec![
+            self.first.describe_structure(),
+            self.second.describe_structure(),
+        ];
+        Structure::new::<Self>("ComposedFold", children)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F1, F2> FoldDescribe for DeltaComposed<F1, F2>
+where
+    F1: Incremental + Fold + FoldDescribe,
+    F2: Fold<A = F1::Delta> + FoldDescribe,
+{
+    fn describe_structure(&self) -> Structure {
+        let children = vec![
+            self.first.describe_structure(),
+            self.second.describe_structure(),
+        ];
+        Structure::new::<Self>("DeltaComposed", children)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A: Clone, F: Fold<A = A> + FoldDescribe> FoldDescribe for Batched<F> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("Batched", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe> FoldDescribe for Many<F> {
+    fn describe_structure(&self) -> Structure {
+        let name = format!("Many x{}", self.n);
+        Structure::new::<Self>(name, vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe, const N: usize> FoldDescribe for ManyN<F, N> {
+    fn describe_structure(&self) -> Structure {
+        let name = format!("ManyN x{}", N);
+        Structure::new::<Self>(name, vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe> FoldDescribe for Maybe<F> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("Maybe", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe> FoldDescribe for WithDefault<F, F::B>
+where
+    F::B: Clone,
+{
+    fn describe_structure(&self) -> Structure {
+        let children = vec![self.inner.inner.describe_structure()];
+        Structure::new::<Self>("WithDefault", children)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F1, F2, P> FoldDescribe for Partition<F1, F2, P>
+where
+    F1: Fold + FoldDescribe,
+    F2: Fold<A = F1::A> + FoldDescribe,
+    P: Fn(&F1::A) -> bool,
+{
+    fn describe_structure(&self) -> Structure {
+        let children = vec![self.f1.describe_structure(), self.f2.describe_structure()];
+        Structure::new::<Self>("Partition", children)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F1: FoldDescribe, F2: FoldDescribe> FoldDescribe for Zip<F1, F2> {
+    fn describe_structure(&self) -> Structure {
+        let children = vec![self.f1.describe_structure(), self.f2.describe_structure()];
+        Structure::new::<Self>("Zip", children)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F1: Fold + FoldDescribe, F2: Fold + FoldDescribe> FoldDescribe for ParEither<F1, F2> {
+    fn describe_structure(&self) -> Structure {
+        let children = vec![self.f1.describe_structure(), self.f2.describe_structure()];
+        Structure::new::<Self>("ParEither", children)
+    }
+}
+
+/// The boxed fold's own structure is erased
+#[cfg(feature = "std")]
+impl<A, B> FoldDescribe for BoxedFold<A, B> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("BoxedFold")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe, E> FoldDescribe for PartitionErrors<F, E> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("PartitionErrors", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(feature = "std")]
+impl<F: FoldDescribe, C: Fn(usize)> FoldDescribe for WithProgress<F, C> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("WithProgress", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    #[test]
+    fn partition() {
+        let fld = Sum::<i64>::SUM.partition(|x| x % 2 == 0, Count::COUNT);
+        let (evens, odds) = run_fold_iter(&fld, 0..10);
+        assert_eq!(evens, 20);
+        assert_eq!(odds, 5);
+    }
+
+    #[test]
+    fn par_state_is_flat() {
+        // Nested pairs need no boxing or padding, so a 4-way `par` steps
+        // four adjacent scalars, as a hand-rolled loop would
+        let fld = Min::MIN.par(Max::MAX).par(Sum::SUM).par(Last::LAST);
+        assert_eq!(core::mem::size_of_val(&fld.init(0i32)), 16);
+        assert_eq!(run_fold1_iter(&fld, 1..=10), Some((((1, 10), 55), 10)));
+    }
+
+    #[test]
+    fn fold_fn_mut_captures() {
+        let mut seen = 0;
+        let longest = run_fold_iter(
+            &fold_fn_mut(String::new(), |acc: &mut String, x: &str| {
+                seen += 1;
+                if x.len() > acc.len() {
+                    *acc = x.to_owned();
+                }
+            }),
+            ["a", "abc", "ab"].into_iter(),
+        );
+        assert_eq!(longest, "abc");
+        assert_eq!(seen, 3);
+    }
+
+    #[test]
+    fn grouped_chunks() {
+        let fld = Last::LAST.group_by(|x: &i64| x % 7);
+        let xs: Vec<i64> = (0..1000).map(|i| (i * 31) % 1000).collect();
+        let mut by_step = fld.init(xs[0]);
+        xs[1..].iter().for_each(|&x| fld.step(x, &mut by_step));
+        let mut by_chunk = fld.init(xs[0]);
+        xs[1..]
+            .chunks(100)
+            .for_each(|ch| fld.step_chunk(ch.to_vec(), &mut by_chunk));
+        assert_eq!(fld.output(by_chunk), fld.output(by_step));
+    }
+
+    #[test]
+    fn with_progress() {
+        use std::sync::Mutex;
 
-    fn step(&self, x: Self::A, acc: &mut Self::M) {
-        if (self.pred)(&x) {
-            self.inner.step(x, acc)
-        }
+        let reports = Mutex::new(Vec::new());
+        let fld = Sum::<i64>::SUM.with_progress(4, |n| reports.lock().unwrap().push(n));
+        assert_eq!(run_fold_iter(&fld, 0..10), 45);
+
+        // a new state counts from zero
+        let mut acc = fld.empty();
+        fld.step_chunk(vec![1, 2, 3], &mut acc);
+        fld.step_chunk(vec![4, 5], &mut acc);
+        assert_eq!(acc.seen(), 5);
+        assert_eq!(*reports.lock().unwrap(), vec![4, 8, 5]);
+
+        let reports = Mutex::new(Vec::new());
+        let fld = Sum::<i64>::SUM
+            .with_progress_timed(Duration::ZERO, |n| reports.lock().unwrap().push(n));
+        assert_eq!(run_fold_iter(&fld, 0..3), 3);
+        assert_eq!(*reports.lock().unwrap(), vec![1, 2, 3]);
     }
 
-    fn step_chunk(&self, xs: Vec<Self::A>, acc: &mut Self::M) {
-        // This cannot be close to optimal
-        // I should not pay this allocation each time
-        // Consider passing mask to step_chunk
-        let mut xs_in = Vec::with_capacity(xs.len() / 2);
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn cancellable_streams() {
+        use futures::future::pending;
+        use futures::stream;
 
-        for x in xs {
-            if (self.pred)(&x) {
-                xs_in.push(x)
-            }
-        }
-        self.inner.step_chunk(xs_in, acc);
+        let done =
+            run_fold_stream_cancellable(&Sum::<i64>::SUM, stream::iter(0..5), pending()).await;
+        assert_eq!(done, Outcome::Complete(10));
+
+        // the stream stalls after five values, and is cancelled
+        let stalled = || stream::iter(0..5).chain(stream::pending());
+        let sleep = || tokio::time::sleep(Duration::from_millis(10));
+        let out = run_fold_stream_cancellable(&Sum::<i64>::SUM, stalled(), sleep()).await;
+        assert_eq!(out, Outcome::Cancelled(10));
+
+        let out = run_fold_par_stream_cancellable(&Sum::<i64>::SUM, 2, stalled(), sleep())
+            .await
+            .unwrap();
+        assert!(!out.is_complete());
+        assert!(matches!(out.into_result(), Err(Error::Cancelled)));
     }
 
-    fn output(&self, acc: Self::M) -> Self::B {
-        self.inner.output(acc)
+    #[cfg(feature = "std")]
+    #[test]
+    fn run_stats() {
+        let (out, stats) = run_fold_with_stats(&Sum::<i64>::SUM, 0..10, 4);
+        assert_eq!(out, 45);
+        assert_eq!((stats.items, stats.chunks), (10, 3));
+
+        let (out, stats) = run_fold_with_stats(&Count::COUNT, std::iter::empty::<()>(), 4);
+        assert_eq!((out, stats.items, stats.chunks), (0, 0, 0));
+        assert_eq!(RunStats::default().throughput(), None);
+        let stats = RunStats {
+            items: 10,
+            chunks: 1,
+            duration: Duration::from_millis(500),
+        };
+        assert_eq!(stats.throughput(), Some(20.0));
     }
 
-    fn init(&self, x: Self::A) -> Self::M {
-        self.inner.init(x)
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn par_stream_worker_fails() {
+        use futures::stream;
+
+        let fld = Sum::<i64>::SUM.pre_map(|x: i64| {
+            assert!(x != 3, "bad value");
+            x
+        });
+        let out = run_fold_par_stream(&fld, 2, stream::iter(0..5)).await;
+        assert!(matches!(out, Err(Error::Join(_))));
+        let out = run_fold_par_stream(&fld, 2, stream::iter(4..8)).await;
+        assert_eq!(out.unwrap(), 22);
     }
-}
 
-impl<F: Fold, P: Fn(&F::A) -> bool> Fold for FilteredFold<F, P> {
-    fn empty(&self) -> Self::M {
-        self.inner.empty()
+    #[test]
+    fn pointers_to_folds() {
+        let fld = Sum::<i64>::SUM.par(Count::COUNT);
+        assert_eq!(run_fold_iter(&&fld, 0..4), (6, 4));
+        assert_eq!(run_fold_iter(&Box::new(fld), 0..4), (6, 4));
+        assert_eq!(run_fold_iter(&Rc::new(fld), 0..4), (6, 4));
+        let boxed: Box<dyn Fold<A = i64, B = i64, M = i64>> = Box::new(Sum::SUM);
+        assert_eq!(run_fold_iter(&boxed, 0..4), 6);
     }
-}
 
-impl<F: FoldPar, P: Fn(&F::A) -> bool> FoldPar for FilteredFold<F, P> {
-    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
-        self.inner.merge(m1, m2)
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn par_stream_shared_fold() {
+        use futures::stream;
+        use std::sync::Mutex;
+
+        // a closure owning a mutex is not `Clone`, but its `Arc` is
+        let offset = Mutex::new(1);
+        let fld = Arc::new(Sum::<i64>::SUM.pre_map(move |x: i64| x + *offset.lock().unwrap()));
+        let out = run_fold_par_stream(&fld, 2, stream::iter(0..4)).await;
+        assert_eq!(out.unwrap(), 10);
     }
-}
 
-#[derive(Copy, Clone)]
-pub struct GroupedFold<F, GetKey> {
-    inner: F,
-    get_key: GetKey,
-}
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn error_policies() {
+        use futures::stream;
 
-impl<F: Fold1, Key: Hash + Eq, GetKey: Fn(&F::A) -> Key> Fold1 for GroupedFold<F, GetKey> {
-    type A = F::A;
-    type B = FxHashMap<Key, F::B>;
-    type M = FxHashMap<Key, F::M>;
+        let xs = || stream::iter(["1", "2", "x", "3", "y"].map(str::parse::<i64>));
+        let fld = Sum::<i64>::SUM;
+        let out = run_fold_par_try_stream(&fld, 2, ErrorPolicy::FailFast, xs()).await;
+        assert!(matches!(out, Err(Error::Source(_))));
 
-    fn init(&self, _x: Self::A) -> Self::M {
-        FxHashMap::default()
+        let (sum, errs) = run_fold_par_try_stream(&fld, 2, ErrorPolicy::SkipAndCount, xs())
+            .await
+            .unwrap();
+        assert_eq!((sum, errs.count, errs.errors.len()), (6, 2, 0));
+
+        let (sum, errs) = run_fold_par_try_stream(&fld, 2, ErrorPolicy::CollectErrors, xs())
+            .await
+            .unwrap();
+        assert_eq!((sum, errs.count, errs.errors.len()), (6, 2, 2));
     }
 
-    fn step(&self, x: Self::A, acc: &mut Self::M) {
-        let key = (self.get_key)(&x);
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn stream_timeout() {
+        use futures::stream;
 
-        if let Some(m) = acc.get_mut(&key) {
-            self.inner.step(x, m);
-        } else {
-            acc.insert(key, self.inner.init(x));
-        }
+        let timeout = Duration::from_millis(10);
+        let out = run_fold_stream_timeout(&Count::COUNT, stream::iter(0..5), timeout).await;
+        assert_eq!(out, Outcome::Complete(5));
+
+        let stalled = stream::iter(0..5).chain(stream::pending());
+        let out = run_fold_stream_timeout(&Count::COUNT, stalled, timeout).await;
+        assert_eq!(out, Outcome::Cancelled(5));
     }
 
-    // fn step_chunk()
-    // It should be possible to do something clever here,
-    // but if you have moderately high cardinality within
-    // each chunk, then I think it might just be overhead
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn emit_partial_outputs() {
+        use futures::stream;
 
-    fn output(&self, acc: Self::M) -> Self::B {
-        acc.into_iter()
-            .map(|(k, m)| (k, self.inner.output(m)))
+        let outs: Vec<_> = emit_every(&Sum::<i64>::SUM, stream::iter(1..=5), 2)
             .collect()
-    }
-}
+            .await;
+        assert_eq!(outs, vec![3, 10, 15]);
+        let outs: Vec<_> = emit_every(&Sum::<i64>::SUM, stream::iter(1..=4), 2)
+            .collect()
+            .await;
+        assert_eq!(outs, vec![3, 10, 10]);
+        let outs: Vec<_> = emit_every(&Sum::<i64>::SUM, stream::iter(0..0), 2)
+            .collect()
+            .await;
+        assert_eq!(outs, vec![0]);
 
-impl<F: Fold, Key: Hash + Eq, GetKey: Fn(&F::A) -> Key> Fold for GroupedFold<F, GetKey> {
-    fn empty(&self) -> Self::M {
-        FxHashMap::default()
+        // five values straight away, then five more after a pause
+        let pause = || tokio::time::sleep(Duration::from_millis(60));
+        let xs = stream::iter(0..5).chain(stream::once(pause()).flat_map(|_| stream::iter(0..5)));
+        let outs: Vec<_> = emit_every_duration(&Count::COUNT, xs, Duration::from_millis(25))
+            .collect()
+            .await;
+        assert_eq!(outs.first(), Some(&5));
+        assert_eq!(outs.last(), Some(&10));
+        assert!(outs.len() >= 3);
     }
-}
 
-impl<F: FoldPar, Key: Hash + Eq, GetKey: Fn(&F::A) -> Key> FoldPar for GroupedFold<F, GetKey>
-where
-    F::A: Copy,
-{
-    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
-        for (k, v) in m2.into_iter() {
-            if let Some(v1) = m1.get_mut(&k) {
-                self.inner.merge(v1, v);
-            } else {
-                m1.insert(k, v);
-            }
+    #[test]
+    fn tree_merges() {
+        assert_eq!(merge_tree(&Sum::<usize>::SUM, 0..100), Some(4950));
+        assert_eq!(merge_tree(&Sum::<usize>::SUM, []), None);
+        // the order of the states is kept
+        let fld = WithExamples::new(Count::COUNT, 40, Examples::First);
+        for threads in [1, 2, 4] {
+            let states = (0..37).map(|i| fld.init(i)).collect();
+            let out = fld.output(merge_tree_on(&fld, states, threads).unwrap());
+            assert_eq!(out, (37, (0..37).collect()));
+        }
+        #[cfg(feature = "async")]
+        for n in [0, 1, 13, 64] {
+            let mut merged = PairwiseMerge::new();
+            (0..n).for_each(|i| merged.push(&fld, fld.init(i)));
+            let out = merged.finish(&fld).map(|m| fld.output(m));
+            assert_eq!(out, (n > 0).then(|| (n, (0..n).take(40).collect())));
         }
     }
-}
-
-#[derive(Copy, Clone)]
-pub struct PreMap<F: Fold1, A2, PreFunc: Fn(A2) -> F::A> {
-    inner: F,
-    pre_func: PreFunc,
-    ghost: PhantomData<A2>,
-}
 
-impl<F: Fold1, A2, PreFunc: Fn(A2) -> F::A> Fold1 for PreMap<F, A2, PreFunc> {
-    type A = A2;
-    type B = F::B;
-    type M = F::M;
+    #[test]
+    fn merge_partials() {
+        let fld = Sum::<i64>::SUM.group_by(|x: &i64| x % 2);
+        let partials = (0..4).map(|i| {
+            let mut acc = fld.empty();
+            fld.step_chunk((i * 10..(i + 1) * 10).collect(), &mut acc);
+            acc
+        });
+        let out = merge_outputs(&fld, partials.clone());
+        assert_eq!((out[&0], out[&1]), (380, 400));
 
-    fn init(&self, x: Self::A) -> Self::M {
-        self.inner.init((self.pre_func)(x))
+        let failed = try_merge_outputs(&fld, partials.map(Ok).chain([Err("truncated")]));
+        assert_eq!(failed.err(), Some("truncated"));
+        assert_eq!(merge_outputs(&Sum::<i64>::SUM, []), 0);
     }
 
-    fn step(&self, x: Self::A, acc: &mut Self::M) {
-        self.inner.step((self.pre_func)(x), acc)
+    #[test]
+    fn group_with_total() {
+        let fld = Sum::<i64>::SUM.group_by_with_total(|x: &i64| x % 3);
+        let (groups, total) = run_fold_iter(&fld, 0..10);
+        assert_eq!(total, 45);
+        assert_eq!((groups[&0], groups[&1], groups[&2]), (18, 12, 15));
+
+        let (groups, total) = run_fold_iter(&fld, core::iter::empty());
+        assert!(groups.is_empty());
+        assert_eq!(total, 0);
     }
 
-    fn output(&self, acc: Self::M) -> Self::B {
-        self.inner.output(acc)
+    #[test]
+    fn pivot_tables() {
+        let xs = [("a", 0usize, 1i64), ("a", 1, 2), ("b", 1, 3), ("a", 0, 4)];
+        let fld = Sum::<i64>::SUM
+            .pre_map(|x: (&str, usize, i64)| x.2)
+            .pivot(|x| x.0, |x| x.1);
+        let mut acc = fld.init(xs[0]);
+        let mut acc2 = fld.empty();
+        fld.step(xs[1], &mut acc);
+        fld.step_chunk(xs[2..].to_vec(), &mut acc2);
+        fld.merge(&mut acc, acc2);
+        let out = fld.output(acc);
+        assert_eq!((out["a"][&0], out["a"][&1], out["b"][&1]), (5, 2, 3));
+        assert!(!out["b"].contains_key(&0));
+
+        let fld = Sum::<i64>::SUM
+            .pre_map(|x: (&str, usize, i64)| x.2)
+            .pivot_dense(|x| x.0, |x| x.1, 3);
+        let out = run_fold_iter(&fld, xs.into_iter());
+        assert_eq!(out["a"], vec![5, 2, 0]);
+        assert_eq!(out["b"], vec![0, 3, 0]);
     }
-}
 
-impl<F: Fold, A2, PreFunc: Fn(A2) -> F::A> Fold for PreMap<F, A2, PreFunc> {
-    fn empty(&self) -> Self::M {
-        self.inner.empty()
+    #[test]
+    fn group_examples() {
+        let fld = Sum::<i64>::SUM.group_by(|x: &i64| x % 2);
+        let first = run_fold_iter(&fld.with_examples(2, Examples::First), 0..10);
+        assert_eq!(first[&0], (20, vec![0, 2]));
+        assert_eq!(first[&1], (25, vec![1, 3]));
+
+        let fld = fld.with_examples(3, Examples::Last);
+        let mut acc = fld.empty();
+        fld.step_chunk((0..6).collect(), &mut acc);
+        let mut acc2 = fld.empty();
+        fld.step_chunk((6..10).collect(), &mut acc2);
+        fld.merge(&mut acc, acc2);
+        let last = fld.output(acc);
+        assert_eq!(last[&0], (20, vec![4, 6, 8]));
+        assert_eq!(last[&1], (25, vec![5, 7, 9]));
     }
-}
 
-impl<F: FoldPar, A2, PreFunc: Fn(A2) -> F::A> FoldPar for PreMap<F, A2, PreFunc> {
-    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
-        self.inner.merge(m1, m2)
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_by_key() {
+        use rayon::prelude::*;
+
+        let xs: Vec<i64> = (0..10_000).map(|i| (i * 7919) % 1000).collect();
+        let fld = Sum::<i64>::SUM.group_by(|x: &i64| x % 300);
+        let grouped = run_fold_par_iter_by_key(xs.par_iter().copied(), &fld, 4);
+        assert_eq!(grouped, run_fold_iter(&fld, xs.iter().copied()));
+        assert_eq!(grouped.len(), 300);
+
+        // the inner fold need not be mergeable
+        let fld = Max::MAX.group_by(|x: &i64| x % 3);
+        let grouped = run_fold_par_iter_by_key(xs.par_iter().copied(), &fld, 2);
+        assert_eq!(grouped[&0], 999);
     }
-}
 
-#[derive(Copy, Clone)]
-pub struct PostMap<F: Fold1, B2, PostFunc: Fn(F::B) -> B2> {
-    inner: F,
-    post_func: PostFunc,
-}
+    #[test]
+    fn weighted() {
+        // (value, frequency) rows
+        let rows = [(1i64, 2usize), (5, 1), (1, 3), (2, 4)];
+        let fld = Count::COUNT
+            .par(Sum::SUM.pre_map(|(x, _): (i64, usize)| x as usize))
+            .weighted(|(_, n): &(i64, usize)| *n);
+        assert_eq!(run_fold_iter(&fld, rows.into_iter()), (10, 18));
 
-impl<F: Fold1, B2, PostFunc: Fn(F::B) -> B2> Fold1 for PostMap<F, B2, PostFunc> {
-    type A = F::A;
-    type B = B2;
-    type M = F::M;
+        let counts = Count::COUNT
+            .group_by(|(x, _): &(i64, usize)| *x)
+            .weighted(|(_, n): &(i64, usize)| *n);
+        let out = run_fold_iter(&counts, rows.into_iter());
+        assert_eq!((out[&1], out[&2], out[&5]), (5, 4, 1));
 
-    fn init(&self, x: Self::A) -> Self::M {
-        self.inner.init(x)
+        let (mut m1, mut m2) = (counts.empty(), counts.empty());
+        counts.step_chunk(rows[..2].to_vec(), &mut m1);
+        counts.step_chunk(rows[2..].to_vec(), &mut m2);
+        counts.merge(&mut m1, m2);
+        assert_eq!(counts.output(m1), out);
     }
 
-    fn step(&self, x: Self::A, acc: &mut Self::M) {
-        self.inner.step(x, acc)
+    #[test]
+    fn sorted_groups() {
+        let xs = [5i64, 3, 8, 3, 1, 8, 8, 2];
+        let fld = Count::COUNT.group_by(|x: &i64| *x).sorted();
+        let out = run_fold_iter(&fld, xs.iter().copied());
+        assert_eq!(
+            out.into_iter().collect::<Vec<_>>(),
+            vec![(1, 1), (2, 1), (3, 2), (5, 1), (8, 3)]
+        );
+
+        // most common first, ties by key
+        let fld = Count::COUNT
+            .group_by(|x: &i64| *x)
+            .sorted_by(|n: &usize| core::cmp::Reverse(*n));
+        let out = run_fold_iter(&fld, xs.iter().copied());
+        assert_eq!(out, vec![(8, 3), (3, 2), (1, 1), (2, 1), (5, 1)]);
     }
 
-    fn output(&self, acc: Self::M) -> Self::B {
-        (self.post_func)(self.inner.output(acc))
+    #[test]
+    fn relative_groups() {
+        let xs = ["a", "b", "a", "c", "a", "b", "c", "a"];
+        let fld = Count::COUNT
+            .group_by(|x: &&str| *x)
+            .normalize_by_total(|n: &usize| *n as f64);
+        let out = run_fold_iter(&fld, xs.iter().copied());
+        assert_eq!(out["a"], (4, 0.5));
+        assert_eq!(out["b"], (2, 0.25));
+        assert_eq!(out.values().map(|(_, share)| share).sum::<f64>(), 1.0);
+
+        // most common first, ties by key
+        let fld = Count::COUNT
+            .group_by(|x: &&str| *x)
+            .rank_keys(|n: &usize| core::cmp::Reverse(*n));
+        let out = run_fold_iter(&fld, xs.iter().copied());
+        assert_eq!((out["a"], out["b"], out["c"]), ((4, 1), (2, 2), (2, 3)));
     }
-}
 
-impl<F: Fold, B2, PostFunc: Fn(F::B) -> B2> Fold for PostMap<F, B2, PostFunc> {
-    fn empty(&self) -> Self::M {
-        self.inner.empty()
+    #[test]
+    fn state_size() {
+        let fld = Count::COUNT.group_by(|x: &i64| x % 10);
+        let acc = (0..100).fold(fld.empty(), |mut acc, x| {
+            fld.step(x, &mut acc);
+            acc
+        });
+        let bytes = fld.state_bytes(&acc);
+        assert!(bytes >= 10 * size_of::<(i64, usize)>());
+
+        // stop once the state outgrows a budget
+        let fld = Count::COUNT.group_by(|x: &i64| *x);
+        let out = run_fold_iter_sized(&fld, 0..10_000, 100, |bytes| {
+            if bytes > 4096 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert!(!out.is_complete());
+        assert!(out.into_inner().len() < 10_000);
+
+        let out = run_fold_iter_sized(&Sum::<i64>::SUM, 0..10, 3, |_| ControlFlow::Continue(()));
+        assert_eq!(out, Outcome::Complete(45));
     }
-}
 
-impl<F: FoldPar, B2, PostFunc: Fn(F::B) -> B2> FoldPar for PostMap<F, B2, PostFunc> {
-    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
-        self.inner.merge(m1, m2)
+    #[test]
+    fn bounded_runs() {
+        let fld = Count::COUNT.group_by(|x: &i64| *x);
+        let options = RunOptions {
+            max_state_bytes: Some(4096),
+            check_every: 100,
+        };
+        let out = run_fold_iter_bounded(&fld, 0..10_000, options);
+        assert!(matches!(out, Err(Error::StateTooLarge { max: 4096, .. })));
+
+        let out = run_fold_iter_bounded(&fld, (0..10_000).map(|x| x % 10), options);
+        assert_eq!(out.unwrap().len(), 10);
+        let out = run_fold_iter_bounded(&fld, 0..10_000, RunOptions::default());
+        assert_eq!(out.unwrap().len(), 10_000);
+
+        // the end of an input shorter than check_every is checked
+        let options = RunOptions {
+            check_every: 100_000,
+            ..options
+        };
+        let out = run_fold_iter_bounded(&fld, 0..10_000, options);
+        assert!(matches!(out, Err(Error::StateTooLarge { .. })));
     }
-}
 
-#[derive(Copy, Clone)]
-pub struct ComposedFold<F1: Fold1, F2: Fold1> {
-    first: F1,
-    second: F2,
-}
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn bounded_stream() {
+        use futures::stream;
 
-impl<F1: Fold1, F2: Fold1<A = F1::B>> Fold1 for ComposedFold<F1, F2>
-where
-    F1::M: Copy,
-{
-    type A = F1::A;
+        let fld = Count::COUNT.group_by(|x: &i64| *x);
+        let options = RunOptions {
+            max_state_bytes: Some(4096),
+            ..RunOptions::default()
+        };
+        let out = run_fold_stream_bounded(&fld, stream::iter(0..10_000), options).await;
+        assert!(matches!(out, Err(Error::StateTooLarge { .. })));
+        let out = run_fold_stream_bounded(&fld, stream::iter(0..10), options).await;
+        assert_eq!(out.unwrap().len(), 10);
+    }
 
-    type B = F2::B;
+    #[test]
+    fn scan_checkpoints() {
+        let mut it = scan(Sum::<i64>::SUM, 1..=6).indexed();
+        assert_eq!(it.next(), Some((0, 1)));
+        assert_eq!(it.next(), Some((1, 3)));
+        assert_eq!(*it.inner().state(), 3);
 
-    type M = (F1::M, F2::M);
+        // resume the rest of the input from a checkpoint
+        let (rest, state, index) = it.into_inner().into_parts();
+        let resumed: Vec<_> = ScanIter::resume(Sum::SUM, rest, state, index)
+            .indexed()
+            .collect();
+        assert_eq!(resumed, vec![(2, 6), (3, 10), (4, 15), (5, 21)]);
+    }
 
-    fn init(&self, x: Self::A) -> Self::M {
-        let m1 = self.first.init(x);
-        let m2 = self.second.init(self.first.output(m1));
-        (m1, m2)
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn scan_stream_checkpoints() {
+        let mut st = ScanStream::new(Sum::<i64>::SUM, futures::stream::iter(1..=4));
+        assert_eq!(st.next().await, Some(1));
+        assert_eq!((*st.state(), st.index()), (1, 1));
+        let rest: Vec<_> = st.indexed().collect().await;
+        assert_eq!(rest, vec![(1, 3), (2, 6), (3, 10)]);
     }
 
-    fn step(&self, x: Self::A, acc: &mut Self::M) {
-        let (m1, m2) = acc;
-        self.first.step(x, m1);
-        let y = self.first.output(*m1);
-        self.second.step(y, m2);
+    #[test]
+    fn windows() {
+        let sums: Vec<i64> = run_fold_windows_iter(&Sum::SUM, 1..=7, 3, 2).collect();
+        assert_eq!(sums, vec![6, 12, 18]);
+        let sums: Vec<i64> = run_fold_windows_iter(&Sum::SUM, 1..=9, 2, 4).collect();
+        assert_eq!(sums, vec![3, 11]);
+        assert_eq!(
+            run_fold_windows_iter(&Sum::<i64>::SUM, 1..=2, 3, 1).count(),
+            0
+        );
     }
 
-    fn output(&self, acc: Self::M) -> Self::B {
-        let (_m1, m2) = acc;
-        self.second.output(m2)
+    #[test]
+    fn segments() {
+        let lines = ["a", "b", "", "c", "", "", "d"];
+        let paragraphs = Count::COUNT.filter(|l: &&str| !l.is_empty());
+        let counts: Vec<_> =
+            run_fold_segments(&paragraphs, lines.into_iter(), |l| l.is_empty()).collect();
+        assert_eq!(counts, vec![2, 1, 0, 1]);
+
+        // split on day rollovers, tracking the current day
+        let hours = [1u32, 5, 23, 25, 30, 49];
+        let mut day = None;
+        let daily: Vec<_> = run_fold_segments(&Count::COUNT, hours.into_iter(), |h| {
+            day.replace(h / 24).is_some_and(|d| d != h / 24)
+        })
+        .collect();
+        assert_eq!(daily, vec![3, 2, 1]);
     }
-}
 
-impl<F1: Fold, F2: Fold1<A = F1::B>> Fold for ComposedFold<F1, F2>
-where
-    F1::M: Copy,
-{
-    fn empty(&self) -> Self::M {
-        let m1 = self.first.empty();
-        let m2 = self.second.init(self.first.output(m1));
-        (m1, m2)
+    #[test]
+    fn differences() {
+        let prices = [100i64, 103, 101, 106, 106];
+        let fld = Max::MAX.optional().par(Count::COUNT).differences();
+        assert_eq!(run_fold_iter(&fld, prices.into_iter()), (Some(5), 4));
+        assert_eq!(
+            run_fold1_iter(&fld, prices[..1].iter().copied()),
+            Some((None, 0))
+        );
+
+        // the difference across a split is kept by the merge
+        let steps = Sum::<i64>::SUM.differences();
+        let (mut m1, mut m2) = (steps.empty(), steps.empty());
+        steps.step_chunk(prices[..2].to_vec(), &mut m1);
+        steps.step_chunk(prices[2..].to_vec(), &mut m2);
+        steps.merge_at(&mut m1, m2, 2);
+        assert_eq!(steps.output(m1), 6);
     }
-}
 
-// This is a simple version of a scan that doesn't really work
-// because filtered folds will break.
-// Consider scan(filtered(summer, is_odd), xs)
-// this will return an iterator the same length as the input
-// it wont really allow for filtering
-#[allow(dead_code)]
-pub fn scan<F: Fold>(fld: F, iter: impl Iterator<Item = F::A>) -> impl Iterator<Item = F::B>
-where
-    F::M: Copy,
-{
-    let mut acc = fld.empty();
-    iter.map(move |x| {
-        fld.step(x, &mut acc);
-        fld.output(acc)
-    })
-}
+    #[test]
+    fn pairwise() {
+        let states = ["idle", "busy", "busy", "idle", "busy"];
+        let transitions = Count::COUNT.filter(|(a, b)| a != b).pairwise();
+        assert_eq!(run_fold_iter(&transitions, states.into_iter()), 3);
 
-#[derive(Clone, Copy)]
-pub struct Batched<F: Fold1> {
-    inner: F,
-}
-impl<A: Clone, F: Fold<A = A>> Fold1 for Batched<F> {
-    type A = Vec<F::A>;
+        let xs = [1, 2, 2, 5, 4];
+        let rising = Count::COUNT.filter(|(a, b): &(i32, i32)| a < b).pairwise();
+        assert_eq!(run_fold1_iter(&rising, xs[..1].iter().copied()), Some(0));
 
-    type B = F::B;
+        // the pair across a split is kept by the merge
+        let (mut m1, mut m2) = (rising.empty(), rising.empty());
+        rising.step_chunk(xs[..3].to_vec(), &mut m1);
+        rising.step_chunk(xs[3..].to_vec(), &mut m2);
+        rising.merge_at(&mut m1, m2, 3);
+        assert_eq!(rising.output(m1), 2);
+        assert_eq!(run_fold_iter(&rising, xs.into_iter()), 2);
+    }
 
-    type M = F::M;
+    #[test]
+    fn clamp() {
+        let xs = [-5, 1, 2, 3, 40];
+        let fld = Sum::SUM.par(Max::MAX).clamp(0, 10);
+        assert_eq!(run_fold1_iter(&fld, xs.into_iter()), Some((16, 10)));
+        assert_eq!(run_fold1_iter(&fld, [-5].into_iter()), Some((0, 0)));
 
-    // this will panic on empty chunk
-    fn init(&self, x: Self::A) -> Self::M {
-        let mut acc = self.inner.empty();
-        self.inner.step_chunk(x, &mut acc);
-        acc
+        let sum = Sum::SUM.clamp(0, 10);
+        let mut acc = sum.empty();
+        sum.step_chunk(xs.to_vec(), &mut acc);
+        assert_eq!(sum.output(acc), 16);
     }
 
-    fn step(&self, x: Self::A, acc: &mut Self::M) {
-        self.inner.step_chunk(x, acc)
+    #[test]
+    fn scans() {
+        let xs = [1, 2, 3, 4];
+        let pre: Vec<_> = prescan(Sum::<i64>::SUM, xs.into_iter()).collect();
+        assert_eq!(pre, vec![0, 1, 3, 6]);
+
+        let states: Vec<_> =
+            scan_state(Count::COUNT.par(Sum::<i64>::SUM), xs.into_iter()).collect();
+        assert_eq!(states, vec![(1, 1), (2, 3), (3, 6), (4, 10)]);
+
+        assert_eq!(scan_rev(Sum::<i64>::SUM, xs.into_iter()), vec![10, 9, 7, 4]);
     }
 
-    fn output(&self, acc: Self::M) -> Self::B {
-        self.inner.output(acc)
+    #[test]
+    fn then_delta() {
+        // the largest group size seen, without recomputing every group
+        let fld = Count::COUNT
+            .group_by(|x: &i64| x % 3)
+            .then_delta(Max::MAX.or_default().pre_map(|(_, n): (i64, usize)| n));
+        assert_eq!(run_fold_iter(&fld, [0, 1, 3, 6, 4].into_iter()), 3);
+
+        let fld = Sum::<i64>::SUM.filter(|x| *x > 0).then_delta(Count::COUNT);
+        assert_eq!(run_fold_iter(&fld, [1, -2, 3, -4].into_iter()), 2);
     }
-}
 
-impl<A: Clone, F: Fold<A = A>> Fold for Batched<F> {
-    fn empty(&self) -> Self::M {
-        self.inner.empty()
+    /// `init(x)` should agree with `empty` followed by `step(x)`
+    fn check_init<F: Fold>(fld: &F, x: F::A)
+    where
+        F::A: Clone,
+        F::B: PartialEq + std::fmt::Debug,
+    {
+        let mut acc = fld.empty();
+        fld.step(x.clone(), &mut acc);
+        assert_eq!(fld.output(fld.init(x)), fld.output(acc));
     }
-}
 
-impl<A: Clone, F: FoldPar<A = A> + Fold> FoldPar for Batched<F> {
-    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
-        self.inner.merge(m1, m2)
+    #[test]
+    fn init_is_empty_then_step() {
+        check_init(&Sum::<i64>::SUM, 3);
+        check_init(&Count::<i64>::COUNT, 3);
+        check_init(&Sum::<i64>::SUM.group_by(|x| x % 2), 3);
+        check_init(&Count::COUNT.filter(|x: &i64| *x > 0), 3);
+        check_init(&Sum::<i64>::SUM.pre_map(|x: i64| x * 2), 3);
+        check_init(&Sum::<i64>::SUM.post_map(|x| x + 1), 3);
+        check_init(&Sum::<i64>::SUM.par(Count::COUNT), 3);
+        check_init(
+            &Sum::<i64>::SUM.zip_with(Count::COUNT, |s, n| s * n as i64),
+            3,
+        );
+        check_init(&Sum::<i64>::SUM.zip(Count::COUNT), (3, "a"));
+        check_init(&Sum::<i64>::SUM.batched(), vec![1, 2]);
+        check_init(&Sum::<i64>::SUM.many(2), vec![1, 2]);
+        check_init(&Max::<i64>::MAX.optional(), 3);
+        check_init(&Max::<i64>::MAX.or_default(), 3);
+        check_init(&Sum::<i64>::SUM.partition(|x| x % 2 == 0, Count::COUNT), 3);
+        check_init(
+            &Sum::<i64>::SUM.par_either(Count::COUNT),
+            Either::Right("a"),
+        );
+        check_init(&Sum::<i64>::SUM.partition_errors::<&str>(), Err("e"));
+        check_init(&Sum::<i64>::SUM.boxed(), 3);
+        check_init(&Sum::<i64>::SUM.with_progress(10, |_| {}), 3);
+        check_init(&Count::COUNT.then_delta(Sum::<usize>::SUM), 3);
+        check_init(&Fold1From::new(Mean), 3.0);
     }
-}
 
-/// Perform a fold in parallel with itself over a wide stream
-#[derive(Copy, Clone)]
-pub struct Many<F: Fold1> {
-    inner: F,
-    n: usize,
-}
-impl<F: Fold1> Fold1 for Many<F> {
-    type A = Vec<F::A>;
+    /// Running mean, implemented without a separate `init`
+    struct Mean;
 
-    type B = Vec<F::B>;
+    impl FoldImpl for Mean {
+        type A = f64;
+        type B = f64;
+        type M = (usize, f64);
 
-    type M = Vec<F::M>;
+        fn empty(&self) -> Self::M {
+            (0, 0.0)
+        }
 
-    fn init(&self, x: Self::A) -> Self::M {
-        x.into_iter().map(|x| self.inner.init(x)).collect()
-    }
+        fn step(&self, x: Self::A, (n, mean): &mut Self::M) {
+            *n += 1;
+            *mean += (x - *mean) / *n as f64;
+        }
 
-    fn step(&self, x: Self::A, acc: &mut Self::M) {
-        for (a, x) in acc.iter_mut().zip(x.into_iter()) {
-            self.inner.step(x, a)
+        fn output(&self, (_, mean): Self::M) -> Self::B {
+            mean
         }
     }
 
-    fn output(&self, acc: Self::M) -> Self::B {
-        acc.into_iter().map(|a| self.inner.output(a)).collect()
+    #[test]
+    fn zip() {
+        let fld = Sum::<i64>::SUM.zip(Max::MAX);
+        let xs = vec![(1, "a"), (2, "c"), (3, "b")];
+        let ans = run_fold1_iter(&fld, xs.into_iter());
+        assert_eq!(ans, Some((6, "c")));
     }
-}
 
-impl<F: Fold> Fold for Many<F> {
-    fn empty(&self) -> Self::M {
-        let mut accs = Vec::with_capacity(self.n);
-        for _ in 0..self.n {
-            accs.push(self.inner.empty());
-        }
-        accs
+    #[test]
+    fn with_default() {
+        let fld = Max::MAX.with_default(-1).par(Min::MIN.or_default());
+        assert_eq!(run_fold_iter(&fld, 0..0), (-1, 0));
+        assert_eq!(run_fold_iter(&fld, 3..10), (9, 3));
     }
-}
 
-impl<F: FoldPar> FoldPar for Many<F> {
-    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
-        for (m1, m2) in m1.iter_mut().zip(m2.into_iter()) {
-            self.inner.merge(m1, m2)
-        }
+    #[test]
+    fn optional() {
+        let fld = First::FIRST.optional().par(Last::LAST.optional());
+        assert_eq!(run_fold_iter(&fld, 0..0), (None, None));
+        assert_eq!(run_fold_iter(&fld, 3..10), (Some(3), Some(9)));
+    }
+
+    #[test]
+    fn many() {
+        let rows = vec![vec![1, 2], vec![3, 4, 5], vec![6]];
+        let fld = Sum::<i64>::SUM.many(2).resizing();
+        assert_eq!(
+            run_fold_iter(&fld, rows.clone().into_iter()),
+            vec![10, 6, 5]
+        );
+
+        let mut acc = fld.empty();
+        fld.step_chunk(rows, &mut acc);
+        assert_eq!(fld.output(acc), vec![10, 6, 5]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn many_strict_width() {
+        let rows = vec![vec![1, 2], vec![3, 4, 5]];
+        run_fold_iter(&Sum::<i64>::SUM.many(2), rows.into_iter());
+    }
+
+    #[test]
+    fn many_n() {
+        let rows = vec![[1, 2, 3], [4, 5, 6]];
+        let fld = Sum::<i64>::SUM.many_n::<3>();
+        assert_eq!(run_fold_iter(&fld, rows.clone().into_iter()), [5, 7, 9]);
+
+        let mut acc = fld.init([1, 1, 1]);
+        fld.step_chunk(rows, &mut acc);
+        assert_eq!(fld.output(acc), [6, 8, 10]);
+    }
+
+    #[test]
+    fn partition_errors() {
+        let xs = ["1", "2", "x", "3", "y"];
+        let fld = Sum::<i64>::SUM.partition_errors();
+        let (sum, errs) = run_fold_iter(&fld, xs.iter().map(|s| s.parse::<i64>().map_err(|_| *s)));
+        assert_eq!(sum, Some(6));
+        assert_eq!(errs.count, 2);
+        assert_eq!(errs.errors, vec!["x", "y"]);
+
+        // a `Fold1` has no output when every value is an error
+        let fld = Max::<i64>::MAX.partition_errors();
+        let (max, errs) = run_fold_iter(&fld, [Err("x"), Err("y")].into_iter());
+        assert_eq!(max, None);
+        assert_eq!(errs.count, 2);
     }
 }
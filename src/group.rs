@@ -0,0 +1,1153 @@
+//! Variants of `group_by` for long running and high cardinality inputs
+use crate::fold::*;
+use crate::sketch::hash64;
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, VecDeque};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash};
+use std::marker::PhantomData;
+use std::ops::Add;
+
+#[cfg(feature = "async")]
+use futures::{Stream, StreamExt};
+use hashbrown::hash_map::RawEntryMut;
+#[cfg(feature = "stats-rand")]
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use rustc_hash::{FxHashMap, FxHasher};
+#[cfg(feature = "stats-rand")]
+use std::sync::{Arc, Mutex};
+
+/// Grouped fold which closes groups once they have been idle for
+/// longer than a time to live, see `Fold1::group_by_with_ttl`
+#[derive(Copy, Clone)]
+pub struct GroupedTtl<F, GetKey, GetTime, D> {
+    inner: F,
+    get_key: GetKey,
+    get_time: GetTime,
+    ttl: D,
+}
+
+impl<F, GetKey, GetTime, D> GroupedTtl<F, GetKey, GetTime, D> {
+    pub(crate) fn new(inner: F, get_key: GetKey, get_time: GetTime, ttl: D) -> Self {
+        GroupedTtl {
+            inner,
+            get_key,
+            get_time,
+            ttl,
+        }
+    }
+}
+
+/// State of a `GroupedTtl`
+pub struct TtlState<Key, T, M, B> {
+    /// Open groups, with the time they were last seen and their entry
+    /// in `idle`
+    groups: FxHashMap<Key, (T, u64, M)>,
+    /// Keys of the open groups by the time they were last seen, with a
+    /// sequence number to order and tell apart those seen at once
+    idle: BTreeMap<(T, u64), Key>,
+    /// Sequence number of the next entry in `idle`
+    seq: u64,
+    /// Latest time seen so far
+    now: Option<T>,
+    /// Outputs of closed groups
+    expired: VecDeque<(Key, B)>,
+}
+
+impl<F, Key, GetKey, T, GetTime, D> GroupedTtl<F, GetKey, GetTime, D>
+where
+    F: Fold1,
+    Key: Hash + Eq + Clone,
+    GetKey: Fn(&F::A) -> Key,
+    T: Copy + Ord + Add<D, Output = T>,
+    GetTime: Fn(&F::A) -> T,
+    D: Copy,
+{
+    /// Close every group idle for longer than the ttl at time `now`,
+    /// in the order they were last seen
+    fn sweep(&self, acc: &mut <Self as Fold1>::M, now: T) {
+        while let Some(entry) = acc.idle.first_entry() {
+            if entry.key().0 + self.ttl >= now {
+                break;
+            }
+            let k = entry.remove();
+            let (_, _, m) = acc.groups.remove(&k).unwrap();
+            acc.expired.push_back((k, self.inner.output(m)));
+        }
+    }
+}
+
+impl<F, Key, GetKey, T, GetTime, D> Fold1 for GroupedTtl<F, GetKey, GetTime, D>
+where
+    F: Fold1,
+    Key: Hash + Eq + Clone,
+    GetKey: Fn(&F::A) -> Key,
+    T: Copy + Ord + Add<D, Output = T>,
+    GetTime: Fn(&F::A) -> T,
+    D: Copy,
+{
+    type A = F::A;
+    /// Closed groups in the order they were last seen, followed by
+    /// the groups still open. A key appears once per period of activity.
+    type B = Vec<(Key, F::B)>;
+    type M = TtlState<Key, T, F::M, F::B>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let t = (self.get_time)(&x);
+        let now = acc.now.map_or(t, |now| now.max(t));
+        acc.now = Some(now);
+        self.sweep(acc, now);
+
+        let key = (self.get_key)(&x);
+        if let Some((seen, seq, m)) = acc.groups.get_mut(&key) {
+            if t > *seen {
+                let k = acc.idle.remove(&(*seen, *seq)).unwrap();
+                (*seen, *seq) = (t, acc.seq);
+                acc.idle.insert((t, acc.seq), k);
+                acc.seq += 1;
+            }
+            self.inner.step(x, m);
+        } else {
+            acc.idle.insert((t, acc.seq), key.clone());
+            acc.groups.insert(key, (t, acc.seq, self.inner.init(x)));
+            acc.seq += 1;
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.expired
+            .into_iter()
+            .chain(
+                acc.groups
+                    .into_iter()
+                    .map(|(k, (_, _, m))| (k, self.inner.output(m))),
+            )
+            .collect()
+    }
+}
+
+impl<F, Key, GetKey, T, GetTime, D> Fold for GroupedTtl<F, GetKey, GetTime, D>
+where
+    F: Fold1,
+    Key: Hash + Eq + Clone,
+    GetKey: Fn(&F::A) -> Key,
+    T: Copy + Ord + Add<D, Output = T>,
+    GetTime: Fn(&F::A) -> T,
+    D: Copy,
+{
+    fn empty(&self) -> Self::M {
+        TtlState {
+            groups: FxHashMap::default(),
+            idle: BTreeMap::new(),
+            seq: 0,
+            now: None,
+            expired: VecDeque::new(),
+        }
+    }
+}
+
+/// Run a grouped fold with a ttl over a stream, yielding each group's
+/// output as soon as it is closed rather than keeping it in the state.
+/// Groups still open at the end of the stream are yielded last.
+#[cfg(feature = "async")]
+pub fn run_fold_stream_ttl<'f, F, Key, GetKey, T, GetTime, D>(
+    fold: &'f GroupedTtl<F, GetKey, GetTime, D>,
+    xs: impl Stream<Item = F::A> + 'f,
+) -> impl Stream<Item = (Key, F::B)> + 'f
+where
+    F: Fold1,
+    Key: Hash + Eq + Clone + 'f,
+    GetKey: Fn(&F::A) -> Key,
+    T: Copy + Ord + Add<D, Output = T> + 'f,
+    GetTime: Fn(&F::A) -> T,
+    D: Copy,
+{
+    futures::stream::unfold(
+        (Box::pin(xs.fuse()), fold.empty()),
+        move |(mut xs, mut acc)| async move {
+            loop {
+                if let Some(out) = acc.expired.pop_front() {
+                    return Some((out, (xs, acc)));
+                }
+                match xs.next().await {
+                    Some(x) => fold.step(x, &mut acc),
+                    None if acc.groups.is_empty() => return None,
+                    None => {
+                        while let Some((_, k)) = acc.idle.pop_first() {
+                            let (_, _, m) = acc.groups.remove(&k).unwrap();
+                            acc.expired.push_back((k, fold.inner.output(m)));
+                        }
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Grouped fold which keeps the states of its groups in one slab,
+/// see `Fold1::group_by_arena`
+#[derive(Copy, Clone)]
+pub struct GroupedArena<F, GetKey> {
+    inner: F,
+    get_key: GetKey,
+}
+
+impl<F, GetKey> GroupedArena<F, GetKey> {
+    pub(crate) fn new(inner: F, get_key: GetKey) -> Self {
+        GroupedArena { inner, get_key }
+    }
+}
+
+/// State of a `GroupedArena`
+pub struct ArenaState<Key, M> {
+    /// Index of each group's state in `states`
+    index: FxHashMap<Key, usize>,
+    states: Vec<M>,
+}
+
+impl<Key, M> ArenaState<Key, M> {
+    /// Each group with its state, in the order the groups were created
+    fn into_groups(self) -> impl Iterator<Item = (Key, M)> {
+        let mut keys: Vec<Option<Key>> = (0..self.states.len()).map(|_| None).collect();
+        for (k, i) in self.index {
+            keys[i] = Some(k);
+        }
+        keys.into_iter().flatten().zip(self.states)
+    }
+}
+
+impl<F, Key, GetKey> Fold1 for GroupedArena<F, GetKey>
+where
+    F: Fold1,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    type A = F::A;
+    type B = FxHashMap<Key, F::B>;
+    type M = ArenaState<Key, F::M>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = ArenaState {
+            index: FxHashMap::default(),
+            states: Vec::new(),
+        };
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        match acc.index.entry((self.get_key)(&x)) {
+            Entry::Occupied(e) => self.inner.step(x, &mut acc.states[*e.get()]),
+            Entry::Vacant(e) => {
+                e.insert(acc.states.len());
+                acc.states.push(self.inner.init(x));
+            }
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.into_groups()
+            .map(|(k, m)| (k, self.inner.output(m)))
+            .collect()
+    }
+}
+
+impl<F, Key, GetKey> Fold for GroupedArena<F, GetKey>
+where
+    F: Fold,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn empty(&self) -> Self::M {
+        ArenaState {
+            index: FxHashMap::default(),
+            states: Vec::new(),
+        }
+    }
+}
+
+impl<F, Key, GetKey> FoldPar for GroupedArena<F, GetKey>
+where
+    F: FoldPar,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        if m1.states.is_empty() {
+            *m1 = m2;
+            return;
+        }
+        for (k, m) in m2.into_groups() {
+            match m1.index.entry(k) {
+                Entry::Occupied(e) => self.inner.merge(&mut m1.states[*e.get()], m),
+                Entry::Vacant(e) => {
+                    e.insert(m1.states.len());
+                    m1.states.push(m);
+                }
+            }
+        }
+    }
+}
+
+/// Heap memory owned by the keys is not counted
+impl<F, Key, GetKey> StateSize for GroupedArena<F, GetKey>
+where
+    F: StateSize,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>()
+            + m.index.capacity() * size_of::<(Key, usize)>()
+            + m.states.capacity() * size_of::<F::M>()
+            + m.states
+                .iter()
+                .map(|s| heap_bytes(&self.inner, s))
+                .sum::<usize>()
+    }
+}
+
+/// Fold over a fixed number of columns, keeping a state for each and
+/// sending every value to the column given by `col_index`,
+/// see `Fold1::pivot_dense`
+#[derive(Copy, Clone)]
+pub struct DenseColumns<F, ColIndex> {
+    inner: F,
+    col_index: ColIndex,
+    n_cols: usize,
+}
+
+impl<F, ColIndex> DenseColumns<F, ColIndex> {
+    pub fn new(inner: F, col_index: ColIndex, n_cols: usize) -> Self {
+        DenseColumns {
+            inner,
+            col_index,
+            n_cols,
+        }
+    }
+}
+
+impl<F, ColIndex> Fold1 for DenseColumns<F, ColIndex>
+where
+    F: Fold,
+    ColIndex: Fn(&F::A) -> usize,
+{
+    type A = F::A;
+    /// An output for every column, those without values being the
+    /// output of the empty state
+    type B = Vec<F::B>;
+    type M = Vec<F::M>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let i = (self.col_index)(&x);
+        assert!(
+            i < self.n_cols,
+            "DenseColumns: column {i} out of {}",
+            self.n_cols
+        );
+        self.inner.step(x, &mut acc[i])
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.into_iter().map(|m| self.inner.output(m)).collect()
+    }
+}
+
+impl<F, ColIndex> Fold for DenseColumns<F, ColIndex>
+where
+    F: Fold,
+    ColIndex: Fn(&F::A) -> usize,
+{
+    fn empty(&self) -> Self::M {
+        (0..self.n_cols).map(|_| self.inner.empty()).collect()
+    }
+}
+
+impl<F, ColIndex> FoldPar for DenseColumns<F, ColIndex>
+where
+    F: Fold + FoldPar,
+    ColIndex: Fn(&F::A) -> usize,
+{
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (s1, s2) in m1.iter_mut().zip(m2) {
+            self.inner.merge(s1, s2)
+        }
+    }
+}
+
+impl<F, ColIndex> StateSize for DenseColumns<F, ColIndex>
+where
+    F: Fold + StateSize,
+    ColIndex: Fn(&F::A) -> usize,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>()
+            + m.capacity() * size_of::<F::M>()
+            + m.iter().map(|s| heap_bytes(&self.inner, s)).sum::<usize>()
+    }
+}
+
+/// Grouped fold which only keeps a sample of its keys, see
+/// `Fold1::group_by_sampled`
+#[derive(Copy, Clone)]
+pub struct GroupedSample<F, GetKey> {
+    inner: F,
+    get_key: GetKey,
+    rate: f64,
+    seed: u64,
+    /// Keys whose hash is below this are sampled
+    threshold: u64,
+}
+
+impl<F, GetKey> GroupedSample<F, GetKey> {
+    pub(crate) fn new(inner: F, get_key: GetKey, rate: f64, seed: u64) -> Self {
+        assert!(
+            rate > 0.0 && rate <= 1.0,
+            "group_by_sampled: rate must be in (0, 1]"
+        );
+        GroupedSample {
+            inner,
+            get_key,
+            rate,
+            seed,
+            threshold: (rate * u64::MAX as f64) as u64,
+        }
+    }
+
+    fn is_sampled<Key: Hash>(&self, key: &Key) -> bool {
+        self.rate >= 1.0 || hash64(&(self.seed, key)) < self.threshold
+    }
+}
+
+/// Output of `GroupedSample`, the groups of the sampled keys
+#[derive(Clone, Debug)]
+pub struct SampledGroups<Key, B> {
+    groups: FxHashMap<Key, B>,
+    rate: f64,
+}
+
+impl<Key, B> SampledGroups<Key, B> {
+    /// The groups of the sampled keys, each exact
+    pub fn groups(&self) -> &FxHashMap<Key, B> {
+        &self.groups
+    }
+
+    pub fn into_groups(self) -> FxHashMap<Key, B> {
+        self.groups
+    }
+
+    /// Fraction of keys sampled
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Estimated number of distinct keys in the input
+    pub fn estimate_keys(&self) -> f64 {
+        self.groups.len() as f64 / self.rate
+    }
+
+    /// Estimated sum over all keys of a count-like `value` of their
+    /// outputs, such as the number of values in each group
+    pub fn estimate_total(&self, value: impl Fn(&B) -> f64) -> f64 {
+        self.groups.values().map(value).sum::<f64>() / self.rate
+    }
+}
+
+impl<F, Key, GetKey> Fold1 for GroupedSample<F, GetKey>
+where
+    F: Fold1,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    type A = F::A;
+    type B = SampledGroups<Key, F::B>;
+    type M = FxHashMap<Key, F::M>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = FxHashMap::default();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let key = (self.get_key)(&x);
+        if !self.is_sampled(&key) {
+            return;
+        }
+        match acc.entry(key) {
+            Entry::Occupied(mut e) => self.inner.step(x, e.get_mut()),
+            Entry::Vacant(e) => {
+                e.insert(self.inner.init(x));
+            }
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        SampledGroups {
+            groups: acc
+                .into_iter()
+                .map(|(k, m)| (k, self.inner.output(m)))
+                .collect(),
+            rate: self.rate,
+        }
+    }
+}
+
+impl<F, Key, GetKey> Fold for GroupedSample<F, GetKey>
+where
+    F: Fold,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn empty(&self) -> Self::M {
+        FxHashMap::default()
+    }
+}
+
+impl<F, Key, GetKey> FoldPar for GroupedSample<F, GetKey>
+where
+    F: FoldPar,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (k, m) in m2 {
+            match m1.entry(k) {
+                Entry::Occupied(mut e) => self.inner.merge(e.get_mut(), m),
+                Entry::Vacant(e) => {
+                    e.insert(m);
+                }
+            }
+        }
+    }
+}
+
+/// Heap memory owned by the keys is not counted
+impl<F, Key, GetKey> StateSize for GroupedSample<F, GetKey>
+where
+    F: StateSize,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>()
+            + m.capacity() * size_of::<(Key, F::M)>()
+            + m.values()
+                .map(|s| heap_bytes(&self.inner, s))
+                .sum::<usize>()
+    }
+}
+
+/// Which inputs `WithExamples` keeps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Examples {
+    /// The first inputs seen
+    First,
+    /// The last inputs seen
+    Last,
+    /// A uniform sample of the inputs
+    #[cfg(feature = "stats-rand")]
+    Sampled,
+}
+
+/// Fold which also keeps some of its inputs as examples, see
+/// `GroupedFold::with_examples`
+#[derive(Clone)]
+pub struct WithExamples<F> {
+    inner: F,
+    n: usize,
+    keep: Examples,
+    /// Seeds the generator of each new state when sampling, so that
+    /// the system's entropy is read once rather than once per group
+    #[cfg(feature = "stats-rand")]
+    seeds: Option<Arc<Mutex<SmallRng>>>,
+}
+
+impl<F> WithExamples<F> {
+    pub fn new(inner: F, n: usize, keep: Examples) -> Self {
+        WithExamples {
+            inner,
+            n,
+            keep,
+            #[cfg(feature = "stats-rand")]
+            seeds: (keep == Examples::Sampled)
+                .then(|| Arc::new(Mutex::new(SmallRng::from_entropy()))),
+        }
+    }
+
+    fn no_examples<A>(&self) -> ExampleState<A> {
+        match self.keep {
+            Examples::First => ExampleState::First(Vec::new()),
+            Examples::Last => ExampleState::Last(VecDeque::new()),
+            #[cfg(feature = "stats-rand")]
+            Examples::Sampled => {
+                let mut seeds = self.seeds.as_ref().unwrap().lock().unwrap();
+                ExampleState::Sampled(SmallRng::from_rng(&mut *seeds).unwrap(), Vec::new())
+            }
+        }
+    }
+}
+
+/// Examples kept by `WithExamples`
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum ExampleState<A> {
+    First(Vec<A>),
+    Last(VecDeque<A>),
+    /// Inputs with the random priorities they were given, keeping
+    /// those with the smallest, which makes samples mergeable
+    #[cfg(feature = "stats-rand")]
+    Sampled(SmallRng, Vec<(u64, A)>),
+}
+
+impl<A> ExampleState<A> {
+    /// Keep the input `x` if there is room for it, only then calling
+    /// `x` to clone it
+    fn keep(&mut self, n: usize, x: impl FnOnce() -> A) {
+        match self {
+            ExampleState::First(xs) => {
+                if xs.len() < n {
+                    xs.push(x())
+                }
+            }
+            ExampleState::Last(xs) => {
+                if xs.len() == n {
+                    xs.pop_front();
+                }
+                if n > 0 {
+                    xs.push_back(x())
+                }
+            }
+            #[cfg(feature = "stats-rand")]
+            ExampleState::Sampled(rng, xs) => {
+                let priority = rng.gen();
+                Self::keep_sampled(n, xs, priority, x)
+            }
+        }
+    }
+
+    #[cfg(feature = "stats-rand")]
+    fn keep_sampled(n: usize, xs: &mut Vec<(u64, A)>, priority: u64, x: impl FnOnce() -> A) {
+        if xs.len() < n {
+            xs.push((priority, x()));
+        } else if let Some(max) = xs.iter_mut().max_by_key(|(p, _)| *p) {
+            if priority < max.0 {
+                *max = (priority, x());
+            }
+        }
+    }
+
+    /// Add examples from later inputs
+    fn append(&mut self, n: usize, other: Self) {
+        match (self, other) {
+            #[cfg(feature = "stats-rand")]
+            (ExampleState::Sampled(_, xs), ExampleState::Sampled(_, ys)) => {
+                for (priority, y) in ys {
+                    Self::keep_sampled(n, xs, priority, || y)
+                }
+            }
+            (this, other) => {
+                for y in other.into_vec() {
+                    this.keep(n, || y)
+                }
+            }
+        }
+    }
+
+    fn into_vec(self) -> Vec<A> {
+        match self {
+            ExampleState::First(xs) => xs,
+            ExampleState::Last(xs) => xs.into(),
+            #[cfg(feature = "stats-rand")]
+            ExampleState::Sampled(_, xs) => xs.into_iter().map(|(_, x)| x).collect(),
+        }
+    }
+
+    fn heap_bytes(&self) -> usize {
+        match self {
+            ExampleState::First(xs) => xs.capacity() * size_of::<A>(),
+            ExampleState::Last(xs) => xs.capacity() * size_of::<A>(),
+            #[cfg(feature = "stats-rand")]
+            ExampleState::Sampled(_, xs) => xs.capacity() * size_of::<(u64, A)>(),
+        }
+    }
+}
+
+impl<F> Fold1 for WithExamples<F>
+where
+    F: Fold1,
+    F::A: Clone,
+{
+    type A = F::A;
+    /// The inner output with the examples kept
+    type B = (F::B, Vec<F::A>);
+    type M = (F::M, ExampleState<F::A>);
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut examples = self.no_examples();
+        examples.keep(self.n, || x.clone());
+        (self.inner.init(x), examples)
+    }
+
+    fn step(&self, x: Self::A, (m, examples): &mut Self::M) {
+        examples.keep(self.n, || x.clone());
+        self.inner.step(x, m)
+    }
+
+    fn output(&self, (m, examples): Self::M) -> Self::B {
+        (self.inner.output(m), examples.into_vec())
+    }
+}
+
+impl<F> Fold for WithExamples<F>
+where
+    F: Fold,
+    F::A: Clone,
+{
+    fn empty(&self) -> Self::M {
+        (self.inner.empty(), self.no_examples())
+    }
+}
+
+/// Examples from `m2` are taken to come after those of `m1`
+impl<F> FoldPar for WithExamples<F>
+where
+    F: FoldPar,
+    F::A: Clone,
+{
+    fn merge(&self, (m1, examples1): &mut Self::M, (m2, examples2): Self::M) {
+        self.inner.merge(m1, m2);
+        examples1.append(self.n, examples2);
+    }
+}
+
+/// Heap memory owned by the examples is not counted
+impl<F> StateSize for WithExamples<F>
+where
+    F: StateSize,
+    F::A: Clone,
+{
+    fn state_bytes(&self, (m, examples): &Self::M) -> usize {
+        size_of::<Self::M>() - size_of::<F::M>() + self.inner.state_bytes(m) + examples.heap_bytes()
+    }
+}
+
+/// Borrowed forms of group keys, such as `(&str, u32)` for
+/// `(String, u32)`, which must hash as their owned key does
+pub trait ToOwnedKey: Hash {
+    type Owned: Hash + Eq;
+
+    fn to_owned_key(&self) -> Self::Owned;
+
+    fn eq_owned(&self, owned: &Self::Owned) -> bool;
+}
+
+/// Group keys with a borrowed form, see `Fold1::group_by_ref`
+pub trait OwnedKey: Hash + Eq + Sized {
+    type Ref<'a>: ToOwnedKey<Owned = Self>
+    where
+        Self: 'a;
+}
+
+impl ToOwnedKey for &str {
+    type Owned = String;
+
+    fn to_owned_key(&self) -> String {
+        (*self).to_owned()
+    }
+
+    fn eq_owned(&self, owned: &String) -> bool {
+        *self == owned
+    }
+}
+
+impl OwnedKey for String {
+    type Ref<'a> = &'a str;
+}
+
+impl<T: Clone + Hash + Eq> ToOwnedKey for &[T] {
+    type Owned = Vec<T>;
+
+    fn to_owned_key(&self) -> Vec<T> {
+        self.to_vec()
+    }
+
+    fn eq_owned(&self, owned: &Vec<T>) -> bool {
+        *self == owned.as_slice()
+    }
+}
+
+impl<T: Clone + Hash + Eq> OwnedKey for Vec<T> {
+    type Ref<'a>
+        = &'a [T]
+    where
+        T: 'a;
+}
+
+/// Keys which are their own borrowed form
+macro_rules! impl_owned_key_for_copy {
+    ($($t:ty),*) => {$(
+        impl ToOwnedKey for $t {
+            type Owned = $t;
+
+            fn to_owned_key(&self) -> $t {
+                *self
+            }
+
+            fn eq_owned(&self, owned: &$t) -> bool {
+                self == owned
+            }
+        }
+
+        impl OwnedKey for $t {
+            type Ref<'a> = $t;
+        }
+    )*};
+}
+
+impl_owned_key_for_copy!(u32, u64, usize, i32, i64, bool, char);
+
+impl<K1: ToOwnedKey, K2: ToOwnedKey> ToOwnedKey for (K1, K2) {
+    type Owned = (K1::Owned, K2::Owned);
+
+    fn to_owned_key(&self) -> Self::Owned {
+        (self.0.to_owned_key(), self.1.to_owned_key())
+    }
+
+    fn eq_owned(&self, owned: &Self::Owned) -> bool {
+        self.0.eq_owned(&owned.0) && self.1.eq_owned(&owned.1)
+    }
+}
+
+impl<K1: OwnedKey, K2: OwnedKey> OwnedKey for (K1, K2) {
+    type Ref<'a>
+        = (K1::Ref<'a>, K2::Ref<'a>)
+    where
+        Self: 'a;
+}
+
+impl<K1: ToOwnedKey, K2: ToOwnedKey, K3: ToOwnedKey> ToOwnedKey for (K1, K2, K3) {
+    type Owned = (K1::Owned, K2::Owned, K3::Owned);
+
+    fn to_owned_key(&self) -> Self::Owned {
+        (
+            self.0.to_owned_key(),
+            self.1.to_owned_key(),
+            self.2.to_owned_key(),
+        )
+    }
+
+    fn eq_owned(&self, owned: &Self::Owned) -> bool {
+        self.0.eq_owned(&owned.0) && self.1.eq_owned(&owned.1) && self.2.eq_owned(&owned.2)
+    }
+}
+
+impl<K1: OwnedKey, K2: OwnedKey, K3: OwnedKey> OwnedKey for (K1, K2, K3) {
+    type Ref<'a>
+        = (K1::Ref<'a>, K2::Ref<'a>, K3::Ref<'a>)
+    where
+        Self: 'a;
+}
+
+/// Map of a `GroupedByRef`, which can be looked up by borrowed keys
+pub type RefGroups<Key, M> = hashbrown::HashMap<Key, M, BuildHasherDefault<FxHasher>>;
+
+/// Grouped fold which looks up groups by a borrowed key, only
+/// allocating the owned key for new groups, see `Fold1::group_by_ref`
+pub struct GroupedByRef<F, GetKey, Key> {
+    inner: F,
+    get_key: GetKey,
+    ghost: PhantomData<fn() -> Key>,
+}
+
+impl<F: Clone, GetKey: Clone, Key> Clone for GroupedByRef<F, GetKey, Key> {
+    fn clone(&self) -> Self {
+        GroupedByRef {
+            inner: self.inner.clone(),
+            get_key: self.get_key.clone(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<F: Copy, GetKey: Copy, Key> Copy for GroupedByRef<F, GetKey, Key> {}
+
+impl<F, GetKey, Key> GroupedByRef<F, GetKey, Key> {
+    pub(crate) fn new(inner: F, get_key: GetKey) -> Self {
+        GroupedByRef {
+            inner,
+            get_key,
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<F, GetKey, Key> Fold1 for GroupedByRef<F, GetKey, Key>
+where
+    F: Fold1,
+    Key: OwnedKey,
+    GetKey: for<'a> Fn(&'a F::A) -> Key::Ref<'a>,
+{
+    type A = F::A;
+    type B = FxHashMap<Key, F::B>;
+    type M = RefGroups<Key, F::M>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = RefGroups::default();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        // the borrowed key must be dropped before `x` is moved
+        let found = {
+            let key = (self.get_key)(&x);
+            let hash = acc.hasher().hash_one(&key);
+            match acc.raw_entry_mut().from_hash(hash, |k| key.eq_owned(k)) {
+                RawEntryMut::Occupied(e) => Ok(e.into_mut()),
+                RawEntryMut::Vacant(_) => Err(key.to_owned_key()),
+            }
+        };
+        match found {
+            Ok(m) => self.inner.step(x, m),
+            Err(key) => {
+                acc.insert(key, self.inner.init(x));
+            }
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.into_iter()
+            .map(|(k, m)| (k, self.inner.output(m)))
+            .collect()
+    }
+}
+
+impl<F, GetKey, Key> Fold for GroupedByRef<F, GetKey, Key>
+where
+    F: Fold1,
+    Key: OwnedKey,
+    GetKey: for<'a> Fn(&'a F::A) -> Key::Ref<'a>,
+{
+    fn empty(&self) -> Self::M {
+        RefGroups::default()
+    }
+}
+
+impl<F, GetKey, Key> FoldPar for GroupedByRef<F, GetKey, Key>
+where
+    F: FoldPar,
+    Key: OwnedKey,
+    GetKey: for<'a> Fn(&'a F::A) -> Key::Ref<'a>,
+{
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (k, m) in m2 {
+            match m1.entry(k) {
+                hashbrown::hash_map::Entry::Occupied(mut e) => self.inner.merge(e.get_mut(), m),
+                hashbrown::hash_map::Entry::Vacant(e) => {
+                    e.insert(m);
+                }
+            }
+        }
+    }
+}
+
+/// Heap memory owned by the keys is not counted
+impl<F, GetKey, Key> StateSize for GroupedByRef<F, GetKey, Key>
+where
+    F: StateSize,
+    Key: OwnedKey,
+    GetKey: for<'a> Fn(&'a F::A) -> Key::Ref<'a>,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>()
+            + m.capacity() * size_of::<(Key, F::M)>()
+            + m.values()
+                .map(|m| heap_bytes(&self.inner, m))
+                .sum::<usize>()
+    }
+}
+
+impl<F: FoldDescribe, GetKey, GetTime, D> FoldDescribe for GroupedTtl<F, GetKey, GetTime, D>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("GroupedTtl", vec![self.inner.describe_structure()])
+    }
+}
+
+impl<F: FoldDescribe, GetKey> FoldDescribe for GroupedArena<F, GetKey>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("GroupedArena", vec![self.inner.describe_structure()])
+    }
+}
+
+impl<F: FoldDescribe, GetKey> FoldDescribe for GroupedSample<F, GetKey>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        let name = format!("GroupedSample of {}", self.rate);
+        Structure::new::<Self>(name, vec![self.inner.describe_structure()])
+    }
+}
+
+impl<F: FoldDescribe> FoldDescribe for WithExamples<F>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        let name = format!("WithExamples {:?} {}", self.keep, self.n);
+        Structure::new::<Self>(name, vec![self.inner.describe_structure()])
+    }
+}
+
+impl<F: FoldDescribe, ColIndex> FoldDescribe for DenseColumns<F, ColIndex>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        let name = format!("DenseColumns of {}", self.n_cols);
+        Structure::new::<Self>(name, vec![self.inner.describe_structure()])
+    }
+}
+
+impl<F: FoldDescribe, GetKey, Key> FoldDescribe for GroupedByRef<F, GetKey, Key>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("GroupedByRef", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    #[test]
+    fn ttl_groups() {
+        let events = vec![
+            ("a", 0u64),
+            ("b", 1),
+            ("a", 2),
+            ("a", 6),
+            ("b", 20),
+            ("a", 21),
+        ];
+        let fld = Count::COUNT.group_by_with_ttl(|e: &(&str, u64)| e.0, |e| e.1, 5);
+
+        // the last two groups are still open, in no particular order
+        let mut out = run_fold_iter(&fld, events.into_iter());
+        out[2..].sort();
+        assert_eq!(out, vec![("b", 1), ("a", 3), ("a", 1), ("b", 1)]);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn ttl_stream() {
+        let events = vec![("a", 0u64), ("b", 1), ("a", 6), ("b", 20)];
+        let fld = Count::COUNT.group_by_with_ttl(|e: &(&str, u64)| e.0, |e| e.1, 5);
+        let mut expected = run_fold_iter(&fld, events.clone().into_iter());
+        expected[2..].sort();
+
+        let mut streamed: Vec<_> = run_fold_stream_ttl(&fld, futures::stream::iter(events))
+            .collect()
+            .await;
+        streamed[2..].sort();
+        assert_eq!(streamed, expected);
+    }
+
+    #[test]
+    fn arena_groups() {
+        let xs = (0..1000).map(|i| (i * 7) % 13);
+        let grouped = Sum::<i64>::SUM.group_by(|x: &i64| x % 5);
+        let arena = Sum::<i64>::SUM.group_by_arena(|x: &i64| x % 5);
+        let expected = run_fold_iter(&grouped, xs.clone());
+        assert_eq!(run_fold_iter(&arena, xs.clone()), expected);
+
+        let mut acc = arena.empty();
+        let mut other = arena.empty();
+        xs.enumerate()
+            .for_each(|(i, x)| arena.step(x, if i % 3 == 0 { &mut acc } else { &mut other }));
+        arena.merge(&mut acc, other);
+        assert_eq!(arena.output(acc), expected);
+    }
+
+    #[test]
+    fn sampled_keys() {
+        // 100k keys with 3 values each
+        let xs = (0..300_000u64).map(|i| i % 100_000);
+        let fld = Count::COUNT.group_by_sampled(|x: &u64| *x, 0.05, 7);
+        let out = run_fold_iter(&fld, xs.clone());
+        assert!(out.groups().values().all(|n| *n == 3));
+        let keys = out.estimate_keys();
+        assert!((keys - 100_000.0).abs() < 5_000.0, "{keys}");
+        let total = out.estimate_total(|n| *n as f64);
+        assert!((total - 300_000.0).abs() < 15_000.0, "{total}");
+
+        // the same seed samples the same keys
+        let again = run_fold_iter(&fld, xs.rev());
+        assert_eq!(again.into_groups(), out.into_groups());
+    }
+
+    #[cfg(feature = "stats-rand")]
+    #[test]
+    fn sampled_examples() {
+        let fld = WithExamples::new(Count::COUNT, 20, Examples::Sampled);
+        let run = |xs: std::ops::Range<i64>| {
+            xs.fold(fld.empty(), |mut acc, x| {
+                fld.step(x, &mut acc);
+                acc
+            })
+        };
+        let mut acc = run(0..500);
+        fld.merge(&mut acc, run(500..1000));
+        let (n, mut xs) = fld.output(acc);
+        xs.sort();
+        xs.dedup();
+        assert_eq!((n, xs.len()), (1000, 20));
+        // both halves are all but certain to be sampled from
+        assert!(xs[0] < 500 && xs[19] >= 500);
+    }
+    #[test]
+    fn borrowed_keys() {
+        struct Sale {
+            region: String,
+            year: u32,
+            amount: u64,
+        }
+        let sales = [("north", 2023, 5), ("south", 2023, 1), ("north", 2023, 2)].map(
+            |(region, year, amount)| Sale {
+                region: region.to_owned(),
+                year,
+                amount,
+            },
+        );
+        let fld = Sum::SUM
+            .pre_map(|s: Sale| s.amount)
+            .group_by_ref::<(String, u32), _>(|s| (s.region.as_str(), s.year));
+        let out = run_fold_iter(&fld, sales.into_iter());
+        assert_eq!(out.len(), 2);
+        assert_eq!(out[&("north".to_owned(), 2023)], 7);
+        assert_eq!(out[&("south".to_owned(), 2023)], 1);
+    }
+}
@@ -0,0 +1,231 @@
+//! Runners for plain readers such as files and stdin
+use crate::fold::*;
+use crate::Error;
+
+use std::io::{BufRead, Read};
+#[cfg(feature = "rayon")]
+use std::{fs::File, io::BufReader, path::Path};
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+/// Fold over the lines of a reader, without their line endings
+pub fn run_fold_lines<F>(reader: impl BufRead, fold: &F) -> Result<F::B, Error>
+where
+    F: Fold<A = String>,
+{
+    let mut acc = fold.empty();
+    for line in reader.lines() {
+        fold.step(line?, &mut acc);
+    }
+    Ok(fold.output(acc))
+}
+
+/// Fold over the bytes of a reader in chunks of `chunk_size`.
+/// Only the final chunk may be shorter.
+pub fn run_fold_bytes<F>(mut reader: impl Read, chunk_size: usize, fold: &F) -> Result<F::B, Error>
+where
+    F: Fold<A = Vec<u8>>,
+{
+    assert!(chunk_size > 0, "chunk_size must be positive");
+    let mut acc = fold.empty();
+    loop {
+        let mut chunk = Vec::with_capacity(chunk_size);
+        (&mut reader)
+            .take(chunk_size as u64)
+            .read_to_end(&mut chunk)?;
+        if chunk.is_empty() {
+            break;
+        }
+        let full = chunk.len() == chunk_size;
+        fold.step(chunk, &mut acc);
+        if !full {
+            break;
+        }
+    }
+    Ok(fold.output(acc))
+}
+
+/// Fold over the records of a byte slice in parallel, where records
+/// are separated by `delim`. The slice is split into chunks on record
+/// boundaries, each chunk is folded on the rayon thread pool, and the
+/// results are merged. The delimiters are not passed to the fold.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(len = bytes.len())))]
+#[cfg(feature = "rayon")]
+pub fn run_fold_records_par<'a, F>(bytes: &'a [u8], delim: u8, fold: &F) -> F::B
+where
+    F: Fold<A = &'a [u8]> + FoldPar + Sync,
+    F::M: Send,
+{
+    let chunk_size = (bytes.len() / (4 * rayon::current_num_threads())).max(1 << 16);
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < bytes.len() {
+        let mut end = (start + chunk_size).min(bytes.len());
+        end += bytes[end..]
+            .iter()
+            .position(|&b| b == delim)
+            .map_or(bytes.len() - end, |i| i + 1);
+        chunks.push(&bytes[start..end]);
+        start = end;
+    }
+    #[cfg(feature = "tracing")]
+    tracing::debug!(chunks = chunks.len(), "split input");
+
+    fold.output(
+        chunks
+            .into_par_iter()
+            .map(|chunk| {
+                let mut acc = fold.empty();
+                // a trailing delimiter does not start a new record
+                let chunk = chunk.strip_suffix(&[delim]).unwrap_or(chunk);
+                chunk
+                    .split(|&b| b == delim)
+                    .for_each(|r| fold.step(r, &mut acc));
+                acc
+            })
+            .reduce(
+                || fold.empty(),
+                |mut m1, m2| {
+                    fold.merge(&mut m1, m2);
+                    m1
+                },
+            ),
+    )
+}
+
+/// Fold over the lines of a byte slice in parallel, without their
+/// line endings, see `run_fold_records_par`
+#[cfg(feature = "rayon")]
+pub fn run_fold_lines_par<'a, F>(bytes: &'a [u8], fold: &F) -> F::B
+where
+    F: Fold<A = &'a [u8]> + FoldPar + Sync,
+    F::M: Send,
+{
+    let fold = StripCr { inner: fold };
+    run_fold_records_par(bytes, b'\n', &fold)
+}
+
+/// Fold over the lines of several files in parallel, one file per
+/// task on the rayon thread pool. Returns the output for each file,
+/// in the order of `paths`, along with the output for all of them,
+/// which makes it easy to spot a file skewing the total.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(files = paths.len())))]
+#[cfg(feature = "rayon")]
+pub fn run_fold_files<P, F>(paths: &[P], fold: &F) -> Result<(Vec<F::B>, F::B), Error>
+where
+    P: AsRef<Path> + Sync,
+    F: Fold<A = String> + FoldPar + Sync,
+    F::M: Clone + Send,
+{
+    let states = paths
+        .par_iter()
+        .map(|path| {
+            let mut acc = fold.empty();
+            for line in BufReader::new(File::open(path)?).lines() {
+                fold.step(line?, &mut acc);
+            }
+            Ok(acc)
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut total = fold.empty();
+    let outputs = states
+        .into_iter()
+        .map(|m| {
+            fold.merge(&mut total, m.clone());
+            fold.output(m)
+        })
+        .collect();
+    Ok((outputs, fold.output(total)))
+}
+
+#[cfg(feature = "rayon")]
+/// Strip the `\r` of `\r\n` line endings
+struct StripCr<'f, F> {
+    inner: &'f F,
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, F: Fold1<A = &'a [u8]>> Fold1 for StripCr<'_, F> {
+    type A = &'a [u8];
+    type B = F::B;
+    type M = F::M;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        self.inner.init(x.strip_suffix(b"\r").unwrap_or(x))
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        self.inner.step(x.strip_suffix(b"\r").unwrap_or(x), acc)
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, F: Fold<A = &'a [u8]>> Fold for StripCr<'_, F> {
+    fn empty(&self) -> Self::M {
+        self.inner.empty()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, F: FoldPar<A = &'a [u8]>> FoldPar for StripCr<'_, F> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(m1, m2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    #[test]
+    fn lines_and_bytes() {
+        let text = "one\ntwo\r\nthree\n";
+        let longest = Max::MAX.or_default().pre_map(|l: String| l.len());
+        assert_eq!(run_fold_lines(text.as_bytes(), &longest).unwrap(), 5);
+
+        let sizes = Count::COUNT
+            .par(Sum::<usize>::SUM)
+            .pre_map(|c: Vec<u8>| c.len());
+        assert_eq!(run_fold_bytes(text.as_bytes(), 4, &sizes).unwrap(), (4, 15));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_lines() {
+        let text: String = (0..100_000).map(|i| format!("{}\r\n", i)).collect();
+        let fld = Count::COUNT.par(Sum::<usize>::SUM.pre_map(|l: &[u8]| l.len()));
+        let (n, len) = run_fold_lines_par(text.as_bytes(), &fld);
+        assert_eq!(n, 100_000);
+        assert_eq!(len, text.len() - 2 * n);
+
+        let fld = Count::COUNT;
+        assert_eq!(run_fold_records_par(b"a,b,,c", b',', &fld), 4);
+        assert_eq!(run_fold_records_par(b"", b',', &fld), 0);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn files() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let paths = [dir.join("a.txt"), dir.join("b.txt")];
+        std::fs::write(&paths[0], "1\n2\n3\n").unwrap();
+        std::fs::write(&paths[1], "100\n").unwrap();
+
+        let fld = Sum::<i64>::SUM.pre_map(|l: String| l.parse::<i64>().unwrap());
+        let (each, total) = run_fold_files(&paths, &fld).unwrap();
+        assert_eq!(each, vec![6, 100]);
+        assert_eq!(total, 106);
+
+        let missing = [dir.join("missing.txt")];
+        assert!(run_fold_files(&missing, &fld).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+//! Folding over newline delimited json, either deserialized into
+//! user types or as `serde_json::Value`s
+use crate::fold::*;
+
+use std::io::BufRead;
+
+use serde::de::DeserializeOwned;
+use serde_json::{Error, Value};
+#[cfg(feature = "async")]
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Fold over the lines of a json lines file, skipping blank lines.
+/// Stops at the first line which fails to deserialize.
+pub fn run_fold_json_lines<R, F>(reader: impl BufRead, fold: &F) -> Result<F::B, Error>
+where
+    R: DeserializeOwned,
+    F: Fold<A = R>,
+{
+    let mut acc = fold.empty();
+    for line in reader.lines() {
+        let line = line.map_err(Error::io)?;
+        if !line.trim().is_empty() {
+            fold.step(serde_json::from_str(&line)?, &mut acc);
+        }
+    }
+    Ok(fold.output(acc))
+}
+
+/// Async version of `run_fold_json_lines`
+#[cfg(feature = "async")]
+pub async fn run_fold_json_lines_async<R, F>(
+    reader: impl AsyncBufRead + Unpin,
+    fold: &F,
+) -> Result<F::B, Error>
+where
+    R: DeserializeOwned,
+    F: Fold<A = R>,
+{
+    let mut acc = fold.empty();
+    let mut lines = reader.lines();
+    while let Some(line) = lines.next_line().await.map_err(Error::io)? {
+        if !line.trim().is_empty() {
+            fold.step(serde_json::from_str(&line)?, &mut acc);
+        }
+    }
+    Ok(fold.output(acc))
+}
+
+/// Extract the value at a json pointer such as `/trip/fare`,
+/// for use with `pre_map`
+pub fn pointer(ptr: &str) -> impl Fn(Value) -> Option<Value> + Clone {
+    let ptr = ptr.to_string();
+    move |mut v| v.pointer_mut(&ptr).map(Value::take)
+}
+
+/// Extract a number at a json pointer, see `pointer`
+pub fn pointer_f64(ptr: &str) -> impl Fn(Value) -> Option<f64> + Clone {
+    let ptr = ptr.to_string();
+    move |v| v.pointer(&ptr)?.as_f64()
+}
+
+/// Extract an integer at a json pointer, see `pointer`
+pub fn pointer_i64(ptr: &str) -> impl Fn(Value) -> Option<i64> + Clone {
+    let ptr = ptr.to_string();
+    move |v| v.pointer(&ptr)?.as_i64()
+}
+
+/// Extract a string at a json pointer, see `pointer`
+pub fn pointer_str(ptr: &str) -> impl Fn(Value) -> Option<String> + Clone {
+    let ptr = ptr.to_string();
+    move |mut v| match v.pointer_mut(&ptr)?.take() {
+        Value::String(s) => Some(s),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    const DATA: &str = r#"{"trip": {"fare": 10.5}, "city": "nyc"}
+
+{"trip": {"fare": 2}, "city": "sf"}
+{"trip": {}, "city": "nyc"}
+"#;
+
+    #[test]
+    fn pointers() {
+        let fares = Sum::<f64>::SUM
+            .pre_map(|x: Option<f64>| x.unwrap_or(0.0))
+            .pre_map(pointer_f64("/trip/fare"));
+        assert_eq!(run_fold_json_lines(DATA.as_bytes(), &fares).unwrap(), 12.5);
+
+        let cities = Count::COUNT
+            .group_by(|c: &Option<String>| c.clone())
+            .pre_map(pointer_str("/city"));
+        let out = run_fold_json_lines(DATA.as_bytes(), &cities).unwrap();
+        assert_eq!(out[&Some("nyc".to_string())], 2);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_lines() {
+        let fld = Count::<(i64, i64)>::COUNT;
+        let data = "[1, 2]\n[3, 4]\n";
+        let n = run_fold_json_lines_async(data.as_bytes(), &fld)
+            .await
+            .unwrap();
+        assert_eq!(n, 2);
+        assert!(run_fold_json_lines_async("[1, 2]\n{".as_bytes(), &fld)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,270 @@
+//! Folding over a kafka consumer, with checkpoints of the fold state
+//! and the offsets it covers so that a worker can resume after a restart
+use crate::fold::*;
+
+use ::rdkafka::consumer::{Consumer, StreamConsumer};
+use ::rdkafka::error::KafkaResult;
+use ::rdkafka::message::{Message, OwnedMessage};
+use ::rdkafka::{Offset, TopicPartitionList};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Fold state together with the next offset to read in each
+/// partition it has seen, as `(topic, partition, offset)`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot<M> {
+    pub state: M,
+    pub offsets: Vec<(String, i32, i64)>,
+}
+
+impl<M> Snapshot<M> {
+    /// The partitions of this snapshot, positioned to continue after it
+    pub fn assignment(&self) -> KafkaResult<TopicPartitionList> {
+        self.assignment_with(std::iter::empty())
+    }
+
+    /// The partitions of this snapshot positioned to continue after it,
+    /// and the other `partitions`, as `(topic, partition)`, from their
+    /// beginning, such as those added to a topic since the snapshot
+    pub fn assignment_with<'a>(
+        &self,
+        partitions: impl IntoIterator<Item = (&'a str, i32)>,
+    ) -> KafkaResult<TopicPartitionList> {
+        let mut tpl = TopicPartitionList::new();
+        for (topic, partition, offset) in self.offsets.iter() {
+            tpl.add_partition_offset(topic, *partition, Offset::Offset(*offset))?;
+        }
+        for (topic, partition) in partitions {
+            if tpl.find_partition(topic, partition).is_none() {
+                tpl.add_partition_offset(topic, partition, Offset::Beginning)?;
+            }
+        }
+        Ok(tpl)
+    }
+
+    fn advance(&mut self, topic: &str, partition: i32, next: i64) {
+        match self
+            .offsets
+            .iter_mut()
+            .find(|(t, p, _)| *p == partition && t == topic)
+        {
+            Some((_, _, offset)) => *offset = (*offset).max(next),
+            None => self.offsets.push((topic.to_string(), partition, next)),
+        }
+    }
+}
+
+/// Fold over kafka messages which tracks the offsets it has consumed
+/// alongside the state of the inner fold, see `run_fold_kafka`
+#[derive(Clone, Copy)]
+pub struct Checkpointed<F> {
+    inner: F,
+}
+
+impl<F> Checkpointed<F> {
+    pub fn new(inner: F) -> Self {
+        Checkpointed { inner }
+    }
+}
+
+impl<F: Fold<A = OwnedMessage>> Fold1 for Checkpointed<F> {
+    type A = OwnedMessage;
+    type B = F::B;
+    type M = Snapshot<F::M>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        acc.advance(x.topic(), x.partition(), x.offset() + 1);
+        self.inner.step(x, &mut acc.state);
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        self.inner.output(acc.state)
+    }
+}
+
+impl<F: Fold<A = OwnedMessage>> Fold for Checkpointed<F> {
+    fn empty(&self) -> Self::M {
+        Snapshot {
+            state: self.inner.empty(),
+            offsets: Vec::new(),
+        }
+    }
+}
+
+impl<F: Fold<A = OwnedMessage> + FoldPar> FoldPar for Checkpointed<F> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        self.inner.merge(&mut m1.state, m2.state);
+        for (topic, partition, offset) in m2.offsets {
+            m1.advance(&topic, partition, offset);
+        }
+    }
+}
+
+/// Options for `run_fold_kafka`
+#[derive(Clone, Copy, Debug)]
+pub struct KafkaOptions {
+    /// Number of messages between snapshots
+    pub snapshot_every: usize,
+    /// Stop after this many messages, rather than running until the
+    /// consumer fails
+    pub stop_after: Option<usize>,
+    /// How long to wait for the partitions of the topics when resuming
+    pub metadata_timeout: Duration,
+}
+
+impl Default for KafkaOptions {
+    fn default() -> Self {
+        KafkaOptions {
+            snapshot_every: 10_000,
+            stop_after: None,
+            metadata_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Fold over the messages of a consumer, handing a snapshot of the
+/// state and offsets to `on_snapshot` every `snapshot_every` messages
+/// and when stopping. The snapshot can be persisted (it is serde-able
+/// when the state is) and passed back as `resume`, in which case the
+/// consumer is assigned every partition of the snapshot's topics, at
+/// the snapshot's offsets where it has them and from the beginning of
+/// partitions it has not seen.
+/// Returns the final snapshot; its output is `fold.output(snapshot.state)`.
+///
+/// Panics if `options.snapshot_every` is 0.
+pub async fn run_fold_kafka<F, S>(
+    consumer: &StreamConsumer,
+    fold: &F,
+    resume: Option<Snapshot<F::M>>,
+    options: KafkaOptions,
+    mut on_snapshot: S,
+) -> KafkaResult<Snapshot<F::M>>
+where
+    F: Fold<A = OwnedMessage> + Clone,
+    S: FnMut(&Snapshot<F::M>),
+{
+    assert!(
+        options.snapshot_every > 0,
+        "run_fold_kafka: snapshot_every must be positive"
+    );
+    let fold = Checkpointed::new(fold.clone());
+    let mut acc = match resume {
+        Some(snapshot) => {
+            let mut partitions = Vec::new();
+            let mut topics: Vec<&str> = snapshot.offsets.iter().map(|(t, _, _)| &**t).collect();
+            topics.sort_unstable();
+            topics.dedup();
+            for topic in topics {
+                let metadata = consumer.fetch_metadata(Some(topic), options.metadata_timeout)?;
+                for t in metadata.topics() {
+                    partitions.extend(t.partitions().iter().map(|p| (t.name().to_owned(), p.id())));
+                }
+            }
+            let partitions = partitions.iter().map(|(t, p)| (t.as_str(), *p));
+            consumer.assign(&snapshot.assignment_with(partitions)?)?;
+            snapshot
+        }
+        None => fold.empty(),
+    };
+    let mut stream = consumer.stream();
+    let mut n = 0;
+    while options.stop_after != Some(n) {
+        let Some(msg) = stream.next().await else {
+            break;
+        };
+        fold.step(msg?.detach(), &mut acc);
+        n += 1;
+        if n % options.snapshot_every == 0 {
+            on_snapshot(&acc);
+        }
+    }
+    on_snapshot(&acc);
+    Ok(acc)
+}
+
+impl<F: Fold<A = OwnedMessage> + FoldDescribe> FoldDescribe for Checkpointed<F> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("Checkpointed", vec![self.inner.describe_structure()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    use ::rdkafka::Timestamp;
+
+    fn msg(partition: i32, offset: i64, payload: &str) -> OwnedMessage {
+        OwnedMessage::new(
+            Some(payload.as_bytes().to_vec()),
+            None,
+            "trips".to_string(),
+            Timestamp::NotAvailable,
+            partition,
+            offset,
+            None,
+        )
+    }
+
+    #[test]
+    fn checkpoints() {
+        let fld = Checkpointed::new(
+            Sum::<usize>::SUM.pre_map(|m: OwnedMessage| m.payload().map_or(0, |p| p.len())),
+        );
+        let xs = vec![msg(0, 5, "ab"), msg(1, 2, "c"), msg(0, 6, "def")];
+        let mut acc = fld.empty();
+        for x in xs {
+            fld.step(x, &mut acc);
+        }
+        assert_eq!(
+            acc.offsets,
+            vec![("trips".into(), 0, 7), ("trips".into(), 1, 3)]
+        );
+
+        let saved = serde_json::to_string(&acc).unwrap();
+        let mut resumed: Snapshot<usize> = serde_json::from_str(&saved).unwrap();
+        let tpl = resumed.assignment().unwrap();
+        assert_eq!(
+            tpl.find_partition("trips", 0).unwrap().offset(),
+            Offset::Offset(7)
+        );
+        assert_eq!(
+            tpl.find_partition("trips", 1).unwrap().offset(),
+            Offset::Offset(3)
+        );
+        // a partition added since the snapshot is read from the start
+        let tpl = resumed
+            .assignment_with([("trips", 1), ("trips", 2)])
+            .unwrap();
+        assert_eq!(tpl.count(), 3);
+        assert_eq!(
+            tpl.find_partition("trips", 1).unwrap().offset(),
+            Offset::Offset(3)
+        );
+        assert_eq!(
+            tpl.find_partition("trips", 2).unwrap().offset(),
+            Offset::Beginning
+        );
+
+        // the consumer continues from the snapshot's offsets
+        fld.step(msg(1, 3, "gh"), &mut resumed);
+        assert_eq!(resumed.offsets[1], ("trips".into(), 1, 4));
+        assert_eq!(fld.output(resumed.clone()), 8);
+
+        // a snapshot of another partition merges in alongside
+        let mut other = fld.empty();
+        fld.step(msg(2, 0, "ijk"), &mut other);
+        fld.merge(&mut resumed, other);
+        assert_eq!(resumed.state, 11);
+        assert_eq!(resumed.offsets.len(), 3);
+        assert_eq!(resumed.offsets[1].2, 4);
+    }
+}
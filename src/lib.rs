@@ -1,3 +1,67 @@
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+extern crate alloc;
+// the derive macros name items by `::folds` paths
+#[cfg(feature = "derive")]
+extern crate self as folds;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "async")]
+pub mod async_fold;
+#[cfg(feature = "std")]
+pub mod channel;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "std")]
+pub mod columns;
 pub mod common;
-pub mod stats;
+#[cfg(feature = "csv")]
+pub mod csv;
+#[cfg(feature = "datafusion")]
+pub mod datafusion;
+#[cfg(feature = "std")]
+pub mod describe;
+#[cfg(feature = "std")]
+pub mod error;
 pub mod fold;
+#[cfg(feature = "std")]
+pub mod group;
+#[cfg(feature = "std")]
+pub mod io;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mmap")]
+pub mod mmap;
+#[cfg(feature = "object_store")]
+pub mod object_store;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "polars")]
+pub mod polars;
+#[cfg(feature = "std")]
+pub mod profile;
+#[cfg(feature = "regex")]
+pub mod regex;
+#[cfg(feature = "std")]
+pub mod sketch;
+#[cfg(feature = "async")]
+pub mod spawn;
+#[cfg(feature = "sqlx")]
+pub mod sqlx;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod time;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "std")]
+pub use error::Error;
+#[cfg(feature = "derive")]
+pub use folds_derive::FoldCompose;
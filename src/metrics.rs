@@ -0,0 +1,222 @@
+//! Exporting the outputs of a running fold to the `metrics` crate, so
+//! that a stream aggregation can expose live summaries through
+//! Prometheus or any other installed recorder
+use crate::fold::*;
+use crate::sketch::{CardinalityEstimate, Histogram, QuantileDigest};
+use crate::stats::Moments;
+use crate::time::TimeSummary;
+
+use std::future;
+use std::time::Duration;
+
+use ::metrics::gauge;
+use futures::{Stream, StreamExt};
+
+/// Quantiles exported for distributions, as Prometheus summaries do
+const QUANTILES: [(f64, &str); 5] = [
+    (0.5, "0.5"),
+    (0.9, "0.9"),
+    (0.95, "0.95"),
+    (0.99, "0.99"),
+    (0.999, "0.999"),
+];
+
+/// Fold outputs which can be exported as gauges named from `name`.
+/// Distributions are exported as a gauge `name` for each quantile,
+/// labelled with `quantile`, along with gauges for their count and
+/// other statistics suffixed to `name`.
+pub trait Export {
+    fn export(&self, name: &str);
+}
+
+impl Export for f64 {
+    fn export(&self, name: &str) {
+        gauge!(name.to_owned()).set(*self);
+    }
+}
+
+impl Export for i64 {
+    fn export(&self, name: &str) {
+        gauge!(name.to_owned()).set(*self as f64);
+    }
+}
+
+impl Export for u64 {
+    fn export(&self, name: &str) {
+        gauge!(name.to_owned()).set(*self as f64);
+    }
+}
+
+impl Export for usize {
+    fn export(&self, name: &str) {
+        gauge!(name.to_owned()).set(*self as f64);
+    }
+}
+
+/// Nothing is exported for `None`, leaving the gauges as they were
+impl<T: Export> Export for Option<T> {
+    fn export(&self, name: &str) {
+        if let Some(x) = self {
+            x.export(name)
+        }
+    }
+}
+
+impl Export for Duration {
+    /// In seconds, as Prometheus expects
+    fn export(&self, name: &str) {
+        gauge!(name.to_owned()).set(self.as_secs_f64());
+    }
+}
+
+impl Export for Moments {
+    fn export(&self, name: &str) {
+        gauge!(format!("{name}_count")).set(self.count() as f64);
+        gauge!(format!("{name}_mean")).set(self.mean());
+        gauge!(format!("{name}_std_dev")).set(self.std_dev());
+    }
+}
+
+impl Export for CardinalityEstimate {
+    fn export(&self, name: &str) {
+        gauge!(name.to_owned()).set(self.estimate());
+    }
+}
+
+impl Export for QuantileDigest {
+    fn export(&self, name: &str) {
+        gauge!(format!("{name}_count")).set(self.count());
+        if self.count() == 0.0 {
+            return;
+        }
+        for (q, label) in QUANTILES {
+            gauge!(name.to_owned(), "quantile" => label).set(self.quantile(q));
+        }
+        gauge!(format!("{name}_min")).set(self.min());
+        gauge!(format!("{name}_max")).set(self.max());
+    }
+}
+
+impl Export for Histogram {
+    fn export(&self, name: &str) {
+        gauge!(format!("{name}_count")).set(self.count() as f64);
+        for (q, label) in QUANTILES {
+            if let Some(x) = self.value_at_quantile(q) {
+                gauge!(name.to_owned(), "quantile" => label).set(x as f64);
+            }
+        }
+        self.mean().export(&format!("{name}_mean"));
+        self.max().export(&format!("{name}_max"));
+    }
+}
+
+/// Durations are exported in seconds
+impl Export for TimeSummary<Duration> {
+    fn export(&self, name: &str) {
+        gauge!(format!("{name}_count")).set(self.count() as f64);
+        for (q, label) in QUANTILES {
+            if let Some(x) = self.quantile(q) {
+                gauge!(name.to_owned(), "quantile" => label).set(x.as_secs_f64());
+            }
+        }
+        self.mean().export(&format!("{name}_mean"));
+        self.max().export(&format!("{name}_max"));
+    }
+}
+
+/// Run a fold over a stream of values, exporting the output so far
+/// under `name` every `period` and once more at the end of the stream,
+/// which gives the final output
+pub async fn run_fold_exported<F>(
+    fold: &F,
+    xs: impl Stream<Item = F::A>,
+    period: Duration,
+    name: &str,
+) -> F::B
+where
+    F: Fold,
+    F::M: Clone,
+    F::B: Export,
+{
+    emit_every_duration(fold, xs, period)
+        .fold(None, |_, b| {
+            b.export(name);
+            future::ready(Some(b))
+        })
+        .await
+        .expect("emit_every_duration emits at the end of the stream")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sketch::HdrHistogram;
+
+    use ::metrics::{
+        Counter, Gauge, GaugeFn, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps the last value set on each gauge, keyed by name and labels
+    #[derive(Clone, Default)]
+    struct Gauges(Arc<Mutex<BTreeMap<String, f64>>>);
+
+    struct Slot(Gauges, String);
+
+    impl GaugeFn for Slot {
+        fn increment(&self, _: f64) {}
+        fn decrement(&self, _: f64) {}
+        fn set(&self, value: f64) {
+            self.0 .0.lock().unwrap().insert(self.1.clone(), value);
+        }
+    }
+
+    impl Recorder for Gauges {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, _: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::noop()
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            let labels: Vec<_> = key
+                .labels()
+                .map(|l| format!("{}={}", l.key(), l.value()))
+                .collect();
+            let name = match labels.is_empty() {
+                true => key.name().to_owned(),
+                false => format!("{}{{{}}}", key.name(), labels.join(",")),
+            };
+            Gauge::from_arc(Arc::new(Slot(self.clone(), name)))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> ::metrics::Histogram {
+            ::metrics::Histogram::noop()
+        }
+    }
+
+    #[tokio::test]
+    async fn export_latencies() {
+        let gauges = Gauges::default();
+        let _guard = ::metrics::set_default_local_recorder(&gauges);
+        let xs = futures::stream::iter(1..=1000u64);
+        let out = run_fold_exported(
+            &HdrHistogram::default(),
+            xs,
+            Duration::from_secs(60),
+            "latency_ns",
+        )
+        .await;
+        assert_eq!(out.count(), 1000);
+
+        let gauges = gauges.0.lock().unwrap();
+        assert_eq!(gauges["latency_ns_count"], 1000.0);
+        assert_eq!(gauges["latency_ns_mean"], 500.5);
+        assert_eq!(gauges["latency_ns_max"], 1000.0);
+        assert_eq!(gauges["latency_ns{quantile=0.99}"], 990.0);
+        assert_eq!(gauges.len(), 3 + QUANTILES.len());
+    }
+}
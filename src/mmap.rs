@@ -0,0 +1,37 @@
+//! Memory mapped files, for use with the parallel slice runners
+//! `io::run_fold_lines_par` and `io::run_fold_records_par`
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+pub use memmap2::Mmap;
+
+/// Memory map a file read-only.
+///
+/// # Safety
+///
+/// The file must not be modified or truncated, by this or any other
+/// process, while the map is alive, otherwise reading the map is
+/// undefined behaviour.
+pub unsafe fn map_file(path: impl AsRef<Path>) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    // Safety: upheld by the caller
+    unsafe { Mmap::map(&file) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Count;
+    use crate::io::run_fold_lines_par;
+
+    #[test]
+    fn count_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lines.txt");
+        std::fs::write(&path, "a\nb\nc").unwrap();
+        // Safety: the file is only written by this test
+        let map = unsafe { map_file(&path) }.unwrap();
+        assert_eq!(run_fold_lines_par(&map, &Count::COUNT), 3);
+    }
+}
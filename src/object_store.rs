@@ -0,0 +1,161 @@
+//! Aggregating every csv and parquet object under a prefix of an
+//! object store (S3, GCS, local files, ...)
+use crate::fold::*;
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use ::arrow::csv::reader::{Format, ReaderBuilder};
+use ::arrow::record_batch::RecordBatch;
+use ::object_store::path::Path;
+use ::object_store::{ObjectMeta, ObjectStore};
+use ::parquet::arrow::async_reader::{ParquetObjectReader, ParquetRecordBatchStreamBuilder};
+use ::parquet::errors::ParquetError;
+use futures::{StreamExt, TryStreamExt};
+
+/// Options for `aggregate_prefix`
+#[derive(Clone, Copy, Debug)]
+pub struct StoreOptions {
+    /// Number of objects read concurrently
+    pub concurrency: usize,
+    /// Number of rows per record batch
+    pub batch_size: usize,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        StoreOptions {
+            concurrency: 4,
+            batch_size: 1024,
+        }
+    }
+}
+
+fn external(e: ::object_store::Error) -> ParquetError {
+    ParquetError::External(Box::new(e))
+}
+
+/// Fold over the record batches of every object under `prefix`
+/// ending in `.csv` or `.parquet`, reading up to `concurrency` objects
+/// at once and merging the state of each. Other objects are skipped.
+/// Parquet objects are streamed, while csv objects are fetched whole
+/// and must have a header row; their schema is inferred.
+pub async fn aggregate_prefix<F>(
+    store: Arc<dyn ObjectStore>,
+    prefix: &Path,
+    fold: &F,
+    options: StoreOptions,
+) -> Result<F::B, ParquetError>
+where
+    F: Fold<A = RecordBatch> + FoldPar,
+{
+    let metas: Vec<ObjectMeta> = store
+        .list(Some(prefix))
+        .try_collect()
+        .await
+        .map_err(external)?;
+
+    let metas = metas
+        .into_iter()
+        .filter(|meta| matches!(meta.location.extension(), Some("csv" | "parquet")));
+    let mut states = futures::stream::iter(metas)
+        .map(|meta| fold_object(store.clone(), meta, fold, options))
+        .buffer_unordered(options.concurrency);
+
+    let mut acc = fold.empty();
+    while let Some(m) = states.next().await {
+        fold.merge(&mut acc, m?);
+    }
+    Ok(fold.output(acc))
+}
+
+async fn fold_object<F>(
+    store: Arc<dyn ObjectStore>,
+    meta: ObjectMeta,
+    fold: &F,
+    options: StoreOptions,
+) -> Result<F::M, ParquetError>
+where
+    F: Fold<A = RecordBatch>,
+{
+    let mut acc = fold.empty();
+    if meta.location.extension() == Some("parquet") {
+        let reader = ParquetObjectReader::new(store, meta);
+        let mut batches = ParquetRecordBatchStreamBuilder::new(reader)
+            .await?
+            .with_batch_size(options.batch_size)
+            .build()?;
+        while let Some(batch) = batches.next().await {
+            fold.step(batch?, &mut acc);
+        }
+    } else {
+        let bytes = store
+            .get(&meta.location)
+            .await
+            .map_err(external)?
+            .bytes()
+            .await
+            .map_err(external)?;
+        let format = Format::default().with_header(true);
+        let (schema, _) = format.infer_schema(Cursor::new(&bytes), None)?;
+        let batches = ReaderBuilder::new(Arc::new(schema))
+            .with_format(format)
+            .with_batch_size(options.batch_size)
+            .build(Cursor::new(bytes))?;
+        for batch in batches {
+            fold.step(batch?, &mut acc);
+        }
+    }
+    Ok(acc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Sum;
+    use ::arrow::array::{ArrayRef, AsArray, Float64Array};
+    use ::arrow::compute::cast;
+    use ::arrow::datatypes::{DataType, Float64Type};
+    use ::object_store::memory::InMemory;
+    use ::parquet::arrow::ArrowWriter;
+
+    #[tokio::test]
+    async fn csv_and_parquet_objects() {
+        let store = Arc::new(InMemory::new());
+
+        let batch = RecordBatch::try_from_iter([(
+            "x",
+            Arc::new(Float64Array::from_iter_values((0..100).map(|i| i as f64))) as ArrayRef,
+        )])
+        .unwrap();
+        let mut buf = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buf, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let put = |path: &str, bytes: Vec<u8>| {
+            let store = store.clone();
+            let path = Path::from(path);
+            async move { store.put(&path, bytes.into()).await.unwrap() }
+        };
+        put("data/a.parquet", buf).await;
+        put("data/b.csv", b"x\n1\n2\n3.5\n".to_vec()).await;
+        put("data/c.csv", b"x\n10\n".to_vec()).await;
+        put("data/notes.txt", b"x\n1000\n".to_vec()).await;
+        put("other/d.csv", b"x\n1000\n".to_vec()).await;
+
+        // csv integer columns are inferred as Int64
+        let fld = Sum::<f64>::SUM.batched().pre_map(|batch: RecordBatch| {
+            let col = cast(batch.column(0), &DataType::Float64).unwrap();
+            col.as_primitive::<Float64Type>().values().to_vec()
+        });
+        let options = StoreOptions {
+            concurrency: 2,
+            batch_size: 16,
+        };
+        let total = aggregate_prefix(store, &Path::from("data"), &fld, options)
+            .await
+            .unwrap();
+        assert_eq!(total, 4950.0 + 16.5);
+    }
+}
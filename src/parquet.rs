@@ -0,0 +1,132 @@
+//! Aggregating parquet files with the parallel stream runner
+use crate::fold::*;
+
+use std::path::Path;
+
+use ::arrow::record_batch::RecordBatch;
+use ::parquet::arrow::async_reader::ParquetRecordBatchStreamBuilder;
+use ::parquet::arrow::ProjectionMask;
+use ::parquet::errors::ParquetError;
+
+/// Options for `aggregate_file`
+#[derive(Clone, Copy, Debug)]
+pub struct ParOptions {
+    /// Number of batches folded concurrently
+    pub threads: usize,
+    /// Number of rows per record batch
+    pub batch_size: usize,
+}
+
+impl Default for ParOptions {
+    fn default() -> Self {
+        ParOptions {
+            threads: 4,
+            batch_size: 1024,
+        }
+    }
+}
+
+/// Fold over the record batches of a parquet file in parallel.
+/// Only the columns named in `projection` are read, and they appear
+/// in each batch in the order they are stored in the file.
+/// Fails at the first batch which cannot be read.
+pub async fn aggregate_file<F>(
+    path: impl AsRef<Path>,
+    projection: &[&str],
+    fold: &F,
+    options: ParOptions,
+) -> Result<F::B, ParquetError>
+where
+    F: Fold<A = RecordBatch> + FoldPar + Send + Sync + Clone + 'static,
+    F::M: Send + Sync,
+{
+    let (out, _) =
+        aggregate_file_with_errors(path, projection, fold, options, ErrorPolicy::FailFast).await?;
+    Ok(out)
+}
+
+/// Fold over the record batches of a parquet file in parallel, like
+/// `aggregate_file`, handling batches which cannot be read by `policy`.
+/// The output comes with a summary of the batches which were skipped.
+pub async fn aggregate_file_with_errors<F>(
+    path: impl AsRef<Path>,
+    projection: &[&str],
+    fold: &F,
+    options: ParOptions,
+    policy: ErrorPolicy,
+) -> Result<(F::B, ErrorSummary<ParquetError>), ParquetError>
+where
+    F: Fold<A = RecordBatch> + FoldPar + Send + Sync + Clone + 'static,
+    F::M: Send + Sync,
+{
+    let file = tokio::fs::File::open(path).await?;
+    let builder = ParquetRecordBatchStreamBuilder::new(file)
+        .await?
+        .with_batch_size(options.batch_size);
+
+    let schema = builder.metadata().file_metadata().schema_descr_ptr();
+    let fields = schema.root_schema().get_fields();
+    let indices = projection
+        .iter()
+        .map(|name| {
+            fields
+                .iter()
+                .position(|field| field.name() == *name)
+                .ok_or_else(|| ParquetError::General(format!("no column named {}", name)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mask = ProjectionMask::roots(&schema, indices);
+
+    let stream = builder.with_projection(mask).build()?;
+
+    run_fold_par_try_stream(fold, options.threads, policy, stream)
+        .await
+        .map_err(|e| ParquetError::External(Box::new(e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Sum;
+    use ::arrow::array::{ArrayRef, AsArray, Float64Array, Int64Array};
+    use ::arrow::datatypes::Float64Type;
+    use ::parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn aggregate_projected_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("aggregate.parquet");
+        let batch = RecordBatch::try_from_iter([
+            (
+                "id",
+                Arc::new(Int64Array::from_iter_values(0..100)) as ArrayRef,
+            ),
+            (
+                "x",
+                Arc::new(Float64Array::from_iter_values((0..100).map(|i| i as f64))),
+            ),
+        ])
+        .unwrap();
+        let file = std::fs::File::create(&path).unwrap();
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let fld = Sum::<f64>::SUM.batched().pre_map(|batch: RecordBatch| {
+            batch
+                .column(0)
+                .as_primitive::<Float64Type>()
+                .values()
+                .to_vec()
+        });
+        let options = ParOptions {
+            threads: 2,
+            batch_size: 16,
+        };
+        let total = aggregate_file(&path, &["x"], &fld, options).await.unwrap();
+        assert_eq!(total, 4950.0);
+
+        assert!(aggregate_file(&path, &["y"], &fld, options).await.is_err());
+    }
+}
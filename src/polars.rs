@@ -0,0 +1,85 @@
+//! Running folds over polars series, and as polars expressions
+use crate::fold::*;
+
+use ::polars::prelude::*;
+
+/// Fold over a numeric series, with nulls as `None`.
+/// Each chunk of the series is handed to the fold as one chunk.
+/// Fails if the series is not of type `T`.
+pub fn fold_series<T, F>(series: &Series, fold: &F) -> PolarsResult<F::B>
+where
+    T: PolarsNumericType,
+    F: Fold<A = Option<T::Native>>,
+{
+    let ca = series.unpack::<T>()?;
+    let mut acc = fold.empty();
+    for arr in ca.downcast_iter() {
+        fold.step_chunk(arr.iter().map(|x| x.copied()).collect(), &mut acc);
+    }
+    Ok(fold.output(acc))
+}
+
+/// Turn a fold into an aggregating expression, which folds over each
+/// group when used in a `group_by(...).agg(...)` context, or over the
+/// whole column otherwise. The column is cast to `T` first.
+pub fn fold_expr<T, F>(expr: Expr, fold: F, output_type: DataType) -> Expr
+where
+    T: PolarsNumericType,
+    F: Fold<A = Option<T::Native>> + Send + Sync + 'static,
+    Series: NamedFrom<[F::B; 1], [F::B]>,
+{
+    expr.apply(
+        move |c: Column| {
+            let s = c.as_materialized_series().cast(&T::get_dtype())?;
+            let b = fold_series::<T, F>(&s, &fold)?;
+            Ok(Some(Column::new(c.name().clone(), [b])))
+        },
+        GetOutput::from_type(output_type),
+    )
+    // one value per group, rather than a list
+    .first()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    #[test]
+    fn series_with_nulls() {
+        let a = Series::new("x".into(), &[Some(1.0), None, Some(2.0)]);
+        let b = Series::new("x".into(), &[Some(4.0)]);
+        let mut s = a.clone();
+        s.append(&b).unwrap();
+        assert_eq!(s.n_chunks(), 2);
+
+        let fld = Count::COUNT.par(Sum::<f64>::SUM.pre_map(|x: Option<f64>| x.unwrap_or(0.0)));
+        assert_eq!(fold_series::<Float64Type, _>(&s, &fld).unwrap(), (4, 7.0));
+        assert!(fold_series::<Int32Type, _>(&s, &Count::COUNT).is_err());
+    }
+
+    #[test]
+    fn grouped_expr() {
+        let df = df!(
+            "k" => &["a", "b", "a"],
+            "x" => &[1i64, 2, 3],
+        )
+        .unwrap();
+        let fld = Sum::<i64>::SUM.pre_map(|x: Option<i64>| x.unwrap_or(0));
+        let out = df
+            .lazy()
+            .group_by([col("k")])
+            .agg([fold_expr::<Int64Type, _>(col("x"), fld, DataType::Int64)])
+            .sort(["k"], Default::default())
+            .collect()
+            .unwrap();
+        let xs: Vec<_> = out
+            .column("x")
+            .unwrap()
+            .i64()
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(xs, vec![Some(4), Some(2)]);
+    }
+}
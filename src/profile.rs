@@ -0,0 +1,544 @@
+use crate::columns::Columns;
+use crate::fold::*;
+use crate::sketch::{CardinalityEstimate, HyperLogLog, QuantileDigest, TDigest, TopK, TopKState};
+use crate::stats::{MState, CM4};
+
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use rustc_hash::FxHashMap;
+
+/// Profile of a numeric column, see `numeric`
+#[derive(Clone, Debug)]
+pub struct NumericProfile {
+    /// Number of non-null values
+    pub count: usize,
+    pub nulls: usize,
+    /// Sum of the values, with compensation for rounding errors
+    pub sum: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Sketch of the distribution, for approximate quantiles
+    pub quantiles: QuantileDigest,
+    /// Approximate number of distinct values
+    pub distinct: CardinalityEstimate,
+}
+
+impl fmt::Display for NumericProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "   count: {}", self.count)?;
+        writeln!(f, "   nulls: {}", self.nulls)?;
+        writeln!(f, "     sum: {:.3}", self.sum)?;
+        writeln!(f, "    mean: {:.3}", self.mean)?;
+        writeln!(f, "     std: {:.3}", self.std_dev)?;
+        writeln!(f, "     min: {:.3}", self.min)?;
+        writeln!(f, "     25%: {:.3}", self.quantiles.quantile(0.25))?;
+        writeln!(f, "     50%: {:.3}", self.quantiles.quantile(0.5))?;
+        writeln!(f, "     75%: {:.3}", self.quantiles.quantile(0.75))?;
+        writeln!(f, "     max: {:.3}", self.max)?;
+        write!(f, "distinct: ~{:.0}", self.distinct.estimate())
+    }
+}
+
+/// Fold profiling a numeric column, see `numeric`
+#[derive(Clone, Copy)]
+pub struct Numeric {
+    moments: CM4<f64>,
+    quantiles: TDigest,
+    distinct: HyperLogLog<u64>,
+}
+
+/// Profile a numeric column with missing values, computing the
+/// count, null count, sum, mean, standard deviation, min, max,
+/// approximate quantiles and an approximate distinct count in one pass.
+pub fn numeric() -> Numeric {
+    Numeric {
+        moments: CM4::CM4,
+        quantiles: TDigest::default(),
+        distinct: HyperLogLog::default(),
+    }
+}
+
+#[derive(Clone)]
+pub struct NumericState {
+    count: usize,
+    nulls: usize,
+    /// Running sum and the error compensating its rounding
+    sum: (f64, f64),
+    min: f64,
+    max: f64,
+    moments: MState<f64>,
+    quantiles: QuantileDigest,
+    distinct: Vec<u8>,
+}
+
+/// Add `x` to a running sum by Neumaier's algorithm, keeping the
+/// rounding error of each addition in the second component
+fn add_compensated((sum, err): &mut (f64, f64), x: f64) {
+    let t = *sum + x;
+    *err += if sum.abs() >= x.abs() {
+        (*sum - t) + x
+    } else {
+        (x - t) + *sum
+    };
+    *sum = t;
+}
+
+impl Fold1 for Numeric {
+    type A = Option<f64>;
+    type B = NumericProfile;
+    type M = NumericState;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        match x {
+            Some(x) => {
+                acc.count += 1;
+                add_compensated(&mut acc.sum, x);
+                acc.min = acc.min.min(x);
+                acc.max = acc.max.max(x);
+                self.moments.step(x, &mut acc.moments);
+                self.quantiles.step(x, &mut acc.quantiles);
+                self.distinct.step(x.to_bits(), &mut acc.distinct);
+            }
+            None => acc.nulls += 1,
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let moments = self.moments.output(acc.moments);
+        NumericProfile {
+            count: acc.count,
+            nulls: acc.nulls,
+            sum: acc.sum.0 + acc.sum.1,
+            mean: moments.mean(),
+            std_dev: moments.std_dev(),
+            min: acc.min,
+            max: acc.max,
+            quantiles: self.quantiles.output(acc.quantiles),
+            distinct: self.distinct.output(acc.distinct),
+        }
+    }
+}
+
+impl Fold for Numeric {
+    fn empty(&self) -> Self::M {
+        NumericState {
+            count: 0,
+            nulls: 0,
+            sum: (0.0, 0.0),
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            moments: self.moments.empty(),
+            quantiles: self.quantiles.empty(),
+            distinct: self.distinct.empty(),
+        }
+    }
+}
+
+impl StateSize for Numeric {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>() - std::mem::size_of::<QuantileDigest>()
+            + self.quantiles.state_bytes(&m.quantiles)
+            + m.distinct.capacity()
+    }
+}
+
+impl FoldPar for Numeric {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.count += m2.count;
+        m1.nulls += m2.nulls;
+        add_compensated(&mut m1.sum, m2.sum.0);
+        add_compensated(&mut m1.sum, m2.sum.1);
+        m1.min = m1.min.min(m2.min);
+        m1.max = m1.max.max(m2.max);
+        self.moments.merge(&mut m1.moments, m2.moments);
+        self.quantiles.merge(&mut m1.quantiles, m2.quantiles);
+        self.distinct.merge(&mut m1.distinct, m2.distinct);
+    }
+}
+
+/// Profile of a categorical column, see `categorical`
+#[derive(Clone, Debug)]
+pub struct CategoricalProfile<A> {
+    /// Number of non-null values
+    pub count: usize,
+    pub nulls: usize,
+    /// Approximate number of distinct values
+    pub distinct: CardinalityEstimate,
+    /// Approximate most frequent values, with their counts
+    pub top: Vec<(A, usize)>,
+}
+
+impl<A: fmt::Debug> fmt::Display for CategoricalProfile<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "   count: {}", self.count)?;
+        writeln!(f, "   nulls: {}", self.nulls)?;
+        write!(f, "distinct: ~{:.0}", self.distinct.estimate())?;
+        for (x, n) in self.top.iter() {
+            write!(f, "\n    {:?}: {}", x, n)?;
+        }
+        Ok(())
+    }
+}
+
+/// Fold profiling a categorical column, see `categorical`
+#[derive(Clone, Copy)]
+pub struct Categorical<A> {
+    distinct: HyperLogLog<A>,
+    top: TopK<A>,
+}
+
+/// Profile a categorical column with missing values, computing the
+/// count, null count, an approximate distinct count and the
+/// (approximate) `k` most frequent values in one pass.
+pub fn categorical<A: Hash + Eq + Clone>(k: usize) -> Categorical<A> {
+    Categorical {
+        distinct: HyperLogLog::default(),
+        top: TopK::new(k),
+    }
+}
+
+#[derive(Clone)]
+pub struct CategoricalState<A: Hash + Eq> {
+    count: usize,
+    nulls: usize,
+    distinct: Vec<u8>,
+    top: TopKState<A>,
+}
+
+impl<A: Hash + Eq + Clone> Fold1 for Categorical<A> {
+    type A = Option<A>;
+    type B = CategoricalProfile<A>;
+    type M = CategoricalState<A>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        match x {
+            Some(x) => {
+                acc.count += 1;
+                self.distinct.step(x.clone(), &mut acc.distinct);
+                self.top.step(x, &mut acc.top);
+            }
+            None => acc.nulls += 1,
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        CategoricalProfile {
+            count: acc.count,
+            nulls: acc.nulls,
+            distinct: self.distinct.output(acc.distinct),
+            top: self.top.output(acc.top),
+        }
+    }
+}
+
+impl<A: Hash + Eq + Clone> Fold for Categorical<A> {
+    fn empty(&self) -> Self::M {
+        CategoricalState {
+            count: 0,
+            nulls: 0,
+            distinct: self.distinct.empty(),
+            top: self.top.empty(),
+        }
+    }
+}
+
+impl<A: Hash + Eq + Clone> StateSize for Categorical<A> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>() - std::mem::size_of::<FxHashMap<A, usize>>()
+            + self.top.state_bytes(&m.top)
+            + m.distinct.capacity()
+    }
+}
+
+impl<A: Hash + Eq + Clone> FoldPar for Categorical<A> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.count += m2.count;
+        m1.nulls += m2.nulls;
+        self.distinct.merge(&mut m1.distinct, m2.distinct);
+        self.top.merge(&mut m1.top, m2.top);
+    }
+}
+
+/// Type of a column of strings, the narrowest which all its values
+/// can be parsed as
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColumnType {
+    /// Every value was null
+    Null,
+    Bool,
+    Int,
+    Float,
+    /// Dates as `YYYY-MM-DD`, optionally followed by a time
+    Date,
+    String,
+}
+
+/// Profile of a column of strings, see `infer_type`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeProfile {
+    pub ty: ColumnType,
+    /// Number of non-null values
+    pub count: usize,
+    pub nulls: usize,
+    /// Length of the longest value, in characters
+    pub max_len: usize,
+}
+
+impl TypeProfile {
+    /// Fraction of the values which were null
+    pub fn null_rate(&self) -> f64 {
+        self.nulls as f64 / (self.count + self.nulls) as f64
+    }
+}
+
+/// Fold inferring the type of a column of strings, see `infer_type`
+pub struct InferType<S> {
+    ghost: PhantomData<fn(S)>,
+}
+
+impl<S> Clone for InferType<S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S> Copy for InferType<S> {}
+
+/// Infer the type of a column of strings, such as the fields of a csv
+/// file, along with its null rate and the length of its longest value.
+/// Empty strings, `null`, `NULL` and `NA` are null.
+pub fn infer_type<S: AsRef<str>>() -> InferType<S> {
+    InferType { ghost: PhantomData }
+}
+
+/// Infer the schema of records of strings with the named fields, in order
+pub fn infer_schema<S, I>(names: I) -> Columns<S, TypeProfile>
+where
+    S: AsRef<str> + 'static,
+    I: IntoIterator,
+    I::Item: Into<String>,
+{
+    names.into_iter().fold(Columns::new(), |cols, name| {
+        cols.column(name, infer_type().boxed())
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct TypeState {
+    count: usize,
+    nulls: usize,
+    max_len: usize,
+    /// Whether every value so far was a bool, int, float and date
+    bool: bool,
+    int: bool,
+    float: bool,
+    date: bool,
+}
+
+fn is_null(s: &str) -> bool {
+    matches!(s, "" | "null" | "NULL" | "NA")
+}
+
+fn is_bool(s: &str) -> bool {
+    s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("false")
+}
+
+/// Whether `s` starts with a date as `YYYY-MM-DD`, followed by nothing
+/// or by a time after a `T` or a space
+fn is_date(s: &str) -> bool {
+    let b = s.as_bytes();
+    let digits = |r: std::ops::Range<usize>| b[r].iter().all(u8::is_ascii_digit);
+    if b.len() < 10 || !digits(0..4) || b[4] != b'-' || !digits(5..7) || b[7] != b'-' {
+        return false;
+    }
+    if !digits(8..10) {
+        return false;
+    }
+    let month = (b[5] - b'0') * 10 + (b[6] - b'0');
+    let day = (b[8] - b'0') * 10 + (b[9] - b'0');
+    let time = match b.get(10) {
+        None => true,
+        Some(b'T' | b' ') => b.len() >= 16 && digits(11..13) && b[13] == b':' && digits(14..16),
+        Some(_) => false,
+    };
+    (1..=12).contains(&month) && (1..=31).contains(&day) && time
+}
+
+impl<S: AsRef<str>> Fold1 for InferType<S> {
+    type A = S;
+    type B = TypeProfile;
+    type M = TypeState;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let s = x.as_ref().trim();
+        if is_null(s) {
+            acc.nulls += 1;
+            return;
+        }
+        acc.count += 1;
+        acc.max_len = acc.max_len.max(s.chars().count());
+        acc.int = acc.int && s.parse::<i64>().is_ok();
+        acc.float = acc.float && (acc.int || s.parse::<f64>().is_ok());
+        acc.bool = acc.bool && is_bool(s);
+        acc.date = acc.date && is_date(s);
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let ty = match acc {
+            TypeState { count: 0, .. } => ColumnType::Null,
+            TypeState { bool: true, .. } => ColumnType::Bool,
+            TypeState { int: true, .. } => ColumnType::Int,
+            TypeState { float: true, .. } => ColumnType::Float,
+            TypeState { date: true, .. } => ColumnType::Date,
+            _ => ColumnType::String,
+        };
+        TypeProfile {
+            ty,
+            count: acc.count,
+            nulls: acc.nulls,
+            max_len: acc.max_len,
+        }
+    }
+}
+
+impl<S: AsRef<str>> Fold for InferType<S> {
+    fn empty(&self) -> Self::M {
+        TypeState {
+            count: 0,
+            nulls: 0,
+            max_len: 0,
+            bool: true,
+            int: true,
+            float: true,
+            date: true,
+        }
+    }
+}
+
+impl<S: AsRef<str>> StateSize for InferType<S> {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>()
+    }
+}
+
+impl<S: AsRef<str>> FoldPar for InferType<S> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.count += m2.count;
+        m1.nulls += m2.nulls;
+        m1.max_len = m1.max_len.max(m2.max_len);
+        m1.bool &= m2.bool;
+        m1.int &= m2.int;
+        m1.float &= m2.float;
+        m1.date &= m2.date;
+    }
+}
+
+impl FoldDescribe for Numeric {
+    fn describe_structure(&self) -> Structure {
+        let children = vec![
+            self.moments.describe_structure(),
+            self.quantiles.describe_structure(),
+            self.distinct.describe_structure(),
+        ];
+        Structure::new::<Self>("Numeric", children)
+    }
+}
+
+impl<A: Hash + Eq + Clone> FoldDescribe for Categorical<A> {
+    fn describe_structure(&self) -> Structure {
+        let children = vec![
+            self.distinct.describe_structure(),
+            self.top.describe_structure(),
+        ];
+        Structure::new::<Self>("Categorical", children)
+    }
+}
+
+impl<S: AsRef<str>> FoldDescribe for InferType<S> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("InferType")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_profile() {
+        let xs = (0..1000).map(|i| if i % 10 == 0 { None } else { Some(i as f64) });
+        let p = run_fold_iter(&numeric(), xs);
+        assert_eq!(p.count, 900);
+        assert_eq!(p.nulls, 100);
+        assert_eq!(p.min, 1.0);
+        assert_eq!(p.max, 999.0);
+        assert!((p.mean - 500.0).abs() < 1e-9);
+        assert_eq!(p.sum, 450_000.0);
+        assert!((p.quantiles.quantile(0.5) - 500.0).abs() < 10.0);
+        assert!((p.distinct.estimate() - 900.0).abs() < 45.0);
+    }
+
+    #[test]
+    fn categorical_profile() {
+        let xs = ["a", "b", "a", "c", "a", "b"].into_iter().map(Some);
+        let p = run_fold_iter(&categorical(2), xs.chain([None]));
+        assert_eq!(p.count, 6);
+        assert_eq!(p.nulls, 1);
+        assert_eq!(p.top, vec![("a", 3), ("b", 2)]);
+    }
+
+    #[test]
+    fn compensated_sum() {
+        // a plain running sum loses every 1.0 against 1e16
+        let xs = [1e16, 1.0, 1.0, 1.0, 1.0, -1e16].map(Some);
+        let p = run_fold_iter(&numeric(), xs.into_iter());
+        assert_eq!(p.sum, 4.0);
+    }
+
+    #[test]
+    fn infer_types() {
+        let rows = [
+            ["1", "1.5", "true", "2024-01-31", "a"],
+            ["-2", "3", "False", "2024-02-01T10:00:00", ""],
+            ["", "NA", "", "2024-12-01", "longer"],
+        ];
+        let fld = infer_schema(["id", "price", "flag", "day", "name"]);
+        let rows = rows.into_iter().map(|row| row.to_vec());
+        let schema = run_fold_iter(&fld, rows);
+        let ty = |name: &str| schema[name].ty;
+        assert_eq!(ty("id"), ColumnType::Int);
+        assert_eq!(ty("price"), ColumnType::Float);
+        assert_eq!(ty("flag"), ColumnType::Bool);
+        assert_eq!(ty("day"), ColumnType::Date);
+        assert_eq!(ty("name"), ColumnType::String);
+        assert_eq!(schema["name"].max_len, 6);
+        assert!((schema["id"].null_rate() - 1.0 / 3.0).abs() < 1e-9);
+
+        let ty = |xs: &[&str]| run_fold_iter(&infer_type(), xs.iter()).ty;
+        assert_eq!(ty(&["", "null"]), ColumnType::Null);
+        assert_eq!(ty(&["2024-13-01"]), ColumnType::String);
+        assert_eq!(ty(&["1", "x"]), ColumnType::String);
+    }
+}
@@ -0,0 +1,301 @@
+//! Folds matching a regex against lines of text, for mining logs with
+//! `run_fold_lines`. Lines are numbered from 1 by counting the lines
+//! folded, so merges of line positions need `FoldParOrdered`.
+use crate::fold::*;
+
+use std::marker::PhantomData;
+
+use ::regex::Regex;
+use rustc_hash::FxHashMap;
+
+/// Number of lines which matched, and of matches in them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchCounts {
+    pub lines: usize,
+    pub matches: usize,
+}
+
+/// Count the lines matching a regex and the matches within them,
+/// see `count_matches`
+pub struct CountMatches<S> {
+    re: Regex,
+    ghost: PhantomData<fn(S)>,
+}
+
+impl<S> Clone for CountMatches<S> {
+    fn clone(&self) -> Self {
+        CountMatches {
+            re: self.re.clone(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+/// Count the lines matching `re`, and the non-overlapping matches
+pub fn count_matches<S: AsRef<str>>(re: Regex) -> CountMatches<S> {
+    CountMatches {
+        re,
+        ghost: PhantomData,
+    }
+}
+
+impl<S: AsRef<str>> Fold1 for CountMatches<S> {
+    type A = S;
+    type B = MatchCounts;
+    type M = MatchCounts;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let n = self.re.find_iter(x.as_ref()).count();
+        acc.lines += (n > 0) as usize;
+        acc.matches += n;
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+impl<S: AsRef<str>> Fold for CountMatches<S> {
+    fn empty(&self) -> Self::M {
+        MatchCounts::default()
+    }
+}
+
+impl<S: AsRef<str>> FoldPar for CountMatches<S> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.lines += m2.lines;
+        m1.matches += m2.matches;
+    }
+}
+
+impl<S: AsRef<str>> StateSize for CountMatches<S> {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>()
+    }
+}
+
+impl<S: AsRef<str>> FoldDescribe for CountMatches<S> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>(format!("CountMatches /{}/", self.re))
+    }
+}
+
+/// Count the values of a capture group, see `capture_counts`
+pub struct CaptureCounts<S> {
+    re: Regex,
+    group: usize,
+    ghost: PhantomData<fn(S)>,
+}
+
+impl<S> Clone for CaptureCounts<S> {
+    fn clone(&self) -> Self {
+        CaptureCounts {
+            re: self.re.clone(),
+            group: self.group,
+            ghost: PhantomData,
+        }
+    }
+}
+
+/// Count the values captured by the named `group` of `re`, over every
+/// match in each line. Matches where the group did not take part are
+/// not counted.
+pub fn capture_counts<S: AsRef<str>>(re: Regex, group: &str) -> CaptureCounts<S> {
+    let group = re
+        .capture_names()
+        .position(|name| name == Some(group))
+        .unwrap_or_else(|| panic!("CaptureCounts: no group named {group} in /{re}/"));
+    CaptureCounts {
+        re,
+        group,
+        ghost: PhantomData,
+    }
+}
+
+impl<S: AsRef<str>> Fold1 for CaptureCounts<S> {
+    type A = S;
+    type B = FxHashMap<String, usize>;
+    type M = FxHashMap<String, usize>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        for caps in self.re.captures_iter(x.as_ref()) {
+            if let Some(m) = caps.get(self.group) {
+                match acc.get_mut(m.as_str()) {
+                    Some(n) => *n += 1,
+                    None => {
+                        acc.insert(m.as_str().to_owned(), 1);
+                    }
+                }
+            }
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+impl<S: AsRef<str>> Fold for CaptureCounts<S> {
+    fn empty(&self) -> Self::M {
+        FxHashMap::default()
+    }
+}
+
+impl<S: AsRef<str>> FoldPar for CaptureCounts<S> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (value, n) in m2 {
+            *m1.entry(value).or_default() += n;
+        }
+    }
+}
+
+/// Heap memory owned by the captured strings is not counted
+impl<S: AsRef<str>> StateSize for CaptureCounts<S> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>() + m.capacity() * std::mem::size_of::<(String, usize)>()
+    }
+}
+
+impl<S: AsRef<str>> FoldDescribe for CaptureCounts<S> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>(format!("CaptureCounts /{}/", self.re))
+    }
+}
+
+/// Line numbers of the first and last lines matching a regex, counting
+/// from 1, see `matching_lines`
+pub struct MatchingLines<S> {
+    re: Regex,
+    ghost: PhantomData<fn(S)>,
+}
+
+impl<S> Clone for MatchingLines<S> {
+    fn clone(&self) -> Self {
+        MatchingLines {
+            re: self.re.clone(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+/// Find the first and last lines matching `re`, as `(first, last)`
+/// line numbers counting from 1, or `None` if no line matched
+pub fn matching_lines<S: AsRef<str>>(re: Regex) -> MatchingLines<S> {
+    MatchingLines {
+        re,
+        ghost: PhantomData,
+    }
+}
+
+impl<S: AsRef<str>> Fold1 for MatchingLines<S> {
+    type A = S;
+    type B = Option<(usize, usize)>;
+    /// Number of lines folded, and the first and last which matched
+    type M = (usize, Option<(usize, usize)>);
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, (n, lines): &mut Self::M) {
+        *n += 1;
+        if self.re.is_match(x.as_ref()) {
+            let first = lines.map_or(*n, |(first, _)| first);
+            *lines = Some((first, *n));
+        }
+    }
+
+    fn output(&self, (_, lines): Self::M) -> Self::B {
+        lines
+    }
+}
+
+impl<S: AsRef<str>> Fold for MatchingLines<S> {
+    fn empty(&self) -> Self::M {
+        (0, None)
+    }
+}
+
+impl<S: AsRef<str>> FoldParOrdered for MatchingLines<S> {
+    fn merge_at(&self, (n1, lines1): &mut Self::M, (n2, lines2): Self::M, offset: usize) {
+        if let Some((first2, last2)) = lines2 {
+            let first = lines1.map_or(first2 + offset, |(first1, _)| first1);
+            *lines1 = Some((first, last2 + offset));
+        }
+        *n1 += n2;
+    }
+}
+
+impl<S: AsRef<str>> StateSize for MatchingLines<S> {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>()
+    }
+}
+
+impl<S: AsRef<str>> FoldDescribe for MatchingLines<S> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>(format!("MatchingLines /{}/", self.re))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::run_fold_lines;
+
+    const LOG: &str = "\
+INFO start
+WARN disk 91% user=alice
+ERROR timeout user=bob
+INFO retry user=bob
+ERROR timeout user=bob user=carol
+INFO done
+";
+
+    #[test]
+    fn log_mining() {
+        let errors = count_matches(Regex::new("ERROR").unwrap());
+        let users = capture_counts(Regex::new(r"user=(?<user>\w+)").unwrap(), "user");
+        let fld = errors
+            .par(users)
+            .par(matching_lines(Regex::new("user=").unwrap()));
+        let ((errors, users), lines) = run_fold_iter(&fld, LOG.lines());
+        assert_eq!(
+            errors,
+            MatchCounts {
+                lines: 2,
+                matches: 2
+            }
+        );
+        assert_eq!((users["bob"], users["alice"], users["carol"]), (3, 1, 1));
+        assert_eq!(lines, Some((2, 5)));
+
+        let users = capture_counts(Regex::new(r"user=(?<user>\w+)").unwrap(), "user");
+        let users = run_fold_lines(LOG.as_bytes(), &users).unwrap();
+        assert_eq!(users.len(), 3);
+
+        // line numbers continue across a merge
+        let fld = matching_lines(Regex::new("ERROR").unwrap());
+        let lines: Vec<_> = LOG.lines().collect();
+        let (top, bottom) = lines.split_at(3);
+        let (mut m1, mut m2) = (fld.empty(), fld.empty());
+        top.iter().for_each(|x| fld.step(x, &mut m1));
+        bottom.iter().for_each(|x| fld.step(x, &mut m2));
+        fld.merge_at(&mut m1, m2, 3);
+        assert_eq!(fld.output(m1), Some((3, 5)));
+    }
+}
@@ -0,0 +1,2299 @@
+use crate::fold::*;
+
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
+
+/// 64 bit hash with well mixed bits.
+/// FxHash alone is fast but its low bits are poorly distributed.
+pub(crate) fn hash64<A: Hash>(x: &A) -> u64 {
+    let mut hasher = FxHasher::default();
+    x.hash(&mut hasher);
+    // finalizer from murmur3
+    let mut h = hasher.finish();
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51afd7ed558ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ceb9fe1a85ec53);
+    h ^= h >> 33;
+    h
+}
+
+/// Approximate count of distinct elements using HyperLogLog.
+/// Uses `2^precision` one byte registers, with a relative
+/// standard error of roughly `1.04 / sqrt(2^precision)`.
+#[derive(Clone, Copy)]
+pub struct HyperLogLog<A> {
+    precision: u8,
+    ghost: PhantomData<A>,
+}
+
+impl<A: Hash> HyperLogLog<A> {
+    /// Precision must be between 4 and 16
+    pub fn new(precision: u8) -> Self {
+        assert!(
+            (4..=16).contains(&precision),
+            "HyperLogLog precision must be between 4 and 16"
+        );
+        HyperLogLog {
+            precision,
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<A: Hash> Default for HyperLogLog<A> {
+    fn default() -> Self {
+        Self::new(12)
+    }
+}
+
+/// Output of `HyperLogLog`, an approximate count of distinct values
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CardinalityEstimate {
+    estimate: f64,
+    precision: u8,
+}
+
+impl CardinalityEstimate {
+    /// Approximate number of distinct values
+    pub fn estimate(&self) -> f64 {
+        self.estimate
+    }
+
+    /// Relative standard error of the estimate
+    pub fn relative_error(&self) -> f64 {
+        1.04 / ((1u64 << self.precision) as f64).sqrt()
+    }
+}
+
+impl<A: Hash> Fold1 for HyperLogLog<A> {
+    type A = A;
+    type B = CardinalityEstimate;
+    type M = Vec<u8>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let h = hash64(&x);
+        let p = self.precision as u32;
+        let index = (h >> (64 - p)) as usize;
+        let rank = ((h << p).leading_zeros() + 1).min(64 - p + 1) as u8;
+        if acc[index] < rank {
+            acc[index] = rank;
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let m = acc.len() as f64;
+        let alpha = match acc.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = acc.iter().map(|&r| (-(r as f64)).exp2()).sum();
+        let estimate = alpha * m * m / sum;
+
+        let zeros = acc.iter().filter(|&&r| r == 0).count();
+        let estimate = if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting for small cardinalities
+            m * (m / zeros as f64).ln()
+        } else {
+            estimate
+        };
+        CardinalityEstimate {
+            estimate,
+            precision: self.precision,
+        }
+    }
+}
+
+impl<A: Hash> Fold for HyperLogLog<A> {
+    fn empty(&self) -> Self::M {
+        vec![0; 1 << self.precision]
+    }
+}
+
+impl<A: Hash> FoldPar for HyperLogLog<A> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (r1, r2) in m1.iter_mut().zip(m2) {
+            if *r1 < r2 {
+                *r1 = r2;
+            }
+        }
+    }
+}
+
+impl<A: Hash> StateSize for HyperLogLog<A> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() + m.capacity()
+    }
+}
+
+/// How `CountDistinctByKey` counts the distinct values of each key
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DistinctStrategy {
+    /// Keep every distinct value, for exact counts
+    Exact,
+    /// Keep a HyperLogLog of this precision for each key
+    Approximate(u8),
+    /// Keep every distinct value until a key has more than
+    /// `max_exact`, then switch that key to a HyperLogLog of
+    /// `precision`, bounding the memory used by each key
+    Bounded { max_exact: usize, precision: u8 },
+}
+
+/// Count the distinct values for each key of `(key, value)` pairs,
+/// exactly or approximately according to a `DistinctStrategy`
+pub struct CountDistinctByKey<K, V> {
+    max_exact: usize,
+    precision: u8,
+    ghost: PhantomData<(K, V)>,
+}
+
+impl<K, V> Clone for CountDistinctByKey<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for CountDistinctByKey<K, V> {}
+
+impl<K: Hash + Eq, V: Hash + Eq> CountDistinctByKey<K, V> {
+    pub fn new(strategy: DistinctStrategy) -> Self {
+        let (max_exact, precision) = match strategy {
+            DistinctStrategy::Exact => (usize::MAX, 4),
+            DistinctStrategy::Approximate(precision) => (0, precision),
+            DistinctStrategy::Bounded {
+                max_exact,
+                precision,
+            } => (max_exact, precision),
+        };
+        // checks the precision up front
+        HyperLogLog::<V>::new(precision);
+        CountDistinctByKey {
+            max_exact,
+            precision,
+            ghost: PhantomData,
+        }
+    }
+
+    fn sketch(&self) -> HyperLogLog<V> {
+        HyperLogLog {
+            precision: self.precision,
+            ghost: PhantomData,
+        }
+    }
+
+    /// Switch `set` to a sketch if it has outgrown `max_exact`
+    fn bound(&self, set: &mut DistinctSet<V>) {
+        if let DistinctSet::Exact(values) = set {
+            if values.len() > self.max_exact {
+                let mut regs = self.sketch().empty();
+                for v in values.drain() {
+                    self.sketch().step(v, &mut regs);
+                }
+                *set = DistinctSet::Sketch(regs);
+            }
+        }
+    }
+
+    fn insert(&self, v: V, set: &mut DistinctSet<V>) {
+        match set {
+            DistinctSet::Exact(values) => {
+                values.insert(v);
+                self.bound(set);
+            }
+            DistinctSet::Sketch(regs) => self.sketch().step(v, regs),
+        }
+    }
+}
+
+/// Distinct values of a key seen by `CountDistinctByKey`, either
+/// kept exactly or as HyperLogLog registers
+#[derive(Clone, Debug)]
+pub enum DistinctSet<V> {
+    Exact(FxHashSet<V>),
+    Sketch(Vec<u8>),
+}
+
+/// Number of distinct values of a key, exact unless the key was
+/// counted with a HyperLogLog
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DistinctCount {
+    Exact(usize),
+    Approximate(CardinalityEstimate),
+}
+
+impl DistinctCount {
+    /// The number of distinct values, or an estimate of it
+    pub fn estimate(&self) -> f64 {
+        match self {
+            DistinctCount::Exact(n) => *n as f64,
+            DistinctCount::Approximate(est) => est.estimate(),
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        matches!(self, DistinctCount::Exact(_))
+    }
+}
+
+impl<K: Hash + Eq, V: Hash + Eq> Fold1 for CountDistinctByKey<K, V> {
+    type A = (K, V);
+    type B = FxHashMap<K, DistinctCount>;
+    type M = FxHashMap<K, DistinctSet<V>>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, (k, v): Self::A, acc: &mut Self::M) {
+        let set = acc.entry(k).or_insert_with(|| match self.max_exact {
+            0 => DistinctSet::Sketch(self.sketch().empty()),
+            _ => DistinctSet::Exact(FxHashSet::default()),
+        });
+        self.insert(v, set);
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc.into_iter()
+            .map(|(k, set)| {
+                let count = match set {
+                    DistinctSet::Exact(values) => DistinctCount::Exact(values.len()),
+                    DistinctSet::Sketch(regs) => {
+                        DistinctCount::Approximate(self.sketch().output(regs))
+                    }
+                };
+                (k, count)
+            })
+            .collect()
+    }
+}
+
+impl<K: Hash + Eq, V: Hash + Eq> Fold for CountDistinctByKey<K, V> {
+    fn empty(&self) -> Self::M {
+        FxHashMap::default()
+    }
+}
+
+impl<K: Hash + Eq, V: Hash + Eq> FoldPar for CountDistinctByKey<K, V> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (k, set2) in m2 {
+            let set1 = match m1.entry(k) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => {
+                    e.insert(set2);
+                    continue;
+                }
+            };
+            match set2 {
+                DistinctSet::Exact(values) => {
+                    for v in values {
+                        self.insert(v, set1);
+                    }
+                }
+                DistinctSet::Sketch(regs2) => {
+                    if let DistinctSet::Exact(values) = set1 {
+                        let mut regs = self.sketch().empty();
+                        for v in values.drain() {
+                            self.sketch().step(v, &mut regs);
+                        }
+                        *set1 = DistinctSet::Sketch(regs);
+                    }
+                    if let DistinctSet::Sketch(regs1) = set1 {
+                        self.sketch().merge(regs1, regs2);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Heap memory owned by the keys and values is not counted
+impl<K: Hash + Eq, V: Hash + Eq> StateSize for CountDistinctByKey<K, V> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>()
+            + m.capacity() * size_of::<(K, DistinctSet<V>)>()
+            + m.values()
+                .map(|set| match set {
+                    DistinctSet::Exact(values) => values.capacity() * size_of::<V>(),
+                    DistinctSet::Sketch(regs) => regs.capacity(),
+                })
+                .sum::<usize>()
+    }
+}
+
+/// How `DuplicateStats` remembers the values it has seen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DuplicateMode {
+    /// Keep every distinct value in a set
+    Exact,
+    /// Keep a Bloom filter of `bits` bits with `hashes` hash functions,
+    /// whose false positives make values look like duplicates
+    Bloom { bits: usize, hashes: u32 },
+}
+
+impl DuplicateMode {
+    /// A Bloom filter for about `expected` distinct values with a false
+    /// positive rate of `fp_rate`
+    pub fn bloom(expected: usize, fp_rate: f64) -> Self {
+        assert!(
+            0.0 < fp_rate && fp_rate < 1.0,
+            "DuplicateMode fp_rate must be in (0, 1)"
+        );
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(expected.max(1) as f64) * fp_rate.ln() / (ln2 * ln2)).ceil();
+        let hashes = (bits / expected.max(1) as f64 * ln2).round().max(1.0);
+        DuplicateMode::Bloom {
+            bits: bits as usize,
+            hashes: hashes as u32,
+        }
+    }
+}
+
+/// Count values which were seen before, for data quality checks, and
+/// keep up to `max_examples` of the duplicated values
+pub struct DuplicateStats<A> {
+    mode: DuplicateMode,
+    max_examples: usize,
+    ghost: PhantomData<A>,
+}
+
+impl<A> Clone for DuplicateStats<A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<A> Copy for DuplicateStats<A> {}
+
+impl<A: Hash + Eq> DuplicateStats<A> {
+    pub fn new(mode: DuplicateMode, max_examples: usize) -> Self {
+        if let DuplicateMode::Bloom { bits, hashes } = mode {
+            assert!(bits > 0 && hashes > 0, "Bloom filter must not be empty");
+        }
+        DuplicateStats {
+            mode,
+            max_examples,
+            ghost: PhantomData,
+        }
+    }
+
+    /// Bits of the Bloom filter set for `x`, by double hashing
+    fn bloom_bits(&self, x: &A, bits: usize, hashes: u32) -> impl Iterator<Item = usize> {
+        let h = hash64(x);
+        let (h1, h2) = (h & 0xffff_ffff, (h >> 32) | 1);
+        (0..hashes as u64)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits as u64) as usize)
+    }
+
+    fn add_example(&self, x: A, acc: &mut DuplicateState<A>) {
+        if acc.examples.len() < self.max_examples && !acc.examples.contains(&x) {
+            acc.examples.push(x)
+        }
+    }
+
+    /// Estimated number of distinct values in a Bloom filter, from the
+    /// fraction of its bits which are set
+    fn bloom_distinct(filter: &[u64], bits: usize, hashes: u32) -> f64 {
+        let set = filter.iter().map(|w| w.count_ones() as f64).sum::<f64>();
+        let m = bits as f64;
+        -m / hashes as f64 * (1.0 - set.min(m - 1.0) / m).ln()
+    }
+}
+
+/// Values seen by `DuplicateStats`
+#[derive(Clone, Debug)]
+enum Seen<A> {
+    Exact(FxHashSet<A>),
+    Bloom(Vec<u64>),
+}
+
+/// State of `DuplicateStats`
+#[derive(Clone, Debug)]
+pub struct DuplicateState<A> {
+    seen: Seen<A>,
+    count: u64,
+    duplicates: u64,
+    examples: Vec<A>,
+}
+
+/// Output of `DuplicateStats`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Duplicates<A> {
+    /// Number of values seen
+    pub count: u64,
+    /// Number of values which had been seen before, an overestimate
+    /// when counted with a Bloom filter
+    pub duplicates: u64,
+    /// Some of the duplicated values, each once
+    pub examples: Vec<A>,
+    /// Whether `duplicates` is exact
+    pub exact: bool,
+}
+
+impl<A> Duplicates<A> {
+    /// Number of distinct values
+    pub fn distinct(&self) -> u64 {
+        self.count - self.duplicates
+    }
+
+    /// Fraction of the values which were duplicates
+    pub fn duplicate_rate(&self) -> f64 {
+        self.duplicates as f64 / self.count as f64
+    }
+}
+
+impl<A: Hash + Eq> Fold1 for DuplicateStats<A> {
+    type A = A;
+    type B = Duplicates<A>;
+    type M = DuplicateState<A>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        acc.count += 1;
+        let seen = match (&mut acc.seen, self.mode) {
+            (Seen::Exact(set), _) => match set.contains(&x) {
+                true => true,
+                false => {
+                    set.insert(x);
+                    return;
+                }
+            },
+            (Seen::Bloom(filter), DuplicateMode::Bloom { bits, hashes }) => {
+                let mut seen = true;
+                for i in self.bloom_bits(&x, bits, hashes) {
+                    let (word, bit) = (i / 64, 1 << (i % 64));
+                    seen &= filter[word] & bit != 0;
+                    filter[word] |= bit;
+                }
+                seen
+            }
+            (Seen::Bloom(_), DuplicateMode::Exact) => unreachable!("state of another mode"),
+        };
+        if seen {
+            acc.duplicates += 1;
+            self.add_example(x, acc);
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        Duplicates {
+            count: acc.count,
+            duplicates: acc.duplicates,
+            examples: acc.examples,
+            exact: self.mode == DuplicateMode::Exact,
+        }
+    }
+}
+
+impl<A: Hash + Eq> Fold for DuplicateStats<A> {
+    fn empty(&self) -> Self::M {
+        let seen = match self.mode {
+            DuplicateMode::Exact => Seen::Exact(FxHashSet::default()),
+            DuplicateMode::Bloom { bits, .. } => Seen::Bloom(vec![0; bits.div_ceil(64)]),
+        };
+        DuplicateState {
+            seen,
+            count: 0,
+            duplicates: 0,
+            examples: Vec::new(),
+        }
+    }
+}
+
+/// Values seen by both states are duplicates. With Bloom filters these
+/// are estimated from how much fuller the merged filter is than each.
+impl<A: Hash + Eq> FoldPar for DuplicateStats<A> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.count += m2.count;
+        m1.duplicates += m2.duplicates;
+        for x in m2.examples {
+            self.add_example(x, m1);
+        }
+        match (&mut m1.seen, m2.seen, self.mode) {
+            (Seen::Exact(set1), Seen::Exact(set2), _) => {
+                let mut both = Vec::new();
+                for x in set2 {
+                    if set1.contains(&x) {
+                        both.push(x);
+                    } else {
+                        set1.insert(x);
+                    }
+                }
+                m1.duplicates += both.len() as u64;
+                for x in both {
+                    self.add_example(x, m1);
+                }
+            }
+            (Seen::Bloom(f1), Seen::Bloom(f2), DuplicateMode::Bloom { bits, hashes }) => {
+                let d1 = Self::bloom_distinct(f1, bits, hashes);
+                let d2 = Self::bloom_distinct(&f2, bits, hashes);
+                for (w1, w2) in f1.iter_mut().zip(f2) {
+                    *w1 |= w2;
+                }
+                let both = d1 + d2 - Self::bloom_distinct(f1, bits, hashes);
+                m1.duplicates += both.round().max(0.0) as u64;
+            }
+            _ => unreachable!("state of another mode"),
+        }
+    }
+}
+
+/// Heap memory owned by the values is not counted
+impl<A: Hash + Eq> StateSize for DuplicateStats<A> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        let seen = match &m.seen {
+            Seen::Exact(set) => set.capacity() * size_of::<A>(),
+            Seen::Bloom(filter) => filter.capacity() * size_of::<u64>(),
+        };
+        size_of::<Self::M>() + seen + m.examples.capacity() * size_of::<A>()
+    }
+}
+
+/// Approximate quantiles using a merging t-digest.
+/// Larger compression means more centroids and better accuracy.
+#[derive(Clone, Copy)]
+pub struct TDigest {
+    compression: f64,
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> Self {
+        assert!(compression > 0.0, "TDigest compression must be positive");
+        TDigest { compression }
+    }
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        Self::new(100.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// State and output of `TDigest`, which can be queried for quantiles
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuantileDigest {
+    compression: f64,
+    centroids: Vec<Centroid>,
+    buffer: Vec<Centroid>,
+    count: f64,
+    min: f64,
+    max: f64,
+}
+
+impl QuantileDigest {
+    fn new(compression: f64) -> Self {
+        QuantileDigest {
+            compression,
+            centroids: Vec::new(),
+            buffer: Vec::with_capacity(Self::buffer_size(compression)),
+            count: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    fn buffer_size(compression: f64) -> usize {
+        (compression.ceil() as usize) * 5
+    }
+
+    fn push(&mut self, c: Centroid) {
+        self.buffer.push(c);
+        if self.buffer.len() >= Self::buffer_size(self.compression) {
+            self.compress();
+        }
+    }
+
+    /// Scale function k1 from the t-digest paper, and its inverse
+    fn k(&self, q: f64) -> f64 {
+        self.compression / (2.0 * std::f64::consts::PI) * (2.0 * q - 1.0).asin()
+    }
+
+    fn k_inv(&self, k: f64) -> f64 {
+        let k = k.min(self.compression / 4.0);
+        ((k * 2.0 * std::f64::consts::PI / self.compression).sin() + 1.0) / 2.0
+    }
+
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.buffer);
+        all.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+        let mut merged = Vec::with_capacity(all.len());
+        let mut all = all.into_iter();
+        let mut cur = all.next().unwrap();
+        let mut w_so_far = 0.0;
+        let mut q_limit = self.k_inv(self.k(0.0) + 1.0);
+
+        for next in all {
+            let q = (w_so_far + cur.weight + next.weight) / total;
+            if q <= q_limit {
+                let weight = cur.weight + next.weight;
+                cur.mean += (next.mean - cur.mean) * next.weight / weight;
+                cur.weight = weight;
+            } else {
+                w_so_far += cur.weight;
+                merged.push(cur);
+                q_limit = self.k_inv(self.k(w_so_far / total) + 1.0);
+                cur = next;
+            }
+        }
+        merged.push(cur);
+        self.centroids = merged;
+    }
+
+    /// The centroids with any buffered values merged in, compressing a
+    /// copy so that queries need not take `&mut self`
+    fn merged(&self) -> Cow<'_, [Centroid]> {
+        if self.buffer.is_empty() {
+            Cow::Borrowed(&self.centroids)
+        } else {
+            let mut digest = self.clone();
+            digest.compress();
+            Cow::Owned(digest.centroids)
+        }
+    }
+
+    /// Number of values seen
+    pub fn count(&self) -> f64 {
+        self.count
+    }
+
+    /// Smallest value seen
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    /// Largest value seen
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Estimate the `q`th quantile, `q` should be in `[0, 1]`.
+    /// Returns NaN if no values were seen.
+    pub fn quantile(&self, q: f64) -> f64 {
+        let cs = self.merged();
+        if cs.is_empty() {
+            return f64::NAN;
+        }
+        let target = q.clamp(0.0, 1.0) * self.count;
+
+        // interpolate between centroid centers, using min and max
+        // as the outermost points
+        let first = cs[0];
+        if target < first.weight / 2.0 {
+            return self.min + (first.mean - self.min) * target / (first.weight / 2.0);
+        }
+        let mut cumulative = first.weight / 2.0;
+        for pair in cs.windows(2) {
+            let dw = (pair[0].weight + pair[1].weight) / 2.0;
+            if target < cumulative + dw {
+                let t = (target - cumulative) / dw;
+                return pair[0].mean + t * (pair[1].mean - pair[0].mean);
+            }
+            cumulative += dw;
+        }
+        let last = cs[cs.len() - 1];
+        let t = ((target - cumulative) / (last.weight / 2.0)).min(1.0);
+        last.mean + t * (self.max - last.mean)
+    }
+
+    /// Estimate the fraction of values at or below `x`, the inverse
+    /// of `quantile`. Returns NaN if no values were seen.
+    pub fn cdf(&self, x: f64) -> f64 {
+        let cs = self.merged();
+        if cs.is_empty() {
+            return f64::NAN;
+        }
+        if x < self.min {
+            return 0.0;
+        }
+        if x >= self.max {
+            return 1.0;
+        }
+
+        // the same points as `quantile` interpolates between
+        let mut prev = (self.min, 0.0);
+        let mut cumulative = 0.0;
+        for (i, c) in cs.iter().enumerate() {
+            cumulative += if i == 0 {
+                c.weight / 2.0
+            } else {
+                (cs[i - 1].weight + c.weight) / 2.0
+            };
+            if x < c.mean {
+                let t = (x - prev.0) / (c.mean - prev.0);
+                return (prev.1 + t * (cumulative - prev.1)) / self.count;
+            }
+            prev = (c.mean, cumulative);
+        }
+        let t = (x - prev.0) / (self.max - prev.0);
+        (prev.1 + t * (self.count - prev.1)) / self.count
+    }
+
+    /// Estimate the percentage of values at or below `x`, between 0
+    /// and 100. Returns NaN if no values were seen.
+    pub fn percent_rank(&self, x: f64) -> f64 {
+        100.0 * self.cdf(x)
+    }
+}
+
+impl Fold1 for TDigest {
+    type A = f64;
+    type B = QuantileDigest;
+    type M = QuantileDigest;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        if x.is_nan() {
+            return;
+        }
+        acc.count += 1.0;
+        acc.min = acc.min.min(x);
+        acc.max = acc.max.max(x);
+        acc.push(Centroid {
+            mean: x,
+            weight: 1.0,
+        });
+    }
+
+    fn output(&self, mut acc: Self::M) -> Self::B {
+        acc.compress();
+        acc
+    }
+}
+
+impl Fold for TDigest {
+    fn empty(&self) -> Self::M {
+        QuantileDigest::new(self.compression)
+    }
+}
+
+impl FoldPar for TDigest {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.count += m2.count;
+        m1.min = m1.min.min(m2.min);
+        m1.max = m1.max.max(m2.max);
+        for c in m2.centroids.into_iter().chain(m2.buffer) {
+            m1.push(c);
+        }
+    }
+}
+
+impl StateSize for TDigest {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>()
+            + (m.centroids.capacity() + m.buffer.capacity()) * size_of::<Centroid>()
+    }
+}
+
+/// Sketches which can trade accuracy for memory, so that a memory
+/// budget can be shared between many of them, see `group_by_sketch`
+pub trait SketchBudget: Fold + Sized {
+    /// This sketch, made smaller if needed to use about `bytes` per
+    /// state, though never smaller than a useful minimum
+    fn within(&self, bytes: usize) -> Self;
+    /// Convert a state of this sketch into a state of `smaller`, which
+    /// was made by `within`
+    fn shrink(&self, smaller: &Self, m: Self::M) -> Self::M;
+}
+
+/// Precision is lowered to fit the registers in the budget, down to 4
+impl<A: Hash> SketchBudget for HyperLogLog<A> {
+    fn within(&self, bytes: usize) -> Self {
+        let precision = bytes.max(1).ilog2().clamp(4, self.precision as u32);
+        HyperLogLog::new(precision as u8)
+    }
+
+    /// Each register is folded into the register of the prefix of its
+    /// index, with the dropped bits of the index counting to its rank
+    fn shrink(&self, smaller: &Self, m: Self::M) -> Self::M {
+        let dropped = (self.precision - smaller.precision) as u32;
+        if dropped == 0 {
+            return m;
+        }
+        let max_rank = (64 - smaller.precision as u32 + 1) as u8;
+        let mut regs = smaller.empty();
+        for (i, r) in m.into_iter().enumerate().filter(|(_, r)| *r > 0) {
+            let low = i & ((1 << dropped) - 1);
+            let rank = match low {
+                0 => (dropped as u8 + r).min(max_rank),
+                _ => (low.leading_zeros() - (usize::BITS - dropped) + 1) as u8,
+            };
+            let reg = &mut regs[i >> dropped];
+            *reg = (*reg).max(rank);
+        }
+        regs
+    }
+}
+
+/// Compression is lowered to fit the centroids and buffer in the
+/// budget, down to 10
+impl SketchBudget for TDigest {
+    fn within(&self, bytes: usize) -> Self {
+        let per_unit = (QuantileDigest::buffer_size(1.0) + 1) * size_of::<Centroid>();
+        let compression = (bytes / per_unit) as f64;
+        TDigest::new(compression.clamp(self.compression.min(10.0), self.compression))
+    }
+
+    fn shrink(&self, smaller: &Self, mut m: Self::M) -> Self::M {
+        if smaller.compression < m.compression {
+            m.compression = smaller.compression;
+            m.buffer.append(&mut m.centroids);
+            m.compress();
+            m.buffer = Vec::with_capacity(QuantileDigest::buffer_size(m.compression));
+        }
+        m
+    }
+}
+
+/// A sketch for each key, sharing a memory budget between the keys,
+/// see `group_by_sketch`
+#[derive(Clone, Copy)]
+pub struct GroupedSketch<F, GetKey> {
+    inner: F,
+    get_key: GetKey,
+    budget: usize,
+}
+
+impl<F, GetKey> GroupedSketch<F, GetKey> {
+    pub(crate) fn new(inner: F, get_key: GetKey, budget: usize) -> Self {
+        GroupedSketch {
+            inner,
+            get_key,
+            budget,
+        }
+    }
+}
+
+/// State of `GroupedSketch`, where the sketch of every key gets
+/// `budget / 2^level` bytes
+#[derive(Clone, Debug)]
+pub struct SketchGroups<Key, M> {
+    level: u32,
+    groups: FxHashMap<Key, M>,
+}
+
+impl<F: SketchBudget, GetKey> GroupedSketch<F, GetKey> {
+    fn sketch(&self, level: u32) -> F {
+        self.inner.within(self.budget >> level.min(usize::BITS - 1))
+    }
+
+    /// Level which splits the budget between `keys` keys
+    fn level(keys: usize) -> u32 {
+        keys.max(1).next_power_of_two().trailing_zeros()
+    }
+
+    /// Shrink the sketch of every key from `m.level` to `level`
+    fn raise<Key: Hash + Eq>(&self, m: &mut SketchGroups<Key, F::M>, level: u32) {
+        if level <= m.level {
+            return;
+        }
+        let (from, to) = (self.sketch(m.level), self.sketch(level));
+        m.groups = std::mem::take(&mut m.groups)
+            .into_iter()
+            .map(|(k, acc)| (k, from.shrink(&to, acc)))
+            .collect();
+        m.level = level;
+    }
+}
+
+impl<F, Key, GetKey> Fold1 for GroupedSketch<F, GetKey>
+where
+    F: SketchBudget,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    type A = F::A;
+    type B = FxHashMap<Key, F::B>;
+    type M = SketchGroups<Key, F::M>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let key = (self.get_key)(&x);
+        if !acc.groups.contains_key(&key) {
+            self.raise(acc, Self::level(acc.groups.len() + 1));
+        }
+        let sketch = self.sketch(acc.level);
+        let m = acc.groups.entry(key).or_insert_with(|| sketch.empty());
+        sketch.step(x, m);
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let sketch = self.sketch(acc.level);
+        acc.groups
+            .into_iter()
+            .map(|(k, m)| (k, sketch.output(m)))
+            .collect()
+    }
+}
+
+impl<F, Key, GetKey> Fold for GroupedSketch<F, GetKey>
+where
+    F: SketchBudget,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn empty(&self) -> Self::M {
+        SketchGroups {
+            level: 0,
+            groups: FxHashMap::default(),
+        }
+    }
+}
+
+/// Both states are shrunk to split the budget between all their keys
+impl<F, Key, GetKey> FoldPar for GroupedSketch<F, GetKey>
+where
+    F: SketchBudget + FoldPar,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn merge(&self, m1: &mut Self::M, mut m2: Self::M) {
+        let new_keys = m2
+            .groups
+            .keys()
+            .filter(|k| !m1.groups.contains_key(k))
+            .count();
+        let level = Self::level(m1.groups.len() + new_keys)
+            .max(m1.level)
+            .max(m2.level);
+        self.raise(m1, level);
+        self.raise(&mut m2, level);
+        let sketch = self.sketch(level);
+        for (k, acc2) in m2.groups {
+            match m1.groups.entry(k) {
+                Entry::Occupied(e) => sketch.merge(e.into_mut(), acc2),
+                Entry::Vacant(e) => {
+                    e.insert(acc2);
+                }
+            }
+        }
+    }
+}
+
+/// Heap memory owned by the keys is not counted
+impl<F, Key, GetKey> StateSize for GroupedSketch<F, GetKey>
+where
+    F: SketchBudget + StateSize,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        let sketch = self.sketch(m.level);
+        size_of::<Self::M>()
+            + m.groups.capacity() * size_of::<(Key, F::M)>()
+            + m.groups
+                .values()
+                .map(|acc| sketch.state_bytes(acc) - size_of::<F::M>())
+                .sum::<usize>()
+    }
+}
+
+/// Approximate median absolute deviation, the robust estimate of scale,
+/// using two t-digests: one of the values, for their median, and one
+/// of their distances from a center, for the MAD.
+/// The center is the exact median of the first `warm_up` values, which
+/// are kept until it is known. As distances are from the center rather
+/// than from the median of all the values, the MAD is off by at most
+/// `|median - center|` on top of the error of the digests, which is
+/// small when the warm up values are representative.
+#[derive(Clone, Copy)]
+pub struct MedianAbsDeviation {
+    sketch: TDigest,
+    warm_up: usize,
+}
+
+impl MedianAbsDeviation {
+    pub fn new(sketch: TDigest, warm_up: usize) -> Self {
+        assert!(warm_up > 0, "MedianAbsDeviation warm_up must be positive");
+        MedianAbsDeviation { sketch, warm_up }
+    }
+
+    /// Find the center, from which distances are measured, once the
+    /// warm up is over
+    fn finish_warm_up(&self, acc: &mut MadState) {
+        let mut xs = std::mem::take(&mut acc.warm_up);
+        if xs.is_empty() {
+            return;
+        }
+        let (mid, even) = (xs.len() / 2, xs.len().is_multiple_of(2));
+        let (lower, upper, _) = xs.select_nth_unstable_by(mid, f64::total_cmp);
+        let center = match even {
+            true => (lower.iter().copied().fold(f64::NEG_INFINITY, f64::max) + *upper) / 2.0,
+            false => *upper,
+        };
+        for x in xs {
+            self.sketch.step((x - center).abs(), &mut acc.deviations);
+        }
+        acc.center = Some(center);
+    }
+}
+
+impl Default for MedianAbsDeviation {
+    fn default() -> Self {
+        Self::new(TDigest::default(), 1000)
+    }
+}
+
+/// State of `MedianAbsDeviation`
+#[derive(Clone, Debug)]
+pub struct MadState {
+    values: QuantileDigest,
+    deviations: QuantileDigest,
+    center: Option<f64>,
+    warm_up: Vec<f64>,
+}
+
+/// Output of `MedianAbsDeviation`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MadEstimate {
+    /// Approximate median of the values
+    pub median: f64,
+    /// Approximate median of the distances of the values from `center`
+    pub mad: f64,
+    /// Median of the warm up values, which distances are measured from
+    pub center: f64,
+}
+
+impl MadEstimate {
+    /// The MAD scaled to estimate the standard deviation of normally
+    /// distributed values
+    pub fn std_dev(&self) -> f64 {
+        1.4826 * self.mad
+    }
+}
+
+impl Fold1 for MedianAbsDeviation {
+    type A = f64;
+    type B = MadEstimate;
+    type M = MadState;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        if x.is_nan() {
+            return;
+        }
+        self.sketch.step(x, &mut acc.values);
+        match acc.center {
+            Some(center) => self.sketch.step((x - center).abs(), &mut acc.deviations),
+            None => {
+                acc.warm_up.push(x);
+                if acc.warm_up.len() >= self.warm_up {
+                    self.finish_warm_up(acc)
+                }
+            }
+        }
+    }
+
+    fn output(&self, mut acc: Self::M) -> Self::B {
+        if acc.center.is_none() {
+            self.finish_warm_up(&mut acc)
+        }
+        MadEstimate {
+            median: self.sketch.output(acc.values).quantile(0.5),
+            mad: self.sketch.output(acc.deviations).quantile(0.5),
+            center: acc.center.unwrap_or(f64::NAN),
+        }
+    }
+}
+
+impl Fold for MedianAbsDeviation {
+    fn empty(&self) -> Self::M {
+        MadState {
+            values: self.sketch.empty(),
+            deviations: self.sketch.empty(),
+            center: None,
+            warm_up: Vec::new(),
+        }
+    }
+}
+
+/// Warm up values are measured from the center of the other state if
+/// it has one. Distances from two different centers are merged as
+/// they are, which adds the distance between the centers to the error.
+impl FoldPar for MedianAbsDeviation {
+    fn merge(&self, m1: &mut Self::M, mut m2: Self::M) {
+        if m1.center.is_none() && m2.center.is_some() {
+            std::mem::swap(m1, &mut m2);
+        }
+        self.sketch.merge(&mut m1.values, m2.values);
+        self.sketch.merge(&mut m1.deviations, m2.deviations);
+        match m1.center {
+            Some(center) => {
+                for x in m2.warm_up {
+                    self.sketch.step((x - center).abs(), &mut m1.deviations);
+                }
+            }
+            None => {
+                m1.warm_up.extend(m2.warm_up);
+                if m1.warm_up.len() >= self.warm_up {
+                    self.finish_warm_up(m1)
+                }
+            }
+        }
+    }
+}
+
+impl StateSize for MedianAbsDeviation {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() - 2 * size_of::<QuantileDigest>()
+            + self.sketch.state_bytes(&m.values)
+            + self.sketch.state_bytes(&m.deviations)
+            + m.warm_up.capacity() * size_of::<f64>()
+    }
+}
+
+/// Fold over inputs bounded to quantiles estimated from the first
+/// values, see `Fold1::winsorize_by_quantile`.
+/// There is no `FoldPar`, as the bounds of a state depend on which
+/// values it saw first.
+#[derive(Clone, Copy)]
+pub struct Winsorized<F> {
+    inner: F,
+    sketch: TDigest,
+    lo: f64,
+    hi: f64,
+    warm_up: usize,
+}
+
+impl<F: Fold<A = f64>> Winsorized<F> {
+    pub(crate) fn new(inner: F, sketch: TDigest, lo: f64, hi: f64, warm_up: usize) -> Self {
+        assert!(
+            0.0 <= lo && lo <= hi && hi <= 1.0,
+            "winsorize requires 0 <= lo <= hi <= 1"
+        );
+        Winsorized {
+            inner,
+            sketch,
+            lo,
+            hi,
+            warm_up,
+        }
+    }
+
+    /// Estimate the bounds from the warm up values, then fold them
+    fn finish_warm_up(&self, (warm_up, bounds, acc): &mut <Self as Fold1>::M) {
+        let digest = run_fold_iter(&self.sketch, warm_up.iter().copied());
+        let (lo, hi) = (digest.quantile(self.lo), digest.quantile(self.hi));
+        *bounds = Some((lo, hi));
+        for x in warm_up.drain(..) {
+            self.inner.step(clamp(x, lo, hi), acc);
+        }
+        *warm_up = Vec::new();
+    }
+}
+
+/// NaN is passed through, as are all values if the bounds are NaN
+fn clamp(x: f64, lo: f64, hi: f64) -> f64 {
+    if x < lo {
+        lo
+    } else if x > hi {
+        hi
+    } else {
+        x
+    }
+}
+
+impl<F: Fold<A = f64>> Fold1 for Winsorized<F> {
+    type A = f64;
+    type B = F::B;
+    /// The warm up values, the bounds once they are estimated, and the
+    /// state of the inner fold
+    type M = (Vec<f64>, Option<(f64, f64)>, F::M);
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        match acc.1 {
+            Some((lo, hi)) => self.inner.step(clamp(x, lo, hi), &mut acc.2),
+            None => {
+                acc.0.push(x);
+                if acc.0.len() >= self.warm_up {
+                    self.finish_warm_up(acc)
+                }
+            }
+        }
+    }
+
+    fn output(&self, mut acc: Self::M) -> Self::B {
+        if acc.1.is_none() {
+            self.finish_warm_up(&mut acc)
+        }
+        self.inner.output(acc.2)
+    }
+}
+
+impl<F: Fold<A = f64>> Fold for Winsorized<F> {
+    fn empty(&self) -> Self::M {
+        (Vec::new(), None, self.inner.empty())
+    }
+}
+
+impl<F: Fold<A = f64> + StateSize> StateSize for Winsorized<F> {
+    fn state_bytes(&self, (warm_up, _, acc): &Self::M) -> usize {
+        size_of::<Self::M>() - size_of::<F::M>()
+            + warm_up.capacity() * size_of::<f64>()
+            + self.inner.state_bytes(acc)
+    }
+}
+
+/// Approximate most frequent elements using the space saving algorithm.
+/// Tracks `capacity` candidates, and outputs the `k` most frequent
+/// with their (over)estimated counts. A step costs `O(log capacity)`
+/// and a merge `O(capacity)`.
+#[derive(Clone, Copy)]
+pub struct TopK<A> {
+    k: usize,
+    capacity: usize,
+    ghost: PhantomData<A>,
+}
+
+impl<A: Hash + Eq + Clone> TopK<A> {
+    /// Track the `k` most frequent elements, using `10 * k` candidates
+    pub fn new(k: usize) -> Self {
+        Self::with_capacity(k, 10 * k)
+    }
+
+    pub fn with_capacity(k: usize, capacity: usize) -> Self {
+        assert!(capacity >= k, "TopK capacity must be at least k");
+        TopK {
+            k,
+            capacity,
+            ghost: PhantomData,
+        }
+    }
+}
+
+/// State of `TopK`, the candidates with their counts in a binary
+/// min-heap on the count, so that the candidate to evict is at the root
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopKState<A: Hash + Eq> {
+    heap: Vec<(A, usize)>,
+    /// Position of each candidate in `heap`
+    index: FxHashMap<A, usize>,
+}
+
+impl<A: Hash + Eq + Clone> TopKState<A> {
+    fn new() -> Self {
+        TopKState {
+            heap: Vec::new(),
+            index: FxHashMap::default(),
+        }
+    }
+
+    /// Build the state from candidates in any order
+    fn from_vec(heap: Vec<(A, usize)>) -> Self {
+        let index = heap
+            .iter()
+            .enumerate()
+            .map(|(i, (x, _))| (x.clone(), i))
+            .collect();
+        let mut state = TopKState { heap, index };
+        for i in (0..state.heap.len() / 2).rev() {
+            state.sift_down(i);
+        }
+        state
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        *self.index.get_mut(&self.heap[i].0).unwrap() = i;
+        *self.index.get_mut(&self.heap[j].0).unwrap() = j;
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 && self.heap[(i - 1) / 2].1 > self.heap[i].1 {
+            self.swap(i, (i - 1) / 2);
+            i = (i - 1) / 2;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let (l, r) = (2 * i + 1, 2 * i + 2);
+            let mut min = i;
+            if l < self.heap.len() && self.heap[l].1 < self.heap[min].1 {
+                min = l;
+            }
+            if r < self.heap.len() && self.heap[r].1 < self.heap[min].1 {
+                min = r;
+            }
+            if min == i {
+                return;
+            }
+            self.swap(i, min);
+            i = min;
+        }
+    }
+}
+
+impl<A: Hash + Eq + Clone> Fold1 for TopK<A> {
+    type A = A;
+    type B = Vec<(A, usize)>;
+    type M = TopKState<A>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        if let Some(&i) = acc.index.get(&x) {
+            acc.heap[i].1 += 1;
+            acc.sift_down(i);
+        } else if acc.heap.len() < self.capacity {
+            acc.index.insert(x.clone(), acc.heap.len());
+            acc.heap.push((x, 1));
+            acc.sift_up(acc.heap.len() - 1);
+        } else {
+            // replace the least frequent candidate, inheriting its count
+            let (evicted, min) = std::mem::replace(&mut acc.heap[0], (x.clone(), 0));
+            acc.index.remove(&evicted);
+            acc.index.insert(x, 0);
+            acc.heap[0].1 = min + 1;
+            acc.sift_down(0);
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let mut top = acc.heap;
+        top.sort_by(|(_, n1), (_, n2)| n2.cmp(n1));
+        top.truncate(self.k);
+        top
+    }
+}
+
+impl<A: Hash + Eq + Clone> Fold for TopK<A> {
+    fn empty(&self) -> Self::M {
+        TopKState::new()
+    }
+}
+
+impl<A: Hash + Eq + Clone> FoldPar for TopK<A> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        let TopKState {
+            mut heap,
+            mut index,
+        } = std::mem::replace(m1, TopKState::new());
+        for (x, n) in m2.heap {
+            match index.get(&x) {
+                Some(&i) => heap[i].1 += n,
+                None => {
+                    index.insert(x.clone(), heap.len());
+                    heap.push((x, n));
+                }
+            }
+        }
+        if heap.len() > self.capacity {
+            // keep the most frequent candidates
+            heap.select_nth_unstable_by(self.capacity, |(_, n1), (_, n2)| n2.cmp(n1));
+            heap.truncate(self.capacity);
+        }
+        *m1 = TopKState::from_vec(heap);
+    }
+}
+
+/// Heap memory owned by the elements is not counted
+impl<A: Hash + Eq + Clone> StateSize for TopK<A> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>()
+            + m.heap.capacity() * size_of::<(A, usize)>()
+            + m.index.capacity() * size_of::<(A, usize)>()
+    }
+}
+
+/// Histogram of `u64` values such as latencies in nanoseconds, with
+/// the bucketing of HDR histograms: values are recorded to within a
+/// relative error of `10^-significant_digits` over the whole range.
+#[derive(Clone, Copy)]
+pub struct HdrHistogram {
+    /// log2 of the number of sub buckets in each bucket
+    sub_bits: u32,
+    significant_digits: u8,
+}
+
+impl HdrHistogram {
+    /// Significant digits must be between 1 and 5
+    pub fn new(significant_digits: u8) -> Self {
+        assert!(
+            (1..=5).contains(&significant_digits),
+            "HdrHistogram significant digits must be between 1 and 5"
+        );
+        let sub_buckets = 2 * 10u64.pow(significant_digits as u32);
+        HdrHistogram {
+            sub_bits: sub_buckets.next_power_of_two().trailing_zeros(),
+            significant_digits,
+        }
+    }
+}
+
+impl Default for HdrHistogram {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+/// State and output of `HdrHistogram`, which can be queried for
+/// percentiles and exported bucket by bucket
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Histogram {
+    sub_bits: u32,
+    significant_digits: u8,
+    /// Grown as larger values are recorded
+    counts: Vec<u64>,
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    fn new(sub_bits: u32, significant_digits: u8) -> Self {
+        Histogram {
+            sub_bits,
+            significant_digits,
+            counts: Vec::new(),
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    /// The first bucket holds `0..2^sub_bits` exactly, and each bucket
+    /// after covers twice the range of the last with half as many
+    /// sub buckets, each twice as wide
+    fn index(&self, x: u64) -> usize {
+        let mask = (1 << self.sub_bits) - 1;
+        let bucket = (63 - (x | mask).leading_zeros()) - (self.sub_bits - 1);
+        let sub = (x >> bucket) as usize;
+        ((bucket as usize) << (self.sub_bits - 1)) + sub
+    }
+
+    /// The lowest and highest values recorded at `index`
+    fn range(&self, index: usize) -> (u64, u64) {
+        let half = 1 << (self.sub_bits - 1);
+        if index < 2 * half {
+            return (index as u64, index as u64);
+        }
+        let bucket = index / half - 1;
+        let low = ((index - bucket * half) as u64) << bucket;
+        (low, low + ((1 << bucket) - 1))
+    }
+
+    fn record(&mut self, x: u64, n: u64) {
+        let i = self.index(x);
+        if i >= self.counts.len() {
+            self.counts.resize(i + 1, 0);
+        }
+        self.counts[i] += n;
+        self.count += n;
+        self.sum += x as u128 * n as u128;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    /// Number of values recorded
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Smallest value recorded, exactly
+    pub fn min(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// Largest value recorded, exactly
+    pub fn max(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// Mean of the values recorded, exactly
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+
+    pub fn significant_digits(&self) -> u8 {
+        self.significant_digits
+    }
+
+    /// The value below which a fraction `q` of the values fall,
+    /// reported as the highest value equivalent to it like HDR
+    /// histograms do, and capped at the largest value recorded
+    pub fn value_at_quantile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let target = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, &n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= target {
+                return Some(self.range(i).1.min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    /// As `value_at_quantile`, with `p` between 0 and 100
+    pub fn value_at_percentile(&self, p: f64) -> Option<u64> {
+        self.value_at_quantile(p / 100.0)
+    }
+
+    /// The fraction of values at or below `x`, counting values
+    /// equivalent to `x` as equal to it
+    pub fn cdf(&self, x: u64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let i = self.index(x).min(self.counts.len().saturating_sub(1));
+        let below: u64 = self.counts[..=i].iter().sum();
+        Some(below as f64 / self.count as f64)
+    }
+
+    /// As `cdf`, as a percentage between 0 and 100
+    pub fn percent_rank(&self, x: u64) -> Option<f64> {
+        self.cdf(x).map(|f| 100.0 * f)
+    }
+
+    /// The non-empty buckets, as the lowest and highest values they
+    /// hold along with their counts
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &n)| n > 0)
+            .map(|(i, &n)| {
+                let (low, high) = self.range(i);
+                (low, high, n)
+            })
+    }
+}
+
+impl Fold1 for HdrHistogram {
+    type A = u64;
+    type B = Histogram;
+    type M = Histogram;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        acc.record(x, 1)
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+/// Weights are frequencies
+impl StepWeighted for HdrHistogram {
+    type Weight = u64;
+
+    fn step_weighted(&self, x: Self::A, weight: u64, acc: &mut Self::M) {
+        acc.record(x, weight)
+    }
+}
+
+impl Fold for HdrHistogram {
+    fn empty(&self) -> Self::M {
+        Histogram::new(self.sub_bits, self.significant_digits)
+    }
+}
+
+impl FoldPar for HdrHistogram {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        if m2.counts.len() > m1.counts.len() {
+            m1.counts.resize(m2.counts.len(), 0);
+        }
+        for (n1, n2) in m1.counts.iter_mut().zip(m2.counts) {
+            *n1 += n2;
+        }
+        m1.count += m2.count;
+        m1.sum += m2.sum;
+        m1.min = m1.min.min(m2.min);
+        m1.max = m1.max.max(m2.max);
+    }
+}
+
+impl StateSize for HdrHistogram {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() + m.counts.capacity() * size_of::<u64>()
+    }
+}
+
+/// Downsample a series of `(x, y)` points to at most `max_points` for
+/// plotting, keeping its shape. The points are split into buckets of
+/// consecutive points, keeping the lowest and highest of each, and the
+/// buckets are merged in pairs whenever there are more than
+/// `max_points` of them. The output is chosen from the kept points by
+/// largest-triangle-three-buckets, and includes the first and last
+/// points. Points with a NaN coordinate are skipped.
+#[derive(Clone, Copy, Debug)]
+pub struct Downsample {
+    max_points: usize,
+}
+
+impl Downsample {
+    pub fn new(max_points: usize) -> Self {
+        assert!(max_points >= 3, "Downsample: max_points must be at least 3");
+        Downsample { max_points }
+    }
+
+    /// Merge adjacent buckets until there are at most `max_points`
+    fn shrink(&self, acc: &mut DownsampleState) {
+        while acc.buckets.len() > self.max_points {
+            acc.buckets = acc
+                .buckets
+                .chunks(2)
+                .map(|pair| match pair {
+                    [b1, b2] => b1.merge(b2),
+                    [b] => *b,
+                    _ => unreachable!(),
+                })
+                .collect();
+            acc.size *= 2;
+        }
+    }
+}
+
+type Point = (f64, f64);
+
+/// Consecutive points, of which only the ends and extremes are kept
+#[derive(Clone, Copy, Debug)]
+struct PointBucket {
+    count: usize,
+    first: Point,
+    last: Point,
+    lowest: Point,
+    highest: Point,
+}
+
+impl PointBucket {
+    fn new(p: Point) -> Self {
+        PointBucket {
+            count: 1,
+            first: p,
+            last: p,
+            lowest: p,
+            highest: p,
+        }
+    }
+
+    fn push(&mut self, p: Point) {
+        self.count += 1;
+        self.last = p;
+        if p.1 < self.lowest.1 {
+            self.lowest = p;
+        }
+        if p.1 > self.highest.1 {
+            self.highest = p;
+        }
+    }
+
+    fn merge(&self, next: &Self) -> Self {
+        PointBucket {
+            count: self.count + next.count,
+            first: self.first,
+            last: next.last,
+            lowest: match next.lowest.1 < self.lowest.1 {
+                true => next.lowest,
+                false => self.lowest,
+            },
+            highest: match next.highest.1 > self.highest.1 {
+                true => next.highest,
+                false => self.highest,
+            },
+        }
+    }
+}
+
+/// State of `Downsample`, buckets of about `size` points each
+#[derive(Clone, Debug)]
+pub struct DownsampleState {
+    buckets: Vec<PointBucket>,
+    size: usize,
+}
+
+/// Pick `n` of `points`, keeping the first and last, where each point
+/// picked makes the largest triangle with the last picked and the mean
+/// of the points in the next bucket
+fn lttb(points: &[Point], n: usize) -> Vec<Point> {
+    if points.len() <= n {
+        return points.to_vec();
+    }
+    let every = (points.len() - 2) as f64 / (n - 2) as f64;
+    let mut picked = Vec::with_capacity(n);
+    picked.push(points[0]);
+    let mut a = points[0];
+    for i in 0..n - 2 {
+        let start = (i as f64 * every) as usize + 1;
+        let end = ((i + 1) as f64 * every) as usize + 1;
+        let next = &points[end..(((i + 2) as f64 * every) as usize + 1).min(points.len())];
+        let len = next.len() as f64;
+        let mean = next
+            .iter()
+            .fold((0.0, 0.0), |(x, y), p| (x + p.0 / len, y + p.1 / len));
+        let area = |p: &Point| ((a.0 - mean.0) * (p.1 - a.1) - (a.0 - p.0) * (mean.1 - a.1)).abs();
+        a = *points[start..end]
+            .iter()
+            .max_by(|p, q| area(p).total_cmp(&area(q)))
+            .unwrap();
+        picked.push(a);
+    }
+    picked.push(points[points.len() - 1]);
+    picked
+}
+
+impl Fold1 for Downsample {
+    type A = (f64, f64);
+    type B = Vec<(f64, f64)>;
+    type M = DownsampleState;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, p: Self::A, acc: &mut Self::M) {
+        if p.0.is_nan() || p.1.is_nan() {
+            return;
+        }
+        match acc.buckets.last_mut() {
+            Some(bucket) if bucket.count < acc.size => bucket.push(p),
+            _ => {
+                acc.buckets.push(PointBucket::new(p));
+                self.shrink(acc);
+            }
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let mut points: Vec<Point> = Vec::with_capacity(2 * acc.buckets.len() + 2);
+        let mut keep = |p: Point| {
+            if points.last() != Some(&p) {
+                points.push(p)
+            }
+        };
+        for b in acc.buckets.iter() {
+            keep(b.first);
+            // the extremes in the order they were seen, which for
+            // series sorted by x is in order of x
+            let (p1, p2) = match b.lowest.0 <= b.highest.0 {
+                true => (b.lowest, b.highest),
+                false => (b.highest, b.lowest),
+            };
+            keep(p1);
+            keep(p2);
+            keep(b.last);
+        }
+        lttb(&points, self.max_points)
+    }
+}
+
+impl Fold for Downsample {
+    fn empty(&self) -> Self::M {
+        DownsampleState {
+            buckets: Vec::new(),
+            size: 1,
+        }
+    }
+}
+
+/// The buckets of `m2` follow those of `m1`, merged until there are few
+/// enough, so the buckets of the two may differ in size
+impl FoldParOrdered for Downsample {
+    fn merge_at(&self, m1: &mut Self::M, m2: Self::M, _offset: usize) {
+        m1.size = m1.size.max(m2.size);
+        m1.buckets.extend(m2.buckets);
+        self.shrink(m1);
+    }
+}
+
+impl StateSize for Downsample {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() + m.buckets.capacity() * size_of::<PointBucket>()
+    }
+}
+
+impl<A> FoldDescribe for HyperLogLog<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("HyperLogLog")
+    }
+}
+
+impl<K: Hash + Eq, V: Hash + Eq> FoldDescribe for CountDistinctByKey<K, V> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("CountDistinctByKey")
+    }
+}
+
+impl FoldDescribe for TDigest {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("TDigest")
+    }
+}
+
+impl FoldDescribe for Downsample {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Downsample")
+    }
+}
+
+impl FoldDescribe for MedianAbsDeviation {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("MedianAbsDeviation")
+    }
+}
+
+impl<F, Key, GetKey> FoldDescribe for GroupedSketch<F, GetKey>
+where
+    F: SketchBudget + FoldDescribe,
+    Key: Hash + Eq,
+    GetKey: Fn(&F::A) -> Key,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("GroupedSketch", vec![self.inner.describe_structure()])
+    }
+}
+
+impl<A> FoldDescribe for DuplicateStats<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("DuplicateStats")
+    }
+}
+
+impl<F: Fold<A = f64> + FoldDescribe> FoldDescribe for Winsorized<F> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>(
+            "Winsorized",
+            vec![
+                self.inner.describe_structure(),
+                self.sketch.describe_structure(),
+            ],
+        )
+    }
+}
+
+impl FoldDescribe for HdrHistogram {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("HdrHistogram")
+    }
+}
+
+impl<A> FoldDescribe for TopK<A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("TopK")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::Sum;
+
+    #[test]
+    fn init_is_empty_then_step() {
+        let hll = HyperLogLog::default();
+        let mut acc = hll.empty();
+        hll.step("a", &mut acc);
+        assert_eq!(hll.init("a"), acc);
+
+        let top = TopK::new(2);
+        let mut acc = top.empty();
+        top.step("a", &mut acc);
+        assert_eq!(top.init("a"), acc);
+
+        let td = TDigest::default();
+        let mut acc = td.empty();
+        td.step(1.0, &mut acc);
+        assert_eq!(
+            td.output(td.init(1.0)).quantile(0.5),
+            td.output(acc).quantile(0.5)
+        );
+    }
+
+    #[test]
+    fn median_abs_deviation() {
+        // a shuffle of 0..1000, whose median is 499.5 and MAD 250
+        let xs = (0..1000).map(|i| ((i * 7919) % 1000) as f64);
+        let mad = MedianAbsDeviation::new(TDigest::default(), 100);
+        let out = run_fold_iter(&mad, xs.clone());
+        assert!((out.median - 499.5).abs() < 5.0, "{out:?}");
+        assert!(
+            (out.mad - 250.0).abs() < 5.0 + (out.median - out.center).abs(),
+            "{out:?}"
+        );
+
+        // robust to outliers
+        let out = run_fold_iter(&mad, xs.clone().chain([1e9; 20]));
+        assert!((out.mad - 250.0).abs() < 15.0, "{out:?}");
+
+        // merging a state still in its warm up
+        let (mut m1, mut m2) = (mad.empty(), mad.empty());
+        xs.clone().take(950).for_each(|x| mad.step(x, &mut m1));
+        xs.skip(950).for_each(|x| mad.step(x, &mut m2));
+        mad.merge(&mut m2, m1);
+        let out = mad.output(m2);
+        assert!(
+            (out.mad - 250.0).abs() < 5.0 + (out.median - out.center).abs(),
+            "{out:?}"
+        );
+
+        let out = run_fold_iter(&mad, [1.0, 2.0, 4.0].into_iter());
+        assert_eq!((out.center, out.mad), (2.0, 1.0));
+    }
+
+    #[test]
+    fn winsorize() {
+        let xs = (1..=100).map(f64::from).chain([1e6, -1e6]);
+        let fld = TDigest::default().winsorize_by_quantile(TDigest::default(), 0.05, 0.95, 100);
+        let digest = run_fold_iter(&fld, xs);
+        assert_eq!(digest.count(), 102.0);
+        assert!((5.0..=6.0).contains(&digest.min()), "{}", digest.min());
+        assert!((95.0..=96.0).contains(&digest.max()), "{}", digest.max());
+
+        // bounds are estimated at the end when the warm up is not over
+        let fld = Sum::SUM.winsorize_by_quantile(TDigest::default(), 0.0, 0.5, 1000);
+        let sum = run_fold_iter(&fld, [1.0, 2.0, 3.0, 100.0].into_iter());
+        assert!(sum < 20.0, "{sum}");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_outputs() {
+        let hll = run_fold_iter(&HyperLogLog::default(), 0..100);
+        let json = serde_json::to_string(&hll).unwrap();
+        let back: CardinalityEstimate = serde_json::from_str(&json).unwrap();
+        assert!((back.estimate() - hll.estimate()).abs() < 1e-9);
+
+        let digest = run_fold_iter(&TDigest::default(), (0..100).map(|i| i as f64));
+        let json = serde_json::to_string(&digest).unwrap();
+        let back: QuantileDigest = serde_json::from_str(&json).unwrap();
+        assert!((back.quantile(0.9) - digest.quantile(0.9)).abs() < 1e-9);
+
+        let hist = run_fold_iter(&HdrHistogram::default(), 0..10_000);
+        let json = serde_json::to_string(&hist).unwrap();
+        assert_eq!(serde_json::from_str::<Histogram>(&json).unwrap(), hist);
+
+        // partial states from elsewhere, merged without the values
+        let fld = HdrHistogram::default();
+        let partials: Vec<String> = [0..5000, 5000..10_000]
+            .into_iter()
+            .map(|xs| serde_json::to_string(&scan_state(fld, xs).last().unwrap()).unwrap())
+            .collect();
+        let merged = try_merge_outputs(&fld, partials.iter().map(|s| serde_json::from_str(s)));
+        assert_eq!(merged.unwrap(), hist);
+    }
+
+    #[test]
+    fn hdr_percentiles() {
+        let fld = HdrHistogram::new(3);
+        // latencies from 1us to 10s
+        let xs: Vec<u64> = (0..100_000u64).map(|i| 1000 + i * i).collect();
+        let mut sorted = xs.clone();
+        sorted.sort();
+        let (a, b) = xs.split_at(12_345);
+        let mut acc = run_fold_iter(&fld, a.iter().copied());
+        fld.merge(&mut acc, run_fold_iter(&fld, b.iter().copied()));
+        assert_eq!(acc, run_fold_iter(&fld, xs.iter().copied()));
+
+        assert_eq!(acc.count(), 100_000);
+        assert_eq!((acc.min(), acc.max()), (Some(1000), Some(sorted[99_999])));
+        for p in [50.0, 90.0, 99.0, 99.9] {
+            let exact = sorted[(p / 100.0 * 100_000.0) as usize - 1];
+            let value = acc.value_at_percentile(p).unwrap();
+            assert!(
+                value >= exact && (value - exact) as f64 <= exact as f64 * 1e-3,
+                "p{p}: {value} vs {exact}"
+            );
+        }
+        let total: u64 = acc.buckets().map(|(_, _, n)| n).sum();
+        assert_eq!(total, 100_000);
+        assert!(acc.buckets().all(|(low, high, _)| low <= high));
+    }
+
+    #[test]
+    fn hll_estimate() {
+        for n in [10usize, 1000, 100_000] {
+            let est = run_fold_iter(&HyperLogLog::default(), (0..n).chain(0..n)).estimate();
+            let err = (est - n as f64).abs() / n as f64;
+            assert!(err < 0.05, "n = {n}, estimate = {est}");
+        }
+    }
+
+    #[test]
+    fn distinct_by_key() {
+        // key i has i + 1 distinct values, each seen twice
+        let xs: Vec<(u64, u64)> = (0..50u64)
+            .flat_map(|k| (0..=k).chain(0..=k).map(move |v| (k, v)))
+            .collect();
+        let (a, b) = xs.split_at(xs.len() / 3);
+        for strategy in [
+            DistinctStrategy::Exact,
+            DistinctStrategy::Approximate(12),
+            DistinctStrategy::Bounded {
+                max_exact: 20,
+                precision: 12,
+            },
+        ] {
+            let fld = CountDistinctByKey::new(strategy);
+            let states: Vec<_> = [a, b]
+                .into_iter()
+                .map(|xs| scan_state(fld, xs.iter().copied()).last().unwrap())
+                .collect();
+            let mut acc = fld.empty();
+            for m in states {
+                fld.merge(&mut acc, m);
+            }
+            let out = fld.output(acc);
+            for k in 0..50 {
+                let count = out[&k];
+                let est = count.estimate();
+                assert!((est - (k + 1) as f64).abs() <= 0.05 * (k + 1) as f64 + 0.5);
+                let exact = match strategy {
+                    DistinctStrategy::Exact => true,
+                    DistinctStrategy::Approximate(_) => false,
+                    DistinctStrategy::Bounded { max_exact, .. } => k < max_exact as u64,
+                };
+                assert_eq!(count.is_exact(), exact, "{strategy:?} {k}");
+            }
+        }
+    }
+
+    #[test]
+    fn duplicates() {
+        let ids = [1u64, 2, 3, 2, 4, 1, 2, 5];
+        let exact = DuplicateStats::new(DuplicateMode::Exact, 10);
+        let out = run_fold_iter(&exact, ids.into_iter());
+        assert_eq!((out.count, out.duplicates, out.distinct()), (8, 3, 5));
+        assert_eq!(out.examples, vec![2, 1]);
+
+        // a value in both halves is a duplicate once merged
+        let state = |fld: &DuplicateStats<u64>, xs: &mut dyn Iterator<Item = u64>| {
+            let mut acc = fld.empty();
+            xs.for_each(|x| fld.step(x, &mut acc));
+            acc
+        };
+        let mut acc = state(&exact, &mut ids[..4].iter().copied());
+        exact.merge(&mut acc, state(&exact, &mut ids[4..].iter().copied()));
+        let merged = exact.output(acc);
+        assert_eq!((merged.duplicates, merged.examples.len()), (3, 2));
+
+        // bounded memory, with a few false positives
+        let bloom = DuplicateStats::new(DuplicateMode::bloom(10_000, 0.01), 3);
+        let out = run_fold_iter(&bloom, (0..10_000u64).chain(0..1000));
+        assert!(!out.exact && out.examples.len() == 3);
+        assert!((1000..1100).contains(&out.duplicates), "{}", out.duplicates);
+
+        let mut acc = state(&bloom, &mut (0..5000).chain(0..500));
+        bloom.merge(&mut acc, state(&bloom, &mut (5000..10_000).chain(0..500)));
+        let dups = bloom.output(acc).duplicates;
+        assert!((950..1100).contains(&dups), "{dups}");
+    }
+
+    #[test]
+    fn sketch_budget() {
+        // shrinking registers gives those of the smaller sketch
+        let (big, small) = (HyperLogLog::new(12), HyperLogLog::new(8));
+        let (mut regs_big, mut regs_small) = (big.empty(), small.empty());
+        for x in 0..10_000u64 {
+            big.step(x, &mut regs_big);
+            small.step(x, &mut regs_small);
+        }
+        assert_eq!(big.shrink(&small, regs_big), regs_small);
+
+        let budget = 64 * 1024;
+        let fld = HyperLogLog::new(14).group_by_sketch(|x: &u64| x % 64, budget);
+        let state = |xs: std::ops::Range<u64>| {
+            let mut acc = fld.empty();
+            xs.for_each(|x| fld.step(x, &mut acc));
+            acc
+        };
+        let mut acc = state(0..50_000);
+        assert!(fld.state_bytes(&acc) < 2 * budget);
+        fld.merge(&mut acc, state(50_000..100_000));
+        assert!(fld.state_bytes(&acc) < 2 * budget);
+        let out = fld.output(acc);
+        assert_eq!(out.len(), 64);
+        // 1024 registers for each key
+        let est = out[&3];
+        assert!((est.estimate() - 1562.0).abs() < 4.0 * est.relative_error() * 1562.0);
+
+        let fld = TDigest::new(200.0).group_by_sketch(|x: &f64| *x as u64 % 100, budget);
+        let digests = run_fold_iter(&fld, (0..100_000).map(|i| i as f64));
+        let median = digests[&7].quantile(0.5);
+        assert!((median - 50_000.0).abs() < 500.0, "{median}");
+    }
+
+    #[test]
+    fn downsample() {
+        let series = (0..100_000).map(|i| {
+            let x = i as f64;
+            // a spike which a plot must not lose
+            let y = if i == 51_234 {
+                100.0
+            } else {
+                (x / 1000.0).sin()
+            };
+            (x, y)
+        });
+        let fld = Downsample::new(200);
+        let points = run_fold_iter(&fld, series.clone());
+        assert_eq!(points.len(), 200);
+        assert_eq!(points[0], (0.0, 0.0));
+        assert_eq!(points[199].0, 99_999.0);
+        assert!(points.contains(&(51_234.0, 100.0)));
+        assert!(points.windows(2).all(|w| w[0].0 < w[1].0));
+
+        let (mut acc, mut rest) = (fld.empty(), fld.empty());
+        series
+            .clone()
+            .take(30_000)
+            .for_each(|p| fld.step(p, &mut acc));
+        series.skip(30_000).for_each(|p| fld.step(p, &mut rest));
+        fld.merge_at(&mut acc, rest, 30_000);
+        let merged = fld.output(acc);
+        assert_eq!(merged.len(), 200);
+        assert!(merged.contains(&(51_234.0, 100.0)));
+
+        let few = run_fold_iter(&fld, [(0.0, 1.0), (1.0, f64::NAN), (2.0, 3.0)].into_iter());
+        assert_eq!(few, vec![(0.0, 1.0), (2.0, 3.0)]);
+    }
+
+    #[test]
+    fn tdigest_quantiles() {
+        let n = 100_000;
+        let xs = (0..n).map(|i| ((i * 7919) % n) as f64);
+        let digest = run_fold_iter(&TDigest::default(), xs);
+        assert_eq!(digest.count(), n as f64);
+        assert_eq!(digest.quantile(0.0), 0.0);
+        assert_eq!(digest.quantile(1.0), (n - 1) as f64);
+        for q in [0.01, 0.25, 0.5, 0.75, 0.99] {
+            let err = (digest.quantile(q) - q * n as f64).abs() / n as f64;
+            assert!(err < 0.01, "q = {q}, err = {err}");
+        }
+
+        // a state still holding buffered values can be queried too
+        let fld = TDigest::default();
+        let mut acc = fld.empty();
+        (1..=10).for_each(|x| fld.step(x as f64, &mut acc));
+        assert_eq!(acc.quantile(0.0), 1.0);
+        assert_eq!(acc.cdf(10.0), 1.0);
+        assert_eq!(acc.quantile(0.5), fld.output(acc.clone()).quantile(0.5));
+    }
+
+    #[test]
+    fn cdf_queries() {
+        let n = 100_000;
+        let xs = (0..n).map(|i| ((i * 7919) % n) as f64);
+        let digest = run_fold_iter(&TDigest::default(), xs);
+        assert_eq!(digest.cdf(-1.0), 0.0);
+        assert_eq!(digest.cdf(n as f64), 1.0);
+        for x in [1_000.0, 25_000.0, 50_000.0, 99_000.0] {
+            let err = (digest.cdf(x) - x / n as f64).abs();
+            assert!(err < 0.01, "x = {x}, err = {err}");
+            let q = digest.quantile(digest.cdf(x));
+            assert!((q - x).abs() < 1e-6 * n as f64, "x = {x}, q = {q}");
+        }
+        // the share of values above 90 percent of the range
+        assert!((100.0 - digest.percent_rank(90_000.0) - 10.0).abs() < 1.0);
+
+        let hist = run_fold_iter(&HdrHistogram::default(), 1..=1000);
+        assert_eq!(hist.cdf(0), Some(0.0));
+        assert_eq!(hist.cdf(500), Some(0.5));
+        assert_eq!(hist.percent_rank(5000), Some(100.0));
+        assert!(run_fold_iter(&TDigest::default(), std::iter::empty())
+            .cdf(1.0)
+            .is_nan());
+    }
+
+    #[test]
+    fn top_k() {
+        let xs = (0..1000).map(|i| if i % 3 == 0 { i % 4 } else { i });
+        let top = run_fold_iter(&TopK::new(2), xs);
+        assert_eq!(top.len(), 2);
+        assert!(top.iter().all(|(x, _)| *x < 4));
+
+        // heavy hitters survive eviction, and merging halves
+        let xs: Vec<_> = (0..10_000)
+            .map(|i| if i % 2 == 0 { i % 6 } else { i })
+            .collect();
+        let fld = TopK::with_capacity(3, 20);
+        let (mut m1, mut m2) = (fld.empty(), fld.empty());
+        xs[..5000].iter().for_each(|&x| fld.step(x, &mut m1));
+        xs[5000..].iter().for_each(|&x| fld.step(x, &mut m2));
+        fld.merge(&mut m1, m2);
+        let mut top: Vec<_> = fld.output(m1).into_iter().map(|(x, _)| x).collect();
+        top.sort();
+        assert_eq!(top, vec![0, 2, 4]);
+        assert_eq!(run_fold_iter(&fld, xs.into_iter()).len(), 3);
+    }
+}
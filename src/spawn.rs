@@ -0,0 +1,92 @@
+//! Spawning blocking work for the parallel stream runners, so they
+//! are not tied to one async runtime
+use crate::Error;
+
+use futures::future::{self, BoxFuture, FutureExt};
+
+/// Runs blocking work off the async executor. The returned future
+/// resolves to `Error::Join` when the work panicked or was cancelled.
+pub trait Spawner {
+    fn spawn_blocking<T, W>(&self, work: W) -> BoxFuture<'static, Result<T, Error>>
+    where
+        T: Send + 'static,
+        W: FnOnce() -> T + Send + 'static;
+}
+
+/// Spawns onto tokio's blocking thread pool
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Tokio;
+
+impl Spawner for Tokio {
+    fn spawn_blocking<T, W>(&self, work: W) -> BoxFuture<'static, Result<T, Error>>
+    where
+        T: Send + 'static,
+        W: FnOnce() -> T + Send + 'static,
+    {
+        tokio::task::spawn_blocking(work)
+            .map(|res| res.map_err(|e| Error::Join(Box::new(e))))
+            .boxed()
+    }
+}
+
+/// Spawns onto async-std's blocking thread pool.
+/// Panics in the work are propagated rather than turned into an error.
+#[cfg(feature = "async-std")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AsyncStd;
+
+#[cfg(feature = "async-std")]
+impl Spawner for AsyncStd {
+    fn spawn_blocking<T, W>(&self, work: W) -> BoxFuture<'static, Result<T, Error>>
+    where
+        T: Send + 'static,
+        W: FnOnce() -> T + Send + 'static,
+    {
+        async_std::task::spawn_blocking(work).map(Ok).boxed()
+    }
+}
+
+/// Runs the work immediately on the current task. There is no
+/// parallelism, but it works on any executor, including none.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Inline;
+
+impl Spawner for Inline {
+    fn spawn_blocking<T, W>(&self, work: W) -> BoxFuture<'static, Result<T, Error>>
+    where
+        T: Send + 'static,
+        W: FnOnce() -> T + Send + 'static,
+    {
+        future::ready(Ok(work())).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+    use crate::fold::*;
+
+    #[test]
+    fn spawners() {
+        let xs = || futures::stream::iter(0..100);
+        let out = futures::executor::block_on(run_fold_par_stream_with(
+            &Inline,
+            &Sum::<i64>::SUM,
+            4,
+            xs(),
+        ));
+        assert_eq!(out.ok(), Some(4950));
+
+        #[cfg(feature = "async-std")]
+        {
+            let out = async_std::task::block_on(run_fold_par_stream_with(
+                &AsyncStd,
+                &Sum::<i64>::SUM,
+                4,
+                xs(),
+            ));
+            assert_eq!(out.ok(), Some(4950));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Folding over database rows streamed with sqlx
+use crate::fold::*;
+
+use std::pin::pin;
+
+use ::sqlx::{Error, FromRow, Row};
+use futures::{Stream, StreamExt};
+
+/// Fold over a stream of rows, such as `query(..).fetch(&pool)`,
+/// stopping at the first error. Rows are folded as they arrive,
+/// so the result set is never buffered.
+/// Use `pre_map` to pull values out of each row.
+pub async fn run_fold_rows<R, F>(
+    rows: impl Stream<Item = Result<R, Error>>,
+    fold: &F,
+) -> Result<F::B, Error>
+where
+    F: Fold<A = R>,
+{
+    let mut rows = pin!(rows);
+    let mut acc = fold.empty();
+    while let Some(row) = rows.next().await {
+        fold.step(row?, &mut acc);
+    }
+    Ok(fold.output(acc))
+}
+
+/// Fold over a stream of rows, decoding each one with `FromRow`
+pub async fn run_fold_rows_as<T, R, F>(
+    rows: impl Stream<Item = Result<R, Error>>,
+    fold: &F,
+) -> Result<F::B, Error>
+where
+    R: Row,
+    T: for<'r> FromRow<'r, R>,
+    F: Fold<A = T>,
+{
+    let mut rows = pin!(rows);
+    let mut acc = fold.empty();
+    while let Some(row) = rows.next().await {
+        fold.step(T::from_row(&row?)?, &mut acc);
+    }
+    Ok(fold.output(acc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+
+    use ::sqlx::sqlite::{SqlitePool, SqliteRow};
+
+    #[derive(FromRow)]
+    struct Trip {
+        fare: f64,
+    }
+
+    #[tokio::test]
+    async fn sqlite_rows() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        ::sqlx::query("create table trips (fare real)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        ::sqlx::query("insert into trips values (1.5), (2.5), (4.0)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let rows = ::sqlx::query("select fare from trips").fetch(&pool);
+        let fld = Sum::<f64>::SUM.pre_map(|row: SqliteRow| row.get(0));
+        assert_eq!(run_fold_rows(rows, &fld).await.unwrap(), 8.0);
+
+        let rows = ::sqlx::query("select fare from trips").fetch(&pool);
+        let fld = Sum::<i64>::SUM.pre_map(|t: Trip| (t.fare > 2.0) as i64);
+        assert_eq!(run_fold_rows_as(rows, &fld).await.unwrap(), 2);
+
+        let rows = ::sqlx::query("select nope from trips").fetch(&pool);
+        assert!(run_fold_rows(rows, &Count::COUNT).await.is_err());
+    }
+}
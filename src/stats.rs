@@ -1,7 +1,9 @@
 use crate::fold::*;
-use rand::distributions::Uniform;
-use rand::Rng;
-use rand::{self, SeedableRng};
+use std::hash::Hash;
+
+#[cfg(feature = "stats-rand")]
+use rand::{self, distributions::Uniform, Rng, SeedableRng};
+use rustc_hash::FxHashMap;
 
 /// First 4 central moments
 #[derive(Clone, Copy)]
@@ -15,6 +17,48 @@ impl CM4<f64> {
     };
 }
 
+/// Output of `CM4`, the first four moments of the values seen.
+/// The higher moments are NaN when there are too few values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Moments {
+    count: usize,
+    mean: f64,
+    variance: f64,
+    skewness: f64,
+    kurtosis: f64,
+}
+
+impl Moments {
+    /// Number of values seen
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Sample variance
+    pub fn variance(&self) -> f64 {
+        self.variance
+    }
+
+    /// Sample standard deviation
+    pub fn std_dev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    pub fn skewness(&self) -> f64 {
+        self.skewness
+    }
+
+    /// Kurtosis, which is 3 for a normal distribution
+    pub fn kurtosis(&self) -> f64 {
+        self.kurtosis
+    }
+}
+
 // from https://web.archive.org/web/20140423031833/http://people.xiph.org/~tterribe/notes/homs.html
 
 #[derive(Clone, Copy)]
@@ -29,7 +73,7 @@ pub struct MState<A> {
 impl Fold1 for CM4<f64> {
     type A = f64;
 
-    type B = (f64, f64, f64, f64);
+    type B = Moments;
 
     type M = MState<f64>;
 
@@ -37,9 +81,9 @@ impl Fold1 for CM4<f64> {
         MState {
             n: 1,
             m: x,
-            m2: x,
-            m3: x,
-            m4: x,
+            m2: 0.0,
+            m3: 0.0,
+            m4: 0.0,
         }
     }
 
@@ -63,12 +107,13 @@ impl Fold1 for CM4<f64> {
     }
 
     fn output(&self, acc: Self::M) -> Self::B {
-        (
-            acc.m,
-            acc.m2 / ((acc.n as f64) - 1.0),
-            acc.m3 * acc.m2.powf(-1.5) * (acc.n as f64).sqrt(),
-            (acc.n as f64) * acc.m4 * acc.m2.powi(-2),
-        )
+        Moments {
+            count: acc.n,
+            mean: acc.m,
+            variance: acc.m2 / ((acc.n as f64) - 1.0),
+            skewness: acc.m3 * acc.m2.powf(-1.5) * (acc.n as f64).sqrt(),
+            kurtosis: (acc.n as f64) * acc.m4 * acc.m2.powi(-2),
+        }
     }
 }
 
@@ -88,6 +133,13 @@ impl FoldPar for CM4<f64> {
     // todo: remove divisions by using delta / nAB
     //   probably will not mattter and might make it harder to read
     fn merge(&self, acc1: &mut Self::M, acc2: Self::M) {
+        if acc2.n == 0 {
+            return;
+        }
+        if acc1.n == 0 {
+            *acc1 = acc2;
+            return;
+        }
         let n_a = acc1.n as f64;
         let n_b = acc2.n as f64;
         let n_ab = n_a + n_b;
@@ -95,16 +147,17 @@ impl FoldPar for CM4<f64> {
         acc1.n += acc2.n;
         acc1.m += delta * n_b / n_ab;
 
+        // the higher moments need the old values of the lower ones
         let m2_a = acc1.m2;
         let m2_b = acc2.m2;
+        let m3_a = acc1.m3;
+        let m3_b = acc2.m3;
         acc1.m2 += acc2.m2 + delta * delta * n_a * n_b / n_ab;
 
         acc1.m3 += acc2.m3
             + delta.powi(3) * n_a * n_b * n_ab.powi(-2) * (n_a - n_b)
             + 3.0 * delta * (n_a * m2_b - n_b * m2_a) / n_ab;
 
-        let m3_a = acc1.m3;
-        let m3_b = acc2.m3;
         acc1.m4 += acc2.m4
             + delta.powi(4) * n_a * n_b * (n_a * n_a - n_a * n_b + n_b * n_b) * n_ab.powi(-3)
             + 6.0 * delta * delta * (n_a * n_a * m2_b + n_b * n_b * m2_a) * n_ab.powi(-2)
@@ -112,23 +165,615 @@ impl FoldPar for CM4<f64> {
     }
 }
 
+impl StateSize for CM4<f64> {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>()
+    }
+}
+
+/// Sums of the errors of `(prediction, actual)` pairs
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ErrorSums {
+    n: usize,
+    /// Predictions at or above the actual value
+    covered: usize,
+    pinball: f64,
+    abs: f64,
+    sq: f64,
+}
+
+impl ErrorSums {
+    fn add(&mut self, m: Self) {
+        self.n += m.n;
+        self.covered += m.covered;
+        self.pinball += m.pinball;
+        self.abs += m.abs;
+        self.sq += m.sq;
+    }
+}
+
+/// Pinball loss of predictions of the `tau` quantile, over
+/// `(prediction, actual)` pairs, along with their absolute and squared
+/// errors
+#[derive(Clone, Copy, Debug)]
+pub struct PinballLoss {
+    tau: f64,
+}
+
+impl PinballLoss {
+    pub fn new(tau: f64) -> Self {
+        assert!(
+            0.0 < tau && tau < 1.0,
+            "PinballLoss: tau must be between 0 and 1"
+        );
+        PinballLoss { tau }
+    }
+}
+
+/// Output of `PinballLoss`, the means of the losses over the pairs.
+/// The means are NaN when there were no pairs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct QuantileLoss {
+    pub count: usize,
+    pub pinball: f64,
+    pub mean_abs_error: f64,
+    pub mean_squared_error: f64,
+    /// Fraction of actual values at or below their prediction, which
+    /// is close to `tau` for a calibrated model
+    pub coverage: f64,
+}
+
+impl Fold1 for PinballLoss {
+    type A = (f64, f64);
+    type B = QuantileLoss;
+    type M = ErrorSums;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, (prediction, actual): Self::A, acc: &mut Self::M) {
+        let err = actual - prediction;
+        acc.n += 1;
+        acc.covered += (err <= 0.0) as usize;
+        acc.pinball += (self.tau * err).max((self.tau - 1.0) * err);
+        acc.abs += err.abs();
+        acc.sq += err * err;
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let n = acc.n as f64;
+        QuantileLoss {
+            count: acc.n,
+            pinball: acc.pinball / n,
+            mean_abs_error: acc.abs / n,
+            mean_squared_error: acc.sq / n,
+            coverage: acc.covered as f64 / n,
+        }
+    }
+}
+
+impl Fold for PinballLoss {
+    fn empty(&self) -> Self::M {
+        ErrorSums::default()
+    }
+}
+
+impl FoldPar for PinballLoss {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.add(m2)
+    }
+}
+
+impl StateSize for PinballLoss {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>()
+    }
+}
+
+/// Common metrics of point forecasts over `(prediction, actual)` pairs
+#[derive(Clone, Copy, Debug)]
+pub struct ForecastMetrics;
+
+impl ForecastMetrics {
+    pub const FORECAST_METRICS: Self = ForecastMetrics;
+}
+
+/// Output of `ForecastMetrics`. The metrics are NaN when there were no
+/// pairs, and the MAPE when every actual value was zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Forecast {
+    pub count: usize,
+    /// Mean absolute error
+    pub mae: f64,
+    /// Root mean squared error
+    pub rmse: f64,
+    /// Mean absolute percentage error, over the pairs whose actual
+    /// value is not zero
+    pub mape: f64,
+    /// Mean of prediction less actual, positive when over forecasting
+    pub bias: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ForecastSums {
+    n: usize,
+    abs: f64,
+    sq: f64,
+    bias: f64,
+    /// Number of pairs with a nonzero actual value, and the sum of
+    /// their absolute percentage errors
+    n_pct: usize,
+    abs_pct: f64,
+}
+
+impl Fold1 for ForecastMetrics {
+    type A = (f64, f64);
+    type B = Forecast;
+    type M = ForecastSums;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, (prediction, actual): Self::A, acc: &mut Self::M) {
+        let err = prediction - actual;
+        acc.n += 1;
+        acc.abs += err.abs();
+        acc.sq += err * err;
+        acc.bias += err;
+        if actual != 0.0 {
+            acc.n_pct += 1;
+            acc.abs_pct += (err / actual).abs();
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let n = acc.n as f64;
+        Forecast {
+            count: acc.n,
+            mae: acc.abs / n,
+            rmse: (acc.sq / n).sqrt(),
+            mape: 100.0 * acc.abs_pct / acc.n_pct as f64,
+            bias: acc.bias / n,
+        }
+    }
+}
+
+impl Fold for ForecastMetrics {
+    fn empty(&self) -> Self::M {
+        ForecastSums::default()
+    }
+}
+
+impl FoldPar for ForecastMetrics {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.n += m2.n;
+        m1.abs += m2.abs;
+        m1.sq += m2.sq;
+        m1.bias += m2.bias;
+        m1.n_pct += m2.n_pct;
+        m1.abs_pct += m2.abs_pct;
+    }
+}
+
+impl StateSize for ForecastMetrics {
+    fn state_bytes(&self, _m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>()
+    }
+}
+
+/// Confusion matrix of `(predicted, actual)` labels, see `Confusion`
+pub struct ConfusionMatrix<L> {
+    ghost: std::marker::PhantomData<L>,
+}
+
+impl<L> Clone for ConfusionMatrix<L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<L> Copy for ConfusionMatrix<L> {}
+
+impl<L: Hash + Eq> ConfusionMatrix<L> {
+    pub const CONFUSION_MATRIX: Self = ConfusionMatrix {
+        ghost: std::marker::PhantomData,
+    };
+}
+
+/// Output of `ConfusionMatrix`, the number of times each label was
+/// predicted for each actual label, with the metrics derived from them.
+/// Metrics which would divide by zero are NaN.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Confusion<L: Hash + Eq> {
+    counts: FxHashMap<(L, L), u64>,
+    total: u64,
+}
+
+impl<L: Hash + Eq + Clone> Confusion<L> {
+    /// Number of values of `actual` predicted as `predicted`
+    pub fn count(&self, predicted: &L, actual: &L) -> u64 {
+        // the key owns its labels, so has to be built to look up
+        let key = (predicted.clone(), actual.clone());
+        self.counts.get(&key).copied().unwrap_or(0)
+    }
+
+    /// Every label which was predicted or actual, in no order
+    pub fn labels(&self) -> Vec<&L> {
+        let mut labels: Vec<&L> = Vec::new();
+        for (p, a) in self.counts.keys() {
+            for l in [p, a] {
+                if !labels.contains(&l) {
+                    labels.push(l)
+                }
+            }
+        }
+        labels
+    }
+
+    /// Sums of the counts where `label` was predicted, was actual, and
+    /// was both
+    fn margins(&self, label: &L) -> (u64, u64, u64) {
+        let (mut predicted, mut actual, mut both) = (0, 0, 0);
+        for ((p, a), n) in self.counts.iter() {
+            predicted += (p == label) as u64 * n;
+            actual += (a == label) as u64 * n;
+            both += (p == label && a == label) as u64 * n;
+        }
+        (predicted, actual, both)
+    }
+
+    /// Fraction of values whose prediction was right
+    pub fn accuracy(&self) -> f64 {
+        let right: u64 = self
+            .counts
+            .iter()
+            .filter(|((p, a), _)| p == a)
+            .map(|(_, n)| n)
+            .sum();
+        right as f64 / self.total as f64
+    }
+
+    /// Fraction of predictions of `label` which were right
+    pub fn precision(&self, label: &L) -> f64 {
+        let (predicted, _, both) = self.margins(label);
+        both as f64 / predicted as f64
+    }
+
+    /// Fraction of values of `label` which were predicted as it
+    pub fn recall(&self, label: &L) -> f64 {
+        let (_, actual, both) = self.margins(label);
+        both as f64 / actual as f64
+    }
+
+    /// Harmonic mean of the precision and recall of `label`
+    pub fn f1(&self, label: &L) -> f64 {
+        let (predicted, actual, both) = self.margins(label);
+        2.0 * both as f64 / (predicted + actual) as f64
+    }
+
+    /// Mean of the F1 scores of every label
+    pub fn macro_f1(&self) -> f64 {
+        let labels = self.labels();
+        labels.iter().map(|l| self.f1(l)).sum::<f64>() / labels.len() as f64
+    }
+}
+
+impl<L: Hash + Eq> Fold1 for ConfusionMatrix<L> {
+    type A = (L, L);
+    type B = Confusion<L>;
+    type M = FxHashMap<(L, L), u64>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        *acc.entry(x).or_default() += 1;
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        Confusion {
+            total: acc.values().sum(),
+            counts: acc,
+        }
+    }
+}
+
+impl<L: Hash + Eq> Fold for ConfusionMatrix<L> {
+    fn empty(&self) -> Self::M {
+        FxHashMap::default()
+    }
+}
+
+impl<L: Hash + Eq> FoldPar for ConfusionMatrix<L> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (k, n) in m2 {
+            *m1.entry(k).or_default() += n;
+        }
+    }
+}
+
+/// Heap memory owned by the labels is not counted
+impl<L: Hash + Eq> StateSize for ConfusionMatrix<L> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>() + m.capacity() * std::mem::size_of::<((L, L), u64)>()
+    }
+}
+
+/// Approximate area under the ROC curve of `(score, label)` pairs,
+/// from histograms of the scores of the positive and negative labels.
+/// Scores should be in `[0, 1]`, others are counted in the first or
+/// last bin, and NaN scores are not counted. Scores in the same bin
+/// count as ties, so the error is at most the fraction of pairs which
+/// share a bin.
+#[derive(Clone, Copy, Debug)]
+pub struct RocAuc {
+    bins: usize,
+}
+
+impl RocAuc {
+    pub fn new(bins: usize) -> Self {
+        assert!(bins > 0, "RocAuc: bins must be positive");
+        RocAuc { bins }
+    }
+
+    fn bin(&self, score: f64) -> usize {
+        ((score * self.bins as f64) as usize).min(self.bins - 1)
+    }
+}
+
+impl Default for RocAuc {
+    fn default() -> Self {
+        Self::new(1000)
+    }
+}
+
+/// State of `RocAuc`, the counts of negative and positive labels in
+/// each bin
+#[derive(Clone, Debug)]
+pub struct ScoreHistogram {
+    negative: Vec<u64>,
+    positive: Vec<u64>,
+}
+
+impl Fold1 for RocAuc {
+    type A = (f64, bool);
+    /// NaN unless there were both positive and negative labels
+    type B = f64;
+    type M = ScoreHistogram;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, (score, label): Self::A, acc: &mut Self::M) {
+        if score.is_nan() {
+            return;
+        }
+        let counts = match label {
+            true => &mut acc.positive,
+            false => &mut acc.negative,
+        };
+        counts[self.bin(score)] += 1;
+    }
+
+    /// The probability that a positive scores above a negative, with
+    /// ties counting half
+    fn output(&self, acc: Self::M) -> Self::B {
+        let (mut below, mut wins) = (0, 0.0);
+        for (neg, pos) in acc.negative.iter().zip(&acc.positive) {
+            wins += *pos as f64 * (below as f64 + *neg as f64 / 2.0);
+            below += neg;
+        }
+        let total: u64 = acc.positive.iter().sum();
+        wins / (total as f64 * below as f64)
+    }
+}
+
+impl Fold for RocAuc {
+    fn empty(&self) -> Self::M {
+        ScoreHistogram {
+            negative: vec![0; self.bins],
+            positive: vec![0; self.bins],
+        }
+    }
+}
+
+impl FoldPar for RocAuc {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for (n1, n2) in m1.negative.iter_mut().zip(m2.negative) {
+            *n1 += n2;
+        }
+        for (n1, n2) in m1.positive.iter_mut().zip(m2.positive) {
+            *n1 += n2;
+        }
+    }
+}
+
+impl StateSize for RocAuc {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>()
+            + (m.negative.capacity() + m.positive.capacity()) * std::mem::size_of::<u64>()
+    }
+}
+
+/// Reliability curve and Brier score of `(probability, outcome)` pairs,
+/// binning the predicted probabilities into equal width bins.
+/// Probabilities outside `[0, 1]` are counted in the first or last
+/// bin, and NaNs are not counted.
+#[derive(Clone, Copy, Debug)]
+pub struct Calibration {
+    bins: usize,
+}
+
+impl Calibration {
+    pub fn new(bins: usize) -> Self {
+        assert!(bins > 0, "Calibration: bins must be positive");
+        Calibration { bins }
+    }
+
+    fn bin(&self, p: f64) -> usize {
+        ((p * self.bins as f64) as usize).min(self.bins - 1)
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::new(10)
+    }
+}
+
+/// A bin of a reliability curve
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReliabilityBin {
+    /// Range of the predictions in the bin
+    pub lo: f64,
+    pub hi: f64,
+    pub count: u64,
+    /// Mean of the predictions in the bin, NaN if it is empty
+    pub predicted: f64,
+    /// Fraction of the outcomes in the bin which happened, NaN if it
+    /// is empty
+    pub observed: f64,
+}
+
+/// Output of `Calibration`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Reliability {
+    pub bins: Vec<ReliabilityBin>,
+    pub count: u64,
+    /// Mean squared difference of the predictions and the outcomes
+    pub brier: f64,
+}
+
+impl Reliability {
+    /// Expected calibration error, the mean distance of the predicted
+    /// from the observed frequency over the bins, weighted by count
+    pub fn calibration_error(&self) -> f64 {
+        let gaps: f64 = self
+            .bins
+            .iter()
+            .filter(|b| b.count > 0)
+            .map(|b| b.count as f64 * (b.predicted - b.observed).abs())
+            .sum();
+        gaps / self.count as f64
+    }
+}
+
+/// State of `Calibration`
+#[derive(Clone, Debug)]
+pub struct CalibrationState {
+    /// Count, sum of predictions and number of outcomes of each bin
+    bins: Vec<(u64, f64, u64)>,
+    brier: f64,
+}
+
+impl Fold1 for Calibration {
+    type A = (f64, bool);
+    type B = Reliability;
+    type M = CalibrationState;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, (p, outcome): Self::A, acc: &mut Self::M) {
+        if p.is_nan() {
+            return;
+        }
+        let (count, sum, happened) = &mut acc.bins[self.bin(p)];
+        *count += 1;
+        *sum += p;
+        *happened += outcome as u64;
+        acc.brier += (p - outcome as u8 as f64).powi(2);
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        let width = 1.0 / self.bins as f64;
+        let bins: Vec<_> = acc
+            .bins
+            .iter()
+            .enumerate()
+            .map(|(i, &(count, sum, happened))| ReliabilityBin {
+                lo: i as f64 * width,
+                hi: (i + 1) as f64 * width,
+                count,
+                predicted: sum / count as f64,
+                observed: happened as f64 / count as f64,
+            })
+            .collect();
+        let count = bins.iter().map(|b| b.count).sum();
+        Reliability {
+            bins,
+            count,
+            brier: acc.brier / count as f64,
+        }
+    }
+}
+
+impl Fold for Calibration {
+    fn empty(&self) -> Self::M {
+        CalibrationState {
+            bins: vec![(0, 0.0, 0); self.bins],
+            brier: 0.0,
+        }
+    }
+}
+
+impl FoldPar for Calibration {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        for ((n1, s1, h1), (n2, s2, h2)) in m1.bins.iter_mut().zip(m2.bins) {
+            *n1 += n2;
+            *s1 += s2;
+            *h1 += h2;
+        }
+        m1.brier += m2.brier;
+    }
+}
+
+impl StateSize for Calibration {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        std::mem::size_of::<Self::M>() + m.bins.capacity() * std::mem::size_of::<(u64, f64, u64)>()
+    }
+}
+
 /// Resevoir sampling using algorithm L
+#[cfg(feature = "stats-rand")]
 #[derive(Clone, Copy)]
 pub struct SampleN<const N: usize, A> {
     ghost: std::marker::PhantomData<A>,
 }
 
+#[cfg(feature = "stats-rand")]
 impl<const N: usize, A> SampleN<N, A> {
     pub const SAMPLE: Self = SampleN {
         ghost: std::marker::PhantomData,
     };
 }
 
+#[cfg(feature = "stats-rand")]
 pub enum Resevoir<const N: usize, A> {
     Filling(Vec<A>),
     Resevoir(rand::rngs::SmallRng, f64, usize, [A; N]),
 }
 
+#[cfg(feature = "stats-rand")]
 impl<const N: usize, A> Resevoir<N, A>
 where
     for<'a> [A; N]: TryFrom<&'a mut [A]>,
@@ -178,6 +823,7 @@ where
     }
 }
 
+#[cfg(feature = "stats-rand")]
 impl<const N: usize, A> Fold1 for SampleN<N, A>
 where
     for<'a> [A; N]: TryFrom<&'a mut [A]>,
@@ -207,6 +853,7 @@ where
     }
 }
 
+#[cfg(feature = "stats-rand")]
 impl<const N: usize, A> Fold for SampleN<N, A>
 where
     for<'a> [A; N]: TryFrom<&'a mut [A]>,
@@ -216,6 +863,7 @@ where
     }
 }
 
+#[cfg(feature = "stats-rand")]
 impl<const N: usize, A> FoldPar for SampleN<N, A>
 where
     for<'a> [A; N]: TryFrom<&'a mut [A]>,
@@ -227,3 +875,180 @@ where
         }
     }
 }
+
+#[cfg(feature = "stats-rand")]
+impl<const N: usize, A> StateSize for SampleN<N, A>
+where
+    for<'a> [A; N]: TryFrom<&'a mut [A]>,
+{
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        let heap = match m {
+            Resevoir::Filling(xs) => xs.capacity() * std::mem::size_of::<A>(),
+            Resevoir::Resevoir(..) => 0,
+        };
+        std::mem::size_of::<Self::M>() + heap
+    }
+}
+
+impl FoldDescribe for CM4<f64> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("CM4")
+    }
+}
+
+impl FoldDescribe for PinballLoss {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("PinballLoss")
+    }
+}
+
+impl FoldDescribe for ForecastMetrics {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("ForecastMetrics")
+    }
+}
+
+impl<L: Hash + Eq> FoldDescribe for ConfusionMatrix<L> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("ConfusionMatrix")
+    }
+}
+
+impl FoldDescribe for RocAuc {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("RocAuc")
+    }
+}
+
+impl FoldDescribe for Calibration {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Calibration")
+    }
+}
+
+#[cfg(feature = "stats-rand")]
+impl<const N: usize, A> FoldDescribe for SampleN<N, A>
+where
+    Self: Fold1,
+{
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("SampleN")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cm4_init() {
+        let fld = CM4::CM4;
+        let mut from_init = fld.init(1.0);
+        let mut from_empty = fld.empty();
+        fld.step(1.0, &mut from_empty);
+        for x in [2.0, 4.0, 8.0] {
+            fld.step(x, &mut from_init);
+            fld.step(x, &mut from_empty);
+        }
+        assert_eq!(fld.output(from_init), fld.output(from_empty));
+    }
+
+    #[test]
+    fn moments() {
+        let m = run_fold_iter(
+            &CM4::CM4,
+            [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0].into_iter(),
+        );
+        assert_eq!(m.count(), 8);
+        assert_eq!(m.mean(), 5.0);
+        assert!((m.variance() - 32.0 / 7.0).abs() < 1e-12);
+        assert!((m.std_dev() - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&m).unwrap();
+            let back: Moments = serde_json::from_str(&json).unwrap();
+            assert!((back.std_dev() - m.std_dev()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn forecast_errors() {
+        let pairs = [(10.0, 12.0), (5.0, 4.0), (3.0, 0.0), (8.0, 8.0)];
+        let m = run_fold_iter(&ForecastMetrics::FORECAST_METRICS, pairs.into_iter());
+        assert_eq!(m.count, 4);
+        assert_eq!(m.mae, 1.5);
+        assert_eq!(m.rmse, (14.0f64 / 4.0).sqrt());
+        assert_eq!(m.bias, 0.5);
+        // the pair with an actual value of zero is left out
+        assert!((m.mape - 100.0 * (2.0 / 12.0 + 0.25) / 3.0).abs() < 1e-12);
+
+        let fld = PinballLoss::new(0.9);
+        let loss = run_fold_iter(&fld, pairs.into_iter());
+        assert!((loss.pinball - (0.9 * 2.0 + 0.1 * 1.0 + 0.1 * 3.0) / 4.0).abs() < 1e-12);
+        assert_eq!((loss.mean_abs_error, loss.coverage), (1.5, 0.75));
+
+        let (mut acc, mut rest) = (fld.init(pairs[0]), fld.empty());
+        fld.step_chunk(pairs[1..].to_vec(), &mut rest);
+        fld.merge(&mut acc, rest);
+        assert!((fld.output(acc).pinball - loss.pinball).abs() < 1e-12);
+    }
+
+    #[test]
+    fn classification() {
+        let pairs = [
+            ("cat", "cat"),
+            ("cat", "dog"),
+            ("dog", "dog"),
+            ("dog", "dog"),
+        ];
+        let fld = ConfusionMatrix::CONFUSION_MATRIX;
+        let m = run_fold_iter(&fld, pairs.into_iter());
+        assert_eq!((m.count(&"cat", &"dog"), m.count(&"dog", &"cat")), (1, 0));
+        assert_eq!(m.accuracy(), 0.75);
+        assert_eq!((m.precision(&"cat"), m.recall(&"cat")), (0.5, 1.0));
+        assert_eq!((m.precision(&"dog"), m.recall(&"dog")), (1.0, 2.0 / 3.0));
+        assert!((m.f1(&"dog") - 0.8).abs() < 1e-12);
+        assert_eq!(m.labels().len(), 2);
+
+        // positives score higher apart from one pair
+        let scores = [
+            (0.9, true),
+            (0.8, true),
+            (0.3, true),
+            (0.4, false),
+            (0.1, false),
+        ];
+        let fld = RocAuc::default();
+        assert!((run_fold_iter(&fld, scores.into_iter()) - 5.0 / 6.0).abs() < 1e-12);
+        let mut acc = fld.init(scores[0]);
+        fld.step_chunk(scores[1..].to_vec(), &mut acc);
+        // a negative in the same bin as a positive counts as a tie
+        fld.merge(&mut acc, fld.init((0.3001, false)));
+        assert!((fld.output(acc) - 7.5 / 9.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn reliability_curve() {
+        let xs = [
+            (0.1, false),
+            (0.15, true),
+            (0.9, true),
+            (0.95, true),
+            (f64::NAN, true),
+        ];
+        let fld = Calibration::new(2);
+        let r = run_fold_iter(&fld, xs.into_iter());
+        assert_eq!(r.count, 4);
+        assert_eq!((r.bins[0].count, r.bins[0].observed), (2, 0.5));
+        assert!((r.bins[1].predicted - 0.925).abs() < 1e-12);
+        let brier = (0.01 + 0.85f64.powi(2) + 0.01 + 0.0025) / 4.0;
+        assert!((r.brier - brier).abs() < 1e-12);
+        assert!((r.calibration_error() - (2.0 * 0.375 + 2.0 * 0.075) / 4.0).abs() < 1e-12);
+
+        let (mut acc, mut rest) = (fld.init(xs[0]), fld.empty());
+        fld.step_chunk(xs[1..].to_vec(), &mut rest);
+        fld.merge(&mut acc, rest);
+        assert_eq!(fld.output(acc).bins, r.bins);
+    }
+}
@@ -0,0 +1,251 @@
+//! Property based checks of the laws folds are expected to satisfy,
+//! for testing custom folds. Each check generates inputs with the
+//! given strategy, and panics with a minimal counterexample when
+//! the law does not hold.
+use crate::fold::*;
+
+use std::fmt::Debug;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use proptest::sample::Index;
+use proptest::test_runner::{TestCaseResult, TestRunner};
+use rayon::prelude::*;
+
+/// Maximum number of inputs folded into each generated state
+const MAX_LEN: usize = 32;
+
+/// Maximum number of inputs for the parallel runners, which is
+/// enough to span several of their chunks
+const MAX_PAR_LEN: usize = 4096;
+
+fn fold_all<F: Fold>(fold: &F, xs: &[F::A]) -> F::M
+where
+    F::A: Clone,
+{
+    let mut acc = fold.empty();
+    for x in xs {
+        fold.step(x.clone(), &mut acc);
+    }
+    acc
+}
+
+fn merged<F: FoldPar>(fold: &F, mut m1: F::M, m2: F::M) -> F::M {
+    fold.merge(&mut m1, m2);
+    m1
+}
+
+fn check<S: Strategy>(law: &str, strategy: S, test: impl Fn(S::Value) -> TestCaseResult) {
+    if let Err(e) = TestRunner::default().run(&strategy, test) {
+        panic!("{} does not hold: {}", law, e);
+    }
+}
+
+/// Check that `merge(merge(a, b), c)` and `merge(a, merge(b, c))`
+/// have the same output
+pub fn check_merge_associative<F, S>(fold: &F, gen: S)
+where
+    F: Fold + FoldPar,
+    F::A: Clone + Debug,
+    F::B: PartialEq + Debug,
+    S: Strategy<Value = F::A> + Clone,
+{
+    let inputs = (
+        vec(gen.clone(), 0..MAX_LEN),
+        vec(gen.clone(), 0..MAX_LEN),
+        vec(gen, 0..MAX_LEN),
+    );
+    check("merge associativity", inputs, |(xs, ys, zs)| {
+        let left = merged(
+            fold,
+            merged(fold, fold_all(fold, &xs), fold_all(fold, &ys)),
+            fold_all(fold, &zs),
+        );
+        let right = merged(
+            fold,
+            fold_all(fold, &xs),
+            merged(fold, fold_all(fold, &ys), fold_all(fold, &zs)),
+        );
+        prop_assert_eq!(fold.output(left), fold.output(right));
+        Ok(())
+    })
+}
+
+/// Check that `merge(a, b)` and `merge(b, a)` have the same output.
+/// Only commutative folds, unlike `First` or collecting into a `Vec`,
+/// should satisfy this.
+pub fn check_merge_commutative<F, S>(fold: &F, gen: S)
+where
+    F: Fold + FoldPar,
+    F::A: Clone + Debug,
+    F::B: PartialEq + Debug,
+    S: Strategy<Value = F::A> + Clone,
+{
+    let inputs = (vec(gen.clone(), 0..MAX_LEN), vec(gen, 0..MAX_LEN));
+    check("merge commutativity", inputs, |(xs, ys)| {
+        let left = merged(fold, fold_all(fold, &xs), fold_all(fold, &ys));
+        let right = merged(fold, fold_all(fold, &ys), fold_all(fold, &xs));
+        prop_assert_eq!(fold.output(left), fold.output(right));
+        Ok(())
+    })
+}
+
+/// Check that merging with `empty()` on either side does not change
+/// the output
+pub fn check_merge_identity<F, S>(fold: &F, gen: S)
+where
+    F: Fold + FoldPar,
+    F::A: Clone + Debug,
+    F::B: PartialEq + Debug,
+    S: Strategy<Value = F::A>,
+{
+    check("merge identity", vec(gen, 0..MAX_LEN), |xs| {
+        let expected = || fold.output(fold_all(fold, &xs));
+        let left = merged(fold, fold.empty(), fold_all(fold, &xs));
+        prop_assert_eq!(fold.output(left), expected());
+        let right = merged(fold, fold_all(fold, &xs), fold.empty());
+        prop_assert_eq!(fold.output(right), expected());
+        Ok(())
+    })
+}
+
+/// Check that `step_chunk` has the same output as stepping each
+/// element of the chunk in turn, including after earlier steps
+pub fn check_chunk_equivalence<F, S>(fold: &F, gen: S)
+where
+    F: Fold,
+    F::A: Clone + Debug,
+    F::B: PartialEq + Debug,
+    S: Strategy<Value = F::A> + Clone,
+{
+    let inputs = (vec(gen.clone(), 0..MAX_LEN), vec(gen, 0..MAX_LEN));
+    check("chunk equivalence", inputs, |(xs, chunk)| {
+        let mut by_chunk = fold_all(fold, &xs);
+        fold.step_chunk(chunk.clone(), &mut by_chunk);
+        let by_step = fold_all(fold, &[xs, chunk].concat());
+        prop_assert_eq!(fold.output(by_chunk), fold.output(by_step));
+        Ok(())
+    })
+}
+
+/// Check that `run_fold_par_iter` on thread pools of random sizes,
+/// and merging the states of random chunkings of the input, have
+/// the same output as `run_fold_iter`
+pub fn check_par_equivalence<F, S>(fold: &F, gen: S)
+where
+    F: Fold + FoldPar + Sync,
+    F::A: Clone + Debug + Send,
+    F::B: PartialEq + Debug + Send,
+    F::M: Send,
+    S: Strategy<Value = F::A>,
+{
+    let inputs = (
+        vec(gen, 0..MAX_PAR_LEN),
+        vec(any::<Index>(), 0..8),
+        1..8usize,
+    );
+    check("parallel equivalence", inputs, |(xs, splits, threads)| {
+        let expected = || run_fold_iter(fold, xs.iter().cloned());
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        let owned = xs.clone();
+        let par = pool.install(move || run_fold_par_iter(owned.into_par_iter(), fold));
+        prop_assert_eq!(par, expected());
+
+        let mut splits: Vec<_> = splits.iter().map(|i| i.index(xs.len() + 1)).collect();
+        splits.sort();
+        let mut acc = fold.empty();
+        let mut start = 0;
+        for end in splits.into_iter().chain([xs.len()]) {
+            acc = merged(fold, acc, fold_all(fold, &xs[start..end]));
+            start = end;
+        }
+        prop_assert_eq!(fold.output(acc), expected());
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::*;
+    use crate::sketch::*;
+
+    fn check_all<F, S>(fold: &F, gen: S)
+    where
+        F: Fold + FoldPar + Sync,
+        F::A: Clone + Debug + Send,
+        F::B: PartialEq + Debug + Send,
+        F::M: Send,
+        S: Strategy<Value = F::A> + Clone,
+    {
+        check_merge_associative(fold, gen.clone());
+        check_merge_commutative(fold, gen.clone());
+        check_merge_identity(fold, gen.clone());
+        check_chunk_equivalence(fold, gen.clone());
+        check_par_equivalence(fold, gen);
+    }
+
+    #[test]
+    fn builtins() {
+        check_all(&Sum::<i64>::SUM, -1000i64..1000);
+        check_all(&Count::<u8>::COUNT, any::<u8>());
+        check_all(&Max::MAX.or_default(), any::<i32>());
+        check_all(&Min::MIN.or_default(), any::<i32>());
+        check_all(&HyperLogLog::default(), any::<u32>());
+        // no evictions, and ties sorted, so the counts are exact
+        let top = TopK::new(8).post_map(|mut top: Vec<(u8, usize)>| {
+            top.sort();
+            top
+        });
+        check_all(&top, 0u8..8);
+    }
+
+    #[test]
+    fn combinators() {
+        let fld = Sum::<i64>::SUM
+            .par(Count::COUNT)
+            .filter(|x: &i64| *x % 3 != 0)
+            .pre_map(|x: i16| x as i64);
+        check_all(&fld, any::<i16>());
+
+        let grouped = Sum::<i64>::SUM.group_by(|x: &i64| x.rem_euclid(4));
+        check_all(&grouped, -100i64..100);
+    }
+
+    #[test]
+    #[should_panic(expected = "merge commutativity does not hold")]
+    fn detects_non_commutative() {
+        let fld = Fold1From::new(Concat);
+        check_merge_commutative(&fld, 0u8..4);
+    }
+
+    struct Concat;
+
+    impl FoldImpl for Concat {
+        type A = u8;
+        type B = Vec<u8>;
+        type M = Vec<u8>;
+
+        fn empty(&self) -> Self::M {
+            Vec::new()
+        }
+
+        fn step(&self, x: Self::A, acc: &mut Self::M) {
+            acc.push(x)
+        }
+
+        fn output(&self, acc: Self::M) -> Self::B {
+            acc
+        }
+    }
+
+    impl FoldImplPar for Concat {
+        fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+            m1.extend(m2)
+        }
+    }
+}
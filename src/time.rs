@@ -0,0 +1,630 @@
+//! Folds over durations and timestamps, which the numeric folds do
+//! not cover, since `Duration` has no `From<u8>` for `Sum` and
+//! timestamps cannot be added at all
+use crate::fold::*;
+use crate::sketch::{QuantileDigest, TDigest};
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, Utc};
+
+/// Times which can be folded as a count of ticks from some origin
+pub trait Ticks: Sized {
+    /// Number of ticks in a second
+    const TICKS_PER_SECOND: i128;
+
+    fn to_ticks(&self) -> i128;
+    fn from_ticks(ticks: i128) -> Self;
+}
+
+/// Nanoseconds, saturating at zero and `u64::MAX`
+impl Ticks for Duration {
+    const TICKS_PER_SECOND: i128 = 1_000_000_000;
+
+    fn to_ticks(&self) -> i128 {
+        self.as_nanos() as i128
+    }
+
+    fn from_ticks(ticks: i128) -> Self {
+        Duration::from_nanos(ticks.clamp(0, u64::MAX as i128) as u64)
+    }
+}
+
+/// Microseconds since the unix epoch
+#[cfg(feature = "chrono")]
+impl Ticks for DateTime<Utc> {
+    const TICKS_PER_SECOND: i128 = 1_000_000;
+
+    fn to_ticks(&self) -> i128 {
+        self.timestamp_micros() as i128
+    }
+
+    fn from_ticks(ticks: i128) -> Self {
+        let micros = ticks.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        let nanos = (micros.rem_euclid(1_000_000) * 1000) as u32;
+        DateTime::from_timestamp(micros.div_euclid(1_000_000), nanos).unwrap_or_default()
+    }
+}
+
+/// Number of ticks of `T` in a duration, rounded down
+fn duration_ticks<T: Ticks>(d: Duration) -> i128 {
+    d.as_nanos() as i128 * T::TICKS_PER_SECOND / 1_000_000_000
+}
+
+/// Count, mean, min, max and approximate quantiles of durations or
+/// timestamps, see `durations` and `timestamps`
+pub struct TimeStats<T> {
+    quantiles: TDigest,
+    ghost: PhantomData<T>,
+}
+
+impl<T> Clone for TimeStats<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TimeStats<T> {}
+
+impl<T: Ticks> TimeStats<T> {
+    /// Quantiles are estimated with a t-digest of this compression
+    pub fn new(compression: f64) -> Self {
+        TimeStats {
+            quantiles: TDigest::new(compression),
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<T: Ticks> Default for TimeStats<T> {
+    fn default() -> Self {
+        TimeStats {
+            quantiles: TDigest::default(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+/// Summarise durations, such as request latencies
+pub fn durations() -> TimeStats<Duration> {
+    TimeStats::default()
+}
+
+/// Summarise timestamps, such as event times
+#[cfg(feature = "chrono")]
+pub fn timestamps() -> TimeStats<DateTime<Utc>> {
+    TimeStats::default()
+}
+
+/// State and output of `TimeStats`
+#[derive(Clone, Debug)]
+pub struct TimeSummary<T> {
+    count: usize,
+    sum: i128,
+    min: i128,
+    max: i128,
+    quantiles: QuantileDigest,
+    ghost: PhantomData<T>,
+}
+
+impl<T: Ticks> TimeSummary<T> {
+    /// Number of values seen
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Smallest value seen, if any
+    pub fn min(&self) -> Option<T> {
+        (self.count > 0).then(|| T::from_ticks(self.min))
+    }
+
+    /// Largest value seen, if any
+    pub fn max(&self) -> Option<T> {
+        (self.count > 0).then(|| T::from_ticks(self.max))
+    }
+
+    /// Mean of the values seen, if any
+    pub fn mean(&self) -> Option<T> {
+        (self.count > 0).then(|| T::from_ticks(self.sum / self.count as i128))
+    }
+
+    /// Estimate the `q`th quantile, `q` should be in `[0, 1]`
+    pub fn quantile(&self, q: f64) -> Option<T> {
+        (self.count > 0).then(|| T::from_ticks(self.quantiles.quantile(q).round() as i128))
+    }
+}
+
+impl TimeSummary<Duration> {
+    /// Sum of the durations seen
+    pub fn total(&self) -> Duration {
+        Duration::from_ticks(self.sum)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TimeSummary<DateTime<Utc>> {
+    /// Time between the first and last timestamps, if any
+    pub fn span(&self) -> Option<chrono::Duration> {
+        Some(self.max()? - self.min()?)
+    }
+}
+
+impl<T: Ticks> Fold1 for TimeStats<T> {
+    type A = T;
+    type B = TimeSummary<T>;
+    type M = TimeSummary<T>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let t = x.to_ticks();
+        acc.count += 1;
+        acc.sum += t;
+        acc.min = acc.min.min(t);
+        acc.max = acc.max.max(t);
+        self.quantiles.step(t as f64, &mut acc.quantiles);
+    }
+
+    fn output(&self, mut acc: Self::M) -> Self::B {
+        acc.quantiles = self.quantiles.output(acc.quantiles);
+        acc
+    }
+}
+
+impl<T: Ticks> Fold for TimeStats<T> {
+    fn empty(&self) -> Self::M {
+        TimeSummary {
+            count: 0,
+            sum: 0,
+            min: i128::MAX,
+            max: i128::MIN,
+            quantiles: self.quantiles.empty(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<T: Ticks> FoldPar for TimeStats<T> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.count += m2.count;
+        m1.sum += m2.sum;
+        m1.min = m1.min.min(m2.min);
+        m1.max = m1.max.max(m2.max);
+        self.quantiles.merge(&mut m1.quantiles, m2.quantiles);
+    }
+}
+
+impl<T: Ticks> StateSize for TimeStats<T> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() - size_of::<QuantileDigest>()
+            + self.quantiles.state_bytes(&m.quantiles)
+    }
+}
+
+impl<T: Ticks> FoldDescribe for TimeStats<T> {
+    fn describe_structure(&self) -> Structure {
+        Structure::new::<Self>("TimeStats", vec![self.quantiles.describe_structure()])
+    }
+}
+
+/// Counts of events in each bucket of time, such as each second, and
+/// their average rate, see `rate_per_second` and `rate_per_minute`.
+/// Folds over `(timestamp, event)` pairs.
+/// Only the latest `max_buckets` buckets are kept, the counts of older
+/// buckets are dropped, though they still count towards the rate.
+pub struct Rate<T, X> {
+    bucket_ticks: i128,
+    max_buckets: usize,
+    ghost: PhantomData<fn(T, X)>,
+}
+
+impl<T, X> Clone for Rate<T, X> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T, X> Copy for Rate<T, X> {}
+
+impl<T: Ticks, X> Rate<T, X> {
+    pub fn new(bucket: Duration, max_buckets: usize) -> Self {
+        let bucket_ticks = duration_ticks::<T>(bucket);
+        assert!(bucket_ticks > 0, "Rate bucket must be at least one tick");
+        assert!(max_buckets > 0, "Rate max_buckets must be positive");
+        Rate {
+            bucket_ticks,
+            max_buckets,
+            ghost: PhantomData,
+        }
+    }
+
+    fn trim(&self, buckets: &mut BTreeMap<i128, u64>) {
+        while buckets.len() > self.max_buckets {
+            buckets.pop_first();
+        }
+    }
+}
+
+/// Count events in each second, keeping the latest hour of buckets
+pub fn rate_per_second<T: Ticks, X>() -> Rate<T, X> {
+    Rate::new(Duration::from_secs(1), 3600)
+}
+
+/// Count events in each minute, keeping the latest day of buckets
+pub fn rate_per_minute<T: Ticks, X>() -> Rate<T, X> {
+    Rate::new(Duration::from_secs(60), 1440)
+}
+
+/// State and output of `Rate`
+#[derive(Clone, Debug)]
+pub struct Throughput<T> {
+    bucket_ticks: i128,
+    /// Count of each bucket, by its start in buckets since the origin
+    buckets: BTreeMap<i128, u64>,
+    count: u64,
+    min: i128,
+    max: i128,
+    ghost: PhantomData<T>,
+}
+
+impl<T: Ticks> Throughput<T> {
+    /// Number of events seen
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Start of each bucket with its count of events, oldest first.
+    /// Buckets without events are left out.
+    pub fn buckets(&self) -> impl Iterator<Item = (T, u64)> + '_ {
+        self.buckets
+            .iter()
+            .map(|(i, n)| (T::from_ticks(i * self.bucket_ticks), *n))
+    }
+
+    /// The bucket with the most events, the earliest of any ties
+    pub fn peak(&self) -> Option<(T, u64)> {
+        self.buckets()
+            .reduce(|peak, b| if b.1 > peak.1 { b } else { peak })
+    }
+
+    /// Average number of events per second, over the buckets from the
+    /// first event to the last, if any
+    pub fn per_second(&self) -> Option<f64> {
+        (self.count > 0).then(|| {
+            let buckets =
+                self.max.div_euclid(self.bucket_ticks) - self.min.div_euclid(self.bucket_ticks) + 1;
+            let seconds = (buckets * self.bucket_ticks) as f64 / T::TICKS_PER_SECOND as f64;
+            self.count as f64 / seconds
+        })
+    }
+}
+
+impl<T: Ticks, X> Fold1 for Rate<T, X> {
+    type A = (T, X);
+    type B = Throughput<T>;
+    type M = Throughput<T>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, (t, _): Self::A, acc: &mut Self::M) {
+        let t = t.to_ticks();
+        acc.count += 1;
+        acc.min = acc.min.min(t);
+        acc.max = acc.max.max(t);
+        *acc.buckets
+            .entry(t.div_euclid(self.bucket_ticks))
+            .or_default() += 1;
+        self.trim(&mut acc.buckets);
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+impl<T: Ticks, X> Fold for Rate<T, X> {
+    fn empty(&self) -> Self::M {
+        Throughput {
+            bucket_ticks: self.bucket_ticks,
+            buckets: BTreeMap::new(),
+            count: 0,
+            min: i128::MAX,
+            max: i128::MIN,
+            ghost: PhantomData,
+        }
+    }
+}
+
+impl<T: Ticks, X> FoldPar for Rate<T, X> {
+    fn merge(&self, m1: &mut Self::M, m2: Self::M) {
+        m1.count += m2.count;
+        m1.min = m1.min.min(m2.min);
+        m1.max = m1.max.max(m2.max);
+        for (i, n) in m2.buckets {
+            *m1.buckets.entry(i).or_default() += n;
+        }
+        self.trim(&mut m1.buckets);
+    }
+}
+
+/// Memory of the nodes of the map of buckets is not counted
+impl<T: Ticks, X> StateSize for Rate<T, X> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() + m.buckets.len() * size_of::<(i128, u64)>()
+    }
+}
+
+impl<T: Ticks, X> FoldDescribe for Rate<T, X> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Rate")
+    }
+}
+
+/// Gaps between consecutive timestamps, for checking that a time series
+/// has no missing stretches, see `gaps`.
+/// Timestamps should be in order, and states are merged in order by
+/// `FoldParOrdered`; a timestamp before the latest one seen is not a gap.
+pub struct Gaps<T> {
+    threshold: i128,
+    max_intervals: usize,
+    ghost: PhantomData<T>,
+}
+
+impl<T> Clone for Gaps<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Gaps<T> {}
+
+impl<T: Ticks> Gaps<T> {
+    /// Count gaps longer than `threshold`, keeping the first
+    /// `max_intervals` of them
+    pub fn new(threshold: Duration, max_intervals: usize) -> Self {
+        Gaps {
+            threshold: duration_ticks::<T>(threshold),
+            max_intervals,
+            ghost: PhantomData,
+        }
+    }
+
+    fn gap(&self, from: i128, to: i128, acc: &mut GapSummary<T>) {
+        if to <= from {
+            return;
+        }
+        if acc.largest.is_none_or(|(a, b)| to - from > b - a) {
+            acc.largest = Some((from, to));
+        }
+        if to - from > self.threshold {
+            acc.count += 1;
+            if acc.intervals.len() < self.max_intervals {
+                acc.intervals.push((from, to));
+            }
+        }
+    }
+}
+
+/// Find gaps longer than `threshold`, keeping the first hundred
+pub fn gaps<T: Ticks>(threshold: Duration) -> Gaps<T> {
+    Gaps::new(threshold, 100)
+}
+
+/// State and output of `Gaps`
+#[derive(Clone, Debug)]
+pub struct GapSummary<T> {
+    /// First and latest timestamps, which merges find the gap between
+    ends: Option<(i128, i128)>,
+    largest: Option<(i128, i128)>,
+    count: usize,
+    intervals: Vec<(i128, i128)>,
+    ghost: PhantomData<T>,
+}
+
+impl<T: Ticks> GapSummary<T> {
+    /// Number of gaps longer than the threshold
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// The timestamps either side of the longest gap, if there were
+    /// two distinct timestamps
+    pub fn largest(&self) -> Option<(T, T)> {
+        self.largest
+            .map(|(a, b)| (T::from_ticks(a), T::from_ticks(b)))
+    }
+
+    /// The timestamps either side of the first gaps longer than the
+    /// threshold, in order
+    pub fn intervals(&self) -> impl Iterator<Item = (T, T)> + '_ {
+        self.intervals
+            .iter()
+            .map(|(a, b)| (T::from_ticks(*a), T::from_ticks(*b)))
+    }
+
+    /// Whether every gap was within the threshold
+    pub fn is_complete(&self) -> bool {
+        self.count == 0
+    }
+}
+
+impl<T: Ticks> Fold1 for Gaps<T> {
+    type A = T;
+    type B = GapSummary<T>;
+    type M = GapSummary<T>;
+
+    fn init(&self, x: Self::A) -> Self::M {
+        let mut acc = self.empty();
+        self.step(x, &mut acc);
+        acc
+    }
+
+    fn step(&self, x: Self::A, acc: &mut Self::M) {
+        let t = x.to_ticks();
+        match acc.ends {
+            Some((first, last)) => {
+                self.gap(last, t, acc);
+                acc.ends = Some((first, last.max(t)));
+            }
+            None => acc.ends = Some((t, t)),
+        }
+    }
+
+    fn output(&self, acc: Self::M) -> Self::B {
+        acc
+    }
+}
+
+impl<T: Ticks> Fold for Gaps<T> {
+    fn empty(&self) -> Self::M {
+        GapSummary {
+            ends: None,
+            largest: None,
+            count: 0,
+            intervals: Vec::new(),
+            ghost: PhantomData,
+        }
+    }
+}
+
+/// The gap across the two states comes between their gaps
+impl<T: Ticks> FoldParOrdered for Gaps<T> {
+    fn merge_at(&self, m1: &mut Self::M, m2: Self::M, _offset: usize) {
+        let (first2, last2) = match m2.ends {
+            Some(ends) => ends,
+            None => return,
+        };
+        let first = match m1.ends {
+            Some((first1, last1)) => {
+                self.gap(last1, first2, m1);
+                first1
+            }
+            None => first2,
+        };
+        m1.ends = Some((first, m1.ends.map_or(last2, |(_, last1)| last1.max(last2))));
+        if let Some((a, b)) = m2.largest {
+            if m1.largest.is_none_or(|(c, d)| b - a > d - c) {
+                m1.largest = Some((a, b));
+            }
+        }
+        m1.count += m2.count;
+        let room = self.max_intervals - m1.intervals.len().min(self.max_intervals);
+        m1.intervals.extend(m2.intervals.into_iter().take(room));
+    }
+}
+
+impl<T: Ticks> StateSize for Gaps<T> {
+    fn state_bytes(&self, m: &Self::M) -> usize {
+        size_of::<Self::M>() + m.intervals.capacity() * size_of::<(i128, i128)>()
+    }
+}
+
+impl<T: Ticks> FoldDescribe for Gaps<T> {
+    fn describe_structure(&self) -> Structure {
+        Structure::leaf::<Self>("Gaps")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_summary() {
+        let xs = (1..=100).map(Duration::from_millis);
+        let s = run_fold_iter(&durations(), xs);
+        assert_eq!(s.count(), 100);
+        assert_eq!(s.total(), Duration::from_millis(5050));
+        assert_eq!(s.mean(), Some(Duration::from_micros(50_500)));
+        assert_eq!(s.min(), Some(Duration::from_millis(1)));
+        assert_eq!(s.max(), Some(Duration::from_millis(100)));
+        let p99 = s.quantile(0.99).unwrap();
+        assert!(p99 > Duration::from_millis(97) && p99 <= Duration::from_millis(100));
+
+        let empty = run_fold_iter(&durations(), std::iter::empty());
+        assert_eq!((empty.mean(), empty.quantile(0.5)), (None, None));
+    }
+
+    #[test]
+    fn event_rate() {
+        // event times since the start of a log, with their lines
+        let events = [0, 200, 900, 1_100, 3_500, 3_600, 3_700]
+            .map(|ms| (Duration::from_millis(ms), "GET /"));
+        let fld = rate_per_second();
+        let rate = run_fold_iter(&fld, events.into_iter());
+        assert_eq!(rate.count(), 7);
+        let buckets: Vec<_> = rate.buckets().collect();
+        assert_eq!(
+            buckets,
+            [(0, 3), (1, 1), (3, 3)].map(|(s, n)| (Duration::from_secs(s), n))
+        );
+        assert_eq!(rate.peak(), Some((Duration::ZERO, 3)));
+        assert_eq!(rate.per_second(), Some(7.0 / 4.0));
+
+        // merged chunks, keeping the latest two buckets
+        let fld = Rate::new(Duration::from_secs(1), 2);
+        let mut acc = run_fold_iter(&fld, events[4..].iter().copied());
+        fld.merge(&mut acc, run_fold_iter(&fld, events[..4].iter().copied()));
+        assert_eq!(acc.buckets().map(|(_, n)| n).collect::<Vec<_>>(), [1, 3]);
+        assert_eq!(acc.per_second(), Some(7.0 / 4.0));
+    }
+
+    #[test]
+    fn missing_intervals() {
+        let secs = [0, 1, 2, 10, 11, 12, 13, 30, 31];
+        let xs = secs.map(Duration::from_secs);
+        let fld = gaps(Duration::from_secs(5));
+        let out = run_fold_iter(&fld, xs.into_iter());
+        assert_eq!(out.count(), 2);
+        let (from, to) = out.largest().unwrap();
+        assert_eq!((from.as_secs(), to.as_secs()), (13, 30));
+        let intervals: Vec<_> = out
+            .intervals()
+            .map(|(a, b)| (a.as_secs(), b.as_secs()))
+            .collect();
+        assert_eq!(intervals, [(2, 10), (13, 30)]);
+
+        // the gap across a split is found by the merge
+        for split in 0..secs.len() {
+            let mut acc = run_fold_iter(&fld, xs[..split].iter().copied());
+            fld.merge_at(
+                &mut acc,
+                run_fold_iter(&fld, xs[split..].iter().copied()),
+                split,
+            );
+            assert_eq!(acc.count(), 2);
+            assert_eq!(acc.largest().unwrap().0.as_secs(), 13);
+            assert_eq!(acc.intervals().count(), 2);
+        }
+
+        let fld = Gaps::new(Duration::from_secs(5), 1);
+        let out = run_fold_iter(&fld, xs.into_iter());
+        assert_eq!((out.count(), out.intervals().count()), (2, 1));
+        assert!(run_fold_iter(&fld, xs[..3].iter().copied()).is_complete());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn timestamp_summary() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let xs = (0..60).map(|i| start + chrono::Duration::seconds(i));
+        let s = run_fold_iter(&timestamps(), xs);
+        assert_eq!(s.min(), Some(start));
+        assert_eq!(s.span(), Some(chrono::Duration::seconds(59)));
+        assert_eq!(
+            s.mean(),
+            Some(start + chrono::Duration::milliseconds(29_500))
+        );
+    }
+}
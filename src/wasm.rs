@@ -0,0 +1,41 @@
+//! Runners for the browser, folding over a JS `ReadableStream` such
+//! as the body of a `fetch` response or `File.stream()`
+use crate::fold::*;
+
+use js_sys::{Reflect, Uint8Array};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{ReadableStream, ReadableStreamDefaultReader};
+
+/// Fold over the chunks of a `ReadableStream` until it is done.
+/// The stream is locked while it is read, and released afterwards.
+/// Errors are the ones the stream was errored with.
+pub async fn run_fold_readable_stream<F>(stream: &ReadableStream, fold: &F) -> Result<F::B, JsValue>
+where
+    F: Fold<A = JsValue>,
+{
+    let reader: ReadableStreamDefaultReader = stream.get_reader().unchecked_into();
+    let mut acc = fold.empty();
+    let result = loop {
+        let chunk = match JsFuture::from(reader.read()).await {
+            Ok(chunk) => chunk,
+            Err(e) => break Err(e),
+        };
+        match Reflect::get(&chunk, &"done".into()) {
+            Ok(done) if done.is_truthy() => break Ok(fold.output(acc)),
+            Ok(_) => {}
+            Err(e) => break Err(e),
+        }
+        match Reflect::get(&chunk, &"value".into()) {
+            Ok(x) => fold.step(x, &mut acc),
+            Err(e) => break Err(e),
+        }
+    };
+    reader.release_lock();
+    result
+}
+
+/// Copy a chunk of a byte stream into rust, for use with `pre_map`
+pub fn bytes(chunk: JsValue) -> Vec<u8> {
+    Uint8Array::new(&chunk).to_vec()
+}
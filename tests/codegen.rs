@@ -0,0 +1,89 @@
+//! Checks of the code generated for fused pipelines: a 4-way `par` is
+//! compiled in release mode against the library this test was built
+//! with, and its assembly must be one loop with no calls.
+#![cfg(target_arch = "x86_64")]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+const PROBE: &str = r#"
+use folds::common::*;
+use folds::fold::*;
+
+#[no_mangle]
+pub fn fused_par(xs: &[i32]) -> Option<(((i32, i32), i32), i32)> {
+    run_fold1_iter(
+        &Min::MIN.par(Max::MAX).par(Sum::SUM).par(Last::LAST),
+        xs.iter().copied(),
+    )
+}
+"#;
+
+/// The most recently built rlib of the library, next to this test
+fn library() -> (PathBuf, PathBuf) {
+    let deps = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_owned();
+    let rlib = std::fs::read_dir(&deps)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with("libfolds-") && name.ends_with(".rlib")
+        })
+        .max_by_key(|path| path.metadata().unwrap().modified().unwrap())
+        .expect("no rlib of the library next to the test");
+    (rlib, deps)
+}
+
+/// Assembly of the function `name` in the probe, compiled with `-O`
+fn assembly(name: &str) -> Vec<String> {
+    let (rlib, deps) = library();
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("codegen-{name}"));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("probe.rs"), PROBE).unwrap();
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let status = Command::new(rustc)
+        .current_dir(&dir)
+        .args(["--edition", "2021", "--crate-type", "lib", "--emit", "asm"])
+        .args(["-C", "opt-level=3", "-C", "codegen-units=1"])
+        .arg("--extern")
+        .arg(format!("folds={}", rlib.display()))
+        .arg("-L")
+        .arg(format!("dependency={}", deps.display()))
+        .args(["-o", "probe.s", "probe.rs"])
+        .status()
+        .unwrap();
+    assert!(status.success(), "the probe failed to compile");
+
+    let asm = std::fs::read_to_string(dir.join("probe.s")).unwrap();
+    asm.lines()
+        .skip_while(|line| *line != format!("{name}:"))
+        .take_while(|line| !line.starts_with(".Lfunc_end"))
+        .map(|line| line.trim().to_owned())
+        .collect()
+}
+
+#[test]
+fn par_is_one_loop_without_calls() {
+    let asm = assembly("fused_par");
+    assert!(!asm.is_empty(), "fused_par not found in the assembly");
+
+    let calls: Vec<_> = asm.iter().filter(|line| line.starts_with("call")).collect();
+    assert!(calls.is_empty(), "calls in fused_par: {calls:?}");
+
+    // a loop is a jump back to a label defined before it
+    let mut labels = Vec::new();
+    let mut loops = 0;
+    for line in &asm {
+        if let Some(label) = line.strip_suffix(':') {
+            labels.push(label);
+        } else if line.starts_with('j') {
+            let target = line.split_whitespace().last().unwrap();
+            loops += labels.contains(&target) as usize;
+        }
+    }
+    assert_eq!(loops, 1, "fused_par:\n{}", asm.join("\n"));
+}
@@ -0,0 +1,77 @@
+//! Composite folds over record structs with `#[derive(FoldCompose)]`
+use folds::common::*;
+use folds::fold::*;
+use folds::FoldCompose;
+
+#[derive(Clone)]
+struct Trip {
+    fare: f64,
+    passengers: u32,
+}
+
+fn trips() -> Vec<Trip> {
+    (0..10)
+        .map(|i| Trip {
+            fare: i as f64 * 1.5,
+            passengers: i % 4,
+        })
+        .collect()
+}
+
+#[derive(FoldCompose)]
+#[fold(input = Trip, derive(Debug, PartialEq))]
+struct TripTotals {
+    #[fold(get = |t: &Trip| t.fare)]
+    fare: Sum<f64>,
+    #[fold(get = |t: &Trip| t.passengers as u64)]
+    passengers: Sum<u64>,
+    trips: Count<Trip>,
+}
+
+const TOTALS: TripTotals = TripTotals {
+    fare: Sum::SUM,
+    passengers: Sum::SUM,
+    trips: Count::COUNT,
+};
+
+#[test]
+fn composite() {
+    let expected = TripTotalsOutput {
+        fare: 67.5,
+        passengers: 13,
+        trips: 10,
+    };
+    assert_eq!(run_fold_iter(&TOTALS, trips().into_iter()), expected);
+
+    let (mut m1, mut m2) = (TOTALS.empty(), TOTALS.empty());
+    for (i, t) in trips().into_iter().enumerate() {
+        TOTALS.step(t, if i < 3 { &mut m1 } else { &mut m2 });
+    }
+    TOTALS.merge(&mut m1, m2.clone());
+    assert_eq!(TOTALS.output(m1), expected);
+
+    let s = TOTALS.describe_structure();
+    assert_eq!(s.name, "TripTotals");
+    let fields: Vec<_> = s.children.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(fields, ["fare = Sum", "passengers = Sum", "trips = Count"]);
+}
+
+/// `Max` has no empty state, so only `Fold1` applies
+#[derive(FoldCompose)]
+#[fold(input = Trip)]
+struct Busiest {
+    #[fold(get = |t: &Trip| t.passengers)]
+    passengers: Max<u32>,
+    #[fold(get = |t: &Trip| t.fare)]
+    fare: Sum<f64>,
+}
+
+#[test]
+fn fold1_fields() {
+    let fld = Busiest {
+        passengers: Max::MAX,
+        fare: Sum::SUM,
+    };
+    let out = run_fold1_iter(&fld, trips().into_iter()).unwrap();
+    assert_eq!((out.passengers, out.fare), (3, 67.5));
+}
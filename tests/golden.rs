@@ -0,0 +1,163 @@
+//! Streaming statistics and sketches checked against exact
+//! computations on reference datasets, both sequentially and through
+//! the parallel merge paths.
+use folds::common::*;
+use folds::fold::*;
+use folds::sketch::*;
+use folds::stats::*;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+const N: usize = 20_000;
+
+/// Reference datasets, with a range of shapes and offsets
+fn datasets() -> Vec<(&'static str, Vec<f64>)> {
+    let mut rng = SmallRng::seed_from_u64(0);
+    let uniform: Vec<f64> = (0..N).map(|_| rng.gen()).collect();
+    let exponential = uniform.iter().map(|u| -(1.0 - u).ln()).collect();
+    let offset = uniform.iter().map(|u| 1e6 + u).collect();
+    let small = vec![1.0, 2.0, 4.0, 8.0, 16.0];
+    vec![
+        ("uniform", uniform),
+        ("exponential", exponential),
+        ("offset", offset),
+        ("small", small),
+    ]
+}
+
+/// Exact mean, sample variance, skewness and kurtosis in two passes
+fn exact_moments(xs: &[f64]) -> (f64, f64, f64, f64) {
+    let n = xs.len() as f64;
+    let mean = xs.iter().sum::<f64>() / n;
+    let central = |p| xs.iter().map(|x| (x - mean).powi(p)).sum::<f64>();
+    let (m2, m3, m4) = (central(2), central(3), central(4));
+    (
+        mean,
+        m2 / (n - 1.0),
+        m3 * m2.powf(-1.5) * n.sqrt(),
+        n * m4 * m2.powi(-2),
+    )
+}
+
+fn assert_close(name: &str, actual: f64, expected: f64, rel: f64) {
+    let err = (actual - expected).abs() / expected.abs().max(1.0);
+    assert!(
+        err < rel,
+        "{}: {} differs from {} by {:e}",
+        name,
+        actual,
+        expected,
+        err
+    );
+}
+
+fn assert_moments(name: &str, actual: Moments, expected: (f64, f64, f64, f64)) {
+    assert_close(name, actual.mean(), expected.0, 1e-9);
+    assert_close(name, actual.variance(), expected.1, 1e-6);
+    assert_close(name, actual.skewness(), expected.2, 1e-6);
+    assert_close(name, actual.kurtosis(), expected.3, 1e-6);
+}
+
+/// Fold each part separately, then merge them in order
+fn run_merged<F: Fold + FoldPar>(fold: &F, parts: &[&[F::A]]) -> F::B
+where
+    F::A: Copy,
+{
+    let mut acc = fold.empty();
+    for part in parts {
+        let mut m = fold.empty();
+        part.iter().for_each(|&x| fold.step(x, &mut m));
+        fold.merge(&mut acc, m);
+    }
+    fold.output(acc)
+}
+
+#[test]
+fn cm4_moments() {
+    let fld = CM4::CM4;
+    for (name, xs) in datasets() {
+        let expected = exact_moments(&xs);
+        assert_moments(name, run_fold_iter(&fld, xs.iter().copied()), expected);
+        assert_moments(name, run_fold_par_iter(xs.par_iter().copied(), &fld), expected);
+
+        // uneven splits, including single elements and empty parts
+        let (a, rest) = xs.split_at(1);
+        let (b, c) = rest.split_at(rest.len() / 3);
+        assert_moments(name, run_merged(&fld, &[a, b, &[], c]), expected);
+        assert_moments(name, run_merged(&fld, &[c, b, a]), expected);
+    }
+}
+
+#[test]
+fn tdigest_quantiles() {
+    let fld = TDigest::default();
+    for (name, mut xs) in datasets().into_iter().filter(|(_, xs)| xs.len() == N) {
+        let digests = [
+            run_fold_iter(&fld, xs.iter().copied()),
+            run_fold_par_iter(xs.par_iter().copied(), &fld),
+            run_merged(&fld, &xs.chunks(N / 7).collect::<Vec<_>>()),
+        ];
+        xs.sort_by(f64::total_cmp);
+        for digest in digests {
+            assert_eq!(digest.count(), N as f64);
+            assert_eq!(digest.min(), xs[0]);
+            assert_eq!(digest.max(), xs[N - 1]);
+            for q in [0.01, 0.1, 0.25, 0.5, 0.75, 0.9, 0.99] {
+                // compare ranks rather than values, which is what
+                // the t-digest bounds
+                let estimate = digest.quantile(q);
+                let rank = xs.partition_point(|&x| x < estimate) as f64 / N as f64;
+                assert!(
+                    (rank - q).abs() < 0.01,
+                    "{}: quantile {} has rank {}",
+                    name,
+                    q,
+                    rank
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn hll_estimates() {
+    let fld = HyperLogLog::new(12);
+    // three times the standard error
+    let tolerance = 3.0 * 1.04 / 4096f64.sqrt();
+    for distinct in [10, 1_000, 100_000] {
+        let xs: Vec<u64> = (0..4 * distinct).map(|i| i % distinct).collect();
+        let estimates = [
+            run_fold_iter(&fld, xs.iter().copied()),
+            run_fold_par_iter(xs.par_iter().copied(), &fld),
+            run_merged(&fld, &xs.chunks(xs.len() / 5).collect::<Vec<_>>()),
+        ];
+        for estimate in estimates {
+            assert_close("hll", estimate.estimate(), distinct as f64, tolerance);
+        }
+    }
+}
+
+#[test]
+fn topk_counts() {
+    // a zipf-like dataset, where the heavy hitters are clear
+    let xs: Vec<u32> = (1..=200u32)
+        .flat_map(|i| std::iter::repeat_n(i, 2000 / i as usize))
+        .collect();
+    let fld = TopK::new(5);
+    for top in [
+        run_fold_iter(&fld, xs.iter().copied()),
+        run_fold_par_iter(xs.par_iter().copied(), &fld),
+    ] {
+        let keys: Vec<_> = top.iter().map(|(x, _)| *x).collect();
+        assert_eq!(keys, vec![1, 2, 3, 4, 5]);
+        for (x, n) in top {
+            // space saving only overestimates
+            assert!(n >= 2000 / x as usize);
+        }
+    }
+
+    let exact = run_fold_iter(&Count::COUNT.group_by(|x: &u32| *x), xs.into_iter());
+    assert_eq!(exact[&1], 2000);
+}